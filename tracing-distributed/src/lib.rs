@@ -13,8 +13,14 @@ mod telemetry;
 mod telemetry_layer;
 mod trace;
 
-pub use crate::telemetry::{BlackholeTelemetry, Telemetry};
+pub use crate::telemetry::{
+    BlackholeTelemetry, FilteredTelemetry, TeeTelemetry, TeeVisitor, Telemetry,
+};
 pub use crate::telemetry_layer::TelemetryLayer;
 pub use crate::trace::{
-    current_dist_trace_ctx, register_dist_tracing_root, Event, Span, TraceCtxError,
+    current_dist_trace_ctx, current_dist_trace_sampled, current_span_context, dist_trace_ctx_for,
+    register_dist_tracing_root, register_dist_tracing_root_for,
+    register_dist_tracing_root_started_at, register_dist_tracing_root_with_sampling,
+    set_trace_state_for, span_context_for, with_current_span_values, with_current_span_values_mut,
+    with_span_values, with_span_values_mut, Event, Span, SpanContext, TraceCtxError,
 };
@@ -1,5 +1,8 @@
 use crate::trace::{Event, Span};
 use std::marker::PhantomData;
+use std::time::Duration;
+use tracing::field::{Field, Visit};
+use tracing::Level;
 
 /// Represents the ability to publish events and spans to some arbitrary backend.
 pub trait Telemetry {
@@ -13,6 +16,15 @@ pub trait Telemetry {
     /// Initialize a visitor, used to record values from spans and events as they are observed
     fn mk_visitor(&self) -> Self::Visitor;
 
+    /// Called when a span is created, with the visitor holding the fields it was created
+    /// with, which may be modified. Does nothing by default.
+    fn span_started(
+        &self,
+        _meta: &'static tracing::Metadata<'static>,
+        _values: &mut Self::Visitor,
+    ) {
+    }
+
     /// Report a `Span` with its corresponding `Event`s to this Telemetry instance's backend.
     fn report_span(
         &self,
@@ -21,8 +33,41 @@ pub trait Telemetry {
     );
 
     /// Report an `Event` to this Telemetry instance's backend.
-    /// Only includes `Event`s not part of a `Span`.
+    /// Only includes `Event`s not part of a `Span`, unless `streams_span_events` is true.
     fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>);
+
+    /// How often spans of sampled traces which are still open are passed to
+    /// `report_span_progress`, if at all. `None` by default.
+    ///
+    /// Snapshots are taken when there is activity within the span, i.e. it is entered or
+    /// exited, or an event or child span is created within it, and it has been open or
+    /// last reported for at least the interval.
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Report a snapshot of a span which is still open, with `completed_at` set to the time
+    /// of the snapshot, so long-running spans are visible before they end. The span is
+    /// reported with `report_span` once it ends. Does nothing by default.
+    fn report_span_progress(&self, _span: Span<&Self::Visitor, Self::SpanId, Self::TraceId>) {}
+
+    /// The maximum number of events kept per span until it is reported, if any. Further
+    /// events of the span are dropped and counted in `Span::dropped_events`. `None` by
+    /// default.
+    fn max_events_per_span(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether events within spans of sampled traces are passed to `report_event` as they
+    /// occur, with their trace and parent span, instead of being kept until the span ends.
+    /// Their span is then reported without events. Events recorded before their span was
+    /// registered as a root are passed to `report_event` once it ends. `false` by default.
+    ///
+    /// This keeps the memory of long-running spans bounded, for backends which export
+    /// events independently of their span.
+    fn streams_span_events(&self) -> bool {
+        false
+    }
 }
 
 /// Visitor that records no information when visiting tracing fields.
@@ -66,6 +111,324 @@ where
     fn report_event(&self, _: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {}
 }
 
+/// Visitor recording tracing fields into the visitors of both sides of a [`TeeTelemetry`].
+#[derive(Default, Debug)]
+pub struct TeeVisitor<A, B>(pub A, pub B);
+
+impl<A: Visit, B: Visit> Visit for TeeVisitor<A, B> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.record_f64(field, value);
+        self.1.record_f64(field, value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.record_i64(field, value);
+        self.1.record_i64(field, value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.record_u64(field, value);
+        self.1.record_u64(field, value);
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.0.record_i128(field, value);
+        self.1.record_i128(field, value);
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.0.record_u128(field, value);
+        self.1.record_u128(field, value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.record_bool(field, value);
+        self.1.record_bool(field, value);
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.record_str(field, value);
+        self.1.record_str(field, value);
+    }
+
+    fn record_bytes(&mut self, field: &Field, value: &[u8]) {
+        self.0.record_bytes(field, value);
+        self.1.record_bytes(field, value);
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0.record_error(field, value);
+        self.1.record_error(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.record_debug(field, value);
+        self.1.record_debug(field, value);
+    }
+}
+
+/// Telemetry implementation forwarding every span and event to two inner Telemetry
+/// implementations sharing the same id types, e.g. to publish to two backends at once.
+pub struct TeeTelemetry<A, B>(pub A, pub B);
+
+impl<A, B> Telemetry for TeeTelemetry<A, B>
+where
+    A: Telemetry,
+    A::SpanId: Clone,
+    B: Telemetry<SpanId = A::SpanId, TraceId = A::TraceId>,
+{
+    type Visitor = TeeVisitor<A::Visitor, B::Visitor>;
+    type TraceId = A::TraceId;
+    type SpanId = A::SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        TeeVisitor(self.0.mk_visitor(), self.1.mk_visitor())
+    }
+
+    fn span_started(&self, meta: &'static tracing::Metadata<'static>, values: &mut Self::Visitor) {
+        self.0.span_started(meta, &mut values.0);
+        self.1.span_started(meta, &mut values.1);
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        let (events_a, events_b) = events.into_iter().map(split_event).unzip();
+        let (span_a, span_b) = split_span(span);
+        self.0.report_span(span_a, events_a);
+        self.1.report_span(span_b, events_b);
+    }
+
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        let (event_a, event_b) = split_event(event);
+        self.0.report_event(event_a);
+        self.1.report_event(event_b);
+    }
+
+    /// The shorter interval of both sides, which both receive the snapshots on.
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        match (self.0.heartbeat_interval(), self.1.heartbeat_interval()) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    fn report_span_progress(&self, span: Span<&Self::Visitor, Self::SpanId, Self::TraceId>) {
+        let values = span.values;
+        self.0
+            .report_span_progress(span.clone().with_values(&values.0));
+        self.1.report_span_progress(span.with_values(&values.1));
+    }
+
+    /// The larger maximum of both sides, so neither misses events it would keep; the other
+    /// side may drop the events beyond its own maximum when the span is reported.
+    fn max_events_per_span(&self) -> Option<usize> {
+        Some(
+            self.0
+                .max_events_per_span()?
+                .max(self.1.max_events_per_span()?),
+        )
+    }
+
+    /// Only if both sides stream, as spans are then reported to both without their events.
+    fn streams_span_events(&self) -> bool {
+        self.0.streams_span_events() && self.1.streams_span_events()
+    }
+}
+
+type SpanPair<A, B, SpanId, TraceId> = (Span<A, SpanId, TraceId>, Span<B, SpanId, TraceId>);
+
+fn split_span<A, B, SpanId: Clone, TraceId: Clone>(
+    span: Span<TeeVisitor<A, B>, SpanId, TraceId>,
+) -> SpanPair<A, B, SpanId, TraceId> {
+    let TeeVisitor(values_a, values_b) = span.values;
+    let span_a = Span {
+        id: span.id.clone(),
+        name: span.name.clone(),
+        trace_id: span.trace_id.clone(),
+        trace_state: span.trace_state.clone(),
+        implicit_trace: span.implicit_trace,
+        parent_id: span.parent_id.clone(),
+        local_root: span.local_root,
+        follows_from: span.follows_from.clone(),
+        initialized_at: span.initialized_at,
+        completed_at: span.completed_at,
+        busy: span.busy,
+        idle: span.idle,
+        dropped_events: span.dropped_events,
+        meta: span.meta,
+        service_name: span.service_name,
+        values: values_a,
+    };
+    let span_b = Span {
+        id: span.id,
+        name: span.name,
+        trace_id: span.trace_id,
+        trace_state: span.trace_state,
+        implicit_trace: span.implicit_trace,
+        parent_id: span.parent_id,
+        local_root: span.local_root,
+        follows_from: span.follows_from,
+        initialized_at: span.initialized_at,
+        completed_at: span.completed_at,
+        busy: span.busy,
+        idle: span.idle,
+        dropped_events: span.dropped_events,
+        meta: span.meta,
+        service_name: span.service_name,
+        values: values_b,
+    };
+    (span_a, span_b)
+}
+
+type EventPair<A, B, SpanId, TraceId> = (Event<A, SpanId, TraceId>, Event<B, SpanId, TraceId>);
+
+fn split_event<A, B, SpanId: Clone, TraceId: Clone>(
+    event: Event<TeeVisitor<A, B>, SpanId, TraceId>,
+) -> EventPair<A, B, SpanId, TraceId> {
+    let TeeVisitor(values_a, values_b) = event.values;
+    let event_a = Event {
+        trace_id: event.trace_id.clone(),
+        parent_id: event.parent_id.clone(),
+        initialized_at: event.initialized_at,
+        meta: event.meta,
+        service_name: event.service_name,
+        values: values_a,
+    };
+    let event_b = Event {
+        trace_id: event.trace_id,
+        parent_id: event.parent_id,
+        initialized_at: event.initialized_at,
+        meta: event.meta,
+        service_name: event.service_name,
+        values: values_b,
+    };
+    (event_a, event_b)
+}
+
+type Predicate<T> = Box<dyn Fn(&T) -> bool + Send + Sync>;
+type SpanOf<T> =
+    Span<<T as Telemetry>::Visitor, <T as Telemetry>::SpanId, <T as Telemetry>::TraceId>;
+type EventOf<T> =
+    Event<<T as Telemetry>::Visitor, <T as Telemetry>::SpanId, <T as Telemetry>::TraceId>;
+
+/// Telemetry implementation dropping spans and events not matching a set of predicates before
+/// they reach the inner Telemetry implementation.
+///
+/// Predicates have access to the name, metadata, timings and recorded values of spans and
+/// events, an item is reported only if it matches all predicates. The events of a dropped
+/// span are dropped along with it.
+pub struct FilteredTelemetry<T: Telemetry> {
+    inner: T,
+    span_filters: Vec<Predicate<SpanOf<T>>>,
+    event_filters: Vec<Predicate<EventOf<T>>>,
+}
+
+impl<T: Telemetry> FilteredTelemetry<T> {
+    /// Wraps `inner`, initially reporting all spans and events to it.
+    pub fn new(inner: T) -> Self {
+        FilteredTelemetry {
+            inner,
+            span_filters: Vec::new(),
+            event_filters: Vec::new(),
+        }
+    }
+
+    /// Only reports spans for which `filter` returns true.
+    pub fn filter_spans(
+        mut self,
+        filter: impl Fn(&SpanOf<T>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.span_filters.push(Box::new(filter));
+        self
+    }
+
+    /// Only reports events for which `filter` returns true.
+    pub fn filter_events(
+        mut self,
+        filter: impl Fn(&EventOf<T>) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.event_filters.push(Box::new(filter));
+        self
+    }
+
+    /// Only reports spans and events with a level at most as verbose as `level`.
+    pub fn max_level(self, level: Level) -> Self {
+        self.filter_spans(move |span| *span.meta.level() <= level)
+            .filter_events(move |event| *event.meta.level() <= level)
+    }
+
+    /// Only reports spans lasting at least `duration`.
+    pub fn min_duration(self, duration: Duration) -> Self {
+        self.filter_spans(move |span| {
+            span.completed_at
+                .duration_since(span.initialized_at)
+                .unwrap_or_default()
+                >= duration
+        })
+    }
+
+    /// Returns the inner Telemetry implementation.
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    fn keep_event(&self, event: &EventOf<T>) -> bool {
+        self.event_filters.iter().all(|filter| filter(event))
+    }
+}
+
+impl<T: Telemetry> Telemetry for FilteredTelemetry<T> {
+    type Visitor = T::Visitor;
+    type TraceId = T::TraceId;
+    type SpanId = T::SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        self.inner.mk_visitor()
+    }
+
+    fn span_started(&self, meta: &'static tracing::Metadata<'static>, values: &mut Self::Visitor) {
+        self.inner.span_started(meta, values);
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        mut events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        if self.span_filters.iter().all(|filter| filter(&span)) {
+            events.retain(|event| self.keep_event(event));
+            self.inner.report_span(span, events);
+        }
+    }
+
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        if self.keep_event(&event) {
+            self.inner.report_event(event);
+        }
+    }
+
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        self.inner.heartbeat_interval()
+    }
+
+    /// Snapshots are not filtered, as the predicates apply to completed spans.
+    fn report_span_progress(&self, span: Span<&Self::Visitor, Self::SpanId, Self::TraceId>) {
+        self.inner.report_span_progress(span);
+    }
+
+    fn max_events_per_span(&self) -> Option<usize> {
+        self.inner.max_events_per_span()
+    }
+
+    /// Streamed events are reported even if their span is dropped once it ends.
+    fn streams_span_events(&self) -> bool {
+        self.inner.streams_span_events()
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod test {
     use super::*;
@@ -76,18 +439,56 @@ pub(crate) mod test {
     pub(crate) type TraceId = u64;
     pub(crate) type SpanId = tracing::Id;
 
+    type TestSpan = Span<BlackholeVisitor, SpanId, TraceId>;
+    type TestEvent = Event<BlackholeVisitor, SpanId, TraceId>;
+
     /// Mock telemetry capability
     pub struct TestTelemetry {
-        spans: Arc<Mutex<Vec<Span<BlackholeVisitor, SpanId, TraceId>>>>,
-        events: Arc<Mutex<Vec<Event<BlackholeVisitor, SpanId, TraceId>>>>,
+        spans: Arc<Mutex<Vec<TestSpan>>>,
+        events: Arc<Mutex<Vec<TestEvent>>>,
+        /// The events reported with each span, in the order of `spans`
+        span_events: Arc<Mutex<Vec<Vec<TestEvent>>>>,
+        /// Names of the spans reported with `report_span_progress`
+        snapshots: Arc<Mutex<Vec<String>>>,
+        heartbeat_interval: Option<Duration>,
+        max_events_per_span: Option<usize>,
+        streams_span_events: bool,
     }
 
     impl TestTelemetry {
-        pub fn new(
-            spans: Arc<Mutex<Vec<Span<BlackholeVisitor, SpanId, TraceId>>>>,
-            events: Arc<Mutex<Vec<Event<BlackholeVisitor, SpanId, TraceId>>>>,
-        ) -> Self {
-            TestTelemetry { spans, events }
+        pub fn new(spans: Arc<Mutex<Vec<TestSpan>>>, events: Arc<Mutex<Vec<TestEvent>>>) -> Self {
+            TestTelemetry {
+                spans,
+                events,
+                span_events: Default::default(),
+                snapshots: Default::default(),
+                heartbeat_interval: None,
+                max_events_per_span: None,
+                streams_span_events: false,
+            }
+        }
+
+        pub fn with_heartbeat_interval(mut self, interval: Duration) -> Self {
+            self.heartbeat_interval = Some(interval);
+            self
+        }
+
+        pub fn with_max_events_per_span(mut self, max: usize) -> Self {
+            self.max_events_per_span = Some(max);
+            self
+        }
+
+        pub fn with_streamed_span_events(mut self) -> Self {
+            self.streams_span_events = true;
+            self
+        }
+
+        pub fn span_events(&self) -> Arc<Mutex<Vec<Vec<TestEvent>>>> {
+            self.span_events.clone()
+        }
+
+        pub fn snapshots(&self) -> Arc<Mutex<Vec<String>>> {
+            self.snapshots.clone()
         }
     }
 
@@ -100,20 +501,34 @@ pub(crate) mod test {
             BlackholeVisitor
         }
 
-        fn report_span(
-            &self,
-            span: Span<BlackholeVisitor, SpanId, TraceId>,
-            _: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
-        ) {
+        fn report_span(&self, span: TestSpan, events: Vec<TestEvent>) {
             // succeed or die. failure is unrecoverable (mutex poisoned)
             let mut spans = self.spans.lock().unwrap();
             spans.push(span);
+            self.span_events.lock().unwrap().push(events);
         }
 
-        fn report_event(&self, event: Event<BlackholeVisitor, SpanId, TraceId>) {
+        fn report_event(&self, event: TestEvent) {
             // succeed or die. failure is unrecoverable (mutex poisoned)
             let mut events = self.events.lock().unwrap();
             events.push(event);
         }
+
+        fn heartbeat_interval(&self) -> Option<Duration> {
+            self.heartbeat_interval
+        }
+
+        fn report_span_progress(&self, span: Span<&BlackholeVisitor, SpanId, TraceId>) {
+            assert!(span.completed_at > span.initialized_at);
+            self.snapshots.lock().unwrap().push(span.name);
+        }
+
+        fn max_events_per_span(&self) -> Option<usize> {
+            self.max_events_per_span
+        }
+
+        fn streams_span_events(&self) -> bool {
+            self.streams_span_events
+        }
     }
 }
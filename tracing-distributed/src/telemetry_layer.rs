@@ -1,24 +1,189 @@
 use crate::telemetry::Telemetry;
 use crate::trace;
+use std::any::TypeId;
 use std::marker::PhantomData;
-use std::time::SystemTime;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::level_filters::LevelFilter;
 use tracing::span::{Attributes, Id, Record};
-use tracing::{Event, Subscriber};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Metadata, Subscriber};
 use tracing_subscriber::{layer::Context, registry, Layer};
 
+/// The current time, read from the JS `Date` on `wasm32-unknown-unknown` with the `wasm`
+/// feature, as `SystemTime::now` panics there.
+fn now() -> SystemTime {
+    #[cfg(all(feature = "wasm", target_arch = "wasm32", target_os = "unknown"))]
+    return std::time::UNIX_EPOCH
+        + std::time::Duration::from_secs_f64(js_sys::Date::now() / 1000.0);
+    #[cfg(not(all(feature = "wasm", target_arch = "wasm32", target_os = "unknown")))]
+    SystemTime::now()
+}
+
 /// A `tracing_subscriber::Layer` that publishes events and spans to some backend
 /// using the provided `Telemetry` capability.
+///
+/// Several layers can be added to one subscriber, e.g. to publish to two backends, each
+/// keeping its own state on spans.
 pub struct TelemetryLayer<Telemetry, SpanId, TraceId> {
+    id: LayerId,
     service_name: &'static str,
     pub(crate) telemetry: Telemetry,
     promote_span_id: Box<dyn 'static + Send + Sync + Fn(Id) -> SpanId>,
+    default_trace: Option<TraceCtx<SpanId, TraceId>>,
+    implicit_trace: Option<Box<ImplicitTrace<TraceId>>>,
+    filter: LayerFilter,
+    /// Set once the layer is added to a subscriber
+    with_span: Option<WithSpan>,
     _ttype: PhantomData<TraceId>,
 }
 
+/// A span of the subscriber a `TelemetryLayer` is part of, whatever the type of the
+/// subscriber, for the functions of `trace` which only have the dispatcher of the span.
+pub(crate) trait ErasedSpan {
+    fn metadata(&self) -> &'static tracing::Metadata<'static>;
+    fn extensions(&self) -> registry::Extensions<'_>;
+    fn extensions_mut(&self) -> registry::ExtensionsMut<'_>;
+    fn parent(&self) -> Option<Box<dyn ErasedSpan + '_>>;
+}
+
+impl<'a, R: registry::LookupSpan<'a>> ErasedSpan for registry::SpanRef<'a, R> {
+    fn metadata(&self) -> &'static tracing::Metadata<'static> {
+        registry::SpanRef::metadata(self)
+    }
+
+    fn extensions(&self) -> registry::Extensions<'_> {
+        registry::SpanRef::extensions(self)
+    }
+
+    fn extensions_mut(&self) -> registry::ExtensionsMut<'_> {
+        registry::SpanRef::extensions_mut(self)
+    }
+
+    fn parent(&self) -> Option<Box<dyn ErasedSpan + '_>> {
+        Some(Box::new(registry::SpanRef::parent(self)?))
+    }
+}
+
+/// Looks up a span in the subscriber a `TelemetryLayer` is part of, found by downcasting
+/// the dispatcher of the span, so that any subscriber implementing `LookupSpan` works.
+pub(crate) struct WithSpan(WithSpanFn);
+
+type WithSpanFn = fn(&Dispatch, &Id, &mut dyn FnMut(&dyn ErasedSpan));
+
+impl WithSpan {
+    /// Calls `f` with the span `id` of `dispatch`, unless it is not found.
+    pub(crate) fn with_span(
+        &self,
+        dispatch: &Dispatch,
+        id: &Id,
+        f: &mut dyn FnMut(&dyn ErasedSpan),
+    ) {
+        (self.0)(dispatch, id, f)
+    }
+
+    fn of<S: Subscriber + for<'a> registry::LookupSpan<'a>>() -> Self {
+        WithSpan(|dispatch, id, f| {
+            let subscriber = dispatch
+                .downcast_ref::<S>()
+                .expect("subscriber of the layer should be part of the dispatcher");
+            if let Some(span) = subscriber.span(id) {
+                f(&span);
+            }
+        })
+    }
+}
+
+/// The spans and events a `TelemetryLayer` reports, see `TelemetryLayer::with_max_level`.
+#[derive(Debug, Clone)]
+struct LayerFilter {
+    max_level: LevelFilter,
+    allowed_targets: Option<Vec<String>>,
+    denied_targets: Vec<String>,
+}
+
+impl Default for LayerFilter {
+    fn default() -> Self {
+        Self {
+            max_level: LevelFilter::TRACE,
+            allowed_targets: None,
+            denied_targets: Vec::new(),
+        }
+    }
+}
+
+impl LayerFilter {
+    /// Whether the layer reports the spans or events of `metadata`, see
+    /// `TelemetryLayer::with_max_level`. Decided once per span, as it is created, and
+    /// compares the level first so that most skipped events do not look at their target.
+    fn reports(&self, metadata: &Metadata<'_>) -> bool {
+        let target = metadata.target();
+        metadata.level() <= &self.max_level
+            && self.allowed_targets.as_ref().is_none_or(|allowed| {
+                allowed
+                    .iter()
+                    .any(|prefix| target.starts_with(prefix.as_str()))
+            })
+            && !self
+                .denied_targets
+                .iter()
+                .any(|prefix| target.starts_with(prefix.as_str()))
+    }
+}
+
+/// Returns the id of a trace started for a span outside of any trace, and whether it is
+/// sampled, see `TelemetryLayer::with_implicit_traces`.
+type ImplicitTrace<TraceId> =
+    dyn Fn(&'static tracing::Metadata<'static>) -> (TraceId, bool) + Send + Sync;
+
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub(crate) struct TraceCtx<SpanId, TraceId> {
     pub(crate) parent_span: Option<SpanId>,
     pub(crate) trace_id: TraceId,
+    /// Whether spans of this trace are reported, decided once for the whole trace
+    pub(crate) sampled: bool,
+    /// The W3C `tracestate` of the trace, shared with the spans created within this one
+    pub(crate) trace_state: Option<Arc<str>>,
+    /// Whether the trace was started by the layer for a span which was not part of any,
+    /// see `TelemetryLayer::with_implicit_traces`
+    pub(crate) implicit: bool,
+}
+
+impl<SpanId, TraceId> TraceCtx<SpanId, TraceId>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    /// The trace context of `span`, inherited from the closest ancestor which is part of a
+    /// trace if `span` was created before that ancestor was registered as a root. The
+    /// inherited context is stored on `span` and the spans in between, so it is only looked
+    /// up once. Only the extensions of `layer` are looked at.
+    pub(crate) fn resolve(span: &dyn ErasedSpan, layer: LayerId) -> Option<Self> {
+        if let Some(trace_ctx) = span.extensions().get_for::<Self>(layer) {
+            return Some(trace_ctx.clone());
+        }
+        let trace_ctx = Self::inherit(&*span.parent()?, layer)?;
+        span.extensions_mut().replace_for(layer, trace_ctx.clone());
+        Some(trace_ctx)
+    }
+
+    /// The trace context the children of `span` inherit from it, or from its closest ancestor
+    /// `layer` saw if a per-layer filter disabled `span` for it.
+    fn inherit(span: &dyn ErasedSpan, layer: LayerId) -> Option<Self> {
+        let Some(span_id) = span
+            .extensions()
+            .get_for::<PromotedSpanId<SpanId>>(layer)
+            .map(|id| id.0.clone())
+        else {
+            return Self::inherit(&*span.parent()?, layer);
+        };
+        let trace_ctx = Self::resolve(span, layer)?;
+        Some(TraceCtx {
+            parent_span: Some(span_id),
+            ..trace_ctx
+        })
+    }
 }
 
 /// Used when the trace context is overwritten and indicates this span originally
@@ -26,9 +191,95 @@ pub(crate) struct TraceCtx<SpanId, TraceId> {
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub(crate) struct FollowsFrom<SpanId, TraceId>(pub TraceId, pub SpanId);
 
+/// Marks the span registered as the root of a distributed trace within this process.
+pub(crate) struct LocalRoot;
+
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub(crate) struct PromotedSpanId<SpanId>(pub(crate) SpanId);
 
+/// Identifies a `TelemetryLayer`, namespacing the extensions it stores on spans so that
+/// several layers using the same types can be added to one subscriber.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub(crate) struct LayerId(usize);
+
+impl LayerId {
+    fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        LayerId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// The extensions of type `E` of a span, one per layer which stored one, in the order they
+/// did.
+struct PerLayer<E>(Vec<(LayerId, E)>);
+
+/// Access to the extensions stored on a span by a given `TelemetryLayer`.
+pub(crate) trait LayerExtensions {
+    fn get_for<E: 'static + Send + Sync>(&self, layer: LayerId) -> Option<&E>;
+
+    /// The layers which stored an extension of type `E`, in the order they did.
+    fn layers<E: 'static + Send + Sync>(&self) -> Vec<LayerId>;
+}
+
+/// Mutable access to the extensions stored on a span by a given `TelemetryLayer`.
+pub(crate) trait LayerExtensionsMut {
+    fn get_mut_for<E: 'static + Send + Sync>(&mut self, layer: LayerId) -> Option<&mut E>;
+
+    /// Stores `value` for `layer`, returning the extension it replaces.
+    fn replace_for<E: 'static + Send + Sync>(&mut self, layer: LayerId, value: E) -> Option<E>;
+
+    fn remove_for<E: 'static + Send + Sync>(&mut self, layer: LayerId) -> Option<E>;
+}
+
+impl<E> PerLayer<E> {
+    fn get(&self, layer: LayerId) -> Option<&E> {
+        self.0.iter().find(|(id, _)| *id == layer).map(|(_, e)| e)
+    }
+
+    fn layers(&self) -> Vec<LayerId> {
+        self.0.iter().map(|(id, _)| *id).collect()
+    }
+}
+
+impl LayerExtensions for registry::Extensions<'_> {
+    fn get_for<E: 'static + Send + Sync>(&self, layer: LayerId) -> Option<&E> {
+        self.get::<PerLayer<E>>()?.get(layer)
+    }
+
+    fn layers<E: 'static + Send + Sync>(&self) -> Vec<LayerId> {
+        self.get::<PerLayer<E>>()
+            .map_or_else(Vec::new, PerLayer::layers)
+    }
+}
+
+impl LayerExtensionsMut for registry::ExtensionsMut<'_> {
+    fn get_mut_for<E: 'static + Send + Sync>(&mut self, layer: LayerId) -> Option<&mut E> {
+        let per_layer = self.get_mut::<PerLayer<E>>()?;
+        per_layer
+            .0
+            .iter_mut()
+            .find(|(id, _)| *id == layer)
+            .map(|(_, e)| e)
+    }
+
+    fn replace_for<E: 'static + Send + Sync>(&mut self, layer: LayerId, value: E) -> Option<E> {
+        if let Some(existing) = self.get_mut_for(layer) {
+            return Some(std::mem::replace(existing, value));
+        }
+        match self.get_mut::<PerLayer<E>>() {
+            Some(per_layer) => per_layer.0.push((layer, value)),
+            None => self.insert(PerLayer(vec![(layer, value)])),
+        }
+        None
+    }
+
+    fn remove_for<E: 'static + Send + Sync>(&mut self, layer: LayerId) -> Option<E> {
+        let per_layer = self.get_mut::<PerLayer<E>>()?;
+        let index = per_layer.0.iter().position(|(id, _)| *id == layer)?;
+        Some(per_layer.0.remove(index).1)
+    }
+}
+
 impl<T, SpanId, TraceId> TelemetryLayer<T, SpanId, TraceId>
 where
     SpanId: 'static + Clone + Send + Sync,
@@ -43,12 +294,170 @@ where
         promote_span_id: F,
     ) -> Self {
         TelemetryLayer {
+            id: LayerId::next(),
             service_name,
             telemetry,
             promote_span_id: Box::new(promote_span_id),
+            default_trace: None,
+            implicit_trace: None,
+            filter: LayerFilter::default(),
+            with_span: None,
             _ttype: Default::default(),
         }
     }
+
+    /// Makes every span created outside of any trace a local root of the trace `trace_id`,
+    /// as a child of `remote_parent` if given, as if it was registered with
+    /// `register_dist_tracing_root`. Useful for batch jobs continuing the trace of the
+    /// scheduler which started them, without registering each of their entry points.
+    ///
+    /// `sampled` is the sampling decision of the whole trace. Takes precedence over
+    /// `TelemetryLayer::with_implicit_traces`.
+    pub fn with_default_trace(
+        mut self,
+        trace_id: TraceId,
+        remote_parent: Option<SpanId>,
+        sampled: bool,
+    ) -> Self {
+        self.default_trace = Some(TraceCtx {
+            parent_span: remote_parent,
+            trace_id,
+            sampled,
+            trace_state: None,
+            implicit: false,
+        });
+        self
+    }
+
+    /// Starts a trace for every span created outside of any trace, instead of not reporting
+    /// it, so that all instrumented work is reported even if no root is registered.
+    /// `new_trace` returns the id of the trace and whether it is sampled.
+    ///
+    /// The spans starting these traces are reported as local roots, with
+    /// `Span::implicit_trace` set like the other spans of the trace. Registering the span as
+    /// a root with `register_dist_tracing_root` replaces its implicit trace, but
+    /// children created before keep it.
+    pub fn with_implicit_traces<F>(mut self, new_trace: F) -> Self
+    where
+        F: 'static + Send + Sync + Fn(&'static tracing::Metadata<'static>) -> (TraceId, bool),
+    {
+        self.implicit_trace = Some(Box::new(new_trace));
+        self
+    }
+
+    /// Only reports spans and events up to `max_level`, e.g. `LevelFilter::INFO` to skip
+    /// those at the `DEBUG` and `TRACE` levels.
+    ///
+    /// Unlike a filter of the subscriber, this and the target filters only apply to this
+    /// layer: the spans and events it skips are still seen by the other layers, such as one
+    /// logging to stdout. The events of skipped spans, and the spans created within them,
+    /// are reported under their closest ancestor which is not skipped.
+    pub fn with_max_level(mut self, max_level: impl Into<LevelFilter>) -> Self {
+        self.filter.max_level = max_level.into();
+        self
+    }
+
+    /// Only reports spans and events whose target starts with one of `prefixes`, such as
+    /// `my_app` for the modules of the `my_app` crate.
+    pub fn with_allowed_targets<P: Into<String>>(
+        mut self,
+        prefixes: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.filter
+            .allowed_targets
+            .get_or_insert_with(Vec::new)
+            .extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never reports spans and events whose target starts with one of `prefixes`, such as
+    /// `h2::` and `hyper::` for the internals of HTTP clients. Takes precedence over
+    /// `TelemetryLayer::with_allowed_targets`.
+    pub fn with_denied_targets<P: Into<String>>(
+        mut self,
+        prefixes: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.filter
+            .denied_targets
+            .extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns the `Telemetry` capability this layer publishes to.
+    pub fn telemetry(&self) -> &T {
+        &self.telemetry
+    }
+}
+
+impl<TraceId, SpanId, V, T> TelemetryLayer<T, SpanId, TraceId>
+where
+    TraceId: 'static + Clone + Eq + Send + Sync,
+    SpanId: 'static + Clone + Eq + Send + Sync,
+    V: 'static + tracing::field::Visit + Send + Sync,
+    T: 'static + Telemetry<Visitor = V, TraceId = TraceId, SpanId = SpanId>,
+{
+    /// Reports snapshots of `span` and its ancestors which are part of a sampled trace and
+    /// were not reported for the heartbeat interval of the telemetry.
+    fn heartbeat<'a, R: registry::LookupSpan<'a>>(&self, span: registry::SpanRef<'a, R>) {
+        let Some(interval) = self.telemetry.heartbeat_interval() else {
+            return;
+        };
+        let now = now();
+        for span in span.scope() {
+            let due = span
+                .extensions()
+                .get_for::<LastHeartbeat>(self.id)
+                .is_some_and(|last| now.duration_since(last.0).unwrap_or_default() >= interval);
+            if !due {
+                continue;
+            }
+            let Some(trace_ctx) =
+                TraceCtx::<SpanId, TraceId>::resolve(&span, self.id).filter(|t| t.sampled)
+            else {
+                continue;
+            };
+            let extensions = span.extensions();
+            let timings = extensions
+                .get_for::<Timings>(self.id)
+                .expect("should be present on all spans");
+            let snapshot = trace::Span {
+                id: extensions
+                    .get_for::<PromotedSpanId<SpanId>>(self.id)
+                    .expect("All spans should have a promoted span id")
+                    .0
+                    .clone(),
+                name: span.name().to_string(),
+                meta: span.metadata(),
+                parent_id: trace_ctx.parent_span.clone(),
+                local_root: extensions.get_for::<LocalRoot>(self.id).is_some(),
+                follows_from: extensions
+                    .get_for::<FollowsFrom<SpanId, TraceId>>(self.id)
+                    .map(|t| (t.0.clone(), t.1.clone())),
+                initialized_at: extensions
+                    .get_for::<SpanInitAt>(self.id)
+                    .expect("should be present on all spans")
+                    .0,
+                trace_id: trace_ctx.trace_id.clone(),
+                trace_state: trace_ctx.trace_state.clone(),
+                implicit_trace: trace_ctx.implicit,
+                completed_at: now,
+                // Up to the last time the span was entered or exited
+                busy: timings.busy,
+                idle: timings.idle,
+                // Events are not part of snapshots
+                dropped_events: 0,
+                service_name: self.service_name,
+                values: extensions
+                    .get_for::<V>(self.id)
+                    .expect("should be present on all spans"),
+            };
+            self.telemetry.report_span_progress(snapshot);
+            drop(extensions);
+            if let Some(last) = span.extensions_mut().get_mut_for::<LastHeartbeat>(self.id) {
+                last.0 = now;
+            }
+        }
+    }
 }
 
 impl<S, TraceId, SpanId, V, T> Layer<S> for TelemetryLayer<T, SpanId, TraceId>
@@ -59,66 +468,158 @@ where
     V: 'static + tracing::field::Visit + Send + Sync,
     T: 'static + Telemetry<Visitor = V, TraceId = TraceId, SpanId = SpanId>,
 {
+    fn on_layer(&mut self, _subscriber: &mut S) {
+        self.with_span = Some(WithSpan::of::<S>());
+    }
+
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            Some(self as *const Self as *const ())
+        } else if id == TypeId::of::<WithSpan>() {
+            let with_span = self.with_span.as_ref()?;
+            Some(with_span as *const WithSpan as *const ())
+        } else {
+            None
+        }
+    }
+
+    // The layer does not filter spans or events itself, as that would disable them for the
+    // other layers of the subscriber too. Wrap it in `tracing_subscriber::filter::Filtered`
+    // or use `TelemetryLayer::with_max_level` to restrict what it reports.
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::always()
+    }
+
+    fn enabled(&self, _metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        true
+    }
+
+    fn event_enabled(&self, _event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        true
+    }
+
     fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if !self.filter.reports(attrs.metadata()) {
+            return;
+        }
         let span = ctx.span(id).expect("span data not found during new_span");
 
-        let pinfo = span.parent().and_then(|p| {
-            let extensions = p.extensions();
-            let span_id = extensions
-                .get::<PromotedSpanId<SpanId>>()
-                .expect("All spans should have a promoted span id")
-                .clone()
-                .0;
-            extensions
-                .get::<TraceCtx<SpanId, TraceId>>()
-                .map(|t| (t.trace_id.clone(), span_id))
-        });
+        let pinfo = span
+            .parent()
+            .and_then(|p| TraceCtx::<SpanId, TraceId>::inherit(&p, self.id));
 
         let mut extensions_mut = span.extensions_mut();
-        extensions_mut.insert(SpanInitAt::new());
-        extensions_mut.insert(PromotedSpanId((self.promote_span_id)(id.clone())));
+        let init_at = SpanInitAt::new();
+        if self.telemetry.heartbeat_interval().is_some() {
+            extensions_mut.replace_for(self.id, LastHeartbeat(init_at.0));
+        }
+        extensions_mut.replace_for(self.id, init_at);
+        extensions_mut.replace_for(self.id, Timings::new());
+        extensions_mut.replace_for(self.id, PromotedSpanId((self.promote_span_id)(id.clone())));
         let mut visitor: V = self.telemetry.mk_visitor();
         attrs.record(&mut visitor);
-        extensions_mut.insert::<V>(visitor);
-        extensions_mut.insert::<Vec<trace::Event<V, SpanId, TraceId>>>(Default::default());
-
-        // If parent is part of a trace, then make this span part of the trace too.
-        if let Some((tid, pid)) = pinfo {
-            let trace_ctx = TraceCtx {
-                trace_id: tid,
-                parent_span: Some(pid),
-            };
-            extensions_mut.insert(trace_ctx)
+        self.telemetry.span_started(span.metadata(), &mut visitor);
+        extensions_mut.replace_for::<V>(self.id, visitor);
+        extensions_mut.replace_for::<Vec<trace::Event<V, SpanId, TraceId>>>(self.id, Vec::new());
+
+        // If parent is part of a trace, then make this span part of the trace too. Otherwise
+        // it may be the local root of the default trace or of an implicit one.
+        let trace_ctx = pinfo.or_else(|| {
+            let root = self.default_trace.clone().or_else(|| {
+                let (trace_id, sampled) = (self.implicit_trace.as_ref()?)(span.metadata());
+                Some(TraceCtx {
+                    parent_span: None,
+                    trace_id,
+                    sampled,
+                    trace_state: None,
+                    implicit: true,
+                })
+            })?;
+            extensions_mut.replace_for(self.id, LocalRoot);
+            Some(root)
+        });
+        if let Some(trace_ctx) = trace_ctx {
+            extensions_mut.replace_for(self.id, trace_ctx);
+        }
+        drop(extensions_mut);
+
+        if let Some(parent) = span.parent() {
+            self.heartbeat(parent);
         }
     }
 
     fn on_record(&self, id: &Id, values: &Record, ctx: Context<S>) {
         let span = ctx.span(id).expect("span data not found during on_record");
         let mut extensions_mut = span.extensions_mut();
-        let visitor: &mut V = extensions_mut
-            .get_mut()
-            .expect("fields extension not found during on_record");
-        values.record(visitor);
+        // Missing if the span was created before the layer was added or enabled it
+        if let Some(visitor) = extensions_mut.get_mut_for::<V>(self.id) {
+            values.record(visitor);
+        }
     }
 
-    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
-        let parent_id = if let Some(parent_id) = event.parent() {
-            // explicit parent
-            Some(parent_id.clone())
-        } else if event.is_root() {
-            // don't bother checking thread local if span is explicitly root according to this fn
-            None
-        } else {
-            // implicit parent from threadlocal ctx, or root span if none
-            ctx.current_span().id().cloned()
-        };
+    fn on_enter(&self, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timings) = span.extensions_mut().get_mut_for::<Timings>(self.id) {
+                timings.idle_until_now();
+            }
+            self.heartbeat(span);
+        }
+    }
 
-        let initialized_at = SystemTime::now();
+    fn on_exit(&self, id: &Id, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(timings) = span.extensions_mut().get_mut_for::<Timings>(self.id) {
+                timings.busy_until_now();
+            }
+            self.heartbeat(span);
+        }
+    }
 
-        let mut visitor = self.telemetry.mk_visitor();
-        event.record(&mut visitor);
+    fn on_follows_from(&self, id: &Id, follows: &Id, ctx: Context<S>) {
+        // Only a single link is reported, to the first span of a trace this span follows
+        let follows = ctx.span(follows).and_then(|follows| {
+            let trace_ctx = TraceCtx::<SpanId, TraceId>::resolve(&follows, self.id)?;
+            let span_id = follows
+                .extensions()
+                .get_for::<PromotedSpanId<SpanId>>(self.id)?
+                .0
+                .clone();
+            Some(FollowsFrom(trace_ctx.trace_id, span_id))
+        });
+        if let (Some(follows), Some(span)) = (follows, ctx.span(id)) {
+            let mut extensions_mut = span.extensions_mut();
+            if extensions_mut
+                .get_mut_for::<FollowsFrom<SpanId, TraceId>>(self.id)
+                .is_none()
+            {
+                extensions_mut.replace_for(self.id, follows);
+            }
+        }
+    }
 
-        match parent_id {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !self.filter.reports(event.metadata()) {
+            return;
+        }
+        // The explicit parent, or the closest current span not disabled by the filter of
+        // this layer, if any, or their closest ancestor this layer does not skip
+        let parent = ctx.event_scope(event).and_then(|mut scope| {
+            scope.find(|span| {
+                span.extensions()
+                    .get_for::<PromotedSpanId<SpanId>>(self.id)
+                    .is_some()
+            })
+        });
+
+        let initialized_at = now();
+        // Only recorded once the event is known to be reported
+        let values = || {
+            let mut visitor = self.telemetry.mk_visitor();
+            event.record(&mut visitor);
+            visitor
+        };
+
+        match parent {
             None => {
                 let event = trace::Event {
                     trace_id: None,
@@ -126,106 +627,213 @@ where
                     initialized_at,
                     meta: event.metadata(),
                     service_name: self.service_name,
-                    values: visitor,
+                    values: values(),
                 };
 
                 self.telemetry.report_event(event);
             }
-            Some(parent_id) => {
-                // only report event if its parent span is part of a trace
-                if let Some(parent_trace_ctx) = ctx
-                    .span(&parent_id)
-                    .and_then(|s| s.extensions().get::<TraceCtx<SpanId, TraceId>>().cloned())
-                {
-                    let span = ctx
-                        .span(&parent_id)
-                        .expect("Parent span id should be in the context");
-
-                    let parent_id = Some(
-                        span.extensions()
-                            .get::<PromotedSpanId<SpanId>>()
-                            .expect("All spans should have a promoted span id")
-                            .clone()
-                            .0,
-                    );
-                    let event = trace::Event {
-                        trace_id: Some(parent_trace_ctx.trace_id),
-                        parent_id,
-                        initialized_at,
-                        meta: event.metadata(),
-                        service_name: self.service_name,
-                        values: visitor,
-                    };
+            Some(span) => {
+                let trace_ctx = TraceCtx::<SpanId, TraceId>::resolve(&span, self.id);
+                // only report event if its parent span is part of a sampled trace, or may
+                // still become the root of one
+                if trace_ctx.as_ref().is_some_and(|t| !t.sampled) {
+                    return;
+                }
+
+                let parent_id = Some(
+                    span.extensions()
+                        .get_for::<PromotedSpanId<SpanId>>(self.id)
+                        .expect("All spans should have a promoted span id")
+                        .clone()
+                        .0,
+                );
+                let event = trace::Event {
+                    trace_id: trace_ctx.map(|t| t.trace_id),
+                    parent_id,
+                    initialized_at,
+                    meta: event.metadata(),
+                    service_name: self.service_name,
+                    values: values(),
+                };
+                if event.trace_id.is_some() && self.telemetry.streams_span_events() {
+                    self.telemetry.report_event(event);
+                } else {
+                    // Events before the span is registered as a root are kept with it, and
+                    // dropped along with it if it never is
                     let mut extensions = span.extensions_mut();
-                    extensions
-                        .get_mut::<Vec<trace::Event<V, SpanId, TraceId>>>()
-                        .expect("List of events should have been added to span")
-                        .push(event);
+                    let events = extensions
+                        .get_mut_for::<Vec<trace::Event<V, SpanId, TraceId>>>(self.id)
+                        .expect("List of events should have been added to span");
+                    if self
+                        .telemetry
+                        .max_events_per_span()
+                        .is_some_and(|max| events.len() >= max)
+                    {
+                        match extensions.get_mut_for::<DroppedEvents>(self.id) {
+                            Some(dropped) => dropped.0 += 1,
+                            None => {
+                                extensions.replace_for(self.id, DroppedEvents(1));
+                            }
+                        }
+                    } else {
+                        events.push(event);
+                    }
                 }
+                self.heartbeat(span);
             }
         }
     }
 
     fn on_close(&self, id: Id, ctx: Context<'_, S>) {
         let span = ctx.span(&id).expect("span data not found during on_close");
+        if span
+            .extensions()
+            .get_for::<PromotedSpanId<SpanId>>(self.id)
+            .is_none()
+        {
+            // Created before the layer was added or enabled it
+            return;
+        }
+        // Spans created before their root was registered only get their trace now
+        TraceCtx::<SpanId, TraceId>::resolve(&span, self.id);
 
         let mut extensions_mut = span.extensions_mut();
 
-        // if span's enclosing ctx has a trace id, eval & use to report telemetry
-        if let Some(trace_ctx) = extensions_mut.remove::<TraceCtx<SpanId, TraceId>>() {
+        // if span's enclosing ctx has a sampled trace id, eval & use to report telemetry
+        if let Some(trace_ctx) = extensions_mut
+            .remove_for::<TraceCtx<SpanId, TraceId>>(self.id)
+            .filter(|t| t.sampled)
+        {
             let TraceCtx {
                 parent_span,
                 trace_id,
+                sampled: _,
+                trace_state,
+                implicit: implicit_trace,
             } = trace_ctx;
 
             let visitor: V = extensions_mut
-                .remove()
+                .remove_for(self.id)
                 .expect("should be present on all spans");
             let SpanInitAt(initialized_at) = extensions_mut
-                .remove()
+                .remove_for(self.id)
                 .expect("should be present on all spans");
 
-            let events = extensions_mut
-                .remove::<Vec<trace::Event<V, SpanId, TraceId>>>()
+            let mut events = extensions_mut
+                .remove_for::<Vec<trace::Event<V, SpanId, TraceId>>>(self.id)
                 .expect("List of events should have been added to span");
+            // Events recorded before the span was registered as a root
+            for event in events.iter_mut().filter(|event| event.trace_id.is_none()) {
+                event.trace_id = Some(trace_id.clone());
+            }
+
+            let dropped_events = extensions_mut
+                .remove_for::<DroppedEvents>(self.id)
+                .map_or(0, |dropped| dropped.0);
 
             let id = extensions_mut
-                .remove::<PromotedSpanId<SpanId>>()
+                .remove_for::<PromotedSpanId<SpanId>>(self.id)
                 .expect("All spans should have a promoted span id")
                 .0
                 .clone();
 
             let follows_from = extensions_mut
-                .remove::<FollowsFrom<SpanId, TraceId>>()
+                .remove_for::<FollowsFrom<SpanId, TraceId>>(self.id)
                 .map(|t| (t.0, t.1));
 
             let parent_id = parent_span;
 
-            let completed_at = SystemTime::now();
+            let local_root = extensions_mut.remove_for::<LocalRoot>(self.id).is_some();
+
+            let mut timings: Timings = extensions_mut
+                .remove_for(self.id)
+                .expect("should be present on all spans");
+            timings.idle_until_now();
+
+            let completed_at = now();
 
             let span = trace::Span {
                 id,
                 name: span.name().to_string(),
                 meta: span.metadata(),
                 parent_id,
+                local_root,
                 follows_from,
                 initialized_at,
                 trace_id,
+                trace_state,
+                implicit_trace,
                 completed_at,
+                busy: timings.busy,
+                idle: timings.idle,
+                dropped_events,
                 service_name: self.service_name,
                 values: visitor,
             };
 
-            self.telemetry.report_span(span, events);
+            if self.telemetry.streams_span_events() {
+                for event in events {
+                    self.telemetry.report_event(event);
+                }
+                self.telemetry.report_span(span, Vec::new());
+            } else {
+                self.telemetry.report_span(span, events);
+            }
         };
     }
 }
 
-struct SpanInitAt(SystemTime);
+pub(crate) struct SpanInitAt(pub(crate) SystemTime);
+
+/// When a snapshot of the span was last reported, or when it was created, only set if the
+/// telemetry has a heartbeat interval.
+struct LastHeartbeat(SystemTime);
+
+/// Number of events of the span dropped beyond `Telemetry::max_events_per_span`, only set
+/// once one was dropped.
+struct DroppedEvents(usize);
+
+/// Time a span was entered and not entered so far, reported as `Span::busy` and
+/// `Span::idle`.
+struct Timings {
+    busy: Duration,
+    idle: Duration,
+    /// When the span was last entered or exited, or created
+    last: SystemTime,
+}
+
+impl Timings {
+    fn new() -> Self {
+        Self {
+            busy: Duration::ZERO,
+            idle: Duration::ZERO,
+            last: now(),
+        }
+    }
+
+    /// Accounts for the time since the span was last entered or exited as idle.
+    fn idle_until_now(&mut self) {
+        let elapsed = self.elapsed();
+        self.idle += elapsed;
+    }
+
+    /// Accounts for the time since the span was last entered as busy.
+    fn busy_until_now(&mut self) {
+        let elapsed = self.elapsed();
+        self.busy += elapsed;
+    }
+
+    fn elapsed(&mut self) -> Duration {
+        let now = now();
+        let elapsed = now.duration_since(self.last).unwrap_or_default();
+        self.last = now;
+        elapsed
+    }
+}
 
 impl SpanInitAt {
     fn new() -> Self {
-        let initialized_at = SystemTime::now();
+        let initialized_at = now();
 
         Self(initialized_at)
     }
@@ -235,6 +843,7 @@ impl SpanInitAt {
 mod tests {
     use super::*;
     use crate::telemetry::test::{SpanId, TestTelemetry, TraceId};
+    use crate::telemetry::{FilteredTelemetry, TeeTelemetry};
     use std::sync::Arc;
     use std::sync::Mutex;
     use std::time::Duration;
@@ -326,6 +935,727 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_unsampled_trace_is_not_reported() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root_with_sampling(
+                    explicit_trace_id(),
+                    Some(explicit_parent_span_id()),
+                    false,
+                )
+                .unwrap();
+                tracing::info_span!("child").in_scope(|| tracing::info!("event"));
+            })
+        });
+
+        assert!(spans.lock().unwrap().is_empty());
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unregistered_spans_get_implicit_traces() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x)
+            .with_implicit_traces(|meta| (7, meta.name() != "unsampled"));
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("implicit").in_scope(|| {
+                tracing::info_span!("child").in_scope(|| {});
+            });
+            tracing::info_span!("unsampled").in_scope(|| {
+                tracing::info_span!("child").in_scope(|| {});
+            });
+            tracing::info_span!("registered").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+            });
+        });
+
+        let spans = spans.lock().unwrap();
+        let reported: Vec<_> = spans
+            .iter()
+            .map(|span| (span.name.as_str(), span.trace_id, span.implicit_trace))
+            .collect();
+        assert_eq!(
+            reported,
+            [
+                ("child", 7, true),
+                ("implicit", 7, true),
+                ("registered", explicit_trace_id(), false),
+            ]
+        );
+        assert_eq!(spans[0].parent_id, Some(spans[1].id.clone()));
+        assert_eq!(spans[1].parent_id, None);
+        assert!(!spans[0].local_root && spans[1].local_root);
+    }
+
+    #[test]
+    fn test_unregistered_spans_join_the_default_trace() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x)
+            .with_default_trace(explicit_trace_id(), Some(explicit_parent_span_id()), true)
+            .with_implicit_traces(|_| (7, true));
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("first").in_scope(|| {
+                tracing::info_span!("child").in_scope(|| {});
+            });
+            tracing::info_span!("second").in_scope(|| {});
+        });
+
+        let spans = spans.lock().unwrap();
+        assert!(spans
+            .iter()
+            .all(|span| span.trace_id == explicit_trace_id()));
+        assert!(spans.iter().all(|span| !span.implicit_trace));
+        let roots: Vec<_> = spans
+            .iter()
+            .filter(|span| span.local_root)
+            .map(|span| (span.name.as_str(), span.parent_id.clone()))
+            .collect();
+        assert_eq!(
+            roots,
+            [
+                ("first", Some(explicit_parent_span_id())),
+                ("second", Some(explicit_parent_span_id())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_root_started_at_overrides_start_time() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+        let started_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root_started_at::<SpanId, _>(
+                    explicit_trace_id(),
+                    None,
+                    true,
+                    started_at,
+                )
+                .unwrap();
+            })
+        });
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].initialized_at, started_at);
+    }
+
+    #[test]
+    fn test_busy_and_idle_time_are_reported() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("root");
+            root.in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                std::thread::sleep(Duration::from_millis(20));
+            });
+            std::thread::sleep(Duration::from_millis(40));
+            root.in_scope(|| std::thread::sleep(Duration::from_millis(20)));
+        });
+
+        let spans = spans.lock().unwrap();
+        let (busy, idle) = (spans[0].busy, spans[0].idle);
+        assert!(busy >= Duration::from_millis(40), "busy {:?}", busy);
+        assert!(idle >= Duration::from_millis(40), "idle {:?}", idle);
+        let total = spans[0]
+            .completed_at
+            .duration_since(spans[0].initialized_at);
+        assert!(busy + idle <= total.unwrap());
+    }
+
+    #[test]
+    fn test_heartbeats_report_open_spans() {
+        let cap = TestTelemetry::new(Default::default(), Default::default())
+            .with_heartbeat_interval(Duration::from_millis(20));
+        let snapshots = cap.snapshots();
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("job").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info!("not due yet");
+                std::thread::sleep(Duration::from_millis(30));
+                tracing::info_span!("step").in_scope(|| tracing::info!("due"));
+                tracing::info!("reported already");
+            });
+        });
+
+        assert_eq!(*snapshots.lock().unwrap(), ["job"]);
+    }
+
+    #[test]
+    fn test_events_beyond_the_maximum_are_dropped() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let cap = TestTelemetry::new(spans.clone(), Default::default()).with_max_events_per_span(3);
+        let span_events = cap.span_events();
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("loop").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                for i in 0..10 {
+                    tracing::info!(i, "iteration");
+                }
+                tracing::info_span!("quiet").in_scope(|| tracing::info!("once"));
+            });
+        });
+
+        let spans = spans.lock().unwrap();
+        let span_events = span_events.lock().unwrap();
+        let reported: Vec<_> = spans
+            .iter()
+            .zip(span_events.iter())
+            .map(|(span, events)| (events.len(), span.dropped_events))
+            .collect();
+        assert_eq!(reported, [(1, 0), (3, 7)]);
+    }
+
+    #[test]
+    fn test_streamed_events_are_reported_as_they_occur() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap =
+            TestTelemetry::new(Default::default(), events.clone()).with_streamed_span_events();
+        let span_events = cap.span_events();
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+        let trace_ids = || {
+            let events = events.lock().unwrap();
+            events
+                .iter()
+                .map(|event| event.trace_id)
+                .collect::<Vec<_>>()
+        };
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("job").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info!("started");
+                assert_eq!(trace_ids(), [Some(explicit_trace_id())]);
+                tracing::info!("done");
+            });
+            tracing::info!("outside of a span");
+        });
+
+        assert_eq!(
+            trace_ids(),
+            [Some(explicit_trace_id()), Some(explicit_trace_id()), None]
+        );
+        let span_events = span_events.lock().unwrap();
+        assert_eq!(span_events.iter().map(Vec::len).collect::<Vec<_>>(), [0]);
+
+        // Tees stream events only if both sides do
+        let streamed =
+            |events| TestTelemetry::new(Default::default(), events).with_streamed_span_events();
+        let buffered = TestTelemetry::new(Default::default(), Default::default());
+        assert!(!TeeTelemetry(streamed(Default::default()), buffered).streams_span_events());
+
+        let events: [Arc<Mutex<Vec<_>>>; 2] = Default::default();
+        let tee = TeeTelemetry(streamed(events[0].clone()), streamed(events[1].clone()));
+        let layer = TelemetryLayer::new("test_svc_name", tee, |x| x);
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("job").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info!("started");
+                for events in &events {
+                    assert_eq!(events.lock().unwrap().len(), 1);
+                }
+            });
+        });
+    }
+
+    #[test]
+    fn test_events_before_registration_are_reported_with_the_root() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let cap = TestTelemetry::new(spans.clone(), Default::default());
+        let span_events = cap.span_events();
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                tracing::info!("parsed headers");
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info!("handled");
+            });
+            tracing::info_span!("never registered").in_scope(|| tracing::info!("dropped"));
+        });
+
+        // Names of the reported spans, with the trace ids of their events
+        let spans = spans.lock().unwrap();
+        let span_events = span_events.lock().unwrap();
+        let reported: Vec<_> = spans
+            .iter()
+            .zip(span_events.iter())
+            .map(|(span, events)| {
+                let trace_ids: Vec<_> = events.iter().map(|event| event.trace_id).collect();
+                (span.name.as_str(), trace_ids)
+            })
+            .collect();
+        assert_eq!(
+            reported,
+            [(
+                "request",
+                vec![Some(explicit_trace_id()), Some(explicit_trace_id())]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_children_created_before_registration_join_the_trace() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("root");
+            let child = root.in_scope(|| tracing::info_span!("child"));
+            trace::register_dist_tracing_root_for::<SpanId, _>(
+                &root,
+                explicit_trace_id(),
+                None,
+                true,
+                None,
+            )
+            .unwrap();
+            child.in_scope(|| {
+                let (trace_id, _) = trace::current_dist_trace_ctx::<SpanId, TraceId>().unwrap();
+                assert_eq!(trace_id, explicit_trace_id());
+                tracing::info_span!("grandchild").in_scope(|| {});
+            });
+        });
+
+        let spans = spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["grandchild", "child", "root"]);
+        assert!(spans
+            .iter()
+            .all(|span| span.trace_id == explicit_trace_id()));
+        assert_eq!(spans[0].parent_id, Some(spans[1].id.clone()));
+        assert_eq!(spans[1].parent_id, Some(spans[2].id.clone()));
+        assert!(!spans[1].local_root && spans[2].local_root);
+    }
+
+    #[test]
+    fn test_root_registered_for_span_without_entering_it() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("root");
+            tracing::info_span!("other").in_scope(|| {
+                trace::register_dist_tracing_root_for::<SpanId, _>(
+                    &root,
+                    explicit_trace_id(),
+                    None,
+                    true,
+                    None,
+                )
+                .unwrap();
+            });
+            root.in_scope(|| tracing::info_span!("child").in_scope(|| {}));
+        });
+
+        let spans = spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["child", "root"]);
+        assert!(spans[1].local_root);
+    }
+
+    #[test]
+    fn test_multiple_layers_report_spans_independently() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let implicit_spans = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let implicit_cap: TestTelemetry = TestTelemetry::new(implicit_spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+        let implicit_layer = TelemetryLayer::new("test_svc_name", implicit_cap, |x| x)
+            .with_implicit_traces(|_| (7, true));
+
+        let subscriber = layer
+            .and_then(implicit_layer)
+            .with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("unregistered").in_scope(|| {});
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info_span!("child").in_scope(|| tracing::info!("event"));
+            });
+        });
+
+        let spans = spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["child", "root"]);
+
+        let implicit_spans = implicit_spans.lock().unwrap();
+        let names: Vec<_> = implicit_spans
+            .iter()
+            .map(|span| span.name.as_str())
+            .collect();
+        assert_eq!(names, ["unregistered", "child", "root"]);
+        assert_eq!(implicit_spans[0].trace_id, 7);
+        assert!(implicit_spans[1..]
+            .iter()
+            .all(|span| span.trace_id == explicit_trace_id()));
+    }
+
+    /// Stands in for a layer logging everything, such as `tracing_subscriber::fmt`.
+    struct LogEverything(Arc<Mutex<Vec<&'static str>>>);
+
+    impl<S: Subscriber> Layer<S> for LogEverything {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            self.0.lock().unwrap().push(event.metadata().name());
+        }
+    }
+
+    #[test]
+    fn test_per_layer_filters_apply_to_the_layer_only() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let logged = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x)
+            .with_filter(tracing_subscriber::filter::LevelFilter::INFO);
+
+        let subscriber = layer
+            .and_then(LogEverything(logged.clone()))
+            .with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                let root_ctx = trace::current_dist_trace_ctx::<SpanId, TraceId>().unwrap();
+                tracing::debug_span!("filtered").in_scope(|| {
+                    assert_eq!(
+                        trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None),
+                        Err(trace::TraceCtxError::TelemetryLayerNotRegistered)
+                    );
+                    // The context of the closest reported span
+                    assert_eq!(
+                        trace::current_dist_trace_ctx::<SpanId, TraceId>().unwrap(),
+                        root_ctx
+                    );
+                    tracing::debug!("debug");
+                    tracing::info!("info");
+                    tracing::info_span!("child").in_scope(|| {});
+                });
+            });
+        });
+
+        assert_eq!(logged.lock().unwrap().len(), 2);
+        let spans = spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["child", "root"]);
+        assert_eq!(spans[0].parent_id, Some(spans[1].id.clone()));
+    }
+
+    #[test]
+    fn test_level_and_target_filters_skip_spans() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let logged = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events.clone());
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x)
+            .with_max_level(tracing::Level::INFO)
+            .with_allowed_targets(["tracing_distributed", "h2::"])
+            .with_denied_targets(["h2::"]);
+
+        let subscriber = layer
+            .and_then(LogEverything(logged.clone()))
+            .with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::debug_span!("debug").in_scope(|| {
+                    tracing::info_span!(target: "h2::proto", "h2").in_scope(|| {
+                        tracing::info!(target: "other", "other");
+                        tracing::info_span!("child").in_scope(|| {});
+                    });
+                });
+            });
+        });
+
+        assert_eq!(logged.lock().unwrap().len(), 1);
+        let spans = spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["child", "root"]);
+        assert_eq!(spans[0].parent_id, Some(spans[1].id.clone()));
+        assert_eq!(spans[0].trace_id, explicit_trace_id());
+    }
+
+    /// Collects the metadata of the callsites registered with the subscriber.
+    struct Callsites(Arc<Mutex<Vec<&'static tracing::Metadata<'static>>>>);
+
+    impl<S: Subscriber> Layer<S> for Callsites {
+        fn register_callsite(&self, metadata: &'static tracing::Metadata<'static>) -> Interest {
+            self.0.lock().unwrap().push(metadata);
+            Interest::always()
+        }
+    }
+
+    #[test]
+    fn test_filter_decisions() {
+        let callsites = Arc::new(Mutex::new(Vec::new()));
+        let subscriber =
+            Callsites(callsites.clone()).with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("info", decided = true).in_scope(|| {});
+            tracing::debug_span!("debug", decided = true).in_scope(|| {});
+            tracing::info_span!(target: "h2::proto", "denied", decided = true).in_scope(|| {});
+            tracing::info!(target: "h2", decided = true, "allowed");
+            tracing::warn!(target: "other", decided = true, "not allowed");
+        });
+        // Leaves out the callsites of other tests, registered with every new subscriber
+        let mut callsites = callsites.lock().unwrap();
+        callsites.retain(|metadata| metadata.fields().field("decided").is_some());
+        assert_eq!(callsites.len(), 5);
+
+        let cap: TestTelemetry = TestTelemetry::new(Default::default(), Default::default());
+        let layer: TelemetryLayer<_, _, TraceId> = TelemetryLayer::new("test_svc_name", cap, |x| x)
+            .with_max_level(tracing::Level::INFO)
+            .with_allowed_targets(["tracing_distributed", "h2"])
+            .with_denied_targets(["h2::"]);
+        let reports = callsites
+            .iter()
+            .map(|metadata| layer.filter.reports(metadata))
+            .collect::<Vec<_>>();
+        assert_eq!(reports, [true, false, false, true, false]);
+    }
+
+    /// A subscriber storing span data without being a `Registry`, which it does not expose.
+    /// Spans are never removed from it as it does not take the close guard of the registry.
+    struct OpaqueRegistry(registry::Registry);
+
+    impl Subscriber for OpaqueRegistry {
+        fn enabled(&self, metadata: &tracing::Metadata<'_>) -> bool {
+            self.0.enabled(metadata)
+        }
+
+        fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+            self.0.new_span(attrs)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            self.0.record(span, values)
+        }
+
+        fn record_follows_from(&self, span: &Id, follows: &Id) {
+            self.0.record_follows_from(span, follows)
+        }
+
+        fn event(&self, event: &Event<'_>) {
+            self.0.event(event)
+        }
+
+        fn enter(&self, span: &Id) {
+            self.0.enter(span)
+        }
+
+        fn exit(&self, span: &Id) {
+            self.0.exit(span)
+        }
+
+        fn clone_span(&self, id: &Id) -> Id {
+            self.0.clone_span(id)
+        }
+
+        fn try_close(&self, id: Id) -> bool {
+            self.0.try_close(id)
+        }
+
+        fn current_span(&self) -> tracing_core::span::Current {
+            self.0.current_span()
+        }
+    }
+
+    impl<'a> registry::LookupSpan<'a> for OpaqueRegistry {
+        type Data = <registry::Registry as registry::LookupSpan<'a>>::Data;
+
+        fn span_data(&'a self, id: &Id) -> Option<Self::Data> {
+            self.0.span_data(id)
+        }
+    }
+
+    #[test]
+    fn test_subscribers_other_than_registry_are_supported() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(OpaqueRegistry(registry::Registry::default()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info_span!("child").in_scope(|| {
+                    let (trace_id, _) = trace::current_dist_trace_ctx::<SpanId, TraceId>().unwrap();
+                    assert_eq!(trace_id, explicit_trace_id());
+                    assert!(trace::current_dist_trace_sampled::<SpanId, TraceId>().unwrap());
+                });
+            });
+        });
+
+        // The root is never closed, as the removal of the child would release it
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "child");
+        assert_eq!(spans[0].trace_id, explicit_trace_id());
+        assert!(spans[0].parent_id.is_some());
+    }
+
+    #[test]
+    fn test_trace_state_is_inherited_by_child_spans() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("root");
+            trace::register_dist_tracing_root_for::<SpanId, _>(
+                &root,
+                explicit_trace_id(),
+                None,
+                true,
+                None,
+            )
+            .unwrap();
+            trace::set_trace_state_for::<SpanId, TraceId>(&root, "vendor=1").unwrap();
+            root.in_scope(|| {
+                tracing::info_span!("child").in_scope(|| {
+                    let ctx = trace::current_span_context::<SpanId, TraceId>().unwrap();
+                    assert_eq!(ctx.trace_state.as_deref(), Some("vendor=1"));
+                    assert!(ctx.sampled);
+                })
+            });
+        });
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert!(spans
+            .iter()
+            .all(|span| span.trace_state.as_deref() == Some("vendor=1")));
+    }
+
+    #[test]
+    fn test_follows_from_is_reported() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap: TestTelemetry = TestTelemetry::new(spans.clone(), events);
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            let cause = tracing::info_span!("cause");
+            trace::register_dist_tracing_root_for::<SpanId, _>(
+                &cause,
+                explicit_trace_id(),
+                None,
+                true,
+                None,
+            )
+            .unwrap();
+            let job = tracing::info_span!("job");
+            trace::register_dist_tracing_root_for::<SpanId, TraceId>(&job, 7, None, true, None)
+                .unwrap();
+            job.follows_from(&cause);
+            let cause_id = cause.id().unwrap();
+            drop((cause, job));
+
+            let spans = spans.lock().unwrap();
+            assert_eq!(spans[0].follows_from, None);
+            assert_eq!(spans[1].follows_from, Some((explicit_trace_id(), cause_id)));
+        });
+    }
+
+    #[test]
+    fn test_tee_reports_to_both() {
+        let spans = [
+            Arc::new(Mutex::new(Vec::new())),
+            Arc::new(Mutex::new(Vec::new())),
+        ];
+        let events = [
+            Arc::new(Mutex::new(Vec::new())),
+            Arc::new(Mutex::new(Vec::new())),
+        ];
+        let cap = TeeTelemetry(
+            TestTelemetry::new(spans[0].clone(), events[0].clone()),
+            TestTelemetry::new(spans[1].clone(), events[1].clone()),
+        );
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("event");
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+            })
+        });
+
+        for (spans, events) in spans.iter().zip(events.iter()) {
+            let spans = spans.lock().unwrap();
+            assert_eq!(spans.len(), 1);
+            assert_eq!(spans[0].name, "root");
+            assert_eq!(spans[0].trace_id, explicit_trace_id());
+            assert_eq!(events.lock().unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_filtered_drops_non_matching() {
+        let spans = Arc::new(Mutex::new(Vec::new()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let cap = FilteredTelemetry::new(TestTelemetry::new(spans.clone(), events.clone()))
+            .max_level(tracing::Level::INFO)
+            .filter_spans(|span| span.name != "ignored");
+        let layer = TelemetryLayer::new("test_svc_name", cap, |x| x);
+
+        let subscriber = layer.with_subscriber(registry::Registry::default());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("verbose");
+            tracing::warn!("kept");
+            tracing::info_span!("root").in_scope(|| {
+                trace::register_dist_tracing_root::<SpanId, _>(explicit_trace_id(), None).unwrap();
+                tracing::info_span!("ignored").in_scope(|| {});
+                tracing::debug_span!("verbose").in_scope(|| {});
+            })
+        });
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "root");
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+
     fn with_test_scenario_runner<F>(f: F)
     where
         F: Fn(),
@@ -349,10 +1679,12 @@ mod tests {
 
         assert_eq!(root_span.parent_id, Some(explicit_parent_span_id()));
         assert_eq!(root_span.trace_id, expected_trace_id);
+        assert!(root_span.local_root);
 
         for (span, event) in child_spans.iter().zip(events.iter()) {
             // confirm parent and trace ids are as expected
             assert_eq!(span.parent_id, Some(root_span.id.clone()));
+            assert!(!span.local_root);
             assert_eq!(event.parent_id, Some(span.id.clone()));
             assert_eq!(span.trace_id, explicit_trace_id());
             assert_eq!(event.trace_id, Some(explicit_trace_id()));
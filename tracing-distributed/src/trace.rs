@@ -1,6 +1,50 @@
-use crate::telemetry_layer::{FollowsFrom, PromotedSpanId, TraceCtx};
-use std::time::SystemTime;
-use tracing_subscriber::registry::LookupSpan;
+use crate::telemetry_layer::{
+    ErasedSpan, FollowsFrom, LayerExtensions, LayerExtensionsMut, LayerId, LocalRoot,
+    PromotedSpanId, SpanInitAt, TraceCtx, WithSpan,
+};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Calls `f` with `span` as seen by the `TelemetryLayer` of its subscriber, whatever the type
+/// of the subscriber.
+fn with_layer_span<R>(
+    span: &tracing::Span,
+    f: impl FnOnce(&dyn ErasedSpan) -> Result<R, TraceCtxError>,
+) -> Result<R, TraceCtxError> {
+    span.with_subscriber(|(id, dispatch)| {
+        let with_span = dispatch
+            .downcast_ref::<WithSpan>()
+            .ok_or(TraceCtxError::TelemetryLayerNotRegistered)?;
+        let mut f = Some(f);
+        let mut result = None;
+        with_span.with_span(dispatch, id, &mut |span| {
+            result = f.take().map(|f| f(span));
+        });
+        result.expect("Span should be present in the subscriber")
+    })
+    .ok_or(TraceCtxError::NoEnabledSpan)?
+}
+
+/// The `TelemetryLayer`s with span ids of type `SpanId` which saw `span`, in the order they
+/// did. The trace context of each is kept apart, as the layers may not sample the same
+/// traces.
+fn layers_of<SpanId: 'static + Send + Sync>(span: &dyn ErasedSpan) -> Vec<LayerId> {
+    span.extensions().layers::<PromotedSpanId<SpanId>>()
+}
+
+/// Calls `f` with `span`, or its closest ancestor if per-layer filtering disabled `span` for
+/// all `TelemetryLayer`s with span ids of type `SpanId`, and the layers which saw it.
+fn with_seen_span<SpanId: 'static + Send + Sync, R>(
+    span: &dyn ErasedSpan,
+    f: impl FnOnce(&dyn ErasedSpan, Vec<LayerId>) -> Option<R>,
+) -> Option<R> {
+    let layers = layers_of::<SpanId>(span);
+    if layers.is_empty() {
+        with_seen_span::<SpanId, R>(&*span.parent()?, f)
+    } else {
+        f(span, layers)
+    }
+}
 
 /// Register the current span as the local root of a distributed trace.
 pub fn register_dist_tracing_root<SpanId, TraceId>(
@@ -11,32 +55,110 @@ where
     SpanId: 'static + Clone + Send + Sync,
     TraceId: 'static + Clone + Send + Sync,
 {
-    let span = tracing::Span::current();
+    register_dist_tracing_root_with_sampling(trace_id, remote_parent_span, true)
+}
 
-    span.with_subscriber(|(current_span_id, dispatch)| {
-        let registry = dispatch
-            .downcast_ref::<tracing_subscriber::Registry>()
-            .ok_or(TraceCtxError::RegistrySubscriberNotRegistered)?;
+/// Register the current span as the local root of a distributed trace, along with the
+/// sampling decision for the trace.
+///
+/// If `sampled` is false, neither this span nor any of its descendants within the trace are
+/// reported. This is used to honor the sampling decision of a remote parent.
+pub fn register_dist_tracing_root_with_sampling<SpanId, TraceId>(
+    trace_id: TraceId,
+    remote_parent_span: Option<SpanId>,
+    sampled: bool,
+) -> Result<(), TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    register_dist_tracing_root_for(
+        &tracing::Span::current(),
+        trace_id,
+        remote_parent_span,
+        sampled,
+        None,
+    )
+}
 
-        let span = registry
-            .span(current_span_id)
-            .expect("Span should be present in registry");
+/// Register the current span as the local root of a distributed trace, as
+/// `register_dist_tracing_root_with_sampling` does, and report it as started at `started_at`
+/// instead of when it was created.
+///
+/// Useful when the work of the trace started before this process saw it, such as the time a
+/// request spent queued in a gateway.
+pub fn register_dist_tracing_root_started_at<SpanId, TraceId>(
+    trace_id: TraceId,
+    remote_parent_span: Option<SpanId>,
+    sampled: bool,
+    started_at: SystemTime,
+) -> Result<(), TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    register_dist_tracing_root_for(
+        &tracing::Span::current(),
+        trace_id,
+        remote_parent_span,
+        sampled,
+        Some(started_at),
+    )
+}
+
+/// Register `span` as the local root of a distributed trace, without having to enter it
+/// first, e.g. when it was created on another thread or is held in a struct.
+///
+/// Takes the sampling decision of the trace and, if given, the time to report `span` as
+/// started at, as `register_dist_tracing_root_started_at` does.
+///
+/// The span is registered for every `TelemetryLayer` of the subscriber using span ids of
+/// type `SpanId`. Functions retrieving the trace context use the first of these layers for
+/// which the span is part of a trace.
+pub fn register_dist_tracing_root_for<SpanId, TraceId>(
+    span: &tracing::Span,
+    trace_id: TraceId,
+    remote_parent_span: Option<SpanId>,
+    sampled: bool,
+    started_at: Option<SystemTime>,
+) -> Result<(), TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    with_layer_span(span, |span| {
+        let layers = layers_of::<SpanId>(span);
+        if layers.is_empty() {
+            return Err(TraceCtxError::TelemetryLayerNotRegistered);
+        }
 
         let mut extensions_mut = span.extensions_mut();
+        for layer in layers {
+            extensions_mut.replace_for(layer, LocalRoot);
+            if let Some(started_at) = started_at {
+                extensions_mut.replace_for(layer, SpanInitAt(started_at));
+            }
 
-        if let Some(TraceCtx {
-            parent_span: Some(parent_span),
-            trace_id,
-        }) = extensions_mut.replace(TraceCtx {
-            parent_span: remote_parent_span,
-            trace_id,
-        }) {
-            extensions_mut.replace(FollowsFrom(trace_id, parent_span));
+            if let Some(TraceCtx {
+                parent_span: Some(parent_span),
+                trace_id,
+                ..
+            }) = extensions_mut.replace_for(
+                layer,
+                TraceCtx {
+                    parent_span: remote_parent_span.clone(),
+                    trace_id: trace_id.clone(),
+                    sampled,
+                    trace_state: None,
+                    implicit: false,
+                },
+            ) {
+                extensions_mut.replace_for(layer, FollowsFrom(trace_id, parent_span));
+            }
         }
 
         Ok(())
     })
-    .ok_or(TraceCtxError::NoEnabledSpan)?
 }
 
 /// Retrieve the distributed trace context associated with the current span. Returns the
@@ -47,33 +169,270 @@ where
     SpanId: 'static + Clone + Send + Sync,
     TraceId: 'static + Clone + Send + Sync,
 {
-    let span = tracing::Span::current();
-    span.with_subscriber(|(current_span_id, dispatch)| {
-        let registry = dispatch
-            .downcast_ref::<tracing_subscriber::Registry>()
-            .ok_or(TraceCtxError::RegistrySubscriberNotRegistered)?;
-
-        let trace_id = registry
-            .span(current_span_id)
-            .and_then(|s| {
-                s.extensions()
-                    .get::<TraceCtx<SpanId, TraceId>>()
-                    .map(|x| x.trace_id.clone())
-            })
-            .ok_or(TraceCtxError::NoParentNodeHasTraceCtx)?;
-
-        let span_id = registry
-            .span(current_span_id)
-            .and_then(|s| {
-                s.extensions()
-                    .get::<PromotedSpanId<SpanId>>()
-                    .map(|x| x.0.clone())
+    dist_trace_ctx_for(&tracing::Span::current())
+}
+
+/// Retrieve the distributed trace context associated with `span`, as `current_dist_trace_ctx`
+/// does for the current span.
+///
+/// Useful for propagating the context of a span held elsewhere, such as by middleware,
+/// without entering it.
+pub fn dist_trace_ctx_for<SpanId, TraceId>(
+    span: &tracing::Span,
+) -> Result<(TraceId, SpanId), TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    span_context_for(span).map(|ctx| (ctx.trace_id, ctx.span_id))
+}
+
+/// Retrieve the distributed trace context associated with the current span, along with the
+/// sampling decision and `tracestate` of its trace, to propagate to other processes.
+pub fn current_span_context<SpanId, TraceId>() -> Result<SpanContext<SpanId, TraceId>, TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    span_context_for(&tracing::Span::current())
+}
+
+/// Retrieve the distributed trace context associated with `span`, as `current_span_context`
+/// does for the current span.
+///
+/// If per-layer filtering disabled `span` for the `TelemetryLayer`, the context of its closest
+/// ancestor which is reported is returned, as that is the parent remote spans are reported
+/// under.
+pub fn span_context_for<SpanId, TraceId>(
+    span: &tracing::Span,
+) -> Result<SpanContext<SpanId, TraceId>, TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    with_layer_span(span, |span| {
+        with_seen_span::<SpanId, _>(span, |span, layers| {
+            layers.into_iter().find_map(|layer| {
+                let trace_ctx = TraceCtx::<SpanId, TraceId>::resolve(span, layer)?;
+                let extensions = span.extensions();
+                let span_id = extensions.get_for::<PromotedSpanId<SpanId>>(layer)?;
+
+                Some(SpanContext {
+                    trace_id: trace_ctx.trace_id,
+                    span_id: span_id.0.clone(),
+                    sampled: trace_ctx.sampled,
+                    trace_state: trace_ctx.trace_state,
+                })
             })
-            .ok_or(TraceCtxError::NoParentNodeHasTraceCtx)?;
+        })
+        .ok_or(TraceCtxError::NoParentNodeHasTraceCtx)
+    })
+}
 
-        Ok((trace_id, span_id))
+/// Set the W3C `tracestate` of the trace `span` is part of, as seen by `span` and the spans
+/// created within it from now on, such as the `tracestate` received from a remote parent.
+pub fn set_trace_state_for<SpanId, TraceId>(
+    span: &tracing::Span,
+    trace_state: impl Into<Arc<str>>,
+) -> Result<(), TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    let trace_state = trace_state.into();
+    with_layer_span(span, |span| {
+        let mut found = false;
+        for layer in layers_of::<SpanId>(span) {
+            TraceCtx::<SpanId, TraceId>::resolve(span, layer);
+            let mut extensions_mut = span.extensions_mut();
+            if let Some(trace_ctx) = extensions_mut.get_mut_for::<TraceCtx<SpanId, TraceId>>(layer)
+            {
+                trace_ctx.trace_state = Some(trace_state.clone());
+                found = true;
+            }
+        }
+
+        if found {
+            Ok(())
+        } else {
+            Err(TraceCtxError::NoParentNodeHasTraceCtx)
+        }
     })
-    .ok_or(TraceCtxError::NoEnabledSpan)?
+}
+
+/// Retrieve whether the distributed trace the current span is associated with is sampled,
+/// i.e. whether its spans are reported.
+pub fn current_dist_trace_sampled<SpanId, TraceId>() -> Result<bool, TraceCtxError>
+where
+    SpanId: 'static + Clone + Send + Sync,
+    TraceId: 'static + Clone + Send + Sync,
+{
+    with_layer_span(&tracing::Span::current(), |span| {
+        with_seen_span::<SpanId, _>(span, |span, layers| {
+            layers
+                .into_iter()
+                .find_map(|layer| TraceCtx::<SpanId, TraceId>::resolve(span, layer))
+        })
+        .map(|x| x.sampled)
+        .ok_or(TraceCtxError::NoParentNodeHasTraceCtx)
+    })
+}
+
+/// Call `f` with the metadata of the current span and the values recorded on it by the
+/// `Telemetry` visitor of type `V`.
+///
+/// Useful for making decisions based on the fields of a span before it is closed, such as
+/// whether to sample a trace when registering its root.
+pub fn with_current_span_values<V, R>(
+    f: impl FnOnce(&'static tracing::Metadata<'static>, &V) -> R,
+) -> Result<R, TraceCtxError>
+where
+    V: 'static + Send + Sync,
+{
+    with_span_values(&tracing::Span::current(), f)
+}
+
+/// Call `f` with the metadata of `span` and the values recorded on it by the `Telemetry`
+/// visitor of type `V`, as `with_current_span_values` does.
+///
+/// If several `TelemetryLayer`s use visitors of type `V`, the values of the first one are
+/// used.
+pub fn with_span_values<V, R>(
+    span: &tracing::Span,
+    f: impl FnOnce(&'static tracing::Metadata<'static>, &V) -> R,
+) -> Result<R, TraceCtxError>
+where
+    V: 'static + Send + Sync,
+{
+    with_layer_span(span, |span| {
+        let extensions = span.extensions();
+        let values = extensions
+            .layers::<V>()
+            .first()
+            .and_then(|layer| extensions.get_for::<V>(*layer))
+            .ok_or(TraceCtxError::TelemetryLayerNotRegistered)?;
+
+        Ok(f(span.metadata(), values))
+    })
+}
+
+/// Call `f` with the metadata of the current span and a mutable reference to the values
+/// recorded on it by the `Telemetry` visitor of type `V`.
+///
+/// Useful for adding values to a span which are not fields of it, such as attributes given
+/// when registering the root of a trace.
+pub fn with_current_span_values_mut<V, R>(
+    f: impl FnOnce(&'static tracing::Metadata<'static>, &mut V) -> R,
+) -> Result<R, TraceCtxError>
+where
+    V: 'static + Send + Sync,
+{
+    with_span_values_mut(&tracing::Span::current(), f)
+}
+
+/// Call `f` with the metadata of `span` and a mutable reference to the values recorded on it
+/// by the `Telemetry` visitor of type `V`, as `with_current_span_values_mut` does.
+///
+/// If several `TelemetryLayer`s use visitors of type `V`, only the values of the first one
+/// are passed to `f`.
+pub fn with_span_values_mut<V, R>(
+    span: &tracing::Span,
+    f: impl FnOnce(&'static tracing::Metadata<'static>, &mut V) -> R,
+) -> Result<R, TraceCtxError>
+where
+    V: 'static + Send + Sync,
+{
+    with_layer_span(span, |span| {
+        let layer = *span
+            .extensions()
+            .layers::<V>()
+            .first()
+            .ok_or(TraceCtxError::TelemetryLayerNotRegistered)?;
+        let mut extensions_mut = span.extensions_mut();
+        let values = extensions_mut
+            .get_mut_for::<V>(layer)
+            .ok_or(TraceCtxError::TelemetryLayerNotRegistered)?;
+
+        Ok(f(span.metadata(), values))
+    })
+}
+
+/// The distributed trace context of a span, as propagated to other processes and received
+/// from them.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+#[non_exhaustive]
+pub struct SpanContext<SpanId, TraceId> {
+    /// `TraceId` identifying the trace the span belongs to
+    pub trace_id: TraceId,
+    /// id of the span
+    pub span_id: SpanId,
+    /// Whether the spans of the trace are reported
+    pub sampled: bool,
+    /// W3C `tracestate` of the trace, vendor-specific values propagated along with it
+    pub trace_state: Option<Arc<str>>,
+}
+
+impl<SpanId, TraceId> SpanContext<SpanId, TraceId> {
+    /// Construct the context of a span without a `tracestate`.
+    pub fn new(trace_id: TraceId, span_id: SpanId, sampled: bool) -> Self {
+        SpanContext {
+            trace_id,
+            span_id,
+            sampled,
+            trace_state: None,
+        }
+    }
+
+    /// Set the `tracestate` of the trace.
+    pub fn with_trace_state(mut self, trace_state: impl Into<Arc<str>>) -> Self {
+        self.trace_state = Some(trace_state.into());
+        self
+    }
+}
+
+/// The context is converted as remote, as it is propagated from outside of `opentelemetry`.
+#[cfg(feature = "opentelemetry")]
+impl<SpanId, TraceId> From<SpanContext<SpanId, TraceId>> for opentelemetry::trace::SpanContext
+where
+    SpanId: Into<opentelemetry::trace::SpanId>,
+    TraceId: Into<opentelemetry::trace::TraceId>,
+{
+    fn from(ctx: SpanContext<SpanId, TraceId>) -> Self {
+        let flags = opentelemetry::trace::TraceFlags::default().with_sampled(ctx.sampled);
+        // An invalid `tracestate` is dropped, as it would be by a propagator
+        let trace_state = ctx
+            .trace_state
+            .and_then(|trace_state| trace_state.parse().ok())
+            .unwrap_or_default();
+        opentelemetry::trace::SpanContext::new(
+            ctx.trace_id.into(),
+            ctx.span_id.into(),
+            flags,
+            true,
+            trace_state,
+        )
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+impl<SpanId, TraceId> From<opentelemetry::trace::SpanContext> for SpanContext<SpanId, TraceId>
+where
+    SpanId: From<opentelemetry::trace::SpanId>,
+    TraceId: From<opentelemetry::trace::TraceId>,
+{
+    fn from(ctx: opentelemetry::trace::SpanContext) -> Self {
+        let trace_state = ctx.trace_state().header();
+        let converted = SpanContext::new(
+            ctx.trace_id().into(),
+            ctx.span_id().into(),
+            ctx.is_sampled(),
+        );
+        if trace_state.is_empty() {
+            converted
+        } else {
+            converted.with_trace_state(trace_state)
+        }
+    }
 }
 
 /// Errors that can occur while registering the current span as a distributed trace root or
@@ -82,8 +441,12 @@ where
 #[non_exhaustive]
 pub enum TraceCtxError {
     /// Expected a `TelemetryLayer` to be registered as a subscriber associated with the current Span.
+    ///
+    /// Also returned if the per-layer filter of the `TelemetryLayer` disabled the span.
     TelemetryLayerNotRegistered,
     /// Expected a `tracing_subscriber::Registry` to be registered as a subscriber associated with the current Span.
+    ///
+    /// No longer returned since any subscriber storing span data can be used, kept for compatibility.
     RegistrySubscriberNotRegistered,
     /// Expected the span returned by `tracing::Span::current()` to be enabled, with an associated subscriber.
     NoEnabledSpan,
@@ -107,7 +470,10 @@ impl std::fmt::Display for TraceCtxError {
 impl std::error::Error for TraceCtxError {}
 
 /// A `Span` holds ready-to-publish information gathered during the lifetime of a `tracing::Span`.
+///
+/// Only constructed by `TelemetryLayer`, fields may be added without a breaking change.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct Span<Visitor, SpanId, TraceId> {
     /// id identifying this span
     pub id: SpanId,
@@ -115,14 +481,29 @@ pub struct Span<Visitor, SpanId, TraceId> {
     pub name: String,
     /// `TraceId` identifying the trace to which this span belongs
     pub trace_id: TraceId,
+    /// W3C `tracestate` of the trace when this span was created, if any
+    pub trace_state: Option<Arc<str>>,
+    /// Whether the trace was started by the layer as no root was registered, see
+    /// `TelemetryLayer::with_implicit_traces`
+    pub implicit_trace: bool,
     /// optional parent span id
     pub parent_id: Option<SpanId>,
+    /// Whether this span was registered as the root of its trace within this process, its
+    /// parent, if any, is then a remote span
+    pub local_root: bool,
     /// Specifies original parent if the span originally had a parent span in another trace
     pub follows_from: Option<(TraceId, SpanId)>,
     /// UTC time at which this span was initialized
     pub initialized_at: SystemTime,
     /// `chrono::Duration` elapsed between the time this span was initialized and the time it was completed
     pub completed_at: SystemTime,
+    /// Time the span was entered, i.e. doing work, between its creation and completion
+    pub busy: Duration,
+    /// Time the span was not entered between its creation and completion, e.g. waiting on
+    /// the executor of an async task
+    pub idle: Duration,
+    /// Number of events of the span dropped beyond `Telemetry::max_events_per_span`
+    pub dropped_events: usize,
     /// `tracing::Metadata` for this span
     pub meta: &'static tracing::Metadata<'static>,
     /// name of the service on which this span occured
@@ -131,6 +512,31 @@ pub struct Span<Visitor, SpanId, TraceId> {
     pub values: Visitor,
 }
 
+impl<Visitor, SpanId, TraceId> Span<Visitor, SpanId, TraceId> {
+    /// Replaces the values of the span, e.g. to take ownership of those of a snapshot passed
+    /// to `Telemetry::report_span_progress`.
+    pub fn with_values<V>(self, values: V) -> Span<V, SpanId, TraceId> {
+        Span {
+            id: self.id,
+            name: self.name,
+            trace_id: self.trace_id,
+            trace_state: self.trace_state,
+            implicit_trace: self.implicit_trace,
+            parent_id: self.parent_id,
+            local_root: self.local_root,
+            follows_from: self.follows_from,
+            initialized_at: self.initialized_at,
+            completed_at: self.completed_at,
+            busy: self.busy,
+            idle: self.idle,
+            dropped_events: self.dropped_events,
+            meta: self.meta,
+            service_name: self.service_name,
+            values,
+        }
+    }
+}
+
 /// An `Event` holds ready-to-publish information derived from a `tracing::Event`.
 #[derive(Clone, Debug)]
 pub struct Event<Visitor, SpanId, TraceId> {
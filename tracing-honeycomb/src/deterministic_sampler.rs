@@ -10,7 +10,7 @@ use crate::TraceId;
 pub(crate) fn sample(sample_rate: u32, trace_id: &TraceId) -> bool {
     let sum = Sha1::digest(trace_id.as_ref());
     // Since we are operating on u32's in rust, there is no need for the original's `>>> 0`.
-    let upper_bound = std::u32::MAX / sample_rate;
+    let upper_bound = u32::MAX / sample_rate;
 
     u32::from_be_bytes([sum[0], sum[1], sum[2], sum[3]]) <= upper_bound
 }
@@ -12,13 +12,15 @@ use crate::{SpanId, TraceId};
 pub struct HoneycombTelemetry<R> {
     reporter: R,
     sample_rate: Option<u32>,
+    stream_events: bool,
 }
 
 impl<R: Reporter> HoneycombTelemetry<R> {
-    pub(crate) fn new(reporter: R, sample_rate: Option<u32>) -> Self {
+    pub(crate) fn new(reporter: R, sample_rate: Option<u32>, stream_events: bool) -> Self {
         HoneycombTelemetry {
             reporter,
             sample_rate,
+            stream_events,
         }
     }
 
@@ -60,5 +62,17 @@ impl<R: Reporter> Telemetry for HoneycombTelemetry<R> {
         }
     }
 
-    fn report_event(&self, _event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {}
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        // Events outside of a trace are not reported, only streamed events of spans have one
+        if let Some(trace_id) = &event.trace_id {
+            if self.should_report(trace_id) {
+                let (data, timestamp) = event_to_values(event);
+                self.report_data(data, timestamp);
+            }
+        }
+    }
+
+    fn streams_span_events(&self) -> bool {
+        self.stream_events
+    }
 }
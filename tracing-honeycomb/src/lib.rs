@@ -54,6 +54,13 @@ pub fn current_dist_trace_ctx() -> Result<(TraceId, SpanId), TraceCtxError> {
     tracing_distributed::current_dist_trace_ctx()
 }
 
+/// Retrieve the distributed trace context associated with `span`, without entering it.
+///
+/// Specialized to the honeycomb.io-specific SpanId and TraceId provided by this crate.
+pub fn dist_trace_ctx_for(span: &tracing::Span) -> Result<(TraceId, SpanId), TraceCtxError> {
+    tracing_distributed::dist_trace_ctx_for(span)
+}
+
 /// Construct a TelemetryLayer that does not publish telemetry to any backend.
 ///
 /// Specialized to the honeycomb.io-specific SpanId and TraceId provided by this crate.
@@ -80,7 +87,7 @@ pub fn new_honeycomb_telemetry_layer(
 
     TelemetryLayer::new(
         service_name,
-        HoneycombTelemetry::new(reporter, None),
+        HoneycombTelemetry::new(reporter, None, false),
         move |tracing_id| SpanId { tracing_id },
     )
 }
@@ -113,7 +120,7 @@ pub fn new_honeycomb_telemetry_layer_with_trace_sampling(
 
     TelemetryLayer::new(
         service_name,
-        HoneycombTelemetry::new(reporter, Some(sample_rate)),
+        HoneycombTelemetry::new(reporter, Some(sample_rate), false),
         move |tracing_id| SpanId { tracing_id },
     )
 }
@@ -138,6 +145,7 @@ pub fn new_honeycomb_telemetry_layer_with_trace_sampling(
 pub struct Builder<R> {
     reporter: R,
     sample_rate: Option<u32>,
+    stream_events: bool,
     service_name: &'static str,
 }
 
@@ -147,6 +155,7 @@ impl Builder<StdoutReporter> {
         Self {
             reporter: StdoutReporter,
             sample_rate: None,
+            stream_events: false,
             service_name,
         }
     }
@@ -178,6 +187,7 @@ impl Builder<LibhoneyReporter> {
         Self {
             reporter,
             sample_rate: None,
+            stream_events: false,
             service_name,
         }
     }
@@ -202,12 +212,100 @@ impl<R: Reporter> Builder<R> {
         self
     }
 
+    /// Sends the events of spans to Honeycomb as they occur, instead of keeping them in
+    /// memory until their span ends. Long-running spans then do not accumulate events,
+    /// and their events are visible before the span is.
+    pub fn with_streamed_events(mut self) -> Self {
+        self.stream_events = true;
+        self
+    }
+
     /// Constructs the configured `TelemetryLayer`
     pub fn build(self) -> TelemetryLayer<HoneycombTelemetry<R>, SpanId, TraceId> {
         TelemetryLayer::new(
             self.service_name,
-            HoneycombTelemetry::new(self.reporter, self.sample_rate),
+            HoneycombTelemetry::new(self.reporter, self.sample_rate, self.stream_events),
             move |tracing_id| SpanId { tracing_id },
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<std::sync::Mutex<Vec<HashMap<String, libhoney::Value>>>>);
+
+    impl Reporter for Capture {
+        fn report_data(&self, data: HashMap<String, libhoney::Value>, _timestamp: DateTime<Utc>) {
+            self.0.lock().unwrap().push(data);
+        }
+    }
+
+    #[test]
+    fn dist_trace_ctx_for_span_other_than_the_current_one() {
+        let subscriber = tracing_subscriber::registry().with(new_blackhole_telemetry_layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let trace_id = TraceId::new();
+            let root = tracing::info_span!("root");
+            let child = root.in_scope(|| {
+                register_dist_tracing_root(trace_id.clone(), None).unwrap();
+                tracing::info_span!("child")
+            });
+
+            // Neither span is entered, so there is no current trace context
+            assert_eq!(current_dist_trace_ctx(), Err(TraceCtxError::NoEnabledSpan));
+            let child_ctx = dist_trace_ctx_for(&child).unwrap();
+            assert_eq!(child_ctx.0, trace_id);
+            assert_eq!(
+                Ok(child_ctx.clone()),
+                child.in_scope(current_dist_trace_ctx)
+            );
+            let (_, root_span_id) = dist_trace_ctx_for(&root).unwrap();
+            assert_ne!(root_span_id, child_ctx.1);
+
+            let outside = tracing::info_span!("outside");
+            assert_eq!(
+                dist_trace_ctx_for(&outside),
+                Err(TraceCtxError::NoParentNodeHasTraceCtx)
+            );
+        });
+    }
+
+    #[test]
+    fn streamed_events_are_reported_as_they_occur() {
+        let capture = Capture::default();
+        let layer = Builder {
+            reporter: capture.clone(),
+            sample_rate: None,
+            stream_events: false,
+            service_name: "test_svc_name",
+        }
+        .with_streamed_events()
+        .build();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let trace_id = TraceId::new();
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(trace_id.clone(), None).unwrap();
+                tracing::info!("started");
+
+                // Reported before its span ends, with the trace of the span
+                let reported = capture.0.lock().unwrap();
+                assert_eq!(reported.len(), 1);
+                assert_eq!(
+                    reported[0]["trace.trace_id"],
+                    libhoney::json!(trace_id.to_string())
+                );
+            });
+            // Not part of a trace, so not reported
+            tracing::info!("outside");
+        });
+        assert_eq!(capture.0.lock().unwrap().len(), 2);
+    }
+}
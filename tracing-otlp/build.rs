@@ -2,9 +2,15 @@ use std::io::Result;
 fn main() -> Result<()> {
     println!("cargo::rerun-if-changed=opentelemetry-proto/");
 
-    prost_build::compile_protos(
-        &["opentelemetry-proto/opentelemetry/proto/collector/trace/v1/trace_service.proto"],
-        &["opentelemetry-proto"],
-    )?;
+    prost_build::Config::new()
+        // The upstream comment contains an indented example which rustdoc mistakes for a doctest
+        .disable_comments([".opentelemetry.proto.trace.v1.Span.attributes"])
+        .compile_protos(
+            &[
+                "opentelemetry-proto/opentelemetry/proto/collector/trace/v1/trace_service.proto",
+                "opentelemetry-proto/opentelemetry/proto/collector/metrics/v1/metrics_service.proto",
+            ],
+            &["opentelemetry-proto"],
+        )?;
     Ok(())
 }
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use crate::prost::common::v1::KeyValue;
+
+/// How span and event attributes are transformed on their way out of the process.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AttributeRules {
+    /// Maps keys as recorded to the key they are exported as
+    pub(crate) renames: HashMap<String, String>,
+    pub(crate) filter: AttributeFilter,
+}
+
+impl AttributeRules {
+    /// Renames attributes and removes those which may not be exported, returning how many
+    /// were removed.
+    ///
+    /// Renaming happens first, so the filter applies to the exported keys.
+    pub(crate) fn apply(&self, attributes: &mut Vec<KeyValue>) -> u32 {
+        if !self.renames.is_empty() {
+            for kv in attributes.iter_mut() {
+                if let Some(key) = self.renames.get(&kv.key) {
+                    kv.key.clone_from(key);
+                }
+            }
+        }
+        self.filter.apply(attributes)
+    }
+}
+
+/// Matches attribute keys, either exactly or by prefix.
+///
+/// Converting from a string treats a trailing `*` as a prefix match, so `"http.*"` matches
+/// all keys starting with `http.` while `"http.method"` only matches that exact key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyPattern {
+    /// Matches keys equal to the given string
+    Exact(String),
+    /// Matches keys starting with the given string
+    Prefix(String),
+}
+
+impl KeyPattern {
+    /// Returns whether `key` matches this pattern.
+    pub fn matches(&self, key: &str) -> bool {
+        match self {
+            KeyPattern::Exact(k) => key == k,
+            KeyPattern::Prefix(p) => key.starts_with(p.as_str()),
+        }
+    }
+}
+
+impl From<&str> for KeyPattern {
+    fn from(pattern: &str) -> Self {
+        match pattern.strip_suffix('*') {
+            Some(prefix) => KeyPattern::Prefix(prefix.to_string()),
+            None => KeyPattern::Exact(pattern.to_string()),
+        }
+    }
+}
+
+impl From<String> for KeyPattern {
+    fn from(pattern: String) -> Self {
+        pattern.as_str().into()
+    }
+}
+
+/// Decides which span and event attributes are exported.
+///
+/// If an allowlist is set only matching keys pass, after which any key matching the
+/// denylist is removed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AttributeFilter {
+    pub(crate) allow: Option<Vec<KeyPattern>>,
+    pub(crate) deny: Vec<KeyPattern>,
+}
+
+impl AttributeFilter {
+    fn is_allowed(&self, key: &str) -> bool {
+        self.allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|p| p.matches(key)))
+            && !self.deny.iter().any(|p| p.matches(key))
+    }
+
+    /// Removes all attributes which may not be exported, returning how many were removed.
+    pub(crate) fn apply(&self, attributes: &mut Vec<KeyValue>) -> u32 {
+        let len = attributes.len();
+        attributes.retain(|kv| self.is_allowed(&kv.key));
+        (len - attributes.len()) as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(filter: &AttributeFilter, keys: &[&str]) -> (Vec<String>, u32) {
+        let mut attributes = keys
+            .iter()
+            .map(|k| KeyValue::new(k.to_string(), true.into()))
+            .collect();
+        let dropped = filter.apply(&mut attributes);
+        (attributes.into_iter().map(|kv| kv.key).collect(), dropped)
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let filter = AttributeFilter {
+            allow: Some(vec!["http.*".into(), "user".into()]),
+            deny: vec!["http.request.header.*".into()],
+        };
+        let (kept, dropped) = keys(
+            &filter,
+            &[
+                "http.method",
+                "http.request.header.cookie",
+                "user",
+                "user.id",
+                "other",
+            ],
+        );
+        assert_eq!(kept, vec!["http.method", "user"]);
+        assert_eq!(dropped, 3);
+    }
+
+    #[test]
+    fn rename_before_filter() {
+        let rules = AttributeRules {
+            renames: [(
+                "http_status".to_string(),
+                "http.response.status_code".to_string(),
+            )]
+            .into(),
+            filter: AttributeFilter {
+                allow: Some(vec!["http.*".into()]),
+                deny: vec![],
+            },
+        };
+        let mut attributes = vec![
+            KeyValue::new("http_status".to_string(), 200.into()),
+            KeyValue::new("other".to_string(), 1.into()),
+        ];
+        assert_eq!(rules.apply(&mut attributes), 1);
+        assert_eq!(attributes[0].key, "http.response.status_code");
+    }
+
+    #[test]
+    fn default_allows_everything() {
+        let (kept, dropped) = keys(&AttributeFilter::default(), &["a", "b"]);
+        assert_eq!(kept, vec!["a", "b"]);
+        assert_eq!(dropped, 0);
+    }
+}
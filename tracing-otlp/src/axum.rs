@@ -0,0 +1,99 @@
+use std::convert::Infallible;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+
+use crate::{current_span_context, SpanContext, SpanId, TraceId};
+
+/// `axum` extractor of the distributed trace context of the span handling the request, such
+/// as the span of [`crate::ServerTraceLayer`].
+///
+/// `None` if the request is not handled within a distributed trace, e.g. as the layer is
+/// missing. Never rejects the request.
+///
+/// # Examples
+/// ```
+/// use tracing_otlp::DistTraceContext;
+///
+/// async fn handler(ctx: DistTraceContext) -> String {
+///     match ctx.trace_id() {
+///         Some(trace_id) => format!("handled in trace {trace_id}"),
+///         None => "handled".to_string(),
+///     }
+/// }
+///
+/// let app: axum::Router = axum::Router::new()
+///     .route("/", axum::routing::get(handler))
+///     .layer(tracing_otlp::ServerTraceLayer::new());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistTraceContext(pub Option<SpanContext>);
+
+impl DistTraceContext {
+    /// The id of the trace the request is handled in.
+    pub fn trace_id(&self) -> Option<TraceId> {
+        self.0.as_ref().map(|ctx| ctx.trace_id)
+    }
+
+    /// The id of the span handling the request.
+    pub fn span_id(&self) -> Option<SpanId> {
+        self.0.as_ref().map(|ctx| ctx.span_id)
+    }
+}
+
+#[async_trait]
+impl<S: Sync> FromRequestParts<S> for DistTraceContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self(current_span_context().ok()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, routing::get, Router};
+    use tower_service::Service;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{
+        test::{capture_exporter, current_thread_runtime},
+        ServerTraceLayer,
+    };
+
+    #[test]
+    fn handlers_extract_the_context_of_routed_requests() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let runtime = current_thread_runtime();
+        let response = tracing::subscriber::with_default(subscriber, || {
+            let mut app = Router::new()
+                .route(
+                    "/users/:id",
+                    get(|ctx: DistTraceContext| async move { ctx.trace_id().unwrap().to_string() }),
+                )
+                .layer(
+                    ServerTraceLayer::new()
+                        .trace_id_header(http::HeaderName::from_static("x-trace-id")),
+                );
+            let request = http::Request::get("/users/1").body(Body::empty()).unwrap();
+            runtime.block_on(app.call(request)).unwrap()
+        });
+
+        let trace_id = response.headers()["x-trace-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = runtime
+            .block_on(axum::body::to_bytes(response.into_body(), usize::MAX))
+            .unwrap();
+        assert_eq!(body, trace_id);
+
+        let spans = capture.0.lock().unwrap();
+        assert_eq!(spans[0].name, "GET /users/:id");
+        assert_eq!(
+            spans[0].trace_id,
+            trace_id.parse::<TraceId>().unwrap().to_bytes()
+        );
+    }
+}
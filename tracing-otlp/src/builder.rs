@@ -1,25 +1,137 @@
-use std::{sync::Mutex, time::Duration};
+use std::{error::Error, path::PathBuf, sync::Arc, time::Duration};
 
-use rand::{rngs::StdRng, Rng, SeedableRng};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::level_filters::LevelFilter;
 use tracing_distributed::TelemetryLayer;
+use url::Url;
 
-use crate::{prost::common::v1::any_value::Value, Otlp, SpanId, TraceId};
+#[cfg(feature = "serde")]
+use crate::OtlpConfig;
+use crate::{
+    attributes::{AttributeRules, KeyPattern},
+    error::ErrorHandler,
+    http::{
+        merge_headers, validate_header, AgentConfig, HeaderProvider, HttpClient, SignalHeaders,
+    },
+    metrics::{BatchResultCallback, MetricsConfig},
+    processor::Processors,
+    prost::{
+        collector::trace::v1::ExportTraceServiceRequest,
+        common::v1::{any_value::Value, KeyValue},
+    },
+    route::ResolvedRoute,
+    sampling::Sampling,
+    transport::{Encoder, Transport},
+    worker::{Destination, WorkerConfig},
+    BatchResult, Compression, ConnectionError, IdGenerator, Otlp, OtlpBuildError, OtlpError,
+    RandomIdGenerator, ResourceDetector, Route, Sampler, SamplingDecision, SpanId, SpanProcessor,
+    TailSampler, TraceId, WorkerMode,
+};
+#[cfg(feature = "file")]
+use crate::{file::FileWriter, OtlpFile};
+
+/// How long [`Builder::simple`] waits for an export if there is no request timeout.
+const SIMPLE_EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The spans and events the layer reports, see [`Builder::max_level`].
+#[derive(Debug, Clone)]
+pub(crate) struct LayerFilter {
+    pub(crate) max_level: LevelFilter,
+    pub(crate) allow: Option<Vec<String>>,
+    pub(crate) deny: Vec<String>,
+}
+
+impl Default for LayerFilter {
+    fn default() -> Self {
+        Self {
+            max_level: LevelFilter::TRACE,
+            allow: None,
+            deny: Vec::new(),
+        }
+    }
+}
 
 /// Builder for the [`crate::Otlp`] `tracing` layer.
 ///
 /// Use the [`Builder`] in order to set configuration for the layer and its endpoint.
 pub struct Builder {
     send_interval: Duration,
+    send_interval_jitter: Duration,
+    max_queue_size: usize,
+    level_aware_shedding: bool,
+    simple: bool,
+    heartbeat: Option<Duration>,
+    max_events_per_span: Option<usize>,
+    implicit_traces: bool,
+    default_trace: Option<(TraceId, Option<SpanId>)>,
+    layer_filter: LayerFilter,
+    max_export_batch_size: usize,
+    max_export_batch_bytes: Option<usize>,
+    max_concurrent_exports: usize,
     resource_attributes: Vec<(String, Value)>,
     headers: Vec<(String, String)>,
+    attributes: AttributeRules,
+    processors: Processors,
+    id_generator: Arc<dyn IdGenerator>,
+    sampling: Sampling,
+    tail_sampler: Option<TailSampler>,
+    metrics: Option<MetricsConfig>,
+    error_handler: ErrorHandler,
+    on_batch_result: Option<BatchResultCallback>,
+    disk_buffer: Option<(PathBuf, u64)>,
+    circuit_breaker: Option<(u32, Duration)>,
+    retry_budget: Option<(u32, Duration)>,
+    routes: Vec<Arc<ResolvedRoute>>,
+    traces_endpoint: Option<Url>,
+    metrics_endpoint: Option<Url>,
+    traces_headers: Vec<(String, String)>,
+    metrics_headers: Vec<(String, String)>,
+    authorization: Option<String>,
+    header_provider: Option<HeaderProvider>,
+    agent: AgentConfig,
+    user_agent: String,
+    invalid: Option<OtlpBuildError>,
 }
 
 impl Default for Builder {
     fn default() -> Self {
         Self {
             send_interval: Duration::from_secs(1),
+            send_interval_jitter: Duration::ZERO,
+            max_queue_size: 2048,
+            level_aware_shedding: false,
+            simple: false,
+            heartbeat: None,
+            max_events_per_span: None,
+            implicit_traces: false,
+            default_trace: None,
+            layer_filter: Default::default(),
+            max_export_batch_size: 512,
+            max_export_batch_bytes: None,
+            max_concurrent_exports: 1,
             resource_attributes: Default::default(),
             headers: Default::default(),
+            attributes: Default::default(),
+            processors: Default::default(),
+            id_generator: Arc::new(RandomIdGenerator::default()),
+            sampling: Default::default(),
+            tail_sampler: None,
+            metrics: None,
+            error_handler: Default::default(),
+            on_batch_result: None,
+            disk_buffer: None,
+            circuit_breaker: None,
+            retry_budget: None,
+            routes: Vec::new(),
+            traces_endpoint: None,
+            metrics_endpoint: None,
+            traces_headers: Default::default(),
+            metrics_headers: Default::default(),
+            authorization: None,
+            header_provider: None,
+            agent: Default::default(),
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            invalid: None,
         }
     }
 }
@@ -29,14 +141,258 @@ impl Builder {
         Self::default()
     }
 
+    /// Creates a builder with the settings loaded from a configuration file, see
+    /// [`OtlpConfig`]. The endpoint of the config still has to be passed to the `build`
+    /// methods, other settings may be changed on the returned builder.
+    #[cfg(feature = "serde")]
+    pub fn from_config(config: &OtlpConfig) -> Builder {
+        let mut builder = Self::default().http_headers(&config.headers);
+        if let Some(url) = &config.traces_endpoint {
+            builder = builder.traces_endpoint(url.as_str());
+        }
+        if let Some(url) = &config.metrics_endpoint {
+            builder = builder.metrics_endpoint(url.as_str());
+        }
+        if let Some(service_name) = &config.service_name {
+            builder = builder.service_name(service_name.clone());
+        }
+        if let Some(service_version) = &config.service_version {
+            builder = builder.service_version(service_version.clone());
+        }
+        for (key, value) in &config.resource_attributes {
+            builder = builder.resource_attribute(key.clone(), value.clone());
+        }
+        if !config.span_attributes.is_empty() {
+            let attributes = config.span_attributes.iter().fold(
+                crate::StaticAttributes::new(),
+                |attributes, (key, value)| attributes.attribute(key, value.as_str()),
+            );
+            builder = builder.add_processor(attributes);
+        }
+        if let Some(interval) = config.send_interval_ms {
+            builder = builder.send_interval(Duration::from_millis(interval));
+        }
+        if let Some(jitter) = config.send_interval_jitter_ms {
+            builder = builder.send_interval_jitter(Duration::from_millis(jitter));
+        }
+        if let Some(max_queue_size) = config.max_queue_size {
+            builder = builder.max_queue_size(max_queue_size);
+        }
+        if let Some(failures) = config.circuit_breaker_failures {
+            let probe_interval = config.circuit_breaker_probe_interval_ms.unwrap_or(30_000);
+            builder = builder.circuit_breaker(failures, Duration::from_millis(probe_interval));
+        }
+        if config.max_export_attempts.is_some() || config.max_retry_duration_ms.is_some() {
+            builder = builder.retry_budget(
+                config.max_export_attempts.unwrap_or(u32::MAX),
+                config
+                    .max_retry_duration_ms
+                    .map_or(Duration::MAX, Duration::from_millis),
+            );
+        }
+        if let Some(enabled) = config.level_aware_shedding {
+            builder = builder.level_aware_shedding(enabled);
+        }
+        if let Some(max_export_batch_size) = config.max_export_batch_size {
+            builder = builder.max_export_batch_size(max_export_batch_size);
+        }
+        if let Some(max_export_batch_bytes) = config.max_export_batch_bytes {
+            builder = builder.max_export_batch_bytes(max_export_batch_bytes);
+        }
+        if let Some(max_concurrent_exports) = config.max_concurrent_exports {
+            builder = builder.max_concurrent_exports(max_concurrent_exports);
+        }
+        if let Some(compression) = config.compression {
+            builder = builder.compression(compression);
+        }
+        if let Some(min_bytes) = config.compression_threshold {
+            builder = builder.compression_threshold(min_bytes);
+        }
+        if let Some(timeout) = config.request_timeout_ms {
+            builder = builder.request_timeout(Some(Duration::from_millis(timeout)));
+        }
+        if let Some(sampler) = config.sampler {
+            builder = builder.sampler(sampler);
+        }
+        if config.simple {
+            builder = builder.simple();
+        }
+        builder
+    }
+
     /// Configures the interval at which traces are reported to the OTLP endpoint
     pub fn send_interval(mut self, interval: Duration) -> Self {
         self.send_interval = interval;
         self
     }
 
+    /// Adds a random duration of up to `jitter` to each send interval, none by default.
+    ///
+    /// Replicas started at the same time then do not all export at the same moment, which
+    /// would cause load spikes on the collector.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_otlp::Builder;
+    /// // Export every 1 to 1.5 seconds
+    /// Builder::new()
+    ///     .send_interval(Duration::from_secs(1))
+    ///     .send_interval_jitter(Duration::from_millis(500));
+    /// ```
+    pub fn send_interval_jitter(mut self, jitter: Duration) -> Self {
+        self.send_interval_jitter = jitter;
+        self
+    }
+
+    /// Sets the maximum number of spans waiting to be picked up by the worker, defaults to
+    /// 2048.
+    ///
+    /// Spans finishing while the queue is full are dropped rather than blocking the
+    /// application, and accounted for in [`crate::OtlpStats::spans_dropped`]. Building fails
+    /// with [`OtlpBuildError::InvalidSetting`] for a size of 0, which would drop all spans.
+    pub fn max_queue_size(mut self, max_queue_size: usize) -> Self {
+        if max_queue_size == 0 {
+            self.invalid(OtlpBuildError::InvalidSetting {
+                setting: "max_queue_size",
+                reason: "the queue must hold at least one span",
+            });
+        }
+        self.max_queue_size = max_queue_size;
+        self
+    }
+
+    /// Sheds less severe spans first while the queue fills up, keeping room for the more
+    /// severe ones. Disabled by default, so spans of all levels are only dropped once the
+    /// queue is full.
+    ///
+    /// When enabled, `TRACE` and `DEBUG` spans are dropped once the queue is half full and
+    /// `INFO` spans once it is three quarters full. `WARN` and `ERROR` spans, and spans with
+    /// an error event or status, are only dropped when it is full. Shed spans are
+    /// accounted for per level in [`crate::OtlpStats::spans_shed`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// let layer = Builder::new()
+    ///     .max_queue_size(4096)
+    ///     .level_aware_shedding(true)
+    ///     .build("http://127.0.0.1:4318")
+    ///     .unwrap();
+    /// let shed = layer.telemetry().stats().spans_shed(tracing::Level::DEBUG);
+    /// ```
+    pub fn level_aware_shedding(mut self, enabled: bool) -> Self {
+        self.level_aware_shedding = enabled;
+        self
+    }
+
+    /// Exports each span as soon as it finishes, blocking the thread finishing the span until
+    /// the export is done, at most for the [`Builder::request_timeout`]. Suitable for
+    /// command line tools and jobs which exit before the send interval is up, rather than
+    /// services, as it adds an export request to every span.
+    ///
+    /// This sets [`Builder::max_export_batch_size`] to 1. With a [`Builder::tail_sampling`],
+    /// buffered traces are decided on as soon as any span finishes. In the browser spans are
+    /// exported right away without blocking.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use tracing_otlp::Builder;
+    /// let layer = Builder::new()
+    ///     .simple()
+    ///     .build("http://127.0.0.1:4318")
+    ///     .unwrap();
+    /// ```
+    pub fn simple(mut self) -> Self {
+        self.simple = true;
+        self.max_export_batch_size = 1;
+        self
+    }
+
+    /// Exports snapshots of spans open for longer than `interval`, every `interval` while
+    /// they stay open, so that long-running work such as the root span of a batch job shows
+    /// up before it ends, and is not lost if the process crashes.
+    ///
+    /// Snapshots have the id of the span, the current time as end time and the
+    /// `span.in_progress` attribute set to true, so backends deduplicating spans by id keep
+    /// the span exported once it ends. They are taken when the span or one of its descendants is entered, exited or
+    /// records an event, so a span without any activity inside gets no snapshots.
+    ///
+    /// [`SpanProcessor`]s are called with the snapshots as well. Snapshots are not counted
+    /// by [`Builder::span_metrics`], and not exported with [`Builder::tail_sampling`], which
+    /// only decides on a trace once its spans ended.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().heartbeat(Duration::from_secs(60));
+    /// ```
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Keeps at most `max` events per span, dropping those recorded once a span has this
+    /// many, so a chatty loop within a long-lived span does not buffer events without bound
+    /// until the span ends. The number of dropped events is exported as the
+    /// `dropped_events_count` of the span. Unlimited by default, OpenTelemetry SDKs keep 128.
+    pub fn max_events_per_span(mut self, max: usize) -> Self {
+        self.max_events_per_span = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of spans exported in one request, defaults to 512.
+    ///
+    /// Once this many spans are waiting, they are exported right away instead of at the end
+    /// of the send interval, so bursts do not pile up into oversized requests.
+    pub fn max_export_batch_size(mut self, max_export_batch_size: usize) -> Self {
+        self.max_export_batch_size = max_export_batch_size.max(1);
+        self
+    }
+
+    /// Limits the estimated size of the encoded spans of a request, unlimited by default.
+    /// Set it below the request size limit of the collector.
+    ///
+    /// As with [`Builder::max_export_batch_size`], spans are exported right away once they
+    /// reach this size. A single span larger than the limit is still exported on its own.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new()
+    ///     .max_export_batch_size(1024)
+    ///     .max_export_batch_bytes(4 * 1024 * 1024);
+    /// ```
+    pub fn max_export_batch_bytes(mut self, max_export_batch_bytes: usize) -> Self {
+        self.max_export_batch_bytes = Some(max_export_batch_bytes);
+        self
+    }
+
+    /// Sets the number of export requests which may be in flight at the same time, defaults
+    /// to 1.
+    ///
+    /// With a single request, throughput is bounded by the latency of the endpoint, as the
+    /// worker waits for each response. Higher values export batches from a pool of threads,
+    /// the worker then keeps collecting spans while all of them are busy. Building fails with
+    /// [`OtlpBuildError::InvalidSetting`] for 0.
+    pub fn max_concurrent_exports(mut self, max_concurrent_exports: usize) -> Self {
+        if max_concurrent_exports == 0 {
+            self.invalid(OtlpBuildError::InvalidSetting {
+                setting: "max_concurrent_exports",
+                reason: "at least one export must be allowed",
+            });
+        }
+        self.max_concurrent_exports = max_concurrent_exports;
+        self
+    }
+
     /// Sets the name of this service.
     ///
+    /// Spans with a `service.name` attribute are exported with the resource of that service
+    /// instead, for processes running several logical services. Set it for whole traces with
+    /// [`crate::set_trace_attribute`].
+    ///
     /// See: [https://opentelemetry.io/docs/languages/sdk-configuration/general/#otel_service_name]
     pub fn service_name(mut self, service_name: String) -> Self {
         self.resource_attributes
@@ -44,45 +400,1255 @@ impl Builder {
         self
     }
 
+    /// Sets the version of this service, e.g. the semantic version or git hash.
+    ///
+    /// See: [https://opentelemetry.io/docs/specs/semconv/resource/#service]
+    pub fn service_version(mut self, service_version: String) -> Self {
+        self.resource_attributes
+            .push(("service.version".to_string(), service_version.into()));
+        self
+    }
+
+    /// Sets the id of this instance of the service, which must be unique among all instances
+    /// running at the same time.
+    ///
+    /// See: [https://opentelemetry.io/docs/specs/semconv/resource/#service]
+    pub fn service_instance_id(mut self, service_instance_id: String) -> Self {
+        self.resource_attributes.push((
+            "service.instance.id".to_string(),
+            service_instance_id.into(),
+        ));
+        self
+    }
+
+    /// Sets the id of this instance of the service to a UUID V4 generated at startup.
+    pub fn random_service_instance_id(self) -> Self {
+        self.service_instance_id(uuid::Uuid::new_v4().to_string())
+    }
+
     /// Adds an attribute for this OpenTelemetry resource.
     ///
     /// This may be an attribute such as rust version, program version, MAC address, etc.
+    /// Besides scalars, `Vec`s and maps are accepted and exported as OTLP arrays and
+    /// key-value lists respectively.
     pub fn resource_attribute(mut self, key: String, value: impl Into<Value>) -> Self {
         self.resource_attributes.push((key, value.into()));
         self
     }
 
+    /// Adds every entry of a JSON object as an attribute for this OpenTelemetry resource.
+    ///
+    /// Nested objects and arrays are kept structured, see the `From<serde_json::Value>`
+    /// conversions on [`Value`]. Entries which are `null` are left out, nested `null`s are
+    /// exported as empty values.
+    #[cfg(feature = "json")]
+    pub fn resource_attributes_json(
+        mut self,
+        attributes: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        self.resource_attributes.extend(
+            attributes
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, value.into())),
+        );
+        self
+    }
+
+    /// Adds the attributes found by a [`ResourceDetector`] to this OpenTelemetry resource.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::{Builder, HostDetector, OsDetector, ProcessDetector};
+    /// Builder::new()
+    ///     .resource_detector(HostDetector)
+    ///     .resource_detector(OsDetector)
+    ///     .resource_detector(ProcessDetector);
+    /// ```
+    pub fn resource_detector(mut self, detector: impl ResourceDetector) -> Self {
+        self.resource_attributes.extend(detector.detect());
+        self
+    }
+
     /// Sets the HTTP headers to be added to OTLP requests.
     ///
     /// The headers are given in the form of a tuple, with the first value
-    /// the key and the second the value.
-    pub fn http_headers(mut self, headers: Vec<(String, String)>) -> Self {
-        self.headers = headers;
+    /// the key and the second the value. Besides strings, the `HeaderName` and `HeaderValue`
+    /// types of the `http` crate are accepted. Invalid headers are returned as
+    /// [`OtlpBuildError::InvalidHeader`] by the `build` methods.
+    ///
+    /// # Examples
+    /// ```
+    /// # use http::{header::ACCEPT_LANGUAGE, HeaderValue};
+    /// # use tracing_otlp::Builder;
+    /// Builder::new()
+    ///     .http_headers([("X-Tenant", "checkout")])
+    ///     .traces_http_headers([(ACCEPT_LANGUAGE, HeaderValue::from_static("en"))]);
+    /// ```
+    pub fn http_headers<K: AsRef<str>, V: AsRef<[u8]>>(
+        mut self,
+        headers: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.headers = self.convert_headers(headers);
+        self
+    }
+
+    /// Sets the maximum time of a whole request to the endpoint, from connecting to reading
+    /// the response, defaults to 10 seconds. `None` lets requests wait indefinitely.
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.agent.request_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum time to establish a connection to the endpoint, only limited by
+    /// [`Builder::request_timeout`] by default.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.agent.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for the endpoint to send data, only limited by
+    /// [`Builder::request_timeout`] by default.
+    ///
+    /// Does not apply to [`Builder::build_tokio`].
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.agent.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum time to wait for the endpoint to accept data, only limited by
+    /// [`Builder::request_timeout`] by default.
+    ///
+    /// Does not apply to [`Builder::build_tokio`].
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.agent.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the number of idle connections kept open to the endpoint, defaults to 1. Raise it
+    /// along with [`Builder::max_concurrent_exports`].
+    pub fn max_idle_connections(mut self, max: usize) -> Self {
+        self.agent.max_idle_connections = max;
+        self
+    }
+
+    /// Whether connections are kept open between requests, defaults to true. Without
+    /// keep-alive, each request opens a new connection.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_otlp::Builder;
+    /// Builder::new()
+    ///     .connect_timeout(Duration::from_secs(2))
+    ///     .request_timeout(Some(Duration::from_secs(5)))
+    ///     .keep_alive(false);
+    /// ```
+    pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+        self.agent.keep_alive = keep_alive;
+        self
+    }
+
+    /// Trusts the certificates in `pem` for `https` endpoints, in addition to the Mozilla
+    /// root certificates trusted by default, e.g. for a collector with a certificate issued
+    /// by a private CA. Building fails with [`OtlpBuildError::Tls`] if `pem` holds no valid
+    /// certificate.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use tracing_otlp::Builder;
+    /// let pem = std::fs::read("/etc/otel/ca.pem").unwrap();
+    /// Builder::new()
+    ///     .tls_root_certificates(&pem)
+    ///     .build("https://collector.internal:4318");
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn tls_root_certificates(mut self, pem: impl AsRef<[u8]>) -> Self {
+        match crate::http::tls_config(pem.as_ref()) {
+            Ok(tls_config) => self.agent.tls_config = Some(tls_config),
+            Err(err) => self.invalid(err),
+        }
+        self
+    }
+
+    /// Sets the compression of export requests, uncompressed by default.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "gzip")] {
+    /// # use tracing_otlp::{Builder, Compression};
+    /// Builder::new()
+    ///     .compression(Compression::Gzip)
+    ///     .compression_threshold(4096);
+    /// # }
+    /// ```
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.agent.compression.codec = compression;
+        self
+    }
+
+    /// Sends requests smaller than `min_bytes` uncompressed, as compressing small requests
+    /// costs more CPU time than it saves in bandwidth. Defaults to 1024 bytes, and only
+    /// applies if a [`Builder::compression`] is set.
+    pub fn compression_threshold(mut self, min_bytes: usize) -> Self {
+        self.agent.compression.threshold = min_bytes;
+        self
+    }
+
+    /// Sets the `User-Agent` header of requests, defaults to `tracing-otlp/<version>`.
+    ///
+    /// A `User-Agent` set with [`Builder::http_headers`] takes precedence.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self.check_header("User-Agent", self.user_agent.clone());
+        self
+    }
+
+    /// Appends an identifier of the application to the `User-Agent` header of requests.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// // Sends `User-Agent: tracing-otlp/<version> checkout/1.4.2`
+    /// Builder::new().user_agent_suffix("checkout/1.4.2");
+    /// ```
+    pub fn user_agent_suffix(mut self, suffix: impl AsRef<str>) -> Self {
+        self.user_agent.push(' ');
+        self.user_agent.push_str(suffix.as_ref());
+        self.check_header("User-Agent", self.user_agent.clone());
+        self
+    }
+
+    /// Authenticates requests with a bearer token, sent in the `Authorization` header.
+    ///
+    /// Takes precedence over an `Authorization` header set with [`Builder::http_headers`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().bearer_token("my-token");
+    /// ```
+    pub fn bearer_token(mut self, token: impl AsRef<str>) -> Self {
+        let value = format!("Bearer {}", token.as_ref());
+        self.authorization = self.check_header("Authorization", value);
+        self
+    }
+
+    /// Authenticates requests with HTTP basic authentication, sent in the `Authorization`
+    /// header.
+    ///
+    /// Takes precedence over an `Authorization` header set with [`Builder::http_headers`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().basic_auth("user", "password");
+    /// ```
+    pub fn basic_auth(mut self, user: impl AsRef<str>, password: impl AsRef<str>) -> Self {
+        let credentials = format!("{}:{}", user.as_ref(), password.as_ref());
+        let value = format!("Basic {}", STANDARD.encode(credentials));
+        self.authorization = self.check_header("Authorization", value);
+        self
+    }
+
+    /// Sets a callback returning HTTP headers to add to every request, evaluated by the
+    /// worker before each request, so short-lived credentials can be refreshed without
+    /// rebuilding the layer.
+    ///
+    /// The headers take precedence over all others. The callback should be cheap, such as
+    /// returning a token refreshed in the background, as it delays the export otherwise.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::sync::{Arc, RwLock};
+    /// # use tracing_otlp::Builder;
+    /// let token = Arc::new(RwLock::new("initial-token".to_string()));
+    /// Builder::new().header_provider(move || {
+    ///     let token = token.read().unwrap();
+    ///     vec![("Authorization".to_string(), format!("Bearer {token}"))]
+    /// });
+    /// ```
+    pub fn header_provider(
+        mut self,
+        provider: impl Fn() -> Vec<(String, String)> + Send + Sync + 'static,
+    ) -> Self {
+        self.header_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Sets the HTTP headers added to trace export requests only, in addition to those set
+    /// with [`Builder::http_headers`] and taking precedence over them.
+    pub fn traces_http_headers<K: AsRef<str>, V: AsRef<[u8]>>(
+        mut self,
+        headers: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.traces_headers = self.convert_headers(headers);
+        self
+    }
+
+    /// Sets the HTTP headers added to metrics requests only, in addition to those set with
+    /// [`Builder::http_headers`] and taking precedence over them.
+    pub fn metrics_http_headers<K: AsRef<str>, V: AsRef<[u8]>>(
+        mut self,
+        headers: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.metrics_headers = self.convert_headers(headers);
         self
     }
 
+    /// Sets the full URL spans are sent to, instead of `v1/traces` below the endpoint given
+    /// to [`Builder::build`].
+    ///
+    /// As with `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`, the URL is used as is. Like the endpoint
+    /// given to [`Builder::build`], it may be a `&str` or a [`Url`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new()
+    ///     .traces_endpoint("https://traces.example.com/api/traces")
+    ///     .metrics_endpoint("https://metrics.example.com/v1/metrics")
+    ///     .build("https://ingest.example.com");
+    /// ```
+    pub fn traces_endpoint<U>(mut self, url: U) -> Self
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        match url.try_into() {
+            Ok(url) => self.traces_endpoint = Some(url),
+            Err(err) => self.invalid(err.into()),
+        }
+        self
+    }
+
+    /// Sets the full URL metrics are sent to, instead of `v1/metrics` below the endpoint
+    /// given to [`Builder::build`].
+    ///
+    /// As with `OTEL_EXPORTER_OTLP_METRICS_ENDPOINT`, the URL is used as is.
+    pub fn metrics_endpoint<U>(mut self, url: U) -> Self
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        match url.try_into() {
+            Ok(url) => self.metrics_endpoint = Some(url),
+            Err(err) => self.invalid(err.into()),
+        }
+        self
+    }
+
+    /// Only exports span and event attributes whose key matches one of the given patterns.
+    ///
+    /// Patterns ending in `*` match by prefix, others must match the key exactly. Removed
+    /// attributes are accounted for in the `dropped_attributes_count` of the span or event.
+    pub fn allow_attributes<P: Into<KeyPattern>>(
+        mut self,
+        patterns: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.attributes
+            .filter
+            .allow
+            .get_or_insert_with(Vec::new)
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never exports span and event attributes whose key matches one of the given patterns.
+    ///
+    /// Takes precedence over [`Builder::allow_attributes`]. Patterns follow the same rules.
+    pub fn deny_attributes<P: Into<KeyPattern>>(
+        mut self,
+        patterns: impl IntoIterator<Item = P>,
+    ) -> Self {
+        self.attributes
+            .filter
+            .deny
+            .extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Exports span and event attributes recorded as `from` under the key `to` instead.
+    ///
+    /// This allows bringing existing instrumentation in line with the OpenTelemetry semantic
+    /// conventions, e.g. `http_status` to `http.response.status_code`. Renaming happens before
+    /// [`Builder::allow_attributes`] and [`Builder::deny_attributes`] are applied.
+    pub fn rename_attribute(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.attributes.renames.insert(from.into(), to.into());
+        self
+    }
+
+    /// Adds every `(from, to)` pair as with [`Builder::rename_attribute`].
+    pub fn rename_attributes<K: Into<String>, V: Into<String>>(
+        mut self,
+        renames: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.attributes.renames.extend(
+            renames
+                .into_iter()
+                .map(|(from, to)| (from.into(), to.into())),
+        );
+        self
+    }
+
+    /// Adds a [`SpanProcessor`], called as spans start and before they are exported, after
+    /// the processors added before.
+    pub fn add_processor(mut self, processor: impl SpanProcessor) -> Self {
+        self.processors.push(processor);
+        self
+    }
+
+    /// Sets the [`IdGenerator`] used for the ids of new spans and traces.
+    ///
+    /// Trace ids for new distributed traces can be obtained from it using
+    /// [`crate::new_trace_id`].
+    pub fn id_generator(mut self, id_generator: impl IdGenerator) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Sets the [`Sampler`] deciding which traces are exported, defaults to
+    /// [`Sampler::AlwaysOn`].
+    ///
+    /// Applies to traces registered using [`crate::register_dist_tracing_root`]. Traces
+    /// registered with the decision of their remote parent, using
+    /// [`crate::register_dist_tracing_root_sampled`], follow that decision instead.
+    pub fn sampler(mut self, sampler: Sampler) -> Self {
+        self.sampling.sampler = sampler;
+        self
+    }
+
+    /// Sets a callback deciding whether a trace is sampled, based on its id and the metadata
+    /// and attributes of its root span.
+    ///
+    /// The callback is run when the root is registered using
+    /// [`crate::register_dist_tracing_root`], so only attributes recorded on the root span
+    /// by then are available. Returning [`SamplingDecision::Defer`] leaves the decision to
+    /// the [`Sampler`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::{Builder, SamplingDecision};
+    /// Builder::new().sampling_callback(|_trace_id, meta, attributes| {
+    ///     if meta.name() == "health_check" {
+    ///         SamplingDecision::Drop
+    ///     } else if attributes.iter().any(|kv| kv.key == "customer.vip") {
+    ///         SamplingDecision::Sample
+    ///     } else {
+    ///         SamplingDecision::Defer
+    ///     }
+    /// });
+    /// ```
+    pub fn sampling_callback(
+        mut self,
+        callback: impl Fn(&TraceId, &tracing::Metadata<'static>, &[KeyValue]) -> SamplingDecision
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.sampling.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Exports the spans created outside of any trace, instead of dropping them, by starting
+    /// a trace for each of them as if it was registered as a root with a new trace id.
+    ///
+    /// Their spans have the `trace.implicit` attribute set to true. Whether these traces are
+    /// sampled is decided by the [`Sampler`], the [`Builder::sampling_callback`] only applies
+    /// to registered roots.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().implicit_traces();
+    /// ```
+    pub fn implicit_traces(mut self) -> Self {
+        self.implicit_traces = true;
+        self
+    }
+
+    /// Makes the spans created outside of any trace local roots of the trace `trace_id`, as
+    /// children of `remote_parent` if given, as if they were registered with
+    /// [`crate::register_dist_tracing_root`]. Batch jobs started by a scheduler then continue
+    /// its trace without registering each of their entry points.
+    ///
+    /// Whether the trace is sampled is decided by the [`Sampler`], once for the whole
+    /// process. Takes precedence over [`Builder::implicit_traces`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// // Continue the trace the scheduler passed in the environment
+    /// let mut builder = Builder::new();
+    /// if let Some(ctx) = std::env::var("TRACEPARENT")
+    ///     .ok()
+    ///     .and_then(|traceparent| tracing_otlp::parse_traceparent(&traceparent, None))
+    /// {
+    ///     builder = builder.default_trace(ctx.trace_id, Some(ctx.span_id));
+    /// }
+    /// ```
+    pub fn default_trace(mut self, trace_id: TraceId, remote_parent: Option<SpanId>) -> Self {
+        self.default_trace = Some((trace_id, remote_parent));
+        self
+    }
+
+    /// Only exports spans and events up to `max_level`, e.g. `Level::INFO` to skip those at
+    /// the `DEBUG` and `TRACE` levels.
+    ///
+    /// Unlike a filter of the subscriber, this and the target filters only apply to the
+    /// layer, so other layers such as one logging to stdout still see everything. They are
+    /// cheaper than wrapping the layer in an `EnvFilter`. The events of skipped spans, and
+    /// the spans created within them, are exported under their closest ancestor which is
+    /// not skipped.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().max_level(tracing::Level::INFO);
+    /// ```
+    pub fn max_level(mut self, max_level: impl Into<LevelFilter>) -> Self {
+        self.layer_filter.max_level = max_level.into();
+        self
+    }
+
+    /// Only exports spans and events whose target starts with one of `prefixes`, such as
+    /// `checkout` for the modules of the `checkout` crate.
+    pub fn allow_targets<P: Into<String>>(mut self, prefixes: impl IntoIterator<Item = P>) -> Self {
+        self.layer_filter
+            .allow
+            .get_or_insert_with(Vec::new)
+            .extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never exports spans and events whose target starts with one of `prefixes`. Takes
+    /// precedence over [`Builder::allow_targets`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// // The internals of the HTTP client
+    /// Builder::new().deny_targets(["h2::", "hyper::"]);
+    /// ```
+    pub fn deny_targets<P: Into<String>>(mut self, prefixes: impl IntoIterator<Item = P>) -> Self {
+        self.layer_filter
+            .deny
+            .extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Enables tail sampling in the worker, only exporting the traces selected by the given
+    /// [`TailSampler`].
+    pub fn tail_sampling(mut self, tail_sampler: TailSampler) -> Self {
+        self.tail_sampler = Some(tail_sampler);
+        self
+    }
+
+    /// Enables reporting metrics about the exporter itself to the `/v1/metrics` endpoint at
+    /// the given interval.
+    ///
+    /// The metrics are the number of spans enqueued, exported and dropped, the number of
+    /// failed export requests, and the number of spans waiting to be exported. They share the
+    /// resource attributes of the traces. The interval is shared with
+    /// [`Builder::span_metrics`], the one set last applies.
+    pub fn self_metrics(mut self, interval: Duration) -> Self {
+        self.metrics_config(interval).self_metrics = true;
+        self
+    }
+
+    /// Enables aggregating completed spans into histograms of their duration, which are
+    /// reported to the `/v1/metrics` endpoint at the given interval.
+    ///
+    /// The histograms are exported as `traces.span.metrics.duration` in milliseconds, with
+    /// the `service.name`, `span.name`, `span.kind` and `status.code` attributes, giving
+    /// request rate, error rate and duration metrics without a separate metrics pipeline.
+    /// Only spans of sampled traces are counted, but they are counted before tail sampling.
+    /// The interval is shared with [`Builder::self_metrics`], the one set last applies.
+    ///
+    /// At most 2000 combinations of these attributes are reported, as span names containing
+    /// ids would grow the histograms without bound. Spans of further combinations are
+    /// aggregated into a single histogram with the `otel.metric.overflow` attribute set to
+    /// true.
+    pub fn span_metrics(mut self, interval: Duration) -> Self {
+        self.metrics_config(interval).span_metrics = true;
+        self
+    }
+
+    /// Sets the handler called with the errors encountered while exporting, such as failed
+    /// requests, invalid responses and dropped spans.
+    ///
+    /// Defaults to a `tracing` warning with the [`crate::DIAGNOSTICS_TARGET`] target. Errors
+    /// raised while the handler runs on the same thread are discarded, preventing recursion.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().on_error(|err| eprintln!("OTLP: {err}"));
+    /// ```
+    pub fn on_error(mut self, handler: impl Fn(OtlpError) + Send + Sync + 'static) -> Self {
+        self.error_handler = ErrorHandler::new(handler);
+        self
+    }
+
+    /// Sets a callback called by the worker after each attempt to export a batch of spans,
+    /// e.g. to feed the health of the exporter into a monitoring pipeline.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().on_batch_result(|result| {
+    ///     if let Some(err) = result.error {
+    ///         eprintln!("exporting {} spans failed: {err}", result.spans);
+    ///     }
+    /// });
+    /// ```
+    pub fn on_batch_result(
+        mut self,
+        callback: impl Fn(BatchResult) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_batch_result = Some(Arc::new(callback));
+        self
+    }
+
+    /// Buffers spans on disk when the endpoint is unreachable for longer than the in-memory
+    /// buffer of failed exports can hold, instead of dropping them.
+    ///
+    /// Batches are written to `dir` as encoded export requests, using at most `max_bytes` in
+    /// total, and are replayed oldest first once an export succeeds again. Batches left by a
+    /// previous process are replayed as well, so the directory should not be shared between
+    /// processes running at the same time.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().disk_buffer("/var/spool/traces", 100 * 1024 * 1024);
+    /// ```
+    pub fn disk_buffer(mut self, dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        self.disk_buffer = Some((dir.into(), max_bytes));
+        self
+    }
+
+    /// Gives up on a batch once it failed to export `max_attempts` times, or `max_duration`
+    /// after its first attempt failed. Its spans are then moved to the
+    /// [`Builder::disk_buffer`], if set and not full, and dropped otherwise, accounting for
+    /// them in [`crate::OtlpStats::spans_dropped`]. By default batches are retried until
+    /// they are exported, or moved out of memory once too many spans are outstanding.
+    ///
+    /// Failed batches are retried on the send interval, ahead of the spans finished since.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().retry_budget(5, Duration::from_secs(60));
+    /// ```
+    pub fn retry_budget(mut self, max_attempts: u32, max_duration: Duration) -> Self {
+        self.retry_budget = Some((max_attempts.max(1), max_duration));
+        self
+    }
+
+    /// Suspends exports once `failures` exports failed in a row, so an unreachable endpoint
+    /// is not retried on every send interval. Disabled by default.
+    ///
+    /// While exports are suspended, spans are written to the [`Builder::disk_buffer`] if
+    /// there is one and dropped otherwise, rather than held in memory. Every
+    /// `probe_interval` the next batch is exported as a probe, and exports resume if it
+    /// succeeds. Suspending exports is reported as [`crate::OtlpError::CircuitOpen`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::time::Duration;
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().circuit_breaker(5, Duration::from_secs(30));
+    /// ```
+    pub fn circuit_breaker(mut self, failures: u32, probe_interval: Duration) -> Self {
+        self.circuit_breaker = Some((failures, probe_interval));
+        self
+    }
+
+    /// Sends the spans matching `route` to its own endpoint, with its own headers, rather
+    /// than to the endpoint given to the `build` methods. Routes are tried in the order they
+    /// were added, and spans matching none are sent to the endpoint of the `build` method.
+    ///
+    /// Routes only apply when exporting over HTTP, and batches only hold spans of one route.
+    /// Routed spans are not written to the [`Builder::disk_buffer`], which is replayed to the
+    /// endpoint of the `build` method, and the [`Builder::circuit_breaker`] counts the
+    /// failures of all routes together. Invalid endpoints and headers of routes are returned
+    /// by the `build` methods.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::{Builder, Route};
+    /// let mut builder = Builder::new();
+    /// for tenant in ["acme", "globex"] {
+    ///     let endpoint = format!("https://{tenant}.traces.example.com");
+    ///     builder = builder.route(Route::attribute(endpoint.as_str(), "tenant.id", tenant));
+    /// }
+    /// ```
+    pub fn route(mut self, route: Route) -> Self {
+        let traces = route.endpoint.and_then(|endpoint| {
+            let traces = signal_url(&endpoint, "v1/traces")?;
+            if !["http", "https"].contains(&traces.scheme()) {
+                return Err(OtlpBuildError::UnsupportedScheme {
+                    url: traces.to_string(),
+                });
+            }
+            Ok(traces)
+        });
+        let headers = self.convert_headers(route.headers);
+        match traces {
+            Ok(traces) => self.routes.push(Arc::new(ResolvedRoute {
+                traces,
+                headers,
+                rule: route.rule,
+            })),
+            Err(err) => self.invalid(err),
+        }
+        self
+    }
+
+    /// Converts headers given to the builder, leaving out and remembering invalid ones.
+    fn convert_headers<K: AsRef<str>, V: AsRef<[u8]>>(
+        &mut self,
+        headers: impl IntoIterator<Item = (K, V)>,
+    ) -> Vec<(String, String)> {
+        headers
+            .into_iter()
+            .filter_map(
+                |(name, value)| match validate_header(name.as_ref(), value.as_ref()) {
+                    // Validated to be printable ASCII
+                    Ok(()) => Some((
+                        name.as_ref().to_string(),
+                        String::from_utf8_lossy(value.as_ref()).into_owned(),
+                    )),
+                    Err(err) => {
+                        self.invalid(err);
+                        None
+                    }
+                },
+            )
+            .collect()
+    }
+
+    /// Returns the value of a header if it is valid, remembers the error otherwise.
+    fn check_header(&mut self, name: &str, value: String) -> Option<String> {
+        match validate_header(name, value.as_bytes()) {
+            Ok(()) => Some(value),
+            Err(err) => {
+                self.invalid(err);
+                None
+            }
+        }
+    }
+
+    /// Remembers the first invalid setting, returned by the `build` methods.
+    fn invalid(&mut self, err: OtlpBuildError) {
+        self.invalid.get_or_insert(err);
+    }
+
+    fn metrics_config(&mut self, interval: Duration) -> &mut MetricsConfig {
+        let metrics = self.metrics.get_or_insert(MetricsConfig {
+            interval,
+            self_metrics: false,
+            span_metrics: false,
+        });
+        metrics.interval = interval;
+        metrics
+    }
+
     /// Builds a [`TelemetryLayer`] based on [`Otlp`] the settings provided.
     ///
-    /// The `endpoint` given should be an HTTP URL.
+    /// The `endpoint` given should be an HTTP URL, as a `&str` or a [`Url`]. Spans and
+    /// metrics are sent to `v1/traces` and `v1/metrics` below its path, so collectors behind
+    /// a path prefix such as `https://example.com/otel/` are reachable too.
     ///
     /// # Examples
     /// ```
+    /// # use tracing_otlp::Builder;
+    /// # use url::Url;
     /// Builder::new().build("http://127.0.0.1:4318");
+    ///
+    /// let endpoint = Url::parse("http://127.0.0.1:4318").unwrap();
+    /// Builder::new().build(endpoint);
     /// ```
-    pub fn build(
+    pub fn build<U>(
         self,
-        endpoint: &str,
-    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, url::ParseError> {
-        let rng = Mutex::new(StdRng::from_entropy());
-        Ok(TelemetryLayer::new(
-            "",
-            Otlp::new(
-                endpoint,
-                self.send_interval,
-                self.resource_attributes,
-                self.headers,
-            )?,
-            move |_| SpanId(rng.lock().unwrap().gen()),
+        endpoint: U,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        let (traces, metrics) = self.endpoints(endpoint, &["http", "https"])?;
+        let destination = Destination::Http { traces, metrics };
+        self.build_with(destination, WorkerMode::Thread)
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Otlp`] the settings provided, running its
+    /// worker as a task on the current tokio runtime, with an async HTTP client, instead of
+    /// on a dedicated thread.
+    ///
+    /// The `endpoint` given should be a plain `http` URL, as should those of the
+    /// [`Builder::route`]s, as the async client does not support TLS.
+    /// [`Builder::max_concurrent_exports`] and [`Builder::disk_buffer`] do not apply in this
+    /// mode. The other destinations, such as [`Builder::build_exporter`], export with
+    /// blocking I/O and always run on a thread.
+    ///
+    /// Fails with [`OtlpBuildError::NoRuntime`] if called outside of a tokio runtime.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// Builder::new().build_tokio("http://127.0.0.1:4318");
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn build_tokio<U>(
+        mut self,
+        endpoint: U,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        // Both rely on blocking I/O, which would stall the runtime
+        self.max_concurrent_exports = 1;
+        self.disk_buffer = None;
+
+        let handle =
+            tokio::runtime::Handle::try_current().map_err(|_| OtlpBuildError::NoRuntime)?;
+        let (traces, metrics) = self.endpoints(endpoint, &["http"])?;
+        if let Some(route) = self
+            .routes
+            .iter()
+            .find(|route| route.traces.scheme() != "http")
+        {
+            return Err(OtlpBuildError::UnsupportedScheme {
+                url: route.traces.to_string(),
+            });
+        }
+        let destination = Destination::Http { traces, metrics };
+        self.build_with(destination, WorkerMode::Tokio(handle))
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Otlp`] the settings provided, for use in the
+    /// browser on `wasm32-unknown-unknown`.
+    ///
+    /// The worker runs on the JS event loop, sending spans with `fetch` and flushing them on
+    /// a `setTimeout` timer, so this must be called from a thread with an event loop, such
+    /// as the main thread of a page or a web worker. The endpoint has to allow the origin
+    /// of the page with CORS. [`Builder::max_concurrent_exports`] and
+    /// [`Builder::disk_buffer`] do not apply in this mode.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use tracing_otlp::Builder;
+    /// Builder::new().build_wasm("https://collector.example.com");
+    /// ```
+    #[cfg(feature = "wasm")]
+    pub fn build_wasm<U>(
+        mut self,
+        endpoint: U,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        self.max_concurrent_exports = 1;
+        self.disk_buffer = None;
+
+        let (traces, metrics) = self.endpoints(endpoint, &["http", "https"])?;
+        let destination = Destination::Http { traces, metrics };
+        self.build_with(destination, WorkerMode::Wasm)
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Otlp`] the settings provided, writing spans to
+    /// a file in the OTLP JSON file format instead of sending them to an endpoint.
+    ///
+    /// The HTTP headers and metrics settings do not apply to files.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::{Builder, OtlpFile};
+    /// Builder::new().build_file(OtlpFile::new("traces.jsonl").max_size(10 * 1024 * 1024));
+    /// ```
+    #[cfg(feature = "file")]
+    pub fn build_file(
+        self,
+        file: OtlpFile,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError> {
+        self.build_with(Destination::File(FileWriter::new(file)), WorkerMode::Thread)
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Otlp`] the settings provided, passing each
+    /// export request to `export` instead of sending it to an endpoint, e.g. to ship the
+    /// batches over a message bus, or to capture them in tests.
+    ///
+    /// `export` is called on the worker thread. Requests it fails to export are retried like
+    /// failed HTTP exports. The HTTP and metrics settings do not apply.
+    ///
+    /// # Examples
+    /// ```
+    /// # use prost::Message;
+    /// # use tracing_otlp::Builder;
+    /// # fn publish(topic: &str, payload: Vec<u8>) -> std::io::Result<()> { Ok(()) }
+    /// Builder::new().build_exporter(|request| {
+    ///     publish("traces", request.encode_to_vec())?;
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn build_exporter(
+        self,
+        export: impl Fn(&ExportTraceServiceRequest) -> Result<(), Box<dyn Error + Send + Sync>>
+            + Send
+            + 'static,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError> {
+        self.build_with(Destination::Exporter(Box::new(export)), WorkerMode::Thread)
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Otlp`] the settings provided, encoding each
+    /// export request with `encoder` and sending it with `transport` instead of the built-in
+    /// HTTP exporter, e.g. to send OTLP/JSON or to use another protocol.
+    ///
+    /// The transport is called on the worker thread. The HTTP and metrics settings do not
+    /// apply.
+    ///
+    /// # Examples
+    /// ```
+    /// # use std::error::Error;
+    /// # use tracing_otlp::{Builder, ProtobufEncoder, Transport};
+    /// # struct Queue;
+    /// # impl Transport for Queue {
+    /// #     fn send(&mut self, _: &str, _: &[u8]) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    /// #         Ok(0)
+    /// #     }
+    /// # }
+    /// Builder::new().build_transport(ProtobufEncoder, Queue);
+    /// ```
+    pub fn build_transport(
+        self,
+        encoder: impl Encoder,
+        transport: impl Transport,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError> {
+        let destination = Destination::Transport(Box::new(encoder), Box::new(transport));
+        self.build_with(destination, WorkerMode::Thread)
+    }
+
+    /// The traces and metrics URLs for the endpoint given to the `build` methods, which must
+    /// use one of the given schemes.
+    fn endpoints<U>(&self, endpoint: U, schemes: &[&str]) -> Result<(Url, Url), OtlpBuildError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        let endpoint = endpoint.try_into().map_err(Into::into)?;
+        let url = |explicit: &Option<Url>, path| {
+            let url = match explicit {
+                Some(url) => url.clone(),
+                None => signal_url(&endpoint, path)?,
+            };
+            if !schemes.contains(&url.scheme()) {
+                return Err(OtlpBuildError::UnsupportedScheme {
+                    url: url.to_string(),
+                });
+            }
+            Ok(url)
+        };
+        Ok((
+            url(&self.traces_endpoint, "v1/traces")?,
+            url(&self.metrics_endpoint, "v1/metrics")?,
         ))
     }
+
+    /// Sends an empty export request to the endpoint with the configured headers, so
+    /// unreachable endpoints and rejected credentials are noticed at startup rather than
+    /// when exporting.
+    ///
+    /// This blocks until the endpoint responds, at most for [`Builder::request_timeout`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use tracing_otlp::{Builder, ConnectionError};
+    /// let builder = Builder::new().bearer_token("my-token");
+    /// match builder.validate_connection("https://collector.example.com") {
+    ///     Ok(()) => {}
+    ///     Err(ConnectionError::Unauthorized { .. }) => panic!("invalid collector token"),
+    ///     Err(err) => eprintln!("collector unreachable, exporting anyway: {err}"),
+    /// }
+    /// let layer = builder.build("https://collector.example.com");
+    /// ```
+    pub fn validate_connection<U>(&self, endpoint: U) -> Result<(), ConnectionError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        if let Some(err) = &self.invalid {
+            return Err(ConnectionError::InvalidConfig(err.clone()));
+        }
+        let (traces, _) = self
+            .endpoints(endpoint, &["http", "https"])
+            .map_err(ConnectionError::InvalidConfig)?;
+        let client = HttpClient::new(
+            self.agent.clone(),
+            self.signal_headers(),
+            self.error_handler.clone(),
+        );
+        client.check_traces(&traces)
+    }
+
+    /// The headers of each signal, with the authorization and user agent set.
+    fn signal_headers(&self) -> SignalHeaders {
+        let common = [("User-Agent".to_string(), self.user_agent.clone())];
+        let common = merge_headers(&common, self.headers.clone());
+        let authorization = self
+            .authorization
+            .clone()
+            .map(|value| ("Authorization".to_string(), value));
+        let common = merge_headers(&common, authorization.into_iter().collect());
+        SignalHeaders {
+            traces: merge_headers(&common, self.traces_headers.clone()),
+            metrics: merge_headers(&common, self.metrics_headers.clone()),
+            provider: self.header_provider.clone(),
+            runtime: Default::default(),
+        }
+    }
+
+    fn build_with(
+        self,
+        destination: Destination,
+        mode: WorkerMode,
+    ) -> Result<TelemetryLayer<Otlp, SpanId, TraceId>, OtlpBuildError> {
+        if let Some(err) = self.invalid {
+            return Err(err);
+        }
+        let headers = self.signal_headers();
+        let otlp = Otlp::new(
+            destination,
+            WorkerConfig {
+                send_interval: self.send_interval,
+                send_interval_jitter: self.send_interval_jitter,
+                max_queue_size: self.max_queue_size,
+                level_aware_shedding: self.level_aware_shedding,
+                wait_for_export: self
+                    .simple
+                    .then(|| self.agent.request_timeout.unwrap_or(SIMPLE_EXPORT_TIMEOUT)),
+                heartbeat: self.heartbeat,
+                max_events_per_span: self.max_events_per_span,
+                implicit_traces: self.implicit_traces,
+                default_trace: self.default_trace,
+                layer_filter: self.layer_filter,
+                max_export_batch_size: self.max_export_batch_size,
+                max_export_batch_bytes: self.max_export_batch_bytes,
+                max_concurrent_exports: self.max_concurrent_exports,
+                resource_attributes: self.resource_attributes,
+                headers,
+                agent: self.agent,
+                tail_sampler: self.tail_sampler,
+                metrics: self.metrics,
+                error_handler: self.error_handler,
+                on_batch_result: self.on_batch_result,
+                disk_buffer: self.disk_buffer,
+                circuit_breaker: self.circuit_breaker,
+                retry_budget: self.retry_budget,
+                routes: self.routes,
+                changes: Default::default(),
+            },
+            self.attributes,
+            self.processors,
+            self.id_generator,
+            self.sampling,
+            mode,
+        )?;
+        Ok(otlp.layer())
+    }
+}
+
+/// Appends the path of a signal to the path of `base`, keeping any prefix it has.
+pub(crate) fn signal_url(base: &Url, path: &str) -> Result<Url, url::ParseError> {
+    let mut url = base.clone();
+    url.path_segments_mut()
+        .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+        .pop_if_empty()
+        .extend(path.split('/'));
+    Ok(url)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_signal_paths_to_the_endpoint_path() {
+        let traces = |endpoint: &str| {
+            signal_url(&Url::parse(endpoint).unwrap(), "v1/traces")
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(traces("http://host:4318"), "http://host:4318/v1/traces");
+        assert_eq!(traces("https://host/otel/"), "https://host/otel/v1/traces");
+        assert_eq!(traces("https://host/otel"), "https://host/otel/v1/traces");
+    }
+
+    #[test]
+    fn validates_connection() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for status in ["200 OK", "401 Unauthorized", "404 Not Found"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = stream.read(&mut [0; 4096]);
+                let res =
+                    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                stream.write_all(res.as_bytes()).unwrap();
+            }
+        });
+
+        let builder = Builder::new();
+        builder.validate_connection(endpoint.as_str()).unwrap();
+        assert!(matches!(
+            builder.validate_connection(endpoint.as_str()),
+            Err(ConnectionError::Unauthorized { status: 401 })
+        ));
+        assert!(matches!(
+            builder.validate_connection(endpoint.as_str()),
+            Err(ConnectionError::NotFound { .. })
+        ));
+        assert!(matches!(
+            builder.validate_connection("not a url"),
+            Err(ConnectionError::InvalidConfig(OtlpBuildError::InvalidUrl(
+                _
+            )))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_configuration() {
+        let err = |builder: Builder, endpoint: &str| builder.build(endpoint).err().unwrap();
+        assert!(matches!(
+            err(Builder::new(), "ftp://host"),
+            OtlpBuildError::UnsupportedScheme { .. }
+        ));
+        assert!(matches!(
+            err(
+                Builder::new().http_headers([("X-Key", "a\nb")]),
+                "http://host"
+            ),
+            OtlpBuildError::InvalidHeader { .. }
+        ));
+        assert!(matches!(
+            err(Builder::new().http_headers([("X Key", "a")]), "http://host"),
+            OtlpBuildError::InvalidHeader { .. }
+        ));
+        assert!(matches!(
+            err(
+                Builder::new().bearer_token("a\r\nX-Injected: b"),
+                "http://host"
+            ),
+            OtlpBuildError::InvalidHeader { .. }
+        ));
+        assert!(matches!(
+            err(Builder::new().traces_endpoint("not a url"), "http://host"),
+            OtlpBuildError::InvalidUrl(_)
+        ));
+        assert!(matches!(
+            err(Builder::new().max_queue_size(0), "http://host"),
+            OtlpBuildError::InvalidSetting {
+                setting: "max_queue_size",
+                ..
+            }
+        ));
+        assert!(matches!(
+            err(Builder::new().max_concurrent_exports(0), "http://host"),
+            OtlpBuildError::InvalidSetting {
+                setting: "max_concurrent_exports",
+                ..
+            }
+        ));
+        for pem in [
+            "",
+            "-----BEGIN CERTIFICATE-----\nnot base64\n-----END CERTIFICATE-----\n",
+        ] {
+            assert!(matches!(
+                err(Builder::new().tls_root_certificates(pem), "https://host"),
+                OtlpBuildError::Tls { .. }
+            ));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn adds_resource_attributes_from_json() {
+        use crate::prost::common::v1::AnyValue;
+
+        let json = serde_json::json!({
+            "region": "eu-west-1",
+            "replicas": 3,
+            "zone": null,
+            "labels": { "team": "checkout", "owner": null },
+        });
+        let builder = Builder::new().resource_attributes_json(json.as_object().unwrap().clone());
+
+        let keys: Vec<_> = builder.resource_attributes.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, ["labels", "region", "replicas"]);
+        let labels = AnyValue {
+            value: Some(builder.resource_attributes[0].1.clone()),
+        };
+        assert_eq!(serde_json::Value::from(&labels), json["labels"]);
+        assert_eq!(builder.resource_attributes[2].1, Value::IntValue(3));
+    }
+
+    #[test]
+    fn trusts_tls_root_certificates() {
+        // Self-signed certificate of `collector.test`
+        const CA: &str = "\
+-----BEGIN CERTIFICATE-----\n\
+MIIBiTCCAS+gAwIBAgIUfeL9C1uV0acVNcibWMRugXPDYG4wCgYIKoZIzj0EAwIw\n\
+GTEXMBUGA1UEAwwOY29sbGVjdG9yLnRlc3QwIBcNMjYxMDE0MTExMzE4WhgPMjEy\n\
+NjA5MjAxMTEzMThaMBkxFzAVBgNVBAMMDmNvbGxlY3Rvci50ZXN0MFkwEwYHKoZI\n\
+zj0CAQYIKoZIzj0DAQcDQgAEGvURn+8/E3NozkWjxkfH5UqUjuYcPtAXu34rTgbA\n\
+ZaWYvoJk9qTgjaXh6Al8/INfoUivPIKobQa392lB44CG5aNTMFEwHQYDVR0OBBYE\n\
+FNJFwoFZ23bBrO8N83hD3GizeNwqMB8GA1UdIwQYMBaAFNJFwoFZ23bBrO8N83hD\n\
+3GizeNwqMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIhAL5t4t5z\n\
+Ry+315e1F/fjlsb1CtmVg09hKnYc4ptq0bBTAiB6BtK7z5oX+jUrgZwXsDBUWmk+\n\
+2xtYuFXSPS6Xf7fotw==\n\
+-----END CERTIFICATE-----\n";
+
+        let builder = Builder::new().tls_root_certificates(CA);
+        assert!(builder.invalid.is_none());
+        assert!(builder.agent.tls_config.is_some());
+    }
+
+    #[test]
+    fn accepts_typed_inputs() {
+        use http::{header::AUTHORIZATION, HeaderValue};
+
+        let builder = Builder::new()
+            .http_headers([(AUTHORIZATION, HeaderValue::from_static("Bearer token"))])
+            .traces_endpoint(Url::parse("https://traces.example.com/api/traces").unwrap());
+        assert_eq!(
+            builder.headers,
+            [("authorization".to_string(), "Bearer token".to_string())]
+        );
+        let (traces, metrics) = builder
+            .endpoints(
+                Url::parse("https://ingest.example.com").unwrap(),
+                &["https"],
+            )
+            .unwrap();
+        assert_eq!(traces.as_str(), "https://traces.example.com/api/traces");
+        assert_eq!(metrics.as_str(), "https://ingest.example.com/v1/metrics");
+    }
+
+    #[test]
+    fn encodes_basic_auth() {
+        let builder = Builder::new().basic_auth("Aladdin", "open sesame");
+        assert_eq!(
+            builder.authorization.as_deref(),
+            Some("Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==")
+        );
+    }
 }
@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::ThreadId,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Map, Value as Json};
+use tracing_distributed::{Event, Span, Telemetry, TelemetryLayer};
+
+use crate::{
+    error::ErrorHandler, prost::common::v1::KeyValue, IdGenerator, OtlpError, RandomIdGenerator,
+    SpanId, TraceId, Visitor,
+};
+
+/// [`Telemetry`] implementation writing spans in the Chrome `trace_event` JSON format, which
+/// can be opened in `chrome://tracing` or the Perfetto UI.
+///
+/// Spans are written as complete events on a row per thread they closed on, with their ids
+/// and attributes as arguments. Events are written as instant events. The JSON array is
+/// left open, which both viewers accept, so the file is valid up until the process exits.
+///
+/// # Examples
+/// ```no_run
+/// # use tracing_subscriber::layer::SubscriberExt;
+/// # use tracing_otlp::ChromeTraceTelemetry;
+/// let telemetry = ChromeTraceTelemetry::create("trace.json").unwrap();
+/// let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+/// ```
+pub struct ChromeTraceTelemetry {
+    output: Mutex<Output>,
+    error_handler: ErrorHandler,
+}
+
+struct Output {
+    writer: Box<dyn Write + Send>,
+    started: bool,
+    threads: HashMap<ThreadId, u64>,
+}
+
+impl ChromeTraceTelemetry {
+    /// Creates a [`ChromeTraceTelemetry`] writing to a new file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::with_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Creates a [`ChromeTraceTelemetry`] writing to the given writer.
+    pub fn with_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Mutex::new(Output {
+                writer: Box::new(writer),
+                started: false,
+                threads: HashMap::new(),
+            }),
+            error_handler: Default::default(),
+        }
+    }
+
+    /// Sets the handler called when the output cannot be written to, as
+    /// [`crate::Builder::on_error`] does for OTLP.
+    pub fn on_error(mut self, handler: impl Fn(OtlpError) + Send + Sync + 'static) -> Self {
+        self.error_handler = ErrorHandler::new(handler);
+        self
+    }
+
+    /// Builds a [`TelemetryLayer`] based on this [`ChromeTraceTelemetry`], using random span
+    /// ids.
+    pub fn layer(self) -> TelemetryLayer<Self, SpanId, TraceId> {
+        let id_generator = Arc::new(RandomIdGenerator::default());
+        TelemetryLayer::new("", self, move |_| id_generator.new_span_id())
+    }
+
+    fn write(&self, events: impl FnOnce(u64) -> Vec<Json>) {
+        let mut output = self.output.lock().expect("output lock poisoned");
+        let result = output.write(events);
+        if let Err(err) = result {
+            self.error_handler.handle(OtlpError::Output(err));
+        }
+    }
+}
+
+impl Output {
+    fn write(&mut self, events: impl FnOnce(u64) -> Vec<Json>) -> io::Result<()> {
+        let thread = std::thread::current();
+        let next_tid = self.threads.len() as u64 + 1;
+        let mut events_to_write = Vec::new();
+        let tid = *self.threads.entry(thread.id()).or_insert_with(|| {
+            events_to_write.push(json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": std::process::id(),
+                "tid": next_tid,
+                "args": { "name": thread.name().map_or_else(|| format!("{:?}", thread.id()), str::to_string) },
+            }));
+            next_tid
+        });
+        events_to_write.extend(events(tid));
+
+        for event in events_to_write {
+            let separator = if self.started { ",\n" } else { "[\n" };
+            self.started = true;
+            write!(self.writer, "{separator}{event}")?;
+        }
+        self.writer.flush()
+    }
+}
+
+impl Telemetry for ChromeTraceTelemetry {
+    type Visitor = Visitor;
+
+    type TraceId = TraceId;
+
+    type SpanId = SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        Default::default()
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        self.write(|tid| {
+            let mut args = attributes_to_json(&span.values.0);
+            args.insert("trace_id".to_string(), json!(span.trace_id.to_string()));
+            args.insert("span_id".to_string(), json!(span.id.to_string()));
+            if let Some(parent_id) = span.parent_id {
+                args.insert("parent_id".to_string(), json!(parent_id.to_string()));
+            }
+
+            let start = micros(span.initialized_at);
+            std::iter::once(json!({
+                "name": span.name,
+                "cat": span.meta.target(),
+                "ph": "X",
+                "ts": start,
+                "dur": micros(span.completed_at) - start,
+                "pid": std::process::id(),
+                "tid": tid,
+                "args": args,
+            }))
+            .chain(events.iter().map(|event| instant_event(event, tid)))
+            .collect()
+        });
+    }
+
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        self.write(|tid| vec![instant_event(&event, tid)]);
+    }
+}
+
+fn instant_event(event: &Event<Visitor, SpanId, TraceId>, tid: u64) -> Json {
+    let mut args = attributes_to_json(&event.values.0);
+    args.insert("level".to_string(), json!(event.meta.level().as_str()));
+    json!({
+        "name": event.meta.name(),
+        "cat": event.meta.target(),
+        "ph": "i",
+        "s": "t",
+        "ts": micros(event.initialized_at),
+        "pid": std::process::id(),
+        "tid": tid,
+        "args": args,
+    })
+}
+
+fn micros(t: SystemTime) -> f64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as f64 / 1000.0
+}
+
+fn attributes_to_json(attributes: &[KeyValue]) -> Map<String, Json> {
+    attributes
+        .iter()
+        .map(|kv| {
+            (
+                kv.key.clone(),
+                kv.value.as_ref().map_or(Json::Null, Into::into),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_trace_events() {
+        let writer = SharedWriter::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(ChromeTraceTelemetry::with_writer(writer.clone()).layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("request", http.method = "GET");
+            let _root = root.enter();
+            crate::register_dist_tracing_root(TraceId(1), None).unwrap();
+            tracing::info!("handling");
+        });
+
+        // The array is left open
+        let mut output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        output.push(']');
+        let events: Vec<Json> = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["ph"], "M");
+        assert_eq!(events[1]["ph"], "X");
+        assert_eq!(events[1]["name"], "request");
+        assert_eq!(events[1]["tid"], events[0]["tid"]);
+        assert_eq!(events[1]["args"]["http.method"], "GET");
+        assert_eq!(
+            events[1]["args"]["trace_id"],
+            "00000000000000000000000000000001"
+        );
+        assert!(events[1]["dur"].as_f64().unwrap() >= 0.0);
+        assert_eq!(events[2]["ph"], "i");
+        assert_eq!(events[2]["args"]["message"], "handling");
+        assert_eq!(events[2]["args"]["level"], "INFO");
+    }
+}
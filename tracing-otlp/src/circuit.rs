@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use crate::time::Instant;
+
+/// Suspends exports after consecutive failures, see [`crate::Builder::circuit_breaker`].
+///
+/// While the circuit is open nothing is sent. Once the probe interval is up the next export
+/// is let through as a probe: if it succeeds the circuit closes, otherwise it stays open for
+/// another interval.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    failure_threshold: u32,
+    probe_interval: Duration,
+    /// Exports which failed since the last successful one
+    failures: u32,
+    /// Until when exports are suspended, if the circuit is open
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(failure_threshold: u32, probe_interval: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            probe_interval,
+            failures: 0,
+            open_until: None,
+        }
+    }
+
+    /// Whether exports are suspended, as the circuit is open and the next probe is not due.
+    pub(crate) fn is_open(&self) -> bool {
+        self.open_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// The time between probes while the circuit is open.
+    pub(crate) fn probe_interval(&self) -> Duration {
+        self.probe_interval
+    }
+
+    /// Accounts for a successful export, closing the circuit.
+    pub(crate) fn succeeded(&mut self) {
+        self.failures = 0;
+        self.open_until = None;
+    }
+
+    /// Accounts for a failed export, returning the number of consecutive failures if this
+    /// opened the circuit.
+    pub(crate) fn failed(&mut self) -> Option<u32> {
+        self.failures = self.failures.saturating_add(1);
+        if self.failures < self.failure_threshold {
+            return None;
+        }
+        let was_closed = self.open_until.is_none();
+        self.open_until = Some(Instant::now() + self.probe_interval);
+        was_closed.then_some(self.failures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let mut circuit = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert_eq!(circuit.failed(), None);
+        circuit.succeeded();
+        assert_eq!(circuit.failed(), None);
+        assert_eq!(circuit.failed(), None);
+        assert!(!circuit.is_open());
+
+        assert_eq!(circuit.failed(), Some(3));
+        assert!(circuit.is_open());
+        // Only opening the circuit is reported, not the failed probes
+        assert_eq!(circuit.failed(), None);
+        assert!(circuit.is_open());
+
+        circuit.succeeded();
+        assert!(!circuit.is_open());
+    }
+
+    #[test]
+    fn lets_probes_through_after_the_interval() {
+        let mut circuit = CircuitBreaker::new(1, Duration::ZERO);
+        assert_eq!(circuit.failed(), Some(1));
+        assert!(!circuit.is_open());
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Compression, Sampler};
+
+/// Exporter settings which can be loaded from the configuration file of a service, with any
+/// `serde` format such as YAML or TOML. Apply them with [`crate::Builder::from_config`].
+///
+/// All fields are optional, those which are missing keep the defaults of the [`crate::Builder`].
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{Builder, OtlpConfig};
+/// let config: OtlpConfig = serde_json::from_str(
+///     r#"{
+///         "endpoint": "https://collector.example.com",
+///         "headers": { "X-Tenant": "checkout" },
+///         "service_name": "checkout",
+///         "send_interval_ms": 500,
+///         "sampler": { "trace_id_ratio": 0.25 }
+///     }"#,
+/// )
+/// .unwrap();
+/// let layer = Builder::from_config(&config).build(config.endpoint.as_str());
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct OtlpConfig {
+    /// The endpoint to pass to [`crate::Builder::build`], defaults to `http://localhost:4318`.
+    pub endpoint: String,
+    /// See [`crate::Builder::traces_endpoint`].
+    pub traces_endpoint: Option<String>,
+    /// See [`crate::Builder::metrics_endpoint`].
+    pub metrics_endpoint: Option<String>,
+    /// See [`crate::Builder::http_headers`].
+    pub headers: BTreeMap<String, String>,
+    /// See [`crate::Builder::service_name`].
+    pub service_name: Option<String>,
+    /// See [`crate::Builder::service_version`].
+    pub service_version: Option<String>,
+    /// Resource attributes with string values, as in `OTEL_RESOURCE_ATTRIBUTES`.
+    pub resource_attributes: BTreeMap<String, String>,
+    /// Attributes with string values added to every span, see [`crate::StaticAttributes`].
+    pub span_attributes: BTreeMap<String, String>,
+    /// See [`crate::Builder::send_interval`], in milliseconds.
+    pub send_interval_ms: Option<u64>,
+    /// See [`crate::Builder::send_interval_jitter`], in milliseconds.
+    pub send_interval_jitter_ms: Option<u64>,
+    /// See [`crate::Builder::max_queue_size`].
+    pub max_queue_size: Option<usize>,
+    /// See [`crate::Builder::level_aware_shedding`].
+    pub level_aware_shedding: Option<bool>,
+    /// See [`crate::Builder::max_export_batch_size`].
+    pub max_export_batch_size: Option<usize>,
+    /// See [`crate::Builder::max_export_batch_bytes`].
+    pub max_export_batch_bytes: Option<usize>,
+    /// See [`crate::Builder::max_concurrent_exports`].
+    pub max_concurrent_exports: Option<usize>,
+    /// See [`crate::Builder::request_timeout`], in milliseconds.
+    pub request_timeout_ms: Option<u64>,
+    /// See [`crate::Builder::compression`].
+    pub compression: Option<Compression>,
+    /// See [`crate::Builder::compression_threshold`].
+    pub compression_threshold: Option<usize>,
+    /// See [`crate::Builder::retry_budget`], unlimited if only the duration is set.
+    pub max_export_attempts: Option<u32>,
+    /// See [`crate::Builder::retry_budget`], in milliseconds, unlimited if only the number of
+    /// attempts is set.
+    pub max_retry_duration_ms: Option<u64>,
+    /// See [`crate::Builder::circuit_breaker`], which is enabled if this is set.
+    pub circuit_breaker_failures: Option<u32>,
+    /// See [`crate::Builder::circuit_breaker`], in milliseconds, defaults to 30 seconds.
+    pub circuit_breaker_probe_interval_ms: Option<u64>,
+    /// See [`crate::Builder::sampler`].
+    pub sampler: Option<Sampler>,
+    /// Whether to use [`crate::Builder::simple`], false by default.
+    pub simple: bool,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318".to_string(),
+            traces_endpoint: None,
+            metrics_endpoint: None,
+            headers: Default::default(),
+            service_name: None,
+            service_version: None,
+            resource_attributes: Default::default(),
+            span_attributes: Default::default(),
+            send_interval_ms: None,
+            send_interval_jitter_ms: None,
+            max_queue_size: None,
+            level_aware_shedding: None,
+            max_export_batch_size: None,
+            max_export_batch_bytes: None,
+            max_concurrent_exports: None,
+            request_timeout_ms: None,
+            compression: None,
+            compression_threshold: None,
+            max_export_attempts: None,
+            max_retry_duration_ms: None,
+            circuit_breaker_failures: None,
+            circuit_breaker_probe_interval_ms: None,
+            sampler: None,
+            simple: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deserializes_partial_config() {
+        let config: OtlpConfig = serde_json::from_str(
+            r#"{
+                "headers": { "X-Tenant": "checkout" },
+                "max_queue_size": 512,
+                "sampler": "always_off"
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.endpoint, "http://localhost:4318");
+        assert_eq!(config.headers["X-Tenant"], "checkout");
+        assert_eq!(config.max_queue_size, Some(512));
+        assert_eq!(config.sampler, Some(Sampler::AlwaysOff));
+
+        let config: OtlpConfig =
+            serde_json::from_str(r#"{ "sampler": { "trace_id_ratio": 0.5 } }"#).unwrap();
+        assert_eq!(config.sampler, Some(Sampler::TraceIdRatio(0.5)));
+
+        assert!(serde_json::from_str::<OtlpConfig>(r#"{ "endpont": "typo" }"#).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    error::Error,
+    fmt::Display,
+    io,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// Target of the events emitted by the default error handler, see [`crate::Builder::on_error`].
+///
+/// Events with this target are never exported by the OTLP layer itself.
+pub const DIAGNOSTICS_TARGET: &str = "tracing_otlp::diagnostics";
+
+/// Errors encountered while exporting, passed to the handler set with
+/// [`crate::Builder::on_error`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OtlpError {
+    /// A batch of spans could not be exported, the spans are retried with the next batch
+    Export {
+        destination: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// Metrics could not be sent to the metrics endpoint
+    Metrics {
+        destination: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    /// The response to an export request could not be read or decoded
+    InvalidResponse(Box<dyn Error + Send + Sync>),
+    /// The endpoint accepted the export request, but rejected some of its spans
+    PartialSuccess {
+        rejected_spans: i64,
+        message: String,
+    },
+    /// Spans were discarded without being exported
+    SpansDropped { count: u64, reason: &'static str },
+    /// Reading or writing the disk buffer failed
+    DiskBuffer(io::Error),
+    /// The worker panicked, it is restarted with a fresh connection and without the spans it
+    /// held
+    WorkerPanicked { message: String },
+    /// Exports failed too many times in a row, so they are suspended and only probed every
+    /// `probe_interval`, see [`crate::Builder::circuit_breaker`]
+    CircuitOpen {
+        failures: u32,
+        probe_interval: Duration,
+    },
+    /// Writing spans to the output of a local backend, such as
+    /// [`crate::StdoutTelemetry`], failed
+    Output(io::Error),
+    /// A timestamp is before the Unix epoch, so it is exported as 0
+    InvalidTimestamp { time: SystemTime },
+}
+
+impl Display for OtlpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtlpError::Export {
+                destination,
+                source,
+            } => write!(f, "error exporting spans to {destination}: {source}"),
+            OtlpError::Metrics {
+                destination,
+                source,
+            } => write!(f, "error sending metrics to {destination}: {source}"),
+            OtlpError::InvalidResponse(err) => write!(f, "invalid export response: {err}"),
+            OtlpError::PartialSuccess {
+                rejected_spans,
+                message,
+            } => write!(
+                f,
+                "endpoint rejected {rejected_spans} spans: {}",
+                if message.is_empty() {
+                    "no message"
+                } else {
+                    message
+                }
+            ),
+            OtlpError::SpansDropped { count, reason } => {
+                write!(f, "dropped {count} spans: {reason}")
+            }
+            OtlpError::DiskBuffer(err) => write!(f, "disk buffer error: {err}"),
+            OtlpError::WorkerPanicked { message } => {
+                write!(f, "worker panicked, restarting it: {message}")
+            }
+            OtlpError::CircuitOpen {
+                failures,
+                probe_interval,
+            } => write!(
+                f,
+                "{failures} exports failed in a row, suspending exports and probing every \
+                 {probe_interval:?}"
+            ),
+            OtlpError::Output(err) => write!(f, "could not write spans: {err}"),
+            OtlpError::InvalidTimestamp { time } => {
+                write!(f, "timestamp {time:?} is before the Unix epoch")
+            }
+        }
+    }
+}
+
+impl Error for OtlpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OtlpError::Export { source, .. }
+            | OtlpError::Metrics { source, .. }
+            | OtlpError::InvalidResponse(source) => Some(source.as_ref()),
+            OtlpError::DiskBuffer(err) | OtlpError::Output(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Invalid configuration, or failure to start the worker, returned when building a layer.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum OtlpBuildError {
+    /// An endpoint is not a valid URL
+    InvalidUrl(url::ParseError),
+    /// An endpoint uses a scheme the worker cannot send to
+    UnsupportedScheme { url: String },
+    /// An HTTP header has an invalid name or value
+    InvalidHeader { name: String, reason: &'static str },
+    /// [`crate::Builder::build_tokio`] was called outside of a tokio runtime
+    NoRuntime,
+    /// A thread of the worker could not be spawned
+    Spawn(Arc<io::Error>),
+    /// [`crate::init`] was called while a global default subscriber was already set
+    SubscriberAlreadySet,
+    /// The TLS settings are invalid, for example as a certificate could not be parsed
+    Tls { reason: String },
+    /// A setting of the [`crate::Builder`] is out of range
+    InvalidSetting {
+        setting: &'static str,
+        reason: &'static str,
+    },
+}
+
+impl Display for OtlpBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OtlpBuildError::InvalidUrl(err) => write!(f, "invalid endpoint URL: {err}"),
+            OtlpBuildError::UnsupportedScheme { url } => {
+                write!(f, "unsupported scheme of endpoint {url}")
+            }
+            OtlpBuildError::InvalidHeader { name, reason } => {
+                write!(f, "invalid HTTP header {name:?}: {reason}")
+            }
+            OtlpBuildError::NoRuntime => write!(f, "not called within a tokio runtime"),
+            OtlpBuildError::Spawn(err) => write!(f, "could not spawn worker thread: {err}"),
+            OtlpBuildError::SubscriberAlreadySet => {
+                write!(f, "a global default subscriber is already set")
+            }
+            OtlpBuildError::Tls { reason } => write!(f, "invalid TLS settings: {reason}"),
+            OtlpBuildError::InvalidSetting { setting, reason } => {
+                write!(f, "invalid {setting}: {reason}")
+            }
+        }
+    }
+}
+
+impl Error for OtlpBuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OtlpBuildError::InvalidUrl(err) => Some(err),
+            OtlpBuildError::Spawn(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<url::ParseError> for OtlpBuildError {
+    fn from(err: url::ParseError) -> Self {
+        OtlpBuildError::InvalidUrl(err)
+    }
+}
+
+impl From<Infallible> for OtlpBuildError {
+    fn from(err: Infallible) -> Self {
+        match err {}
+    }
+}
+
+/// Why the endpoint could not be reached, see [`crate::Builder::validate_connection`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConnectionError {
+    /// The endpoint or headers are invalid
+    InvalidConfig(OtlpBuildError),
+    /// The host name of the endpoint could not be resolved
+    Dns(Box<dyn Error + Send + Sync>),
+    /// No connection to the endpoint could be established
+    Connect(Box<dyn Error + Send + Sync>),
+    /// The TLS handshake failed, for example as the certificate is not trusted
+    Tls(Box<dyn Error + Send + Sync>),
+    /// The endpoint rejected the credentials, with status 401 or 403
+    Unauthorized { status: u16 },
+    /// There is no OTLP endpoint at the URL, the path may be wrong
+    NotFound { url: String },
+    /// The endpoint responded with another error status
+    Status { status: u16, message: String },
+    /// The request failed otherwise
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl Display for ConnectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionError::InvalidConfig(err) => write!(f, "{err}"),
+            ConnectionError::Dns(err) => write!(f, "could not resolve endpoint: {err}"),
+            ConnectionError::Connect(err) => write!(f, "could not connect to endpoint: {err}"),
+            ConnectionError::Tls(err) => write!(f, "TLS handshake with endpoint failed: {err}"),
+            ConnectionError::Unauthorized { status } => {
+                write!(f, "endpoint rejected the credentials with status {status}")
+            }
+            ConnectionError::NotFound { url } => write!(f, "no OTLP endpoint at {url}"),
+            ConnectionError::Status { status, message } => {
+                write!(f, "endpoint responded with status {status}: {message}")
+            }
+            ConnectionError::Other(err) => write!(f, "request to endpoint failed: {err}"),
+        }
+    }
+}
+
+impl Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConnectionError::InvalidConfig(err) => Some(err),
+            ConnectionError::Dns(err)
+            | ConnectionError::Connect(err)
+            | ConnectionError::Tls(err)
+            | ConnectionError::Other(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+thread_local! {
+    static HANDLING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Callback receiving the [`OtlpError`]s of the layer and its worker.
+#[derive(Clone)]
+pub(crate) struct ErrorHandler(Arc<dyn Fn(OtlpError) + Send + Sync>);
+
+impl ErrorHandler {
+    pub(crate) fn new(handler: impl Fn(OtlpError) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(handler))
+    }
+
+    /// Passes `err` to the handler, unless it is already handling an error on this thread,
+    /// which would otherwise recurse if handling the error fails in turn.
+    pub(crate) fn handle(&self, err: OtlpError) {
+        struct Reset;
+
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                HANDLING.with(|handling| handling.set(false));
+            }
+        }
+
+        if HANDLING.with(|handling| handling.replace(true)) {
+            return;
+        }
+        let _reset = Reset;
+        (self.0)(err);
+    }
+}
+
+impl std::fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ErrorHandler").finish_non_exhaustive()
+    }
+}
+
+impl Default for ErrorHandler {
+    fn default() -> Self {
+        Self::new(|err| tracing::warn!(target: DIAGNOSTICS_TARGET, "{err}"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn ignores_errors_while_handling() {
+        let handled = Arc::new(Mutex::new(Vec::new()));
+        let inner = {
+            let handled = handled.clone();
+            ErrorHandler::new(move |err| handled.lock().unwrap().push(err.to_string()))
+        };
+        let outer = {
+            let inner = inner.clone();
+            ErrorHandler::new(move |err| {
+                inner.handle(OtlpError::SpansDropped {
+                    count: 1,
+                    reason: "nested",
+                });
+                inner.handle(err)
+            })
+        };
+
+        outer.handle(OtlpError::SpansDropped {
+            count: 2,
+            reason: "outer",
+        });
+        inner.handle(OtlpError::SpansDropped {
+            count: 3,
+            reason: "after",
+        });
+
+        assert_eq!(*handled.lock().unwrap(), ["dropped 3 spans: after"]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{json::trace_request_to_json, prost::collector::trace::v1::ExportTraceServiceRequest};
+
+/// Configures the OTLP file exporter, which writes batches of spans to a file in the OTLP
+/// JSON file format, one export request per line.
+///
+/// The files can be shipped to the collector later on, e.g. using its `otlpjsonfile`
+/// receiver. Pass it to [`crate::Builder::build_file`].
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::OtlpFile;
+/// // Keep at most 5 rotated files of 10 MiB besides the current one
+/// OtlpFile::new("/var/log/traces.jsonl")
+///     .max_size(10 * 1024 * 1024)
+///     .max_files(5);
+/// ```
+#[derive(Debug, Clone)]
+pub struct OtlpFile {
+    pub(crate) path: PathBuf,
+    pub(crate) max_size: Option<u64>,
+    pub(crate) max_files: usize,
+}
+
+impl OtlpFile {
+    /// Writes to the file at `path`, which is created if it does not exist yet and appended to
+    /// otherwise. The file is never rotated unless [`OtlpFile::max_size`] is set.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: None,
+            max_files: 5,
+        }
+    }
+
+    /// Rotates the file before it would grow beyond `max_size` bytes.
+    ///
+    /// On rotation `traces.jsonl` is renamed to `traces.jsonl.1`, which is renamed to
+    /// `traces.jsonl.2` first, and so on.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Sets the number of rotated files kept, defaults to 5. Older files are deleted.
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+}
+
+/// Writes export requests to the file configured by an [`OtlpFile`].
+#[derive(Debug)]
+pub(crate) struct FileWriter {
+    config: OtlpFile,
+    file: Option<File>,
+    size: u64,
+}
+
+impl FileWriter {
+    pub(crate) fn new(config: OtlpFile) -> Self {
+        Self {
+            config,
+            file: None,
+            size: 0,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &PathBuf {
+        &self.config.path
+    }
+
+    pub(crate) fn write(&mut self, req: &ExportTraceServiceRequest) -> io::Result<()> {
+        let mut line = trace_request_to_json(req).to_string();
+        line.push('\n');
+
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if let Some(max_size) = self.config.max_size {
+            if self.size > 0 && self.size + line.len() as u64 > max_size {
+                self.rotate()?;
+            }
+        }
+
+        let file = self.file.as_mut().expect("file was opened");
+        file.write_all(line.as_bytes())?;
+        file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)?;
+        self.size = file.metadata()?.len();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        if self.config.max_files == 0 {
+            std::fs::remove_file(&self.config.path)?;
+        } else {
+            for i in (1..self.config.max_files).rev() {
+                match std::fs::rename(self.rotated_path(i), self.rotated_path(i + 1)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            std::fs::rename(&self.config.path, self.rotated_path(1))?;
+        }
+        self.open()
+    }
+
+    fn rotated_path(&self, i: usize) -> PathBuf {
+        let mut path = self.config.path.clone().into_os_string();
+        path.push(format!(".{i}"));
+        path.into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prost::trace::v1::{ResourceSpans, ScopeSpans, Span};
+
+    fn request(name: &str) -> ExportTraceServiceRequest {
+        ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: None,
+                scope_spans: vec![ScopeSpans {
+                    scope: None,
+                    spans: vec![Span {
+                        name: name.to_string(),
+                        ..Default::default()
+                    }],
+                    schema_url: "".to_string(),
+                }],
+                schema_url: "".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn rotates_files() {
+        let dir = std::env::temp_dir().join(format!("tracing-otlp-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("traces.jsonl");
+
+        let line_len = trace_request_to_json(&request("a")).to_string().len() as u64 + 1;
+        let mut writer = FileWriter::new(OtlpFile::new(&path).max_size(line_len * 2).max_files(2));
+        for name in ["a", "b", "c", "d", "e", "f", "g"] {
+            writer.write(&request(name)).unwrap();
+        }
+
+        let names = |path: PathBuf| -> Vec<String> {
+            std::fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    let json: serde_json::Value = serde_json::from_str(line).unwrap();
+                    json["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"]
+                        .as_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect()
+        };
+        assert_eq!(names(path.clone()), ["g"]);
+        assert_eq!(names(writer.rotated_path(1)), ["e", "f"]);
+        assert_eq!(names(writer.rotated_path(2)), ["c", "d"]);
+        assert!(!writer.rotated_path(3).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
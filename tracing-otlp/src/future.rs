@@ -0,0 +1,39 @@
+use std::future::Future;
+
+use tracing::instrument::{Instrument, Instrumented, WithDispatch, WithSubscriber};
+
+use crate::{current_span_context, TraceRoot};
+
+/// Continues the distributed trace of the current span in futures polled elsewhere, such as
+/// on another runtime or thread pool.
+///
+/// Unlike [`tracing::Instrument::in_current_span`], the current span is not kept open until
+/// the future completes: the future runs in a span of its own, registered as a local root
+/// with the current span as its parent.
+pub trait DistTraceExt: Future + Sized {
+    /// Instruments the future with a span named `future`, continuing the distributed trace
+    /// of the current span if it is part of one, and with the current subscriber.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::{register_dist_tracing_root, DistTraceExt, TraceId};
+    /// # async fn refresh_cache() {}
+    /// # fn spawn_on_background_runtime(_: impl std::future::Future + Send + 'static) {}
+    /// tracing::info_span!("request").in_scope(|| {
+    ///     # let _ =
+    ///     register_dist_tracing_root(TraceId::new(), None);
+    ///     spawn_on_background_runtime(refresh_cache().in_current_dist_trace());
+    /// });
+    /// ```
+    fn in_current_dist_trace(self) -> WithDispatch<Instrumented<Self>> {
+        // Not a child of the current span, which would keep it open until the future ends
+        let span = tracing::info_span!(parent: None, "future", span.kind = "internal");
+        if let Ok(ctx) = current_span_context() {
+            // Cannot fail, the span was created by the subscriber the context is from
+            let _ = TraceRoot::remote(ctx).register_for(&span);
+        }
+        self.instrument(span).with_current_subscriber()
+    }
+}
+
+impl<F: Future> DistTraceExt for F {}
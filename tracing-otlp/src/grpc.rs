@@ -0,0 +1,329 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::field::Empty;
+
+use crate::{inject_http_headers, span_context_for};
+
+/// [`Layer`] tracing each call of the wrapped gRPC client, such as a `tonic` channel, in a
+/// `CLIENT` span and propagating its context in the W3C `traceparent` metadata of the call.
+///
+/// The span is a child of the current span, named after the service and method called, with
+/// the RPC semantic convention attributes, the deadline of the call in `rpc.grpc.timeout_ms`
+/// and its `rpc.grpc.status_code`. It ends once the status of the call is received, and its
+/// status is an error if the call did not succeed.
+///
+/// # Examples
+/// ```
+/// # use tower_layer::Layer;
+/// # #[derive(Clone)]
+/// # struct Channel;
+/// let channel = tracing_otlp::GrpcClientTraceLayer::new().layer(Channel);
+/// // Then create the clients generated by `tonic` with `Client::new(channel)`
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct GrpcClientTraceLayer {
+    _private: (),
+}
+
+impl GrpcClientTraceLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for GrpcClientTraceLayer {
+    type Service = GrpcClientTrace<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcClientTrace { inner }
+    }
+}
+
+/// gRPC client tracing the calls of the inner client, see [`GrpcClientTraceLayer`].
+#[derive(Debug, Clone)]
+pub struct GrpcClientTrace<S> {
+    inner: S,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for GrpcClientTrace<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = http::Response<GrpcBody<ResBody>>;
+    type Error = S::Error;
+    type Future = GrpcFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let span = call_span(&request);
+        if let Ok(ctx) = span_context_for(&span) {
+            inject_http_headers(&ctx, request.headers_mut());
+        }
+        let inner = span.in_scope(|| self.inner.call(request));
+        GrpcFuture {
+            inner,
+            span: Some(span),
+        }
+    }
+}
+
+fn call_span<B>(request: &http::Request<B>) -> tracing::Span {
+    let uri = request.uri();
+    // The path is `/package.Service/Method`
+    let path = uri.path().trim_start_matches('/');
+    let (service, method) = path.split_once('/').unwrap_or((path, ""));
+    let timeout_ms = request
+        .headers()
+        .get("grpc-timeout")
+        .and_then(|timeout| timeout.to_str().ok())
+        .and_then(parse_timeout)
+        .map(|timeout| timeout.as_millis() as i64);
+    tracing::info_span!(
+        "gRPC call",
+        span.name = path,
+        span.kind = "client",
+        span.status = Empty,
+        rpc.system = "grpc",
+        rpc.service = service,
+        rpc.method = method,
+        server.address = uri.host(),
+        server.port = uri.port_u16().map(i64::from),
+        rpc.grpc.timeout_ms = timeout_ms,
+        rpc.grpc.status_code = Empty,
+    )
+}
+
+/// Parses a `grpc-timeout` header, such as `100m` for 100 milliseconds.
+fn parse_timeout(timeout: &str) -> Option<Duration> {
+    let unit = timeout.len().checked_sub(1)?;
+    let (value, unit) = (timeout[..unit].parse::<u64>().ok()?, &timeout[unit..]);
+    Some(match unit {
+        "H" => Duration::from_secs(value.saturating_mul(3600)),
+        "M" => Duration::from_secs(value.saturating_mul(60)),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    })
+}
+
+/// Records the `grpc-status` of a call on its span, if the headers or trailers have it.
+/// Returns whether they did.
+fn record_status(span: &tracing::Span, headers: &http::HeaderMap) -> bool {
+    let Some(status) = headers
+        .get("grpc-status")
+        .and_then(|status| status.to_str().ok()?.parse::<i64>().ok())
+    else {
+        return false;
+    };
+    span.record("rpc.grpc.status_code", status);
+    if status != 0 {
+        span.record("span.status", "error");
+    }
+    true
+}
+
+pin_project! {
+    /// Response future of [`GrpcClientTrace`].
+    #[derive(Debug)]
+    pub struct GrpcFuture<F> {
+        #[pin]
+        inner: F,
+        span: Option<tracing::Span>,
+    }
+}
+
+impl<F, ResBody, E> Future for GrpcFuture<F>
+where
+    F: Future<Output = Result<http::Response<ResBody>, E>>,
+{
+    type Output = Result<http::Response<GrpcBody<ResBody>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let span = this.span.take().expect("polled after completion");
+        let result = span.in_scope(|| this.inner.poll(cx));
+        let Poll::Ready(result) = result else {
+            *this.span = Some(span);
+            return Poll::Pending;
+        };
+        Poll::Ready(match result {
+            Ok(response) => {
+                // Calls failing right away only have headers, with the status
+                let span = (!record_status(&span, response.headers())).then_some(span);
+                Ok(response.map(|inner| GrpcBody { inner, span }))
+            }
+            Err(err) => {
+                span.record("span.status", "error");
+                Err(err)
+            }
+        })
+    }
+}
+
+pin_project! {
+    /// Response body of [`GrpcClientTrace`], ending the span of the call once its status
+    /// is received in the trailers.
+    #[derive(Debug)]
+    pub struct GrpcBody<B> {
+        #[pin]
+        inner: B,
+        span: Option<tracing::Span>,
+    }
+}
+
+impl<B: Body> Body for GrpcBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = ready!(this.inner.poll_frame(cx));
+        if let Some(span) = this.span.as_ref() {
+            match &frame {
+                Some(Ok(frame)) => {
+                    if let Some(trailers) = frame.trailers_ref() {
+                        record_status(span, trailers);
+                        *this.span = None;
+                    }
+                }
+                Some(Err(_)) => {
+                    span.record("span.status", "error");
+                    *this.span = None;
+                }
+                None => *this.span = None,
+            }
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::Infallible, future::Ready};
+
+    use http_body_util::{BodyExt, Empty as EmptyBody, Full};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{
+        prost::{
+            common::v1::KeyValue,
+            trace::v1::{span::SpanKind, status::StatusCode},
+        },
+        test::{capture_exporter, current_thread_runtime},
+    };
+
+    type TestBody = http_body_util::combinators::UnsyncBoxBody<&'static [u8], Infallible>;
+
+    /// Responds with the `grpc-status` of the `x-status` header of the call, in the
+    /// trailers unless there is no message, and checks the call was propagated.
+    struct Respond;
+
+    impl Service<http::Request<()>> for Respond {
+        type Response = http::Response<TestBody>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<()>) -> Self::Future {
+            assert!(request.headers().contains_key("traceparent"));
+            let status = request.headers()["x-status"].clone();
+            let response = if status == "0" {
+                let mut trailers = http::HeaderMap::new();
+                trailers.insert("grpc-status", status);
+                let body = Full::new(&b"message"[..])
+                    .with_trailers(std::future::ready(Some(Ok(trailers))));
+                http::Response::new(body.boxed_unsync())
+            } else {
+                let mut response = http::Response::new(EmptyBody::new().boxed_unsync());
+                response.headers_mut().insert("grpc-status", status);
+                response
+            };
+            std::future::ready(Ok(response))
+        }
+    }
+
+    #[test]
+    fn traces_calls_in_client_spans() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let runtime = current_thread_runtime();
+        tracing::subscriber::with_default(subscriber, || {
+            let mut client = GrpcClientTraceLayer::new().layer(Respond);
+            let _job = tracing::info_span!("job").entered();
+            crate::register_dist_tracing_root(crate::TraceId(1), None).unwrap();
+            for status in ["0", "5"] {
+                let request = http::Request::post("http://localhost:50051/shop.Users/Get")
+                    .header("x-status", status)
+                    .header("grpc-timeout", "2S")
+                    .body(())
+                    .unwrap();
+                runtime.block_on(async {
+                    let response = client.call(request).await.unwrap();
+                    response.into_body().collect().await.unwrap();
+                });
+            }
+        });
+
+        let spans = capture.0.lock().unwrap();
+        let (ok, failed) = (&spans[0], &spans[1]);
+        assert_eq!(ok.name, "shop.Users/Get");
+        assert_eq!(ok.kind, SpanKind::Client as i32);
+        assert_eq!(ok.status, None);
+        let kv = |key: &str, value: &str| KeyValue::new(key.to_string(), value.to_string().into());
+        let int = |key: &str, value: i64| KeyValue::new(key.to_string(), value.into());
+        assert_eq!(
+            ok.attributes,
+            [
+                kv("rpc.system", "grpc"),
+                kv("rpc.service", "shop.Users"),
+                kv("rpc.method", "Get"),
+                kv("server.address", "localhost"),
+                int("server.port", 50051),
+                int("rpc.grpc.timeout_ms", 2000),
+                int("rpc.grpc.status_code", 0),
+            ]
+        );
+        assert!(failed.attributes.contains(&int("rpc.grpc.status_code", 5)));
+        assert_eq!(
+            failed.status.as_ref().map(|status| status.code),
+            Some(StatusCode::Error as i32)
+        );
+    }
+
+    #[test]
+    fn parses_timeouts() {
+        assert_eq!(parse_timeout("100m"), Some(Duration::from_millis(100)));
+        assert_eq!(parse_timeout("1H"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_timeout("5x"), None);
+        assert_eq!(parse_timeout(""), None);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use url::Url;
+
+use crate::{builder::signal_url, http::validate_header, Otlp, OtlpBuildError, Sampler};
+
+/// Changes the settings of an [`Otlp`] exporter while it runs, without rebuilding the
+/// subscriber, e.g. from a dynamic configuration system. Returned by [`Otlp::handle`].
+///
+/// The sampler and headers apply right away, the other settings once the worker wakes up
+/// next, at the latest after its current send interval. Cloning a handle is cheap.
+///
+/// # Examples
+/// ```
+/// # use std::time::Duration;
+/// # use tracing_otlp::{Builder, Sampler};
+/// let layer = Builder::new().build("http://127.0.0.1:4318").unwrap();
+/// let handle = layer.telemetry().handle();
+///
+/// // During an incident
+/// handle.set_sampler(Sampler::AlwaysOn);
+/// handle.set_send_interval(Duration::from_millis(200));
+/// handle.set_endpoint("http://incident-collector:4318").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct OtlpHandle {
+    otlp: Otlp,
+}
+
+impl OtlpHandle {
+    pub(crate) fn new(otlp: Otlp) -> Self {
+        Self { otlp }
+    }
+
+    /// Sets the [`Sampler`] deciding on the traces registered from now on. Traces which
+    /// are already registered keep their decision.
+    pub fn set_sampler(&self, sampler: Sampler) {
+        self.otlp
+            .inner
+            .sampling
+            .write()
+            .expect("sampling lock poisoned")
+            .sampler = sampler;
+    }
+
+    /// Sends spans and metrics to `v1/traces` and `v1/metrics` below the path of
+    /// `endpoint` from now on, like the endpoint given to [`crate::Builder::build`]. This
+    /// replaces the endpoints set with [`crate::Builder::traces_endpoint`] and
+    /// [`crate::Builder::metrics_endpoint`] as well.
+    ///
+    /// Only applies to exporters sending over HTTP. Spans already written to the
+    /// [`crate::Builder::disk_buffer`] are replayed to the new endpoint.
+    pub fn set_endpoint<U>(&self, endpoint: U) -> Result<(), OtlpBuildError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        let endpoint = endpoint.try_into().map_err(Into::into)?;
+        let traces = signal_url(&endpoint, "v1/traces")?;
+        let metrics = signal_url(&endpoint, "v1/metrics")?;
+        if !self.otlp.inner.endpoint_schemes.contains(&traces.scheme()) {
+            return Err(OtlpBuildError::UnsupportedScheme {
+                url: traces.to_string(),
+            });
+        }
+        self.otlp
+            .inner
+            .changes
+            .push(SettingChange::Endpoint { traces, metrics });
+        Ok(())
+    }
+
+    /// Sets HTTP headers added to the requests from now on, replacing those of the
+    /// [`crate::Builder`] with the same name and those set by an earlier call.
+    ///
+    /// Fails with [`OtlpBuildError::InvalidHeader`] without changing the headers if one of
+    /// them is invalid.
+    pub fn set_http_headers<K: AsRef<str>, V: AsRef<[u8]>>(
+        &self,
+        headers: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), OtlpBuildError> {
+        let headers = headers
+            .into_iter()
+            .map(|(name, value)| {
+                validate_header(name.as_ref(), value.as_ref())?;
+                // Validated to be printable ASCII
+                Ok((
+                    name.as_ref().to_string(),
+                    String::from_utf8_lossy(value.as_ref()).into_owned(),
+                ))
+            })
+            .collect::<Result<_, OtlpBuildError>>()?;
+        *self
+            .otlp
+            .inner
+            .runtime_headers
+            .write()
+            .expect("headers lock poisoned") = headers;
+        Ok(())
+    }
+
+    /// Sets the interval on which spans are sent, see [`crate::Builder::send_interval`].
+    pub fn set_send_interval(&self, interval: Duration) {
+        self.otlp
+            .inner
+            .changes
+            .push(SettingChange::SendInterval(interval));
+    }
+
+    /// Sets the maximum number of spans per export request, see
+    /// [`crate::Builder::max_export_batch_size`].
+    pub fn set_max_export_batch_size(&self, max: usize) {
+        self.otlp
+            .inner
+            .changes
+            .push(SettingChange::MaxExportBatchSize(max.max(1)));
+    }
+
+    /// Stops exporting until [`OtlpHandle::resume`] is called, e.g. during maintenance of
+    /// the collector. Spans are kept in memory meanwhile, up to the same limit as while
+    /// exports fail, beyond which the oldest are moved to the [`crate::Builder::disk_buffer`]
+    /// or dropped.
+    ///
+    /// Metrics are not sent while paused either, and flushing does not export anything.
+    pub fn pause(&self) {
+        self.otlp
+            .inner
+            .changes
+            .push(SettingChange::Paused(Some(Pause::Buffer)));
+    }
+
+    /// Stops exporting until [`OtlpHandle::resume`] is called, like [`OtlpHandle::pause`],
+    /// but drops the spans finished meanwhile, accounting for them in
+    /// [`crate::OtlpStats::spans_dropped`].
+    pub fn pause_dropping(&self) {
+        self.otlp
+            .inner
+            .changes
+            .push(SettingChange::Paused(Some(Pause::Drop)));
+    }
+
+    /// Resumes exporting after [`OtlpHandle::pause`], starting with the spans kept meanwhile.
+    pub fn resume(&self) {
+        self.otlp.inner.changes.push(SettingChange::Paused(None));
+    }
+
+    /// Sets the maximum encoded size of export requests, see
+    /// [`crate::Builder::max_export_batch_bytes`]. `None` removes the limit.
+    pub fn set_max_export_batch_bytes(&self, max: Option<usize>) {
+        self.otlp
+            .inner
+            .changes
+            .push(SettingChange::MaxExportBatchBytes(max));
+    }
+}
+
+/// A change of a setting of the worker, made with an [`OtlpHandle`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SettingChange {
+    Endpoint { traces: Url, metrics: Url },
+    SendInterval(Duration),
+    MaxExportBatchSize(usize),
+    MaxExportBatchBytes(Option<usize>),
+    Paused(Option<Pause>),
+}
+
+/// What happens to spans while exports are paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Pause {
+    Buffer,
+    Drop,
+}
+
+/// Changes made with an [`OtlpHandle`] which the worker did not apply yet, shared between
+/// the exporter and its worker.
+#[derive(Debug, Default)]
+pub(crate) struct SettingChanges(Mutex<Vec<SettingChange>>);
+
+impl SettingChanges {
+    pub(crate) fn push(&self, change: SettingChange) {
+        self.0.lock().expect("settings lock poisoned").push(change);
+    }
+
+    /// Takes the changes made since the last call, oldest first.
+    pub(crate) fn take(&self) -> Vec<SettingChange> {
+        std::mem::take(&mut *self.0.lock().expect("settings lock poisoned"))
+    }
+}
+
+/// Headers set with [`OtlpHandle::set_http_headers`], shared with the HTTP clients.
+pub(crate) type RuntimeHeaders = Arc<RwLock<Vec<(String, String)>>>;
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{Builder, TraceId};
+
+    #[test]
+    fn changes_settings() {
+        let otlp = Builder::new()
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let handle = otlp.handle();
+        handle.set_sampler(Sampler::AlwaysOff);
+        let subscriber = tracing_subscriber::registry().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                crate::register_dist_tracing_root(TraceId::new(), None).unwrap();
+                assert!(!crate::is_sampled().unwrap());
+            });
+        });
+
+        handle.set_http_headers([("X-Tenant", "acme")]).unwrap();
+        let invalid = handle.set_http_headers([("X-Tenant", "acme\n")]);
+        assert!(matches!(invalid, Err(OtlpBuildError::InvalidHeader { .. })));
+        assert_eq!(
+            *otlp.inner.runtime_headers.read().unwrap(),
+            [("X-Tenant".to_string(), "acme".to_string())]
+        );
+
+        assert!(handle.set_endpoint("ftp://127.0.0.1").is_err());
+        handle.set_endpoint("http://127.0.0.1:2/otel").unwrap();
+    }
+}
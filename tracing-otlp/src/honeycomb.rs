@@ -0,0 +1,298 @@
+use std::{
+    str::FromStr,
+    sync::{mpsc::Sender, Arc},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Map, Value as Json};
+use tracing_distributed::{Event, Span, Telemetry, TelemetryLayer};
+use url::Url;
+
+use crate::{
+    error::ErrorHandler, json_worker::JsonWorker, prost::common::v1::KeyValue, IdGenerator,
+    OtlpBuildError, OtlpError, RandomIdGenerator, SpanId, TraceId, Visitor,
+};
+
+/// Field names set by the backend itself, attributes with these names are prefixed with
+/// `tracing.` as in `tracing-honeycomb`.
+const RESERVED_WORDS: [&str; 10] = [
+    "trace.span_id",
+    "trace.trace_id",
+    "trace.parent_id",
+    "service_name",
+    "level",
+    "Timestamp",
+    "name",
+    "target",
+    "duration_ms",
+    "meta.annotation_type",
+];
+
+/// Builder for the [`Honeycomb`] `tracing` layer, which sends spans and events to the
+/// Honeycomb events API.
+///
+/// Events use the same fields as `tracing-honeycomb`, so existing queries and boards keep
+/// working when migrating.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::HoneycombBuilder;
+/// HoneycombBuilder::new("api-key".to_string(), "my-dataset".to_string())
+///     .service_name("api".to_string())
+///     .build();
+/// ```
+pub struct HoneycombBuilder {
+    api_key: String,
+    dataset: String,
+    api_host: String,
+    service_name: String,
+    send_interval: Duration,
+    id_generator: Arc<dyn IdGenerator>,
+    error_handler: ErrorHandler,
+}
+
+impl HoneycombBuilder {
+    /// Sends events to `dataset`, authenticated with the given API key.
+    pub fn new(api_key: String, dataset: String) -> Self {
+        Self {
+            api_key,
+            dataset,
+            api_host: "https://api.honeycomb.io".to_string(),
+            service_name: "unknown_service".to_string(),
+            send_interval: Duration::from_secs(1),
+            id_generator: Arc::new(RandomIdGenerator::default()),
+            error_handler: Default::default(),
+        }
+    }
+
+    /// Sets the URL of the Honeycomb API, defaults to `https://api.honeycomb.io`.
+    pub fn api_host(mut self, api_host: String) -> Self {
+        self.api_host = api_host;
+        self
+    }
+
+    /// Sets the `service_name` field of all events.
+    pub fn service_name(mut self, service_name: String) -> Self {
+        self.service_name = service_name;
+        self
+    }
+
+    /// Configures the interval at which events are reported to Honeycomb
+    pub fn send_interval(mut self, interval: Duration) -> Self {
+        self.send_interval = interval;
+        self
+    }
+
+    /// Sets the [`IdGenerator`] used for the ids of new spans.
+    pub fn id_generator(mut self, id_generator: impl IdGenerator) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Sets the handler called with the errors encountered while sending events, as
+    /// [`crate::Builder::on_error`] does for OTLP.
+    pub fn on_error(mut self, handler: impl Fn(OtlpError) + Send + Sync + 'static) -> Self {
+        self.error_handler = ErrorHandler::new(handler);
+        self
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Honeycomb`] the settings provided.
+    pub fn build(self) -> Result<TelemetryLayer<Honeycomb, SpanId, TraceId>, OtlpBuildError> {
+        let mut endpoint = Url::from_str(&self.api_host)?;
+        endpoint
+            .path_segments_mut()
+            .map_err(|_| OtlpBuildError::UnsupportedScheme {
+                url: self.api_host.clone(),
+            })?
+            .pop_if_empty()
+            .extend(["1", "batch", &self.dataset]);
+
+        let tx = JsonWorker::spawn(
+            "Honeycomb worker",
+            endpoint,
+            self.send_interval,
+            vec![("X-Honeycomb-Team".to_string(), self.api_key)],
+            self.error_handler.clone(),
+        )?;
+
+        let id_generator = self.id_generator;
+        Ok(TelemetryLayer::new(
+            "",
+            Honeycomb {
+                tx,
+                service_name: self.service_name,
+                error_handler: self.error_handler,
+            },
+            move |_| id_generator.new_span_id(),
+        ))
+    }
+}
+
+/// Honeycomb events API implementation of [`Telemetry`]. Use [`HoneycombBuilder`] to
+/// instantiate this.
+///
+/// Spans are sent as events with a `duration_ms`, and the events within them as span events.
+pub struct Honeycomb {
+    tx: Sender<Json>,
+    service_name: String,
+    error_handler: ErrorHandler,
+}
+
+impl Honeycomb {
+    fn send(&self, time: SystemTime, data: Map<String, Json>) {
+        let event = json!({ "time": rfc3339(time), "data": data });
+        if self.tx.send(event).is_err() {
+            self.error_handler.handle(OtlpError::SpansDropped {
+                count: 1,
+                reason: "worker thread stopped",
+            });
+        }
+    }
+}
+
+impl Telemetry for Honeycomb {
+    type Visitor = Visitor;
+
+    type TraceId = TraceId;
+
+    type SpanId = SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        Default::default()
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        for event in events {
+            let (time, mut data) = event_to_data(&self.service_name, event);
+            data.insert("meta.annotation_type".to_string(), json!("span_event"));
+            self.send(time, data);
+        }
+        let (time, data) = span_to_data(&self.service_name, span);
+        self.send(time, data);
+    }
+
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        let (time, data) = event_to_data(&self.service_name, event);
+        self.send(time, data);
+    }
+}
+
+fn attributes_to_data(attributes: Vec<KeyValue>) -> Map<String, Json> {
+    attributes
+        .into_iter()
+        .map(|kv| {
+            let key = if RESERVED_WORDS.contains(&kv.key.as_str()) {
+                format!("tracing.{}", kv.key)
+            } else {
+                kv.key
+            };
+            (key, kv.value.as_ref().map_or(Json::Null, Into::into))
+        })
+        .collect()
+}
+
+fn span_to_data(
+    service_name: &str,
+    span: Span<Visitor, SpanId, TraceId>,
+) -> (SystemTime, Map<String, Json>) {
+    let mut data = attributes_to_data(span.values.0);
+    data.insert("trace.span_id".to_string(), json!(span.id.to_string()));
+    data.insert(
+        "trace.trace_id".to_string(),
+        json!(span.trace_id.to_string()),
+    );
+    data.insert(
+        "trace.parent_id".to_string(),
+        json!(span.parent_id.map(|id| id.to_string())),
+    );
+    data.insert("service_name".to_string(), json!(service_name));
+    data.insert("level".to_string(), json!(span.meta.level().as_str()));
+    data.insert("name".to_string(), json!(span.name));
+    data.insert("target".to_string(), json!(span.meta.target()));
+    let duration = span
+        .completed_at
+        .duration_since(span.initialized_at)
+        .unwrap_or_default();
+    data.insert(
+        "duration_ms".to_string(),
+        json!(duration.as_secs_f64() * 1000.0),
+    );
+    (span.initialized_at, data)
+}
+
+fn event_to_data(
+    service_name: &str,
+    event: Event<Visitor, SpanId, TraceId>,
+) -> (SystemTime, Map<String, Json>) {
+    let mut data = attributes_to_data(event.values.0);
+    data.insert(
+        "trace.trace_id".to_string(),
+        json!(event.trace_id.map(|id| id.to_string())),
+    );
+    data.insert(
+        "trace.parent_id".to_string(),
+        json!(event.parent_id.map(|id| id.to_string())),
+    );
+    data.insert("service_name".to_string(), json!(service_name));
+    data.insert("level".to_string(), json!(event.meta.level().as_str()));
+    data.insert("name".to_string(), json!(event.meta.name()));
+    data.insert("target".to_string(), json!(event.meta.target()));
+    (event.initialized_at, data)
+}
+
+/// Formats a time as RFC 3339 in UTC with microsecond precision, as expected by the
+/// Honeycomb batch API.
+fn rfc3339(t: SystemTime) -> String {
+    let since_epoch = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:06}Z",
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000000Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_micros(1_709_210_096_123_456)),
+            "2024-02-29T12:34:56.123456Z"
+        );
+    }
+
+    #[test]
+    fn prefixes_reserved_attributes() {
+        let data = attributes_to_data(vec![
+            KeyValue::new("name".to_string(), "user".to_string().into()),
+            KeyValue::new("user.id".to_string(), 7i64.into()),
+        ]);
+        assert_eq!(
+            Json::Object(data),
+            json!({ "tracing.name": "user", "user.id": 7 })
+        );
+    }
+}
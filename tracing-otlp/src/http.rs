@@ -0,0 +1,476 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use prost::Message;
+use ureq::{Agent, AgentBuilder};
+use url::Url;
+
+use crate::{
+    error::{ConnectionError, ErrorHandler, OtlpBuildError, OtlpError},
+    handle::RuntimeHeaders,
+    prost::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse},
+};
+
+/// Content type of OTLP/HTTP requests and responses.
+pub(crate) const PROTOBUF: &str = "application/x-protobuf";
+
+/// Compression of the export requests, see [`crate::Builder::compression`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum Compression {
+    /// Requests are sent uncompressed
+    #[default]
+    None,
+    /// Requests are compressed with gzip, requires the `gzip` feature
+    #[cfg(feature = "gzip")]
+    Gzip,
+}
+
+/// Codec and threshold of the request compression, as set on the [`crate::Builder`].
+#[derive(Debug, Clone)]
+pub(crate) struct CompressionConfig {
+    pub(crate) codec: Compression,
+    /// Bodies smaller than this are sent uncompressed
+    pub(crate) threshold: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            codec: Compression::None,
+            threshold: 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// Compresses a request body, returning it with its `Content-Encoding`, or `None` if it
+    /// is to be sent as is.
+    pub(crate) fn compress(&self, body: &[u8]) -> Option<(Vec<u8>, &'static str)> {
+        if body.len() < self.threshold {
+            return None;
+        }
+        match self.codec {
+            Compression::None => None,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                use std::io::Write;
+
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::with_capacity(body.len() / 4),
+                    flate2::Compression::fast(),
+                );
+                encoder
+                    .write_all(body)
+                    .and_then(|()| encoder.finish())
+                    .ok()
+                    .map(|body| (body, "gzip"))
+            }
+        }
+    }
+}
+
+/// Connection settings of the HTTP client, as set on the [`crate::Builder`].
+#[derive(Debug, Clone)]
+pub(crate) struct AgentConfig {
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) write_timeout: Option<Duration>,
+    pub(crate) request_timeout: Option<Duration>,
+    pub(crate) max_idle_connections: usize,
+    pub(crate) keep_alive: bool,
+    pub(crate) compression: CompressionConfig,
+    /// Set with [`crate::Builder::tls_root_certificates`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) tls_config: Option<Arc<rustls::ClientConfig>>,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            // As `OTEL_EXPORTER_OTLP_TIMEOUT`, so half-open connections do not stall exports
+            request_timeout: Some(Duration::from_secs(10)),
+            max_idle_connections: 1,
+            keep_alive: true,
+            compression: Default::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            tls_config: None,
+        }
+    }
+}
+
+impl AgentConfig {
+    /// Idle connections kept open per host, none without keep-alive.
+    pub(crate) fn idle_connections(&self) -> usize {
+        if self.keep_alive {
+            self.max_idle_connections
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn agent(&self) -> Agent {
+        let mut builder = AgentBuilder::new()
+            .max_idle_connections(self.idle_connections())
+            .max_idle_connections_per_host(self.idle_connections());
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.timeout_connect(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.timeout_read(timeout);
+        }
+        if let Some(timeout) = self.write_timeout {
+            builder = builder.timeout_write(timeout);
+        }
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(tls_config) = &self.tls_config {
+            builder = builder.tls_config(tls_config.clone());
+        }
+        builder.build()
+    }
+}
+
+/// Builds the TLS settings trusting the certificates in `pem` along with the Mozilla root
+/// certificates `ureq` trusts by default.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn tls_config(pem: &[u8]) -> Result<Arc<rustls::ClientConfig>, OtlpBuildError> {
+    use rustls_pki_types::{pem::PemObject, CertificateDer};
+
+    let tls = |reason: String| OtlpBuildError::Tls { reason };
+    let mut roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let mut added = 0;
+    for cert in CertificateDer::pem_slice_iter(pem) {
+        let cert = cert.map_err(|err| tls(format!("invalid PEM: {err}")))?;
+        roots
+            .add(cert)
+            .map_err(|err| tls(format!("invalid certificate: {err}")))?;
+        added += 1;
+    }
+    if added == 0 {
+        return Err(tls("no certificate in PEM".to_string()));
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| tls(err.to_string()))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Callback returning headers for each request, see [`crate::Builder::header_provider`].
+pub(crate) type HeaderProvider = Arc<dyn Fn() -> Vec<(String, String)> + Send + Sync>;
+
+/// HTTP headers of the requests for each signal, the headers passed by the user along with
+/// those for the signal.
+#[derive(Clone, Default)]
+pub(crate) struct SignalHeaders {
+    pub(crate) traces: Vec<(String, String)>,
+    pub(crate) metrics: Vec<(String, String)>,
+    pub(crate) provider: Option<HeaderProvider>,
+    /// Set with [`crate::OtlpHandle::set_http_headers`], replacing all others
+    pub(crate) runtime: RuntimeHeaders,
+}
+
+impl SignalHeaders {
+    /// Headers of a trace export request.
+    pub(crate) fn for_traces(&self) -> Vec<(String, String)> {
+        self.with_provided(&self.traces)
+    }
+
+    /// Headers of a metrics request.
+    pub(crate) fn for_metrics(&self) -> Vec<(String, String)> {
+        self.with_provided(&self.metrics)
+    }
+
+    fn with_provided(&self, headers: &[(String, String)]) -> Vec<(String, String)> {
+        let headers = match &self.provider {
+            Some(provider) => merge_headers(headers, provider()),
+            None => headers.to_vec(),
+        };
+        let runtime = self.runtime.read().expect("headers lock poisoned");
+        if runtime.is_empty() {
+            return headers;
+        }
+        merge_headers(&headers, runtime.clone())
+    }
+}
+
+/// Checks that a header can be sent, so every request would not fail otherwise.
+pub(crate) fn validate_header(name: &str, value: &[u8]) -> Result<(), OtlpBuildError> {
+    let invalid = |reason| {
+        Err(OtlpBuildError::InvalidHeader {
+            name: name.to_string(),
+            reason,
+        })
+    };
+    let is_token = |b: u8| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b);
+    if name.is_empty() || !name.bytes().all(is_token) {
+        return invalid("the name may only contain letters, digits and !#$%&'*+-.^_`|~");
+    }
+    if !value
+        .iter()
+        .all(|&b| b == b'\t' || (b' '..=b'~').contains(&b))
+    {
+        return invalid("the value may only contain printable ASCII characters");
+    }
+    Ok(())
+}
+
+/// Adds the headers of a signal to the common ones, replacing common headers of the same
+/// name.
+pub(crate) fn merge_headers(
+    common: &[(String, String)],
+    signal: Vec<(String, String)>,
+) -> Vec<(String, String)> {
+    let mut headers: Vec<_> = common
+        .iter()
+        .filter(|(key, _)| !signal.iter().any(|(k, _)| k.eq_ignore_ascii_case(key)))
+        .cloned()
+        .collect();
+    headers.extend(signal);
+    headers
+}
+
+/// Client for the OTLP/HTTP endpoints, shared by the worker and its export threads.
+#[derive(Clone)]
+pub(crate) struct HttpClient {
+    agent: Agent,
+    pub(crate) agent_config: AgentConfig,
+    pub(crate) headers: SignalHeaders,
+    pub(crate) error_handler: ErrorHandler,
+}
+
+impl HttpClient {
+    pub(crate) fn new(
+        agent_config: AgentConfig,
+        headers: SignalHeaders,
+        error_handler: ErrorHandler,
+    ) -> Self {
+        Self {
+            agent: agent_config.agent(),
+            agent_config,
+            headers,
+            error_handler,
+        }
+    }
+
+    /// Replaces the agent, closing its connections.
+    pub(crate) fn reconnect(&mut self) {
+        self.agent = self.agent_config.agent();
+    }
+
+    /// Sends encoded traces to the server, handling its response and returning the number of
+    /// spans rejected by the server.
+    pub(crate) fn send_traces(
+        &self,
+        url: &Url,
+        route_headers: &[(String, String)],
+        body: &[u8],
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let headers = merge_headers(&self.headers.for_traces(), route_headers.to_vec());
+        let res = self.post(url, &headers, body)?;
+        if res.header("content-type") != Some(PROTOBUF) {
+            return Ok(0);
+        }
+        let mut buf: Vec<u8> = Vec::new();
+        if let Err(err) = res.into_reader().read_to_end(&mut buf) {
+            self.error_handler
+                .handle(OtlpError::InvalidResponse(Box::new(err)));
+        }
+        Ok(rejected_spans(&self.error_handler, &buf))
+    }
+
+    /// Sends an empty export request, to check that the endpoint accepts requests.
+    pub(crate) fn check_traces(&self, url: &Url) -> Result<(), ConnectionError> {
+        let body = ExportTraceServiceRequest::default().encode_to_vec();
+        let err = match self.post(url, &self.headers.for_traces(), &body) {
+            Ok(_) => return Ok(()),
+            Err(err) => err,
+        };
+        Err(match err {
+            ureq::Error::Status(status @ (401 | 403), _) => {
+                ConnectionError::Unauthorized { status }
+            }
+            ureq::Error::Status(404, _) => ConnectionError::NotFound {
+                url: url.to_string(),
+            },
+            ureq::Error::Status(status, res) => ConnectionError::Status {
+                status,
+                // Collectors explain the error in the body
+                message: res.into_string().unwrap_or_default(),
+            },
+            ureq::Error::Transport(err) => {
+                let is_tls = url.scheme() == "https"
+                    && std::error::Error::source(&err)
+                        .and_then(|source| source.downcast_ref::<std::io::Error>())
+                        .is_some_and(|err| err.kind() == std::io::ErrorKind::InvalidData);
+                match err.kind() {
+                    ureq::ErrorKind::Dns => ConnectionError::Dns(Box::new(err)),
+                    _ if is_tls => ConnectionError::Tls(Box::new(err)),
+                    ureq::ErrorKind::ConnectionFailed => ConnectionError::Connect(Box::new(err)),
+                    _ => ConnectionError::Other(Box::new(err)),
+                }
+            }
+        })
+    }
+
+    /// Sends encoded metrics to the server.
+    #[allow(clippy::result_large_err)]
+    pub(crate) fn send_metrics(&self, url: &Url, body: &[u8]) -> Result<(), ureq::Error> {
+        self.post(url, &self.headers.for_metrics(), body).map(drop)
+    }
+
+    /// Sends a POST request with a protobuf body and the given HTTP headers.
+    #[allow(clippy::result_large_err)]
+    fn post(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) -> Result<ureq::Response, ureq::Error> {
+        let req = self
+            .agent
+            .request_url("POST", url)
+            .set("Content-Type", PROTOBUF);
+        let req = headers.iter().fold(req, |r, (k, v)| r.set(k, v));
+        match self.agent_config.compression.compress(body) {
+            Some((body, encoding)) => req.set("Content-Encoding", encoding).send_bytes(&body),
+            None => req.send_bytes(body),
+        }
+    }
+}
+
+/// Decodes a protobuf export response, returning the number of spans rejected by the server.
+pub(crate) fn rejected_spans(error_handler: &ErrorHandler, body: &[u8]) -> usize {
+    match ExportTraceServiceResponse::decode(body) {
+        Ok(res) => {
+            let Some(err) = res.partial_success else {
+                return 0;
+            };
+            if !err.error_message.is_empty() || err.rejected_spans != 0 {
+                error_handler.handle(OtlpError::PartialSuccess {
+                    rejected_spans: err.rejected_spans,
+                    message: err.error_message,
+                });
+            }
+            err.rejected_spans.max(0) as usize
+        }
+        Err(err) => {
+            error_handler.handle(OtlpError::InvalidResponse(Box::new(err)));
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sends_uncompressed_by_default() {
+        assert_eq!(CompressionConfig::default().compress(&[b'a'; 4096]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn compresses_bodies_above_the_threshold() {
+        use std::io::Read;
+
+        let body = vec![b'a'; 100];
+        let mut config = CompressionConfig {
+            codec: Compression::Gzip,
+            threshold: 101,
+        };
+        assert_eq!(config.compress(&body), None);
+
+        config.threshold = 100;
+        let (compressed, encoding) = config.compress(&body).unwrap();
+        assert_eq!(encoding, "gzip");
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, body);
+    }
+
+    #[test]
+    fn signal_headers_replace_common_headers() {
+        let header = |k: &str, v: &str| (k.to_string(), v.to_string());
+        let headers = merge_headers(
+            &[header("Authorization", "common"), header("X-Team", "a")],
+            vec![header("authorization", "traces")],
+        );
+        assert_eq!(
+            headers,
+            [header("X-Team", "a"), header("authorization", "traces")]
+        );
+    }
+
+    #[test]
+    fn provided_headers_are_evaluated_per_request() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let headers = SignalHeaders {
+            traces: vec![("Authorization".to_string(), "static".to_string())],
+            provider: Some({
+                let calls = calls.clone();
+                Arc::new(move || {
+                    let n = calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    vec![("Authorization".to_string(), format!("token-{n}"))]
+                })
+            }),
+            ..Default::default()
+        };
+        assert_eq!(headers.for_traces()[0].1, "token-0");
+        assert_eq!(headers.for_traces()[0].1, "token-1");
+        assert_eq!(headers.for_metrics()[0].1, "token-2");
+    }
+
+    #[test]
+    fn requests_time_out() {
+        use std::time::Instant;
+
+        // Accepts connections but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!(
+            "http://{}/v1/traces",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        std::thread::spawn(move || {
+            let connections: Vec<_> = listener.incoming().collect();
+            drop(connections);
+        });
+
+        let timeout = Duration::from_millis(100);
+        for agent_config in [
+            AgentConfig {
+                read_timeout: Some(timeout),
+                request_timeout: None,
+                ..Default::default()
+            },
+            AgentConfig {
+                request_timeout: Some(timeout),
+                ..Default::default()
+            },
+        ] {
+            let client = HttpClient::new(agent_config, Default::default(), Default::default());
+            let started = Instant::now();
+            assert!(client.send_traces(&url, &[], &[]).is_err());
+            assert!(started.elapsed() < Duration::from_secs(5));
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+use tracing::field::Empty;
+
+use crate::{
+    extract_http_headers, inject_http_headers, new_trace_id, propagation::b3, span_context_for,
+    TraceRoot,
+};
+
+/// Creates the `SERVER` span of a request, registered as a child of the remote parent of
+/// its W3C `traceparent` header if any, or as the root of a new trace.
+pub(crate) fn server_span<B>(request: &http::Request<B>) -> tracing::Span {
+    let method = request.method().as_str();
+    let span = tracing::info_span!(
+        "HTTP request",
+        span.name = method,
+        span.kind = "server",
+        span.status = Empty,
+        http.request.method = method,
+        url.path = request.uri().path(),
+        url.query = request.uri().query(),
+        url.scheme = request.uri().scheme_str(),
+        network.protocol.version = protocol_version(request.version()),
+        user_agent.original = Empty,
+        http.route = Empty,
+        http.response.status_code = Empty,
+    );
+    if let Some(user_agent) = request
+        .headers()
+        .get(http::header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+    {
+        span.record("user_agent.original", user_agent);
+    }
+    #[cfg(feature = "axum")]
+    if let Some(route) = request.extensions().get::<axum::extract::MatchedPath>() {
+        span.record("http.route", route.as_str());
+        span.record("span.name", format!("{method} {}", route.as_str()));
+    }
+    let root = match extract_http_headers(request.headers()) {
+        Some(remote) => TraceRoot::remote(remote),
+        None => TraceRoot::new(new_trace_id()),
+    };
+    // Cannot fail, the span was created by the current subscriber
+    let _ = root.register_for(&span);
+    span
+}
+
+fn protocol_version(version: http::Version) -> &'static str {
+    match version {
+        http::Version::HTTP_09 => "0.9",
+        http::Version::HTTP_10 => "1.0",
+        http::Version::HTTP_11 => "1.1",
+        http::Version::HTTP_2 => "2",
+        http::Version::HTTP_3 => "3",
+        _ => "",
+    }
+}
+
+/// The header with the id of the trace of the span to add to its response, if any.
+pub(crate) fn trace_id_header(
+    header: Option<&http::HeaderName>,
+    span: &tracing::Span,
+) -> Option<(http::HeaderName, http::HeaderValue)> {
+    let header = header?.clone();
+    let ctx = span_context_for(span).ok()?;
+    let trace_id = http::HeaderValue::try_from(ctx.trace_id.to_string())
+        .expect("trace id is a valid header value");
+    Some((header, trace_id))
+}
+
+/// Propagation format of the trace context of outgoing requests, in addition to the W3C
+/// `traceparent` and `tracestate` headers, see `ClientTraceLayer::propagate` and
+/// `HyperClientTrace::propagate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Propagation {
+    /// The single `b3` header of Zipkin.
+    B3,
+}
+
+/// Injects the context of the span of a request into its headers, in the W3C trace context
+/// and the additional propagation formats.
+pub(crate) fn inject(
+    span: &tracing::Span,
+    propagation: &[Propagation],
+    headers: &mut http::HeaderMap,
+) {
+    let Ok(ctx) = span_context_for(span) else {
+        return;
+    };
+    inject_http_headers(&ctx, headers);
+    for propagation in propagation {
+        match propagation {
+            Propagation::B3 => {
+                let b3 = http::HeaderValue::try_from(b3(&ctx)).expect("b3 is a valid header value");
+                headers.insert("b3", b3);
+            }
+        }
+    }
+}
+
+/// Creates the `CLIENT` span of a request, as a child of the current span.
+pub(crate) fn client_span<B>(request: &http::Request<B>) -> tracing::Span {
+    let method = request.method().as_str();
+    let uri = request.uri();
+    tracing::info_span!(
+        "HTTP request",
+        span.name = method,
+        span.kind = "client",
+        span.status = Empty,
+        http.request.method = method,
+        url.full = %uri,
+        server.address = uri.host(),
+        server.port = uri.port_u16().map(i64::from),
+        network.protocol.version = protocol_version(request.version()),
+        http.response.status_code = Empty,
+    )
+}
+
+pin_project! {
+    /// Response future of the services tracing HTTP requests, ending the span of the
+    /// request once the response is ready.
+    #[derive(Debug)]
+    pub struct TraceFuture<F> {
+        #[pin]
+        inner: F,
+        span: tracing::Span,
+        // The lowest status code of failed requests
+        error_status: http::StatusCode,
+        response_header: Option<(http::HeaderName, http::HeaderValue)>,
+    }
+}
+
+impl<F> TraceFuture<F> {
+    pub(crate) fn server(
+        inner: F,
+        span: tracing::Span,
+        response_header: Option<(http::HeaderName, http::HeaderValue)>,
+    ) -> Self {
+        Self {
+            inner,
+            span,
+            error_status: http::StatusCode::INTERNAL_SERVER_ERROR,
+            response_header,
+        }
+    }
+
+    pub(crate) fn client(inner: F, span: tracing::Span) -> Self {
+        Self {
+            inner,
+            span,
+            error_status: http::StatusCode::BAD_REQUEST,
+            response_header: None,
+        }
+    }
+}
+
+impl<F, ResBody, E> Future for TraceFuture<F>
+where
+    F: Future<Output = Result<http::Response<ResBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let _entered = this.span.enter();
+        let mut result = ready!(this.inner.poll(cx));
+        match &mut result {
+            Ok(response) => {
+                if let Some((header, value)) = this.response_header.take() {
+                    response.headers_mut().insert(header, value);
+                }
+                let status = response.status();
+                // As an i64, u64 values are recorded as strings
+                this.span
+                    .record("http.response.status_code", i64::from(status.as_u16()));
+                if status >= *this.error_status {
+                    this.span.record("span.status", "error");
+                }
+            }
+            Err(_) => {
+                this.span.record("span.status", "error");
+            }
+        }
+        Poll::Ready(result)
+    }
+}
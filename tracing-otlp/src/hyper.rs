@@ -0,0 +1,200 @@
+use hyper::service::Service;
+
+use crate::http_trace::{
+    client_span, inject, server_span, trace_id_header, Propagation, TraceFuture,
+};
+
+/// `hyper` service tracing each request handled by the inner service in a `SERVER` span,
+/// continuing the trace of the W3C `traceparent` header of the request if any.
+///
+/// The spans are the same as those of `ServerTraceLayer`, for servers using `hyper`
+/// without `tower`.
+///
+/// # Examples
+/// ```
+/// # use std::convert::Infallible;
+/// let service = tracing_otlp::HyperServerTrace::new(hyper::service::service_fn(
+///     |_: http::Request<String>| async {
+///         Ok::<_, Infallible>(http::Response::new(String::from("hello")))
+///     },
+/// ))
+/// .trace_id_header(http::HeaderName::from_static("x-trace-id"));
+/// // Then serve connections with `hyper::server::conn::http1::Builder::serve_connection`
+/// ```
+#[derive(Debug, Clone)]
+pub struct HyperServerTrace<S> {
+    inner: S,
+    trace_id_header: Option<http::HeaderName>,
+}
+
+impl<S> HyperServerTrace<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            trace_id_header: None,
+        }
+    }
+
+    /// Adds the id of the trace of each request to its response in the given header, so
+    /// clients can refer to the trace, e.g. in their own logs or error reports.
+    pub fn trace_id_header(mut self, header: http::HeaderName) -> Self {
+        self.trace_id_header = Some(header);
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for HyperServerTrace<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TraceFuture<S::Future>;
+
+    fn call(&self, request: http::Request<ReqBody>) -> Self::Future {
+        let span = server_span(&request);
+        let response_header = trace_id_header(self.trace_id_header.as_ref(), &span);
+        let inner = span.in_scope(|| self.inner.call(request));
+        TraceFuture::server(inner, span, response_header)
+    }
+}
+
+/// `hyper` service tracing each request sent by the inner service in a `CLIENT` span and
+/// propagating its context in the W3C `traceparent` header of the request.
+///
+/// The spans are the same as those of `ClientTraceLayer`. Wrap the connection or client
+/// sending the requests in a service, such as with [`hyper::service::service_fn`].
+///
+/// # Examples
+/// ```
+/// # use std::convert::Infallible;
+/// # use tracing_otlp::{HyperClientTrace, Propagation};
+/// # #[derive(Clone)]
+/// # struct Client;
+/// # impl Client {
+/// #     async fn request(&self, _: http::Request<String>) -> Result<http::Response<String>, Infallible> {
+/// #         Ok(http::Response::new(String::new()))
+/// #     }
+/// # }
+/// let client = Client;
+/// let client = HyperClientTrace::new(hyper::service::service_fn(move |request| {
+///     let client = client.clone();
+///     async move { client.request(request).await }
+/// }))
+/// .propagate(Propagation::B3);
+/// ```
+#[derive(Debug, Clone)]
+pub struct HyperClientTrace<S> {
+    inner: S,
+    propagation: Vec<Propagation>,
+}
+
+impl<S> HyperClientTrace<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            propagation: Vec::new(),
+        }
+    }
+
+    /// Also propagates the trace context in the given format, for services which do not
+    /// support the W3C trace context yet.
+    pub fn propagate(mut self, propagation: Propagation) -> Self {
+        if !self.propagation.contains(&propagation) {
+            self.propagation.push(propagation);
+        }
+        self
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for HyperClientTrace<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TraceFuture<S::Future>;
+
+    fn call(&self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let span = client_span(&request);
+        inject(&span, &self.propagation, request.headers_mut());
+        let inner = span.in_scope(|| self.inner.call(request));
+        TraceFuture::client(inner, span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+
+    use hyper::service::service_fn;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{
+        prost::trace::v1::{span::SpanKind, status::StatusCode},
+        test::{capture_exporter, current_thread_runtime},
+    };
+
+    /// Responds with the headers of the request, and a 503 when it has `x-fail`.
+    async fn echo(request: http::Request<String>) -> Result<http::Response<String>, Infallible> {
+        let mut response = http::Response::new(String::new());
+        *response.headers_mut() = request.headers().clone();
+        if request.headers().contains_key("x-fail") {
+            *response.status_mut() = http::StatusCode::SERVICE_UNAVAILABLE;
+        }
+        Ok(response)
+    }
+
+    #[test]
+    fn traces_server_and_client_requests() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let runtime = current_thread_runtime();
+        let (server_response, client_response) =
+            tracing::subscriber::with_default(subscriber, || {
+                let server = HyperServerTrace::new(service_fn(echo))
+                    .trace_id_header(http::HeaderName::from_static("x-trace-id"));
+                let request = http::Request::get("/health")
+                    .header(
+                        "traceparent",
+                        "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+                    )
+                    .header("x-fail", "1")
+                    .body(String::new())
+                    .unwrap();
+                let server_response = runtime.block_on(server.call(request)).unwrap();
+
+                let client = HyperClientTrace::new(service_fn(echo)).propagate(Propagation::B3);
+                let client_response = tracing::info_span!("job").in_scope(|| {
+                    crate::register_dist_tracing_root(crate::TraceId(1), None).unwrap();
+                    let request = http::Request::get("http://api:8080/users")
+                        .body(String::new())
+                        .unwrap();
+                    runtime.block_on(client.call(request)).unwrap()
+                });
+                (server_response, client_response)
+            });
+
+        assert_eq!(
+            server_response.headers()["x-trace-id"],
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        let headers = client_response.headers();
+        assert!(headers["traceparent"]
+            .to_str()
+            .unwrap()
+            .starts_with("00-00000000000000000000000000000001-"));
+        assert!(headers.contains_key("b3"));
+
+        let spans = capture.0.lock().unwrap();
+        let (server, client) = (&spans[0], &spans[1]);
+        assert_eq!(server.kind, SpanKind::Server as i32);
+        assert_eq!(
+            server.status.as_ref().map(|status| status.code),
+            Some(StatusCode::Error as i32)
+        );
+        assert_eq!(client.kind, SpanKind::Client as i32);
+        assert_eq!(client.trace_id, crate::TraceId(1).to_bytes());
+    }
+}
@@ -1,9 +1,22 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 /// Unique Span identifier.
 ///
-/// Wraps a `u64`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Wraps a `u64`. Formats as, and parses from, 16 hexadecimal characters as used by W3C
+/// Trace Context and OTLP/JSON. `Display` and `FromStr` are guaranteed to round-trip for
+/// valid (non-zero) ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct SpanId(pub u64);
 
+impl SpanId {
+    /// Returns the big-endian byte representation used by OTLP.
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+}
+
 impl From<u64> for SpanId {
     fn from(value: u64) -> Self {
         SpanId(value)
@@ -16,10 +29,85 @@ impl From<SpanId> for u64 {
     }
 }
 
+impl Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl FromStr for SpanId {
+    type Err = ParseIdError;
+
+    /// Parses a Span Id from 16 hexadecimal characters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s, 16)
+            .and_then(|id| u64::try_from(id).map_err(|_| ParseIdError::InvalidLength))
+            .map(SpanId)
+    }
+}
+
+/// Generates the ids of new traces and spans.
+///
+/// Set a custom generator using [`crate::Builder::id_generator`], e.g. to create ids
+/// compatible with AWS X-Ray, time-prefixed ids, or deterministic ids in tests. The default
+/// is [`RandomIdGenerator`].
+pub trait IdGenerator: Send + Sync + 'static {
+    /// Returns the id for a new trace. Must not be zero.
+    fn new_trace_id(&self) -> TraceId;
+
+    /// Returns the id for a new span. Must not be zero, and should not repeat within a trace.
+    fn new_span_id(&self) -> SpanId;
+}
+
+/// Generates random trace ids from a UUID V4, and span ids which are unique within this
+/// process.
+///
+/// `tracing::span::Id`s are reused by the registry once a span closes, so they can not be
+/// used directly. Instead a counter starting at a random offset is passed through a
+/// bijective mixing function: span ids never repeat within the process, while still being
+/// spread out uniformly so that ids of different processes are unlikely to collide.
+#[derive(Debug)]
+pub struct RandomIdGenerator {
+    counter: AtomicU64,
+}
+
+impl Default for RandomIdGenerator {
+    fn default() -> Self {
+        Self {
+            counter: AtomicU64::new(rand::random()),
+        }
+    }
+}
+
+impl IdGenerator for RandomIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        TraceId::new()
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        loop {
+            let id = mix(self.counter.fetch_add(1, Ordering::Relaxed));
+            // Zero is not a valid span id, which happens once every 2^64 ids
+            if id != 0 {
+                return SpanId(id);
+            }
+        }
+    }
+}
+
+/// The splitmix64 finalizer. Every step is invertible, so this is a permutation of `u64`.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
 /// Uniquely identifies a single distributed trace.
 ///
-/// Wraps a u128, and can be generated new from a UUID V4.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Wraps a u128, and can be generated new from a UUID V4. Formats as, and parses from, 32
+/// hexadecimal characters as used by W3C Trace Context and OTLP/JSON. `Display` and
+/// `FromStr` are guaranteed to round-trip for valid (non-zero) ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct TraceId(pub u128);
 
 impl Default for TraceId {
@@ -32,6 +120,11 @@ impl TraceId {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Returns the big-endian byte representation used by OTLP.
+    pub fn to_bytes(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
 }
 
 impl From<u128> for TraceId {
@@ -45,3 +138,221 @@ impl From<TraceId> for u128 {
         value.0
     }
 }
+
+impl Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+impl FromStr for TraceId {
+    type Err = ParseIdError;
+
+    /// Parses a Trace Id from 32 hexadecimal characters.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex(s, 32).map(TraceId)
+    }
+}
+
+/// Error returned when parsing a [`TraceId`] or [`SpanId`] from a string fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseIdError {
+    /// The string does not have the exact amount of characters required for the id.
+    InvalidLength,
+    /// The string contains a character which is not a hexadecimal digit.
+    InvalidCharacter,
+    /// The id consists of only zeroes, which is not a valid id.
+    Zero,
+}
+
+impl Display for ParseIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseIdError::InvalidLength => "invalid id length",
+            ParseIdError::InvalidCharacter => "invalid hexadecimal character in id",
+            ParseIdError::Zero => "an id may not be all zeroes",
+        })
+    }
+}
+
+impl std::error::Error for ParseIdError {}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::{SpanId, TraceId};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    macro_rules! hex_serde {
+        ($id:ty) => {
+            impl Serialize for $id {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $id {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let s = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+                    s.parse().map_err(D::Error::custom)
+                }
+            }
+        };
+    }
+
+    hex_serde!(TraceId);
+    hex_serde!(SpanId);
+}
+
+#[cfg(feature = "otel-interop")]
+mod otel_impls {
+    use super::{SpanId, TraceId};
+
+    impl From<SpanId> for opentelemetry::trace::SpanId {
+        fn from(value: SpanId) -> Self {
+            Self::from_bytes(value.to_bytes())
+        }
+    }
+
+    impl From<opentelemetry::trace::SpanId> for SpanId {
+        fn from(value: opentelemetry::trace::SpanId) -> Self {
+            SpanId(u64::from_be_bytes(value.to_bytes()))
+        }
+    }
+
+    impl From<TraceId> for opentelemetry::trace::TraceId {
+        fn from(value: TraceId) -> Self {
+            Self::from_bytes(value.to_bytes())
+        }
+    }
+
+    impl From<opentelemetry::trace::TraceId> for TraceId {
+        fn from(value: opentelemetry::trace::TraceId) -> Self {
+            TraceId(u128::from_be_bytes(value.to_bytes()))
+        }
+    }
+}
+
+fn parse_hex(s: &str, len: usize) -> Result<u128, ParseIdError> {
+    if s.len() != len {
+        return Err(ParseIdError::InvalidLength);
+    }
+    // `from_str_radix` would also accept a leading sign
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseIdError::InvalidCharacter);
+    }
+    match u128::from_str_radix(s, 16) {
+        Ok(0) => Err(ParseIdError::Zero),
+        Ok(id) => Ok(id),
+        Err(_) => Err(ParseIdError::InvalidCharacter),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_id_round_trip() {
+        for id in [1, 0xdead_beef, u128::MAX] {
+            let trace_id = TraceId(id);
+            let s = trace_id.to_string();
+            assert_eq!(s.len(), 32);
+            assert_eq!(trace_id, s.parse().unwrap());
+        }
+        assert_eq!(
+            TraceId(0x4bf92f3577b34da6a3ce929d0e0e4736).to_string(),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+    }
+
+    #[test]
+    fn span_id_round_trip() {
+        for id in [1, 0xdead_beef, u64::MAX] {
+            let span_id = SpanId(id);
+            let s = span_id.to_string();
+            assert_eq!(s.len(), 16);
+            assert_eq!(span_id, s.parse().unwrap());
+        }
+        assert_eq!(SpanId(0x00f067aa0ba902b7).to_string(), "00f067aa0ba902b7");
+    }
+
+    #[test]
+    fn generated_span_ids_are_unique() {
+        let generator = RandomIdGenerator {
+            counter: AtomicU64::new(u64::MAX - 500),
+        };
+        let ids: std::collections::HashSet<_> =
+            (0..1000).map(|_| generator.new_span_id()).collect();
+        assert_eq!(ids.len(), 1000);
+        assert!(!ids.contains(&SpanId(0)));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("abc".parse::<SpanId>(), Err(ParseIdError::InvalidLength));
+        assert_eq!(
+            "+0f067aa0ba902b7".parse::<SpanId>(),
+            Err(ParseIdError::InvalidCharacter)
+        );
+        assert_eq!(
+            "0000000000000000".parse::<SpanId>(),
+            Err(ParseIdError::Zero)
+        );
+        assert_eq!(
+            "00f067aa0ba902b7".parse::<TraceId>(),
+            Err(ParseIdError::InvalidLength)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let trace_id = TraceId(0x4bf92f3577b34da6a3ce929d0e0e4736);
+        let json = serde_json::to_string(&trace_id).unwrap();
+        assert_eq!(json, r#""4bf92f3577b34da6a3ce929d0e0e4736""#);
+        assert_eq!(serde_json::from_str::<TraceId>(&json).unwrap(), trace_id);
+
+        let span_id = SpanId(0x00f067aa0ba902b7);
+        let json = serde_json::to_string(&span_id).unwrap();
+        assert_eq!(json, r#""00f067aa0ba902b7""#);
+        assert_eq!(serde_json::from_str::<SpanId>(&json).unwrap(), span_id);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_malformed_ids() {
+        for json in [
+            r#""00f067aa0ba902bz""#,
+            r#""00f067aa""#,
+            r#""0000000000000000""#,
+            "42",
+        ] {
+            assert!(serde_json::from_str::<SpanId>(json).is_err(), "{json}");
+        }
+        let err = serde_json::from_str::<TraceId>(r#""not hex""#).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(&ParseIdError::InvalidLength.to_string()));
+    }
+
+    #[cfg(feature = "otel-interop")]
+    #[test]
+    fn converts_opentelemetry_contexts() {
+        let ctx = crate::SpanContext::new(TraceId(0xabc), SpanId(0xdef), true)
+            .with_trace_state("vendor=value");
+        let otel = opentelemetry::trace::SpanContext::from(ctx.clone());
+        assert_eq!(
+            otel.trace_id().to_string(),
+            "00000000000000000000000000000abc"
+        );
+        assert_eq!(otel.span_id().to_string(), "0000000000000def");
+        assert!(otel.is_sampled() && otel.is_remote());
+        assert_eq!(otel.trace_state().get("vendor"), Some("value"));
+        assert_eq!(crate::SpanContext::from(otel), ctx);
+
+        let unsampled = crate::SpanContext::new(TraceId(1), SpanId(2), false);
+        let otel: opentelemetry::trace::SpanContext = unsampled.clone().into();
+        assert_eq!(crate::SpanContext::from(otel), unsampled);
+    }
+}
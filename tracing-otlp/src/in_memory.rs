@@ -0,0 +1,200 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tracing_distributed::{Event, Span, Telemetry, TelemetryLayer};
+
+use crate::{
+    prost::common::v1::any_value::Value, IdGenerator, RandomIdGenerator, SpanId, TraceId, Visitor,
+};
+
+/// A span captured by [`InMemoryTelemetry`], along with the events recorded within it.
+#[derive(Debug, Clone)]
+pub struct CapturedSpan {
+    pub span: Span<Visitor, SpanId, TraceId>,
+    pub events: Vec<Event<Visitor, SpanId, TraceId>>,
+}
+
+impl CapturedSpan {
+    /// Returns the value of the attribute recorded on the span under `key`, if any.
+    pub fn attribute(&self, key: &str) -> Option<&Value> {
+        attribute(&self.span.values, key)
+    }
+
+    /// Returns the value of the attribute recorded under `key` on any event of the span.
+    pub fn event_attribute(&self, key: &str) -> Option<&Value> {
+        self.events
+            .iter()
+            .find_map(|event| attribute(&event.values, key))
+    }
+}
+
+fn attribute<'a>(values: &'a Visitor, key: &str) -> Option<&'a Value> {
+    values
+        .0
+        .iter()
+        .find(|kv| kv.key == key)
+        .and_then(|kv| kv.value.as_ref())
+        .and_then(|value| value.value.as_ref())
+}
+
+/// [`Telemetry`] implementation capturing finished spans and events in memory, so tests can
+/// assert on the traces emitted by the code under test.
+///
+/// Clones share the captured data, keep one to query after installing the layer.
+///
+/// # Examples
+/// ```
+/// # use tracing_subscriber::layer::SubscriberExt;
+/// # use tracing_otlp::InMemoryTelemetry;
+/// let telemetry = InMemoryTelemetry::new();
+/// let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     let span = tracing::info_span!("request", user.id = 7);
+///     let _guard = span.enter();
+///     tracing_otlp::register_dist_tracing_root(tracing_otlp::new_trace_id(), None).unwrap();
+/// });
+///
+/// let request = telemetry.span("request").unwrap();
+/// assert!(request.attribute("user.id").is_some());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryTelemetry {
+    spans: Arc<Mutex<Vec<CapturedSpan>>>,
+    events: Arc<Mutex<Vec<Event<Visitor, SpanId, TraceId>>>>,
+}
+
+impl InMemoryTelemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a [`TelemetryLayer`] capturing into this [`InMemoryTelemetry`], using random
+    /// span ids.
+    pub fn layer(&self) -> TelemetryLayer<Self, SpanId, TraceId> {
+        let id_generator = Arc::new(RandomIdGenerator::default());
+        TelemetryLayer::new("", self.clone(), move |_| id_generator.new_span_id())
+    }
+
+    /// All spans captured so far, in the order in which they finished.
+    pub fn spans(&self) -> Vec<CapturedSpan> {
+        lock(&self.spans).clone()
+    }
+
+    /// All events captured so far which were not part of a span.
+    pub fn events(&self) -> Vec<Event<Visitor, SpanId, TraceId>> {
+        lock(&self.events).clone()
+    }
+
+    /// The first finished span with the given name.
+    pub fn span(&self, name: &str) -> Option<CapturedSpan> {
+        lock(&self.spans)
+            .iter()
+            .find(|captured| captured.span.name == name)
+            .cloned()
+    }
+
+    /// All finished spans with the given name.
+    pub fn spans_named(&self, name: &str) -> Vec<CapturedSpan> {
+        self.spans_matching(|captured| captured.span.name == name)
+    }
+
+    /// All finished spans of the given trace.
+    pub fn trace(&self, trace_id: TraceId) -> Vec<CapturedSpan> {
+        self.spans_matching(|captured| captured.span.trace_id == trace_id)
+    }
+
+    /// The finished spans whose parent is `parent`.
+    pub fn children(&self, parent: &CapturedSpan) -> Vec<CapturedSpan> {
+        self.spans_matching(|captured| {
+            captured.span.trace_id == parent.span.trace_id
+                && captured.span.parent_id == Some(parent.span.id)
+        })
+    }
+
+    /// Removes all captured spans and events.
+    pub fn clear(&self) {
+        lock(&self.spans).clear();
+        lock(&self.events).clear();
+    }
+
+    fn spans_matching(&self, f: impl Fn(&CapturedSpan) -> bool) -> Vec<CapturedSpan> {
+        lock(&self.spans)
+            .iter()
+            .filter(|captured| f(captured))
+            .cloned()
+            .collect()
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panicking test should not hide the captured data from other assertions
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Telemetry for InMemoryTelemetry {
+    type Visitor = Visitor;
+
+    type TraceId = TraceId;
+
+    type SpanId = SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        Default::default()
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        lock(&self.spans).push(CapturedSpan { span, events });
+    }
+
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        lock(&self.events).push(event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn captures_spans_and_events() {
+        let telemetry = InMemoryTelemetry::new();
+        let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("outside of a trace");
+
+            let root = tracing::info_span!("request");
+            let _root = root.enter();
+            crate::register_dist_tracing_root(TraceId(1), None).unwrap();
+            for i in 0..2i64 {
+                let child = tracing::info_span!("query", i);
+                let _child = child.enter();
+                tracing::info!(rows = 3i64, "done");
+            }
+        });
+
+        assert_eq!(telemetry.spans().len(), 3);
+        assert_eq!(telemetry.events().len(), 1);
+
+        let root = telemetry.span("request").unwrap();
+        let children = telemetry.children(&root);
+        assert_eq!(children.len(), 2);
+        assert_eq!(telemetry.spans_named("query").len(), 2);
+        assert_eq!(telemetry.trace(TraceId(1)).len(), 3);
+        assert_eq!(children[1].attribute("i"), Some(&Value::IntValue(1)));
+        assert_eq!(
+            children[0].event_attribute("rows"),
+            Some(&Value::IntValue(3))
+        );
+
+        telemetry.clear();
+        assert!(telemetry.spans().is_empty());
+    }
+}
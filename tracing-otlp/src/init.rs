@@ -0,0 +1,151 @@
+use std::{str::FromStr, time::Duration};
+
+use tracing_subscriber::{filter::Targets, layer::SubscriberExt, Registry};
+use url::Url;
+
+use crate::{Builder, Otlp, OtlpBuildError};
+
+/// Level filter used when `RUST_LOG` is unset or invalid.
+const DEFAULT_FILTER: &str = "info";
+
+/// Sets up exporting to `endpoint` with the default [`Builder`] settings, see
+/// [`Builder::init`].
+///
+/// # Examples
+/// ```no_run
+/// let _guard = tracing_otlp::init("http://127.0.0.1:4318").unwrap();
+/// // Spans finished before `_guard` is dropped, at the end of `main`, are exported
+/// ```
+pub fn init<U>(endpoint: U) -> Result<FlushGuard, OtlpBuildError>
+where
+    U: TryInto<Url>,
+    U::Error: Into<OtlpBuildError>,
+{
+    Builder::new().init(endpoint)
+}
+
+impl Builder {
+    /// Builds the layer, installs it together with a level filter on a [`Registry`] as the
+    /// global default subscriber, and returns a guard flushing the exporter when dropped.
+    ///
+    /// The filter is read from `RUST_LOG` as `target=level` directives, such as
+    /// `info,my_crate=debug`, and defaults to `info`. Keep the guard alive until the end of
+    /// `main`, so the last spans are exported before the process exits.
+    ///
+    /// Fails with [`OtlpBuildError::SubscriberAlreadySet`] if a global default subscriber
+    /// was already set.
+    pub fn init<U>(self, endpoint: U) -> Result<FlushGuard, OtlpBuildError>
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        let layer = self.build(endpoint)?;
+        let guard = FlushGuard {
+            otlp: layer.telemetry().clone(),
+            timeout: Duration::from_secs(5),
+        };
+        let filter = std::env::var("RUST_LOG")
+            .ok()
+            .and_then(|filter| Targets::from_str(&filter).ok())
+            .unwrap_or_else(|| DEFAULT_FILTER.parse().expect("valid default filter"));
+        // The filter applies to the whole subscriber, so spans it disables are not missing
+        // as parents of the spans exported by the layer
+        let subscriber = Registry::default().with(filter).with(layer);
+        tracing::subscriber::set_global_default(subscriber)
+            .map_err(|_| OtlpBuildError::SubscriberAlreadySet)?;
+        Ok(guard)
+    }
+}
+
+/// Flushes the [`Otlp`] exporter when dropped, waiting at most for its timeout. Returned by
+/// [`init`] and [`Builder::init`].
+#[must_use = "the exporter is flushed when the guard is dropped"]
+pub struct FlushGuard {
+    otlp: Otlp,
+    timeout: Duration,
+}
+
+impl FlushGuard {
+    /// Sets how long dropping the guard waits for the export, defaults to 5 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Flushes the exporter right away, waiting at most for the timeout of the guard, and
+    /// returns whether the export finished in time. See [`Otlp::flush`].
+    ///
+    /// Call this at the end of each invocation on AWS Lambda and similar platforms, which
+    /// freeze the process between invocations so the worker does not get to send the spans
+    /// of the invocation until the next one, if there is any.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # struct Request;
+    /// # struct Response;
+    /// # fn handle(request: Request) -> Response { Response }
+    /// fn handler(guard: &tracing_otlp::FlushGuard, request: Request) -> Response {
+    ///     let response = tracing::info_span!("invocation").in_scope(|| handle(request));
+    ///     guard.flush_before_freeze();
+    ///     response
+    /// }
+    /// ```
+    pub fn flush_before_freeze(&self) -> bool {
+        self.otlp.flush(self.timeout)
+    }
+
+    /// The exporter flushed by this guard.
+    pub fn otlp(&self) -> &Otlp {
+        &self.otlp
+    }
+}
+
+impl Drop for FlushGuard {
+    fn drop(&mut self) {
+        self.otlp.flush(self.timeout);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{register_dist_tracing_root, test::mock_collector_with, TraceId};
+
+    #[test]
+    fn flush_before_freeze_waits_for_the_export() {
+        // Responds after a delay, so returning early would leave the span unexported
+        let endpoint = mock_collector_with(|_| {
+            std::thread::sleep(Duration::from_millis(200));
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        });
+        let layer = Builder::new()
+            .send_interval(Duration::from_secs(3600))
+            .build(endpoint.as_str())
+            .unwrap();
+        let guard = FlushGuard {
+            otlp: layer.telemetry().clone(),
+            timeout: Duration::from_secs(5),
+        };
+        tracing::subscriber::with_default(Registry::default().with(layer), || {
+            tracing::info_span!("invocation").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+
+        let started = Instant::now();
+        assert!(guard.flush_before_freeze());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(guard.otlp().stats().spans_exported(), 1);
+
+        // Gives up once the timeout of the guard elapsed
+        let guard = guard.timeout(Duration::from_millis(50));
+        tracing::subscriber::with_default(Registry::default().with(guard.otlp().layer()), || {
+            tracing::info_span!("invocation").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+        assert!(!guard.flush_before_freeze());
+    }
+}
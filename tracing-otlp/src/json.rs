@@ -0,0 +1,231 @@
+//! Encoding of OTLP messages following the OTLP/JSON mapping of protobuf.
+//!
+//! Ids are lowercase hexadecimal strings, field names are camelCase, enums are integers and
+//! 64 bit integers are decimal strings.
+
+use serde_json::{json, Map, Value as Json};
+
+use crate::prost::{
+    collector::trace::v1::ExportTraceServiceRequest,
+    common::v1::{any_value::Value, AnyValue, InstrumentationScope, KeyValue},
+    resource::v1::Resource,
+    trace::v1::{span, Span},
+};
+
+pub(crate) fn trace_request_to_json(req: &ExportTraceServiceRequest) -> Json {
+    json!({
+        "resourceSpans": req.resource_spans.iter().map(|resource_spans| {
+            let mut object = Map::new();
+            if let Some(resource) = &resource_spans.resource {
+                object.insert("resource".to_string(), resource_to_json(resource));
+            }
+            object.insert(
+                "scopeSpans".to_string(),
+                resource_spans
+                    .scope_spans
+                    .iter()
+                    .map(|scope_spans| {
+                        let mut object = Map::new();
+                        if let Some(scope) = &scope_spans.scope {
+                            object.insert("scope".to_string(), scope_to_json(scope));
+                        }
+                        object.insert(
+                            "spans".to_string(),
+                            scope_spans.spans.iter().map(span_to_json).collect(),
+                        );
+                        object.insert("schemaUrl".to_string(), json!(scope_spans.schema_url));
+                        Json::Object(object)
+                    })
+                    .collect(),
+            );
+            object.insert("schemaUrl".to_string(), json!(resource_spans.schema_url));
+            Json::Object(object)
+        }).collect::<Vec<_>>(),
+    })
+}
+
+fn resource_to_json(resource: &Resource) -> Json {
+    json!({
+        "attributes": attributes_to_json(&resource.attributes),
+        "droppedAttributesCount": resource.dropped_attributes_count,
+    })
+}
+
+fn scope_to_json(scope: &InstrumentationScope) -> Json {
+    json!({
+        "name": scope.name,
+        "version": scope.version,
+        "attributes": attributes_to_json(&scope.attributes),
+        "droppedAttributesCount": scope.dropped_attributes_count,
+    })
+}
+
+fn span_to_json(span: &Span) -> Json {
+    let mut object = json!({
+        "traceId": hex(&span.trace_id),
+        "spanId": hex(&span.span_id),
+        "traceState": span.trace_state,
+        "parentSpanId": hex(&span.parent_span_id),
+        "flags": span.flags,
+        "name": span.name,
+        "kind": span.kind,
+        "startTimeUnixNano": span.start_time_unix_nano.to_string(),
+        "endTimeUnixNano": span.end_time_unix_nano.to_string(),
+        "attributes": attributes_to_json(&span.attributes),
+        "droppedAttributesCount": span.dropped_attributes_count,
+        "events": span.events.iter().map(event_to_json).collect::<Vec<_>>(),
+        "droppedEventsCount": span.dropped_events_count,
+        "links": span.links.iter().map(link_to_json).collect::<Vec<_>>(),
+        "droppedLinksCount": span.dropped_links_count,
+    });
+    if let Some(status) = &span.status {
+        object["status"] = json!({
+            "message": status.message,
+            "code": status.code,
+        });
+    }
+    object
+}
+
+fn event_to_json(event: &span::Event) -> Json {
+    json!({
+        "timeUnixNano": event.time_unix_nano.to_string(),
+        "name": event.name,
+        "attributes": attributes_to_json(&event.attributes),
+        "droppedAttributesCount": event.dropped_attributes_count,
+    })
+}
+
+fn link_to_json(link: &span::Link) -> Json {
+    json!({
+        "traceId": hex(&link.trace_id),
+        "spanId": hex(&link.span_id),
+        "traceState": link.trace_state,
+        "attributes": attributes_to_json(&link.attributes),
+        "droppedAttributesCount": link.dropped_attributes_count,
+        "flags": link.flags,
+    })
+}
+
+fn attributes_to_json(attributes: &[KeyValue]) -> Json {
+    attributes
+        .iter()
+        .map(|kv| {
+            json!({
+                "key": kv.key,
+                "value": kv.value.as_ref().map_or(json!({}), any_value_to_json),
+            })
+        })
+        .collect()
+}
+
+fn any_value_to_json(value: &AnyValue) -> Json {
+    match &value.value {
+        None => json!({}),
+        Some(Value::StringValue(s)) => json!({ "stringValue": s }),
+        Some(Value::BoolValue(b)) => json!({ "boolValue": b }),
+        Some(Value::IntValue(i)) => json!({ "intValue": i.to_string() }),
+        Some(Value::DoubleValue(d)) => json!({ "doubleValue": d }),
+        Some(Value::BytesValue(bytes)) => json!({ "bytesValue": base64(bytes) }),
+        Some(Value::ArrayValue(array)) => json!({
+            "arrayValue": {
+                "values": array.values.iter().map(any_value_to_json).collect::<Vec<_>>(),
+            },
+        }),
+        Some(Value::KvlistValue(kvlist)) => json!({
+            "kvlistValue": { "values": attributes_to_json(&kvlist.values) },
+        }),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prost::{
+        common::v1::ArrayValue,
+        trace::v1::{ResourceSpans, ScopeSpans},
+    };
+
+    #[test]
+    fn encodes_bytes_as_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn encodes_spans_following_otlp_json() {
+        let req = ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: None,
+                scope_spans: vec![ScopeSpans {
+                    scope: None,
+                    spans: vec![Span {
+                        trace_id: vec![0xab; 16],
+                        span_id: vec![0, 0, 0, 0, 0, 0, 0, 1],
+                        name: "request".to_string(),
+                        start_time_unix_nano: 1_700_000_000_000_000_000,
+                        attributes: vec![
+                            KeyValue::new("http.status_code".to_string(), 200i64.into()),
+                            KeyValue::new(
+                                "tags".to_string(),
+                                Value::ArrayValue(ArrayValue {
+                                    values: vec![AnyValue {
+                                        value: Some("a".into()),
+                                    }],
+                                }),
+                            ),
+                        ],
+                        ..Default::default()
+                    }],
+                    schema_url: "".to_string(),
+                }],
+                schema_url: "".to_string(),
+            }],
+        };
+
+        let json = trace_request_to_json(&req);
+        let span = &json["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"], "abababababababababababababababab");
+        assert_eq!(span["spanId"], "0000000000000001");
+        assert_eq!(span["parentSpanId"], "");
+        assert_eq!(span["startTimeUnixNano"], "1700000000000000000");
+        assert_eq!(
+            span["attributes"],
+            json!([
+                { "key": "http.status_code", "value": { "intValue": "200" } },
+                {
+                    "key": "tags",
+                    "value": { "arrayValue": { "values": [{ "stringValue": "a" }] } },
+                },
+            ])
+        );
+        assert!(span.get("status").is_none());
+    }
+}
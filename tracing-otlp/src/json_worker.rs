@@ -0,0 +1,92 @@
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::Value as Json;
+use url::Url;
+
+use crate::{
+    error::{ErrorHandler, OtlpBuildError},
+    http::validate_header,
+    OtlpError,
+};
+
+/// Worker posting the JSON values it receives as arrays to an HTTP endpoint, on an interval.
+///
+/// Used by the backends with a JSON batch API, such as Zipkin and Honeycomb.
+pub(crate) struct JsonWorker {
+    endpoint: Url,
+    rx: Receiver<Json>,
+    send_interval: Duration,
+    headers: Vec<(String, String)>,
+    agent: ureq::Agent,
+    error_handler: ErrorHandler,
+}
+
+impl JsonWorker {
+    /// Spawns a worker thread with the given name, returning the sender to queue values with.
+    pub(crate) fn spawn(
+        name: &str,
+        endpoint: Url,
+        send_interval: Duration,
+        headers: Vec<(String, String)>,
+        error_handler: ErrorHandler,
+    ) -> Result<Sender<Json>, OtlpBuildError> {
+        for (name, value) in &headers {
+            validate_header(name, value.as_bytes())?;
+        }
+        let (tx, rx) = channel();
+        let mut worker = JsonWorker {
+            endpoint,
+            rx,
+            send_interval,
+            headers,
+            agent: ureq::Agent::new(),
+            error_handler,
+        };
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || worker.run_loop())
+            .map_err(|err| OtlpBuildError::Spawn(Arc::new(err)))?;
+        Ok(tx)
+    }
+
+    fn run_loop(&mut self) {
+        let mut values = Vec::new();
+        let mut last_send = Instant::now();
+        loop {
+            let next_send = last_send + self.send_interval;
+            match self.rx.recv_timeout(next_send - Instant::now()) {
+                Ok(value) => values.push(value),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if last_send.elapsed() < self.send_interval {
+                continue;
+            }
+            last_send = Instant::now();
+            if values.is_empty() {
+                continue;
+            }
+
+            let body = Json::Array(std::mem::take(&mut values)).to_string();
+            let req = self
+                .agent
+                .request_url("POST", &self.endpoint)
+                .set("Content-Type", "application/json");
+            let req = self.headers.iter().fold(req, |r, (k, v)| r.set(k, v));
+            if let Err(err) = req.send_string(&body) {
+                self.error_handler.handle(OtlpError::Export {
+                    destination: self.endpoint.to_string(),
+                    source: Box::new(err),
+                });
+            }
+        }
+    }
+}
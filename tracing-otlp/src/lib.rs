@@ -1,42 +1,273 @@
 //! This crate provides a `tracing` implementation for the OpenTelemetry protocol (OTLP),
 //! specifically on top of http/protobuf. It is based on `distributed-tracing` in order
 //! to allow for multi-process tracing.
+//!
+//! A few fields of spans set what is exported for the span itself, instead of attributes:
+//! - `span.name` overrides the name of the span, which must be static for `tracing`.
+//! - `span.kind`, one of `"server"`, `"client"`, `"producer"`, `"consumer"` or `"internal"`,
+//!   sets the kind of the span, which is unspecified otherwise.
+//! - `span.status`, `"ok"` or `"error"`, sets the status of the span, along with the
+//!   message of errors in `span.status_message`.
+//!
+//! The `otel.name`, `otel.kind`, `otel.status_code` and `otel.status_message` fields of
+//! `tracing-opentelemetry` work the same, so code instrumented for it exports as expected.
+//!
+//! Every span has a `busy_ns` and an `idle_ns` attribute, the nanoseconds it was entered
+//! and not entered between its start and end, like the timings of `tracing-subscriber`'s
+//! `fmt` layer. A high idle time of an async task tells a starving executor apart from
+//! slow work. They are added after the [`SpanProcessor`]s ran, and can be filtered out with
+//! [`Builder::deny_attributes`].
+//!
+//! ```
+//! let span = tracing::info_span!(
+//!     "request",
+//!     span.name = "GET /users",
+//!     span.kind = "server",
+//!     span.status = tracing::field::Empty,
+//! );
+//! // Once the request failed
+//! span.record("span.status", "error");
+//! ```
 
 use std::{
-    str::FromStr,
-    sync::mpsc::{channel, Sender},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, SyncSender, TrySendError},
+        Arc, RwLock,
+    },
     thread,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crate::prost::{common::v1::any_value::Value, trace::v1::span};
+use crate::attributes::AttributeRules;
+use crate::builder::LayerFilter;
+use crate::error::ErrorHandler;
+use crate::metrics::ExporterCounters;
+use crate::processor::Processors;
+use crate::prost::common::v1::{any_value::Value, KeyValue};
+use crate::prost::trace::v1::{
+    span::{self, SpanKind},
+    status::StatusCode,
+};
+use handle::{RuntimeHeaders, SettingChanges};
 use prost::trace::v1::span::Link;
+use sampling::Sampling;
+use tail_sampling::QueuedSpan;
+use trace_attributes::TraceAttributes;
 pub use tracing_distributed::{Telemetry, TelemetryLayer, TraceCtxError};
-use url::Url;
-use worker::Worker;
+use worker::{Destination, Worker, WorkerConfig, WorkerMessage};
 
 use crate::prost::trace::v1::Span;
 
+pub use attributes::KeyPattern;
+#[cfg(feature = "axum")]
+pub use axum::DistTraceContext;
 pub use builder::Builder;
+#[cfg(feature = "chrome")]
+pub use chrome::ChromeTraceTelemetry;
+#[cfg(feature = "serde")]
+pub use config::OtlpConfig;
+pub use error::{ConnectionError, OtlpBuildError, OtlpError, DIAGNOSTICS_TARGET};
+#[cfg(feature = "file")]
+pub use file::OtlpFile;
+pub use future::DistTraceExt;
+#[cfg(feature = "grpc")]
+pub use grpc::{GrpcBody, GrpcClientTrace, GrpcClientTraceLayer, GrpcFuture};
+pub use handle::OtlpHandle;
+#[cfg(feature = "honeycomb")]
+pub use honeycomb::{Honeycomb, HoneycombBuilder};
+pub use http::Compression;
+#[cfg(any(feature = "tower", feature = "hyper"))]
+pub use http_trace::{Propagation, TraceFuture};
+#[cfg(feature = "hyper")]
+pub use hyper::{HyperClientTrace, HyperServerTrace};
+pub use id::IdGenerator;
+pub use id::ParseIdError;
+pub use id::RandomIdGenerator;
 pub use id::SpanId;
 pub use id::TraceId;
+#[cfg(feature = "test-util")]
+pub use in_memory::{CapturedSpan, InMemoryTelemetry};
+pub use init::{init, FlushGuard};
+pub use metrics::{BatchResult, OtlpStats};
+pub use processor::{SpanProcessor, StaticAttributes};
+#[cfg(feature = "http")]
+pub use propagation::{extract_http_headers, inject_http_headers};
+pub use propagation::{parse_traceparent, parse_xray_trace_header, traceparent, xray_trace_header};
+pub use resource::{
+    ContainerDetector, HostDetector, OsDetector, ProcessDetector, ResourceDetector,
+};
+pub use root::{with_remote_parent, with_remote_parent_async, TraceRoot};
+pub use route::Route;
+/// [`tracing_distributed::SpanContext`] specialized to the OTLP SpanId and TraceId provided
+/// by this crate.
+pub type SpanContext = tracing_distributed::SpanContext<SpanId, TraceId>;
+pub use sampling::{Sampler, SamplingDecision};
+pub use spawn::spawn_traced;
+#[cfg(feature = "tokio")]
+pub use spawn::{spawn_child, spawn_linked};
+pub use stdout::StdoutTelemetry;
+pub use tail_sampling::TailSampler;
+#[cfg(feature = "tower")]
+pub use tower::{ClientTrace, ClientTraceLayer, ServerTrace, ServerTraceLayer};
+#[cfg(feature = "file")]
+pub use transport::JsonEncoder;
+pub use transport::{Encoder, ProtobufEncoder, Transport};
 pub use visitor::Visitor;
+#[cfg(feature = "zipkin")]
+pub use zipkin::{Zipkin, ZipkinBuilder};
 
+mod attributes;
+#[cfg(feature = "axum")]
+mod axum;
 mod builder;
+#[cfg(feature = "chrome")]
+mod chrome;
+mod circuit;
+#[cfg(feature = "serde")]
+mod config;
+mod error;
+#[cfg(feature = "file")]
+mod file;
+mod future;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod handle;
+#[cfg(feature = "honeycomb")]
+mod honeycomb;
+mod http;
+#[cfg(any(feature = "tower", feature = "hyper"))]
+mod http_trace;
+#[cfg(feature = "hyper")]
+mod hyper;
 mod id;
+#[cfg(feature = "test-util")]
+mod in_memory;
+mod init;
+#[cfg(feature = "file")]
+mod json;
+#[cfg(any(feature = "zipkin", feature = "honeycomb"))]
+mod json_worker;
+mod metrics;
+mod pool;
+mod processor;
+mod propagation;
 
 pub mod prost;
+mod resource;
+mod root;
+mod route;
+mod sampling;
+mod span_fields;
+mod spawn;
+mod spool;
+mod stdout;
+mod tail_sampling;
+mod time;
+#[cfg(feature = "tokio")]
+mod tokio_worker;
+#[cfg(feature = "tower")]
+mod tower;
+mod trace_attributes;
+mod transport;
 mod visitor;
+#[cfg(feature = "wasm")]
+mod wasm_worker;
 mod worker;
+#[cfg(feature = "zipkin")]
+mod zipkin;
 
-/// Register the current span as the local root of a distributed trace.
+/// The items needed in most applications, for a glob import.
+///
+/// # Examples
+/// ```no_run
+/// use tracing_otlp::prelude::*;
+///
+/// let _guard = tracing_otlp::init("http://127.0.0.1:4318").unwrap();
+/// tracing::info_span!("request").in_scope(|| {
+///     register_dist_tracing_root(TraceId::new(), None).unwrap();
+/// });
+/// ```
+pub mod prelude {
+    pub use crate::{
+        current_dist_trace_ctx, register_dist_tracing_root, set_trace_attribute, Builder,
+        FlushGuard, SpanId, TraceId,
+    };
+    pub use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
+}
+
+/// Register the current span as the local root of a distributed trace. See [`TraceRoot`] to
+/// also set attributes or the start time of the root span.
+///
+/// Whether the trace is sampled is decided by the sampling callback and [`Sampler`] of the
+/// OTLP layer, also when a remote parent is given. Use [`register_dist_tracing_root_sampled`]
+/// to follow the decision of the remote parent instead. With several OTLP layers in one
+/// subscriber, the one added last decides for all of them, as `tracing` only gives access to
+/// one layer of each type.
 ///
 /// Specialized to the OTLP SpanId and TraceId provided by this crate.
 pub fn register_dist_tracing_root(
     trace_id: TraceId,
     remote_parent_span: Option<SpanId>,
 ) -> Result<(), TraceCtxError> {
-    tracing_distributed::register_dist_tracing_root(trace_id, remote_parent_span)
+    register_dist_tracing_root_for(&tracing::Span::current(), trace_id, remote_parent_span)
+}
+
+/// Register `span` as the local root of a distributed trace, as
+/// [`register_dist_tracing_root`] does for the current span.
+///
+/// Useful when the span was created on another thread or is held in a struct, as it does not
+/// have to be entered first.
+///
+/// Specialized to the OTLP SpanId and TraceId provided by this crate.
+pub fn register_dist_tracing_root_for(
+    span: &tracing::Span,
+    trace_id: TraceId,
+    remote_parent_span: Option<SpanId>,
+) -> Result<(), TraceCtxError> {
+    tracing_distributed::register_dist_tracing_root_for(
+        span,
+        trace_id,
+        remote_parent_span,
+        layer_samples(span, trace_id),
+        None,
+    )
+}
+
+/// Whether the OTLP layer registered with the subscriber of `span` samples the trace, true if
+/// there is no such layer.
+///
+/// `downcast_ref` returns the outermost OTLP layer, i.e. the one added last, and there is no
+/// way to reach the others through the `Dispatch`.
+fn layer_samples(span: &tracing::Span, trace_id: TraceId) -> bool {
+    span.with_subscriber(|(_, dispatch)| {
+        dispatch
+            .downcast_ref::<TelemetryLayer<Otlp, SpanId, TraceId>>()
+            .map(|layer| {
+                let sampling = layer.telemetry().inner.sampling.read();
+                sampling.expect("sampling lock poisoned").clone()
+            })
+    })
+    .flatten()
+    .is_none_or(|sampling| sampling.should_sample(span, trace_id))
+}
+
+/// Register the current span as the local root of a distributed trace, with the sampling
+/// decision made by its remote parent, e.g. the sampled flag of a W3C `traceparent`.
+///
+/// If `sampled` is false no spans of this trace are exported by this process.
+///
+/// Specialized to the OTLP SpanId and TraceId provided by this crate.
+pub fn register_dist_tracing_root_sampled(
+    trace_id: TraceId,
+    remote_parent_span: Option<SpanId>,
+    sampled: bool,
+) -> Result<(), TraceCtxError> {
+    tracing_distributed::register_dist_tracing_root_with_sampling(
+        trace_id,
+        remote_parent_span,
+        sampled,
+    )
 }
 
 /// Retrieve the distributed trace context associated with the current span.
@@ -49,38 +280,442 @@ pub fn current_dist_trace_ctx() -> Result<(TraceId, SpanId), TraceCtxError> {
     tracing_distributed::current_dist_trace_ctx()
 }
 
+/// Retrieve the distributed trace context associated with `span`, e.g. to inject it into the
+/// headers of a request without entering the span.
+///
+/// Specialized to the OTLP SpanId and TraceId provided by this crate.
+pub fn dist_trace_ctx_for(span: &tracing::Span) -> Result<(TraceId, SpanId), TraceCtxError> {
+    tracing_distributed::dist_trace_ctx_for(span)
+}
+
+/// Retrieve the distributed trace context associated with the current span, with the
+/// sampling decision and W3C `tracestate` of the trace, to propagate to other processes.
+///
+/// Unlike [`current_dist_trace_ctx`] this keeps whether the trace is sampled, so the remote
+/// side can follow the decision with [`TraceRoot::remote`].
+pub fn current_span_context() -> Result<SpanContext, TraceCtxError> {
+    tracing_distributed::current_span_context()
+}
+
+/// Retrieve the distributed trace context associated with `span`, as
+/// [`current_span_context`] does for the current span.
+pub fn span_context_for(span: &tracing::Span) -> Result<SpanContext, TraceCtxError> {
+    tracing_distributed::span_context_for(span)
+}
+
+/// Retrieve whether the distributed trace associated with the current span is sampled.
+///
+/// Specialized to the OTLP SpanId and TraceId provided by this crate.
+pub fn is_sampled() -> Result<bool, TraceCtxError> {
+    tracing_distributed::current_dist_trace_sampled::<SpanId, TraceId>()
+}
+
+/// Returns whether the current span will be exported, i.e. whether it is part of a sampled
+/// distributed trace.
+///
+/// Use this to skip computing expensive attributes, such as serialized request bodies, which
+/// would be discarded anyway.
+pub fn is_recording() -> bool {
+    is_sampled().unwrap_or(false)
+}
+
+/// Sets an attribute on all spans of the current distributed trace in this process, such as
+/// the id of the user of a request, replacing the previous value of the key if any.
+///
+/// The attribute is added to the spans of the trace ending from now on which do not have an
+/// attribute with the same key, up to the local root of the trace. With several OTLP layers
+/// in one subscriber, only the spans exported by the one added last get the attribute.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{register_dist_tracing_root, set_trace_attribute, TraceId};
+/// tracing::info_span!("request").in_scope(|| {
+///     # let _ =
+///     register_dist_tracing_root(TraceId::new(), None);
+///     # let _ =
+///     set_trace_attribute("user.id", 42);
+///     tracing::info_span!("query").in_scope(|| {});
+/// });
+/// ```
+pub fn set_trace_attribute(
+    key: impl Into<String>,
+    value: impl Into<Value>,
+) -> Result<(), TraceCtxError> {
+    let (trace_id, _) = current_dist_trace_ctx()?;
+    // Spans of unsampled traces are not reported, so the attributes would never be removed
+    if !is_sampled()? {
+        return Ok(());
+    }
+    let kv = KeyValue::new(key.into(), value.into());
+    with_current_otlp(|otlp| otlp.inner.trace_attributes.set(trace_id, kv.clone()))
+        .ok_or(TraceCtxError::TelemetryLayerNotRegistered)
+}
+
+/// Generate a new `TraceId` using the [`IdGenerator`] of the OTLP layer registered with the
+/// current subscriber, the one added last if there are several.
+///
+/// Falls back to [`TraceId::new`] if there is no such layer.
+pub fn new_trace_id() -> TraceId {
+    with_current_otlp(|otlp| otlp.inner.id_generator.new_trace_id()).unwrap_or_default()
+}
+
+/// Calls `f` with the [`Otlp`] instance of the OTLP layer registered with the current
+/// subscriber, if any. This is the outermost OTLP layer, see [`layer_samples`].
+fn with_current_otlp<R>(mut f: impl FnMut(&Otlp) -> R) -> Option<R> {
+    tracing::dispatcher::get_default(|dispatch| {
+        dispatch
+            .downcast_ref::<TelemetryLayer<Otlp, SpanId, TraceId>>()
+            .map(|layer| f(layer.telemetry()))
+    })
+}
+
 /// OpenTelemetry protocol implementation of [`Telemetry`]. Use [`Builder`] to instantiate this.
+///
+/// Cloning an `Otlp` is cheap, clones share the same worker and connection. Use
+/// [`Otlp::layer`] to create further layers exporting through it, for example a subscriber
+/// per test.
+#[derive(Clone)]
 pub struct Otlp {
-    tx: Sender<Span>,
+    inner: Arc<OtlpInner>,
+}
+
+struct OtlpInner {
+    tx: SpanSender,
+    id_generator: Arc<dyn IdGenerator>,
+    /// Changed with [`OtlpHandle::set_sampler`]
+    sampling: RwLock<Sampling>,
+    attributes: AttributeRules,
+    processors: Processors,
+    trace_attributes: TraceAttributes,
+    counters: Arc<ExporterCounters>,
+    error_handler: ErrorHandler,
+    max_queue_size: usize,
+    /// See [`Builder::level_aware_shedding`]
+    level_aware_shedding: bool,
+    /// See [`Builder::simple`]
+    wait_for_export: Option<Duration>,
+    /// Whether spans are being dropped, so that only the first drop is passed to the
+    /// error handler instead of every span
+    dropping: AtomicBool,
+    /// Settings changed with an [`OtlpHandle`], for the worker to apply
+    changes: Arc<SettingChanges>,
+    runtime_headers: RuntimeHeaders,
+    /// Schemes of the endpoints the worker can send to
+    endpoint_schemes: &'static [&'static str],
+    /// See [`Builder::heartbeat`]
+    heartbeat: Option<Duration>,
+    /// See [`Builder::max_events_per_span`]
+    max_events_per_span: Option<usize>,
+    /// See [`Builder::implicit_traces`]
+    implicit_traces: bool,
+    /// See [`Builder::default_trace`]
+    default_trace: Option<(TraceId, Option<SpanId>)>,
+    /// See [`Builder::max_level`]
+    layer_filter: LayerFilter,
+}
+
+/// How the worker of an [`Otlp`] layer is run.
+pub(crate) enum WorkerMode {
+    /// On a dedicated thread, with blocking I/O
+    Thread,
+    /// As a task on a tokio runtime, with async I/O
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::runtime::Handle),
+    /// As a future on the JS event loop, with `fetch`
+    #[cfg(feature = "wasm")]
+    Wasm,
+}
+
+/// Channel handing spans to the worker, depending on the [`WorkerMode`].
+enum SpanSender {
+    Thread(SyncSender<WorkerMessage>),
+    #[cfg(feature = "tokio")]
+    Tokio(tokio::sync::mpsc::Sender<WorkerMessage>),
+    /// Unbounded as the bounded channel needs exclusive access to send, limited to the
+    /// maximum queue size instead
+    #[cfg(feature = "wasm")]
+    Wasm {
+        tx: futures_channel::mpsc::UnboundedSender<WorkerMessage>,
+        max_queue_size: usize,
+        counters: Arc<ExporterCounters>,
+    },
+}
+
+impl SpanSender {
+    fn try_send(&self, message: WorkerMessage) -> Result<(), DeliveryError> {
+        match self {
+            SpanSender::Thread(tx) => tx.try_send(message).map_err(|err| match err {
+                TrySendError::Full(_) => DeliveryError::QueueFull,
+                TrySendError::Disconnected(_) => DeliveryError::WorkerStopped,
+            }),
+            #[cfg(feature = "tokio")]
+            SpanSender::Tokio(tx) => {
+                use tokio::sync::mpsc::error::TrySendError;
+                tx.try_send(message).map_err(|err| match err {
+                    TrySendError::Full(_) => DeliveryError::QueueFull,
+                    TrySendError::Closed(_) => DeliveryError::WorkerStopped,
+                })
+            }
+            #[cfg(feature = "wasm")]
+            SpanSender::Wasm {
+                tx,
+                max_queue_size,
+                counters,
+            } => {
+                let queued = counters.spans_enqueued.load(Ordering::Relaxed)
+                    - counters.spans_dequeued.load(Ordering::Relaxed);
+                if queued >= *max_queue_size as u64 {
+                    return Err(DeliveryError::QueueFull);
+                }
+                tx.unbounded_send(message)
+                    .map_err(|_| DeliveryError::WorkerStopped)
+            }
+        }
+    }
+}
+
+/// Why a span could not be handed to the worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DeliveryError {
+    QueueFull,
+    /// Dropped by level-aware shedding before the queue is full
+    Shed,
+    WorkerStopped,
+}
+
+impl DeliveryError {
+    fn reason(self) -> &'static str {
+        match self {
+            DeliveryError::QueueFull => "queue full, dropping spans until it drains",
+            DeliveryError::Shed => "queue filling up, shedding less severe spans",
+            DeliveryError::WorkerStopped => "worker stopped",
+        }
+    }
 }
 
 impl Otlp {
     pub(crate) fn new(
-        endpoint: &str,
-        send_interval: Duration,
-        resource_attributes: Vec<(String, Value)>,
-        http_headers: Vec<(String, String)>,
-    ) -> Result<Self, url::ParseError> {
-        let (tx, rx) = channel();
-
-        let endpoint = Url::from_str(endpoint)?;
-
-        let mut worker = Worker::new(
-            send_interval,
-            endpoint.join("/v1/traces")?,
-            rx,
-            resource_attributes,
-            http_headers,
-        );
+        destination: Destination,
+        worker_config: WorkerConfig,
+        attributes: AttributeRules,
+        processors: Processors,
+        id_generator: Arc<dyn IdGenerator>,
+        sampling: Sampling,
+        mode: WorkerMode,
+    ) -> Result<Self, OtlpBuildError> {
+        let counters = Arc::new(ExporterCounters::default());
+        let error_handler = worker_config.error_handler.clone();
+        let max_queue_size = worker_config.max_queue_size;
+        let level_aware_shedding = worker_config.level_aware_shedding;
+        let wait_for_export = worker_config.wait_for_export;
+        let heartbeat = worker_config.heartbeat;
+        let max_events_per_span = worker_config.max_events_per_span;
+        let implicit_traces = worker_config.implicit_traces;
+        let default_trace = worker_config.default_trace;
+        let layer_filter = worker_config.layer_filter.clone();
+        let changes = worker_config.changes.clone();
+        let runtime_headers = worker_config.headers.runtime.clone();
+        let endpoint_schemes: &[&str] = match mode {
+            #[cfg(feature = "tokio")]
+            WorkerMode::Tokio(_) => &["http"],
+            _ => &["http", "https"],
+        };
 
-        thread::Builder::new()
-            .name("OTLP worker".to_string())
-            .spawn(move || {
-                worker.run_loop();
-            })
-            .expect("Spawning worker should not fail");
+        let tx = match mode {
+            WorkerMode::Thread => {
+                let (tx, rx) = sync_channel(max_queue_size);
+                let worker = Worker::new(destination, worker_config, counters.clone())?;
+                thread::Builder::new()
+                    .name("OTLP worker".to_string())
+                    .spawn(move || worker.run(rx))
+                    .map_err(|err| OtlpBuildError::Spawn(Arc::new(err)))?;
+                SpanSender::Thread(tx)
+            }
+            #[cfg(feature = "tokio")]
+            WorkerMode::Tokio(handle) => {
+                let (tx, rx) = tokio::sync::mpsc::channel(max_queue_size);
+                let worker = Worker::new(destination, worker_config, counters.clone())?;
+                handle.spawn(tokio_worker::run(worker, rx));
+                SpanSender::Tokio(tx)
+            }
+            #[cfg(feature = "wasm")]
+            WorkerMode::Wasm => {
+                let (tx, rx) = futures_channel::mpsc::unbounded();
+                let worker = Worker::new(destination, worker_config, counters.clone())?;
+                wasm_bindgen_futures::spawn_local(wasm_worker::run(worker, rx));
+                SpanSender::Wasm {
+                    tx,
+                    max_queue_size,
+                    counters: counters.clone(),
+                }
+            }
+        };
+
+        Ok(Self {
+            inner: Arc::new(OtlpInner {
+                tx,
+                id_generator,
+                sampling: RwLock::new(sampling),
+                attributes,
+                processors,
+                trace_attributes: Default::default(),
+                counters,
+                error_handler,
+                max_queue_size,
+                level_aware_shedding,
+                wait_for_export,
+                dropping: AtomicBool::new(false),
+                changes,
+                runtime_headers,
+                endpoint_schemes,
+                heartbeat,
+                max_events_per_span,
+                implicit_traces,
+                default_trace,
+                layer_filter,
+            }),
+        })
+    }
+
+    /// Creates a new layer exporting to this exporter, sharing its worker with the layers
+    /// created before.
+    ///
+    /// The worker stops once all layers and clones of the exporter are dropped.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// # use tracing_subscriber::{layer::SubscriberExt, Registry};
+    /// let otlp = Builder::new()
+    ///     .build("http://127.0.0.1:4318")
+    ///     .unwrap()
+    ///     .telemetry()
+    ///     .clone();
+    /// for _ in 0..3 {
+    ///     let subscriber = Registry::default().with(otlp.layer());
+    ///     tracing::subscriber::with_default(subscriber, || tracing::info_span!("test").in_scope(|| {}));
+    /// }
+    /// ```
+    pub fn layer(&self) -> TelemetryLayer<Otlp, SpanId, TraceId> {
+        let id_generator = self.inner.id_generator.clone();
+        let filter = &self.inner.layer_filter;
+        let mut layer = TelemetryLayer::new("", self.clone(), move |_| id_generator.new_span_id())
+            .with_max_level(filter.max_level)
+            .with_denied_targets(filter.deny.iter().cloned());
+        if let Some(allow) = &filter.allow {
+            layer = layer.with_allowed_targets(allow.iter().cloned());
+        }
+        if let Some((trace_id, remote_parent)) = self.inner.default_trace {
+            let sampling = self.inner.sampling.read().expect("sampling lock poisoned");
+            let sampled = sampling.sampler.should_sample(trace_id);
+            layer = layer.with_default_trace(trace_id, remote_parent, sampled);
+        }
+        if !self.inner.implicit_traces {
+            return layer;
+        }
+        let otlp = self.clone();
+        layer.with_implicit_traces(move |_| {
+            let trace_id = otlp.inner.id_generator.new_trace_id();
+            let sampling = otlp.inner.sampling.read().expect("sampling lock poisoned");
+            (trace_id, sampling.sampler.should_sample(trace_id))
+        })
+    }
+
+    /// Hands a span of `level` to the worker without blocking, accounting for it in the
+    /// counters.
+    fn enqueue(&self, span: QueuedSpan, level: tracing::Level) -> Result<(), DeliveryError> {
+        let counters = &self.inner.counters;
+        let result = if self.sheds(level) {
+            Err(DeliveryError::Shed)
+        } else {
+            self.inner.tx.try_send(WorkerMessage::Span(span))
+        };
+        match result {
+            Ok(()) => ExporterCounters::add(&counters.spans_enqueued, 1),
+            Err(err) => {
+                ExporterCounters::add(&counters.spans_dropped, 1);
+                if err == DeliveryError::Shed {
+                    let index = ExporterCounters::level_index(level);
+                    ExporterCounters::add(&counters.spans_shed[index], 1);
+                }
+            }
+        }
+        result
+    }
+
+    /// Whether a span of `level` is shed to keep room in the queue for more severe spans,
+    /// see [`Builder::level_aware_shedding`].
+    fn sheds(&self, level: tracing::Level) -> bool {
+        use tracing::Level;
+
+        if !self.inner.level_aware_shedding {
+            return false;
+        }
+        let limit = self.inner.max_queue_size as u64;
+        let limit = match level {
+            Level::TRACE | Level::DEBUG => limit / 2,
+            Level::INFO => limit * 3 / 4,
+            _ => return false,
+        };
+        let counters = &self.inner.counters;
+        let queued = counters
+            .spans_enqueued
+            .load(Ordering::Relaxed)
+            .saturating_sub(counters.spans_dequeued.load(Ordering::Relaxed));
+        queued >= limit
+    }
 
-        Ok(Self { tx })
+    /// Exports the spans finished so far right away, instead of on the send interval, and
+    /// waits at most `timeout` for the export to finish. Returns whether it finished in
+    /// time; spans of a failed export are retried with the next batch as usual.
+    ///
+    /// This blocks the calling thread, so with [`Builder::build_tokio`] do not call it from
+    /// a single-threaded runtime running the worker. In the browser the export is only
+    /// started, and false returned right away.
+    ///
+    /// Platforms freezing the process between requests, such as AWS Lambda, need this at the
+    /// end of each request, see [`FlushGuard::flush_before_freeze`].
+    pub fn flush(&self, timeout: Duration) -> bool {
+        let deadline = time::Instant::now() + timeout;
+        let (ack, done) = std::sync::mpsc::channel();
+        #[cfg(feature = "wasm")]
+        if let SpanSender::Wasm { tx, .. } = &self.inner.tx {
+            let _ = tx.unbounded_send(WorkerMessage::Flush(ack));
+            return false;
+        }
+        // The queue may be full, in which case the worker is busy draining it
+        loop {
+            match self.inner.tx.try_send(WorkerMessage::Flush(ack.clone())) {
+                Ok(()) => break,
+                Err(DeliveryError::QueueFull) if time::Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(10))
+                }
+                Err(_) => return false,
+            }
+        }
+        // Only the worker holds the sender then, so a panicking worker fails the flush
+        drop(ack);
+        done.recv_timeout(deadline.saturating_duration_since(time::Instant::now()))
+            .is_ok()
+    }
+
+    /// Returns a handle reporting statistics about the exporter of this layer.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::Builder;
+    /// let layer = Builder::new().build("http://127.0.0.1:4318").unwrap();
+    /// let stats = layer.telemetry().stats();
+    /// assert_eq!(stats.spans_dropped(), 0);
+    /// ```
+    pub fn stats(&self) -> OtlpStats {
+        OtlpStats(self.inner.counters.clone())
+    }
+
+    /// Returns a handle changing the settings of this exporter while it runs, see
+    /// [`OtlpHandle`].
+    pub fn handle(&self) -> OtlpHandle {
+        OtlpHandle::new(self.clone())
     }
 }
 
@@ -95,42 +730,110 @@ impl Telemetry for Otlp {
         Default::default()
     }
 
+    fn span_started(&self, meta: &'static tracing::Metadata<'static>, values: &mut Self::Visitor) {
+        self.inner.processors.on_start(meta, &mut values.0);
+    }
+
     fn report_span(
         &self,
         span: tracing_distributed::Span<Self::Visitor, Self::SpanId, Self::TraceId>,
         events: Vec<tracing_distributed::Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
     ) {
+        self.export_span(span, events, false);
+    }
+
+    fn report_event(
+        &self,
+        _event: tracing_distributed::Event<Self::Visitor, Self::SpanId, Self::TraceId>,
+    ) {
+    }
+
+    fn heartbeat_interval(&self) -> Option<Duration> {
+        self.inner.heartbeat
+    }
+
+    fn max_events_per_span(&self) -> Option<usize> {
+        self.inner.max_events_per_span
+    }
+
+    fn report_span_progress(
+        &self,
+        span: tracing_distributed::Span<&Self::Visitor, Self::SpanId, Self::TraceId>,
+    ) {
+        let values = span.values.clone();
+        self.export_span(span.with_values(values), Vec::new(), true);
+    }
+}
+
+impl Otlp {
+    /// Converts a span to OTLP and hands it to the worker, `in_progress` if it is a snapshot
+    /// of a span which is still open, see [`Builder::heartbeat`].
+    fn export_span(
+        &self,
+        span: tracing_distributed::Span<Visitor, SpanId, TraceId>,
+        events: Vec<tracing_distributed::Event<Visitor, SpanId, TraceId>>,
+        in_progress: bool,
+    ) {
+        let (level, implicit_trace) = (*span.meta.level(), span.implicit_trace);
+        // Trace attributes are kept until the root itself ends
+        let (trace_id, local_root) = (span.trace_id, span.local_root && !in_progress);
+        let timings = [("busy_ns", span.busy), ("idle_ns", span.idle)];
+        let mut events = events;
+        let mut dropped_events = span.dropped_events;
+        // Only more than the maximum with a `TeeTelemetry` keeping more events
+        if let Some(max) = self.inner.max_events_per_span {
+            dropped_events += events.len().saturating_sub(max);
+            events.truncate(max);
+        }
+        let has_error_event = events
+            .iter()
+            .any(|ev| *ev.meta.level() == tracing::Level::ERROR);
         let events = events
             .into_iter()
-            .map(|ev| span::Event {
-                time_unix_nano: system_time_to_unix_nanos(&ev.initialized_at),
-                name: "event".to_string(),
-                attributes: ev.values.0,
-                dropped_attributes_count: 0,
+            // Errors of the exporter itself are not exported, as that may be what fails
+            .filter(|ev| ev.meta.target() != DIAGNOSTICS_TARGET)
+            .map(|ev| {
+                let mut attributes = ev.values.0;
+                let dropped_attributes_count = self.inner.attributes.apply(&mut attributes);
+                span::Event {
+                    time_unix_nano: system_time_to_unix_nanos(
+                        &ev.initialized_at,
+                        &self.inner.error_handler,
+                    ),
+                    name: "event".to_string(),
+                    attributes,
+                    dropped_attributes_count,
+                }
             })
             .collect();
-        let span = Span {
-            trace_id: span.trace_id.0.to_be_bytes().to_vec(),
-            span_id: span.id.0.to_be_bytes().to_vec(),
-            trace_state: "".to_string(),
+        let mut span = Span {
+            trace_id: span.trace_id.to_bytes().to_vec(),
+            span_id: span.id.to_bytes().to_vec(),
+            trace_state: span.trace_state.as_deref().unwrap_or_default().to_string(),
             parent_span_id: span
                 .parent_id
-                .map(|pid| pid.0.to_le_bytes().to_vec())
+                .map(|pid| pid.to_bytes().to_vec())
                 .unwrap_or_default(),
             flags: 0,
             name: span.name,
-            kind: 0,
-            start_time_unix_nano: system_time_to_unix_nanos(&span.initialized_at),
-            end_time_unix_nano: system_time_to_unix_nanos(&span.completed_at),
+            kind: SpanKind::Unspecified as i32,
+            start_time_unix_nano: system_time_to_unix_nanos(
+                &span.initialized_at,
+                &self.inner.error_handler,
+            ),
+            end_time_unix_nano: system_time_to_unix_nanos(
+                &span.completed_at,
+                &self.inner.error_handler,
+            ),
             attributes: span.values.0,
             dropped_attributes_count: 0,
             events,
-            dropped_events_count: 0,
+            dropped_events_count: u32::try_from(dropped_events).unwrap_or(u32::MAX),
             links: std::iter::once(span.follows_from)
                 .flatten()
                 .map(|l| Link {
-                    trace_id: l.0 .0.to_be_bytes().to_vec(),
-                    span_id: l.1 .0.to_be_bytes().to_vec(),
+                    trace_id: l.0.to_bytes().to_vec(),
+                    span_id: l.1.to_bytes().to_vec(),
                     trace_state: "".to_string(),
                     attributes: vec![],
                     dropped_attributes_count: 0,
@@ -140,22 +843,995 @@ impl Telemetry for Otlp {
             dropped_links_count: 0,
             status: None,
         };
+        span_fields::apply(&mut span);
+        if in_progress {
+            span.attributes
+                .push(KeyValue::new("span.in_progress".to_string(), true.into()));
+        }
+        if implicit_trace {
+            span.attributes
+                .push(KeyValue::new("trace.implicit".to_string(), true.into()));
+        }
+        self.inner
+            .trace_attributes
+            .apply(trace_id, local_root, &mut span.attributes);
+        if !self.inner.processors.on_end(&mut span) {
+            return;
+        }
+        for (key, duration) in timings {
+            let nanos = i64::try_from(duration.as_nanos()).unwrap_or(i64::MAX);
+            span.attributes
+                .push(KeyValue::new(key.to_string(), nanos.into()));
+        }
+        span.dropped_attributes_count = self.inner.attributes.apply(&mut span.attributes);
 
-        self.tx.send(span).expect("Worker thread should not crash")
-    }
+        let has_error = has_error_event
+            || span
+                .status
+                .as_ref()
+                .is_some_and(|status| status.code == StatusCode::Error as i32);
 
-    fn report_event(
-        &self,
-        _event: tracing_distributed::Event<Self::Visitor, Self::SpanId, Self::TraceId>,
-    ) {
+        // Spans which failed are kept like errors, whatever their level
+        let level = if has_error {
+            tracing::Level::ERROR
+        } else {
+            level
+        };
+        let span = QueuedSpan {
+            span,
+            has_error,
+            in_progress,
+        };
+        match self.enqueue(span, level) {
+            Ok(()) => {
+                self.inner.dropping.store(false, Ordering::Relaxed);
+                if let Some(timeout) = self.inner.wait_for_export {
+                    self.flush(timeout);
+                }
+            }
+            Err(err) => {
+                if !self.inner.dropping.swap(true, Ordering::Relaxed) {
+                    self.inner.error_handler.handle(OtlpError::SpansDropped {
+                        count: 1,
+                        reason: err.reason(),
+                    });
+                }
+            }
+        }
     }
 }
 
-fn system_time_to_unix_nanos(t: &SystemTime) -> u64 {
+fn system_time_to_unix_nanos(t: &SystemTime, error_handler: &ErrorHandler) -> u64 {
     t.duration_since(UNIX_EPOCH)
         .unwrap_or_else(|_| {
-            eprintln!("Time went backwards while calculating OTLP timestamp");
+            error_handler.handle(OtlpError::InvalidTimestamp { time: *t });
             Duration::ZERO
         })
         .as_nanos() as u64
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prost::collector::trace::v1::ExportTraceServiceRequest;
+    use std::error::Error;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn layers_share_the_exporter() {
+        let otlp = Builder::new()
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        for _ in 0..2 {
+            let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+            tracing::subscriber::with_default(subscriber, || {
+                tracing::info_span!("root").in_scope(|| {
+                    register_dist_tracing_root(TraceId::new(), None).unwrap();
+                })
+            });
+        }
+        assert_eq!(otlp.stats().spans_received(), 2);
+    }
+
+    #[test]
+    fn the_layer_added_last_decides_sampling() {
+        let layer = |sampler| {
+            Builder::new()
+                .sampler(sampler)
+                .build("http://127.0.0.1:1")
+                .unwrap()
+        };
+        let subscriber = tracing_subscriber::Registry::default()
+            .with(layer(Sampler::AlwaysOn))
+            .with(layer(Sampler::AlwaysOff));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                assert_eq!(is_sampled(), Ok(false));
+            })
+        });
+    }
+
+    #[test]
+    fn processors_modify_and_drop_spans() {
+        use crate::prost::common::v1::KeyValue;
+        use std::sync::Mutex;
+
+        #[derive(Clone, Default)]
+        struct Team(Arc<Mutex<Vec<Vec<String>>>>);
+
+        impl SpanProcessor for Team {
+            fn on_start(&self, _: &'static tracing::Metadata<'static>, attrs: &mut Vec<KeyValue>) {
+                attrs.push(KeyValue::new("team".to_string(), "checkout".into()));
+            }
+
+            fn on_end(&self, span: &mut Span) -> bool {
+                let keys = span.attributes.iter().map(|kv| kv.key.clone()).collect();
+                self.0.lock().unwrap().push(keys);
+                span.name != "health"
+            }
+        }
+
+        let team = Team::default();
+        let otlp = Builder::new()
+            .add_processor(team.clone())
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root", user = 1).in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                tracing::info_span!("health").in_scope(|| {});
+            })
+        });
+
+        assert_eq!(
+            *team.0.lock().unwrap(),
+            [vec!["team"], vec!["user", "team"]]
+        );
+        assert_eq!(otlp.stats().spans_received(), 1);
+    }
+
+    #[test]
+    fn trace_attributes_apply_to_all_spans_of_the_trace() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(set_trace_attribute("user.id", 1).is_err());
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                tracing::info_span!("before").in_scope(|| {});
+                set_trace_attribute("user.id", 1).unwrap();
+                tracing::info_span!("child").in_scope(|| {});
+            });
+            tracing::info_span!("other").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            });
+        });
+
+        let spans: Vec<_> = capture
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|span| {
+                let keys: Vec<_> = span.attributes.iter().map(|kv| kv.key.as_str()).collect();
+                format!("{}: {}", span.name, keys.join(", "))
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            ["before: ", "child: user.id", "root: user.id", "other: "]
+        );
+        assert!(otlp.inner.trace_attributes.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn trace_root_sets_attributes_and_start_time() {
+        let (otlp, capture) = capture_exporter();
+        let started_at = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root", method = "GET").in_scope(|| {
+                TraceRoot::new(TraceId::new())
+                    .remote_parent(SpanId(7))
+                    .attribute("queue", "checkout")
+                    .started_at(started_at)
+                    .register()
+                    .unwrap();
+            });
+        });
+
+        let spans = capture.0.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(
+            spans[0].start_time_unix_nano,
+            system_time_to_unix_nanos(&started_at, &ErrorHandler::default())
+        );
+        assert_eq!(
+            spans[0].attributes,
+            [
+                KeyValue::new("method".to_string(), "GET".into()),
+                KeyValue::new("queue".to_string(), "checkout".into()),
+            ]
+        );
+        assert_eq!(spans[0].parent_span_id, SpanId(7).to_bytes());
+    }
+
+    #[test]
+    fn registers_root_for_span_held_elsewhere() {
+        let otlp = Builder::new()
+            .sampling_callback(|_, meta, _| match meta.name() {
+                "health" => SamplingDecision::Drop,
+                _ => SamplingDecision::Sample,
+            })
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("health");
+            tracing::info_span!("request").in_scope(|| {
+                register_dist_tracing_root_for(&span, TraceId::new(), None).unwrap();
+            });
+            // Sampling is decided for the registered span, not the current one
+            assert_eq!(span.in_scope(is_sampled), Ok(false));
+
+            let span = tracing::info_span!("health");
+            TraceRoot::new(TraceId::new())
+                .sampled(true)
+                .register_for(&span)
+                .unwrap();
+            assert_eq!(span.in_scope(is_sampled), Ok(true));
+            assert_eq!(
+                dist_trace_ctx_for(&span),
+                span.in_scope(current_dist_trace_ctx)
+            );
+        });
+    }
+
+    #[test]
+    fn sampling_callback_decides_with_root_attributes() {
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let otlp = Builder::new()
+            .sampler(Sampler::AlwaysOff)
+            .sampling_callback({
+                let seen = seen.clone();
+                move |trace_id, meta, attributes| {
+                    seen.lock().unwrap().push(*trace_id);
+                    if meta.name() == "health" {
+                        SamplingDecision::Drop
+                    } else if attributes.iter().any(|kv| kv.key == "customer.vip") {
+                        SamplingDecision::Sample
+                    } else {
+                        SamplingDecision::Defer
+                    }
+                }
+            })
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let sampled = |span: tracing::Span, trace_id, remote_parent| {
+                span.in_scope(|| {
+                    register_dist_tracing_root(trace_id, remote_parent).unwrap();
+                    is_sampled().unwrap()
+                })
+            };
+            assert!(sampled(
+                tracing::info_span!("request", customer.vip = true),
+                TraceId(1),
+                None
+            ));
+            // Also decided by the callback when there is a remote parent
+            assert!(sampled(
+                tracing::info_span!("request", customer.vip = true),
+                TraceId(2),
+                Some(SpanId(3))
+            ));
+            assert!(!sampled(
+                tracing::info_span!("health", customer.vip = true),
+                TraceId(4),
+                None
+            ));
+            // Deferred to the sampler, which is always off
+            assert!(!sampled(tracing::info_span!("request"), TraceId(5), None));
+        });
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [TraceId(1), TraceId(2), TraceId(4), TraceId(5)]
+        );
+    }
+
+    #[test]
+    fn is_sampled_and_is_recording_follow_the_trace() {
+        let otlp = Builder::new()
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("sampled").in_scope(|| {
+                register_dist_tracing_root_sampled(TraceId::new(), None, true).unwrap();
+                tracing::info_span!("child").in_scope(|| {
+                    assert_eq!(is_sampled(), Ok(true));
+                    assert!(is_recording());
+                });
+            });
+            tracing::info_span!("unsampled").in_scope(|| {
+                register_dist_tracing_root_sampled(TraceId::new(), Some(SpanId(1)), false).unwrap();
+                tracing::info_span!("child").in_scope(|| {
+                    assert_eq!(is_sampled(), Ok(false));
+                    assert!(!is_recording());
+                });
+            });
+            tracing::info_span!("outside").in_scope(|| {
+                assert_eq!(is_sampled(), Err(TraceCtxError::NoParentNodeHasTraceCtx));
+                assert!(!is_recording());
+            });
+        });
+
+        let subscriber = tracing_subscriber::Registry::default();
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("no layer").in_scope(|| {
+                assert_eq!(
+                    is_sampled(),
+                    Err(TraceCtxError::TelemetryLayerNotRegistered)
+                );
+                assert!(!is_recording());
+            });
+        });
+    }
+
+    #[test]
+    fn span_context_carries_sampling_and_trace_state() {
+        let otlp = Builder::new()
+            .sampler(Sampler::AlwaysOff)
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let remote = SpanContext::new(TraceId(1), SpanId(2), true).with_trace_state("a=1");
+            tracing::info_span!("request").in_scope(|| {
+                TraceRoot::remote(remote.clone()).register().unwrap();
+                tracing::info_span!("child").in_scope(|| {
+                    let ctx = current_span_context().unwrap();
+                    assert_eq!(ctx.trace_id, remote.trace_id);
+                    assert!(ctx.sampled, "the remote decision overrides the sampler");
+                    assert_eq!(ctx.trace_state.as_deref(), Some("a=1"));
+                    assert_eq!(current_dist_trace_ctx(), Ok((ctx.trace_id, ctx.span_id)));
+                });
+            });
+
+            tracing::info_span!("request").in_scope(|| {
+                register_dist_tracing_root(TraceId(3), None).unwrap();
+                let ctx = current_span_context().unwrap();
+                assert!(!ctx.sampled);
+                assert_eq!(ctx.trace_state, None);
+            });
+        });
+    }
+
+    #[test]
+    fn runs_closures_and_futures_with_a_remote_parent() {
+        let otlp = Builder::new()
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            let remote = SpanContext::new(TraceId(1), SpanId(2), false);
+            let ctx = with_remote_parent(remote.clone(), || current_span_context().unwrap());
+            assert_eq!(ctx.trace_id, TraceId(1));
+            assert!(!ctx.sampled);
+            assert!(current_span_context().is_err(), "the span is exited again");
+
+            let future = with_remote_parent_async(remote, async { is_sampled() });
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            assert_eq!(runtime.block_on(future), Ok(false));
+        });
+    }
+
+    #[test]
+    fn spawned_threads_continue_the_trace() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let parent = tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                let worker = spawn_traced("worker", || current_span_context().unwrap());
+                let ctx = worker.unwrap().join().unwrap();
+                assert_eq!(ctx.trace_id, current_span_context().unwrap().trace_id);
+                current_span_context().unwrap()
+            })
+        });
+
+        let spans = capture.0.lock().unwrap();
+        assert_eq!(spans[0].name, "thread");
+        assert_eq!(spans[0].kind, SpanKind::Internal as i32);
+        assert_eq!(spans[0].trace_id, parent.trace_id.to_bytes());
+        assert_eq!(spans[0].parent_span_id, parent.span_id.to_bytes());
+        assert_eq!(
+            spans[0].attributes,
+            [KeyValue::new("thread.name".to_string(), "worker".into())]
+        );
+    }
+
+    #[test]
+    fn futures_continue_the_trace_on_other_threads() {
+        let otlp = Builder::new()
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let (future, parent) = tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                let future = async { current_span_context().unwrap() }.in_current_dist_trace();
+                (future, current_span_context().unwrap())
+            })
+        });
+
+        // Polled without the subscriber being the default of the thread
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let ctx = std::thread::spawn(move || runtime.block_on(future))
+            .join()
+            .unwrap();
+        assert_eq!(ctx.trace_id, parent.trace_id);
+        assert_ne!(ctx.span_id, parent.span_id);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn spawns_tasks_as_children_or_linked_traces() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            runtime.block_on(async {
+                let (child, linked) = tracing::info_span!("request").in_scope(|| {
+                    register_dist_tracing_root(TraceId::new(), None).unwrap();
+                    (spawn_child(async {}), spawn_linked(async {}))
+                });
+                // The request ends before its tasks are run
+                child.await.unwrap();
+                linked.await.unwrap();
+            })
+        });
+
+        let spans = capture.0.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["request", "future", "task"]);
+        let (request, child, linked) = (&spans[0], &spans[1], &spans[2]);
+        assert_eq!(child.trace_id, request.trace_id);
+        assert_eq!(child.parent_span_id, request.span_id);
+        assert_ne!(linked.trace_id, request.trace_id);
+        assert!(linked.parent_span_id.is_empty());
+        assert_eq!(linked.links[0].trace_id, request.trace_id);
+        assert_eq!(linked.links[0].span_id, request.span_id);
+    }
+
+    #[test]
+    fn sheds_less_severe_spans_first() {
+        use tracing::Level;
+
+        let otlp = Builder::new()
+            .max_queue_size(8)
+            .level_aware_shedding(true)
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        let fill = |queued| {
+            let counters = &otlp.inner.counters;
+            counters.spans_dequeued.store(0, Ordering::Relaxed);
+            counters.spans_enqueued.store(queued, Ordering::Relaxed);
+        };
+        let sheds = |levels: &[Level]| levels.iter().map(|&l| otlp.sheds(l)).collect::<Vec<_>>();
+        let levels = [Level::DEBUG, Level::INFO, Level::WARN];
+
+        fill(3);
+        assert_eq!(sheds(&levels), [false, false, false]);
+        fill(4);
+        assert_eq!(sheds(&levels), [true, false, false]);
+        fill(6);
+        assert_eq!(sheds(&levels), [true, true, false]);
+
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug_span!("shed").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                // An error keeps the span even though it is a debug span
+                tracing::debug_span!("kept").in_scope(|| tracing::error!("failed"));
+            })
+        });
+        let stats = otlp.stats();
+        assert_eq!(stats.spans_shed(Level::DEBUG), 1);
+        assert_eq!(stats.spans_dropped(), 1);
+    }
+
+    /// [`SpanProcessor`] capturing the spans as they end, before they are queued for export.
+    #[derive(Clone, Default)]
+    pub(crate) struct Capture(pub(crate) Arc<std::sync::Mutex<Vec<Span>>>);
+
+    impl SpanProcessor for Capture {
+        fn on_end(&self, span: &mut Span) -> bool {
+            self.0.lock().unwrap().push(span.clone());
+            true
+        }
+    }
+
+    /// Builds an exporter capturing its spans with the returned [`Capture`]. Its endpoint is
+    /// never reached.
+    pub(crate) fn capture_exporter() -> (Otlp, Capture) {
+        let capture = Capture::default();
+        let otlp = Builder::new()
+            .add_processor(capture.clone())
+            .build("http://127.0.0.1:1")
+            .unwrap()
+            .telemetry()
+            .clone();
+        (otlp, capture)
+    }
+
+    /// Runtime driving the services traced in the tests of the `tower` and `hyper` integrations.
+    #[cfg(any(feature = "tower", feature = "hyper"))]
+    pub(crate) fn current_thread_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    }
+
+    /// Starts an OTLP/HTTP endpoint accepting all requests, returning its URL.
+    pub(crate) fn mock_collector() -> String {
+        mock_collector_with(|_| {
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+    }
+
+    /// Starts an OTLP/HTTP endpoint responding to the nth request with `response(n)`,
+    /// returning its URL.
+    pub(crate) fn mock_collector_with(
+        response: impl Fn(usize) -> String + Send + 'static,
+    ) -> String {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(len) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                reader.read_exact(&mut vec![0; content_length]).unwrap();
+                reader.get_mut().write_all(response(i).as_bytes()).unwrap();
+            }
+        });
+        endpoint
+    }
+
+    /// Exports a root span with `builder` to a mock collector, returning the exporter.
+    fn export_root(builder: Builder) -> Otlp {
+        let layer = builder.build(mock_collector().as_str()).unwrap();
+        let otlp = layer.telemetry().clone();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+        otlp
+    }
+
+    /// Spans exported by a layer built with [`capture_layer`].
+    type Captured = Arc<std::sync::Mutex<Vec<Span>>>;
+
+    /// Builds a layer with `builder` capturing the spans it exports, returning it along with
+    /// its exporter and the captured spans.
+    fn capture_layer(builder: Builder) -> (TelemetryLayer<Otlp, SpanId, TraceId>, Otlp, Captured) {
+        capture_layer_with(builder, |_| Ok(()))
+    }
+
+    /// Builds a layer as [`capture_layer`] does, calling `export` first with each request.
+    /// The spans are only captured if it succeeds.
+    fn capture_layer_with(
+        builder: Builder,
+        export: impl Fn(&ExportTraceServiceRequest) -> Result<(), Box<dyn Error + Send + Sync>>
+            + Send
+            + 'static,
+    ) -> (TelemetryLayer<Otlp, SpanId, TraceId>, Otlp, Captured) {
+        let spans = Captured::default();
+        let captured = spans.clone();
+        let layer = builder
+            .build_exporter(move |request| {
+                export(request)?;
+                let resource_spans = request.resource_spans.iter();
+                let spans = resource_spans.flat_map(|r| &r.scope_spans[0].spans);
+                captured.lock().unwrap().extend(spans.cloned());
+                Ok(())
+            })
+            .unwrap();
+        let otlp = layer.telemetry().clone();
+        (layer, otlp, spans)
+    }
+
+    #[test]
+    fn panics_of_export_threads_fail_the_export() {
+        use std::sync::{mpsc, Mutex};
+
+        // The first response is invalid, and the handler panics on it in the export thread
+        let endpoint = mock_collector_with(|i| match i {
+            0 => format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: 1\r\n\
+                 Connection: close\r\n\r\n\x7f",
+                http::PROTOBUF
+            ),
+            _ => "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        });
+        let (failed_tx, failed) = mpsc::channel();
+        let failed_tx = Mutex::new(failed_tx);
+        let layer = Builder::new()
+            .max_concurrent_exports(2)
+            .send_interval(Duration::from_millis(10))
+            .on_error(move |err| match err {
+                OtlpError::InvalidResponse(_) => panic!("handler failed"),
+                OtlpError::Export { source, .. } => {
+                    let _ = failed_tx.lock().unwrap().send(source.to_string());
+                }
+                _ => {}
+            })
+            .build(endpoint.as_str())
+            .unwrap();
+        let otlp = layer.telemetry().clone();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+
+        let err = failed.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(err, "export panicked: handler failed");
+        // The failed batch is retried on flush
+        assert!(otlp.flush(Duration::from_secs(5)));
+        assert_eq!(otlp.stats().spans_exported(), 1);
+    }
+
+    #[test]
+    fn flush_exports_before_the_send_interval() {
+        let otlp = export_root(Builder::new().send_interval(Duration::from_secs(3600)));
+        assert!(otlp.flush(Duration::from_secs(5)));
+        assert_eq!(otlp.stats().spans_exported(), 1);
+    }
+
+    #[test]
+    fn simple_mode_exports_each_span_right_away() {
+        let otlp = export_root(Builder::new().simple());
+        assert_eq!(otlp.stats().spans_exported(), 1);
+    }
+
+    #[test]
+    fn spans_report_busy_and_idle_time() {
+        let (layer, otlp, spans) = capture_layer(Builder::new());
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("root");
+            root.in_scope(|| register_dist_tracing_root(TraceId::new(), None).unwrap());
+            thread::sleep(Duration::from_millis(20));
+            root.in_scope(|| thread::sleep(Duration::from_millis(20)));
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        let nanos = |key: &str| match spans[0].attributes.iter().find(|kv| kv.key == key) {
+            Some(KeyValue {
+                value:
+                    Some(crate::prost::common::v1::AnyValue {
+                        value: Some(Value::IntValue(nanos)),
+                    }),
+                ..
+            }) => *nanos,
+            other => panic!("{key}: {other:?}"),
+        };
+        assert!(nanos("busy_ns") >= 20_000_000);
+        assert!(nanos("idle_ns") >= 20_000_000);
+    }
+
+    #[test]
+    fn heartbeat_exports_snapshots_of_open_spans() {
+        let (layer, otlp, spans) =
+            capture_layer(Builder::new().heartbeat(Duration::from_millis(20)));
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("job").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                set_trace_attribute("job.id", 7).unwrap();
+                thread::sleep(Duration::from_millis(30));
+                tracing::info!("progress");
+            })
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        let in_progress = KeyValue::new("span.in_progress".to_string(), true.into());
+        let job_id = KeyValue::new("job.id".to_string(), 7i64.into());
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].span_id, spans[1].span_id);
+        assert!(spans[0].attributes.contains(&in_progress));
+        assert!(!spans[1].attributes.contains(&in_progress));
+        assert!(spans[0].end_time_unix_nano <= spans[1].end_time_unix_nano);
+        // Trace attributes are still applied to the span once it ends
+        assert!(spans.iter().all(|span| span.attributes.contains(&job_id)));
+    }
+
+    #[test]
+    fn drops_events_beyond_the_maximum() {
+        let (layer, otlp, spans) = capture_layer(Builder::new().max_events_per_span(2));
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("loop").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                for i in 0..5 {
+                    tracing::info!(i, "iteration");
+                }
+            })
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans[0].events.len(), 2);
+        assert_eq!(spans[0].dropped_events_count, 3);
+    }
+
+    #[test]
+    fn implicit_traces_export_unregistered_spans() {
+        let (layer, otlp, spans) = capture_layer(Builder::new().implicit_traces());
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("startup").in_scope(|| {
+                assert!(is_sampled().unwrap());
+                tracing::info_span!("migrate").in_scope(|| {});
+            });
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        let implicit = KeyValue::new("trace.implicit".to_string(), true.into());
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].trace_id, spans[1].trace_id);
+        assert_eq!(spans[0].parent_span_id, spans[1].span_id);
+        assert!(spans.iter().all(|span| span.attributes.contains(&implicit)));
+    }
+
+    #[test]
+    fn spans_outside_of_a_trace_join_the_default_trace() {
+        let (trace_id, scheduler) = (TraceId::new(), SpanId(42));
+        let (layer, otlp, spans) =
+            capture_layer(Builder::new().default_trace(trace_id, Some(scheduler)));
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("extract").in_scope(|| {
+                assert_eq!(current_dist_trace_ctx().unwrap().0, trace_id);
+            });
+            tracing::info_span!("load").in_scope(|| {});
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 2);
+        for span in spans.iter() {
+            assert_eq!(span.trace_id, trace_id.to_bytes());
+            assert_eq!(span.parent_span_id, scheduler.to_bytes());
+        }
+    }
+
+    #[test]
+    fn level_and_target_filters_skip_spans() {
+        let (layer, otlp, spans) = capture_layer(
+            Builder::new()
+                .max_level(tracing::Level::INFO)
+                .deny_targets(["h2::"]),
+        );
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("request").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+                tracing::debug_span!("parse").in_scope(|| {});
+                tracing::info_span!(target: "h2::proto", "stream").in_scope(|| {
+                    tracing::info_span!("handler").in_scope(|| {});
+                });
+            });
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["handler", "request"]);
+        assert_eq!(spans[0].parent_span_id, spans[1].span_id);
+    }
+
+    #[test]
+    fn custom_exporter_receives_the_requests() {
+        use std::sync::atomic::AtomicUsize;
+
+        let attempts = AtomicUsize::new(0);
+        let (layer, otlp, spans) = capture_layer_with(Builder::new(), move |_| {
+            if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+                return Err("bus unavailable".into());
+            }
+            Ok(())
+        });
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+        // The first attempt fails, and the batch is retried
+        otlp.flush(Duration::from_secs(5));
+        assert!(spans.lock().unwrap().is_empty());
+        otlp.flush(Duration::from_secs(5));
+
+        let spans = spans.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "root");
+        assert_eq!(otlp.stats().spans_exported(), 1);
+    }
+
+    #[test]
+    fn worker_restarts_after_a_panic() {
+        use std::sync::{atomic::AtomicBool, Mutex};
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let handled = errors.clone();
+        let panicked = AtomicBool::new(false);
+        let builder =
+            Builder::new().on_error(move |err| handled.lock().unwrap().push(err.to_string()));
+        let (layer, otlp, spans) = capture_layer_with(builder, move |_| {
+            if !panicked.swap(true, Ordering::Relaxed) {
+                panic!("exporter failed");
+            }
+            Ok(())
+        });
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        let root = |name| {
+            tracing::info_span!("root", otel.name = name).in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            root("lost");
+            // The flush is not acknowledged, as the worker panicked while flushing
+            assert!(!otlp.flush(Duration::from_secs(5)));
+            root("kept");
+            assert!(otlp.flush(Duration::from_secs(5)));
+        });
+
+        let names: Vec<_> = spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        assert_eq!(names, ["kept"]);
+        assert_eq!(
+            *errors.lock().unwrap(),
+            [
+                "worker panicked, restarting it: exporter failed",
+                "dropped 1 spans: worker panicked",
+            ]
+        );
+        assert_eq!(otlp.stats().spans_dropped(), 1);
+        assert_eq!(otlp.stats().spans_exported(), 1);
+    }
+
+    #[test]
+    fn drops_spans_while_the_queue_is_full() {
+        use std::sync::{atomic::AtomicUsize, mpsc, Mutex};
+
+        // The exporter blocks while the test holds the gate, so the worker stops taking spans
+        let gate = Arc::new(Mutex::new(()));
+        let exporter_gate = gate.clone();
+        let (entered_tx, entered) = mpsc::channel();
+        let entered_tx = Mutex::new(entered_tx);
+        let reports = Arc::new(AtomicUsize::new(0));
+        let reported = reports.clone();
+        let layer = Builder::new()
+            .send_interval(Duration::from_secs(3600))
+            .max_queue_size(1)
+            .on_error(move |err| {
+                if let OtlpError::SpansDropped { reason, .. } = err {
+                    assert_eq!(reason, DeliveryError::QueueFull.reason());
+                    reported.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .build_exporter(move |_| {
+                let _ = entered_tx.lock().unwrap().send(());
+                let _gate = exporter_gate.lock().unwrap();
+                Ok(())
+            })
+            .unwrap();
+        let otlp = layer.telemetry().clone();
+        let stats = otlp.stats();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        let root = || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            for run in 1..=2 {
+                let closed = gate.lock().unwrap();
+                root();
+                let flush = thread::spawn({
+                    let otlp = otlp.clone();
+                    move || otlp.flush(Duration::from_secs(5))
+                });
+                entered.recv_timeout(Duration::from_secs(5)).unwrap();
+
+                // One span fits in the queue, the others are dropped and reported once
+                root();
+                root();
+                root();
+                assert_eq!(stats.spans_dropped(), 2 * run);
+                assert_eq!(reports.load(Ordering::Relaxed), run as usize);
+
+                drop(closed);
+                assert!(flush.join().unwrap());
+                // Exports the queued span, the next enqueued span reports drops again
+                assert!(otlp.flush(Duration::from_secs(5)));
+                entered.try_iter().for_each(drop);
+            }
+        });
+        assert_eq!(stats.spans_exported(), 4);
+    }
+
+    #[test]
+    fn parent_span_id_is_the_span_id_of_the_parent() {
+        let (layer, otlp, spans) = capture_layer(Builder::new());
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                let remote_parent = SpanId(0x0102_0304_0506_0708);
+                register_dist_tracing_root(TraceId::new(), Some(remote_parent)).unwrap();
+                tracing::info_span!("child").in_scope(|| {});
+            })
+        });
+        otlp.flush(Duration::from_secs(5));
+
+        // Parent ids were once exported little-endian, unlike span ids
+        let spans = spans.lock().unwrap();
+        let (child, root) = (&spans[0], &spans[1]);
+        assert_eq!(child.parent_span_id, root.span_id);
+        assert_eq!(root.parent_span_id, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
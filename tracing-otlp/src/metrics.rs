@@ -0,0 +1,662 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use tracing::Level;
+use url::Url;
+
+use crate::{
+    error::ErrorHandler,
+    prost::{
+        collector::metrics::v1::ExportMetricsServiceRequest,
+        common::v1::{any_value::Value, AnyValue, InstrumentationScope, KeyValue},
+        metrics::v1::{
+            metric::Data, number_data_point, AggregationTemporality, Gauge, Histogram,
+            HistogramDataPoint, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum,
+        },
+        resource::v1::Resource,
+        trace::v1::{span::SpanKind, status::StatusCode, Span},
+    },
+    system_time_to_unix_nanos,
+    time::{now, Instant},
+};
+
+/// Counters describing the health of the exporter, shared between the layer and the worker.
+#[derive(Debug, Default)]
+pub(crate) struct ExporterCounters {
+    /// Spans handed to the worker by the layer
+    pub(crate) spans_enqueued: AtomicU64,
+    /// Spans taken off the queue by the worker
+    pub(crate) spans_dequeued: AtomicU64,
+    /// Spans held by the worker, waiting for the next batch or a tail sampling decision
+    pub(crate) spans_pending: AtomicU64,
+    /// Spans accepted by the OTLP endpoint
+    pub(crate) spans_exported: AtomicU64,
+    /// Spans which were lost, e.g. because the endpoint was unreachable for too long or
+    /// rejected them
+    pub(crate) spans_dropped: AtomicU64,
+    /// Export requests which succeeded
+    pub(crate) batches_exported: AtomicU64,
+    /// Export requests which failed
+    pub(crate) batches_failed: AtomicU64,
+    /// Duration of the last export request in nanoseconds, 0 if there was none yet
+    pub(crate) last_export_nanos: AtomicU64,
+    /// Spans dropped by level-aware shedding, indexed by [`ExporterCounters::level_index`],
+    /// also counted in `spans_dropped`
+    pub(crate) spans_shed: [AtomicU64; 5],
+    /// Spans of traces discarded by tail sampling, not counted in `spans_dropped`
+    pub(crate) spans_tail_dropped: AtomicU64,
+}
+
+impl ExporterCounters {
+    pub(crate) fn add(counter: &AtomicU64, n: usize) {
+        counter.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Number of spans enqueued but not yet exported or dropped.
+    pub(crate) fn queue_depth(&self) -> u64 {
+        let queued = self
+            .spans_enqueued
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.spans_dequeued.load(Ordering::Relaxed));
+        queued + self.spans_pending.load(Ordering::Relaxed)
+    }
+
+    /// Index of the counter of `level` in `spans_shed`.
+    pub(crate) fn level_index(level: Level) -> usize {
+        match level {
+            Level::TRACE => 0,
+            Level::DEBUG => 1,
+            Level::INFO => 2,
+            Level::WARN => 3,
+            _ => 4,
+        }
+    }
+}
+
+/// Handle reporting statistics about the exporter of an [`crate::Otlp`] layer, see
+/// [`crate::Otlp::stats`].
+///
+/// Counters are cumulative since the layer was built.
+#[derive(Debug, Clone)]
+pub struct OtlpStats(pub(crate) Arc<ExporterCounters>);
+
+impl OtlpStats {
+    /// Spans handed to the exporter by the layer.
+    pub fn spans_received(&self) -> u64 {
+        self.0.spans_enqueued.load(Ordering::Relaxed)
+    }
+
+    /// Spans accepted by the destination.
+    pub fn spans_exported(&self) -> u64 {
+        self.0.spans_exported.load(Ordering::Relaxed)
+    }
+
+    /// Spans which were lost, e.g. because the destination was unreachable for too long or
+    /// rejected them in a partial success.
+    pub fn spans_dropped(&self) -> u64 {
+        self.0.spans_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Spans of `level` dropped to keep room in the queue for more severe spans, see
+    /// [`crate::Builder::level_aware_shedding`]. These are included in
+    /// [`OtlpStats::spans_dropped`].
+    pub fn spans_shed(&self, level: Level) -> u64 {
+        self.0.spans_shed[ExporterCounters::level_index(level)].load(Ordering::Relaxed)
+    }
+
+    /// Spans of traces discarded by tail sampling, see [`crate::Builder::tail_sampling`].
+    /// These are not included in [`OtlpStats::spans_dropped`], as they were not lost.
+    pub fn spans_tail_dropped(&self) -> u64 {
+        self.0.spans_tail_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Export requests which succeeded.
+    pub fn batches_exported(&self) -> u64 {
+        self.0.batches_exported.load(Ordering::Relaxed)
+    }
+
+    /// Export requests which failed.
+    pub fn batches_failed(&self) -> u64 {
+        self.0.batches_failed.load(Ordering::Relaxed)
+    }
+
+    /// Duration of the last export request, whether or not it succeeded.
+    pub fn last_export_latency(&self) -> Option<Duration> {
+        match self.0.last_export_nanos.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Spans waiting to be exported.
+    pub fn queue_depth(&self) -> u64 {
+        self.0.queue_depth()
+    }
+}
+
+/// Outcome of an export attempt, passed to the callback set with
+/// [`crate::Builder::on_batch_result`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct BatchResult {
+    /// Number of spans in the batch
+    pub spans: usize,
+    /// Size of the batch encoded as protobuf, in bytes
+    pub payload_size: usize,
+    /// Time taken by the export attempt
+    pub duration: Duration,
+    /// Number of spans the destination reported as rejected in a partial success, these are
+    /// counted as dropped
+    pub rejected_spans: usize,
+    /// Description of the error if the export failed, the spans are then retried
+    pub error: Option<String>,
+}
+
+/// Callback receiving the [`BatchResult`] of each export attempt.
+pub(crate) type BatchResultCallback = Arc<dyn Fn(BatchResult) + Send + Sync>;
+
+/// Configuration of the metrics reported by the worker, as set on the [`crate::Builder`].
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsConfig {
+    pub(crate) interval: Duration,
+    pub(crate) self_metrics: bool,
+    pub(crate) span_metrics: bool,
+}
+
+/// Periodically reports the [`ExporterCounters`] and [`SpanMetrics`] on the OTLP metrics
+/// signal.
+#[derive(Debug)]
+pub(crate) struct MetricsExporter {
+    pub(crate) endpoint: Url,
+    interval: Duration,
+    self_metrics: bool,
+    span_metrics: Option<SpanMetrics>,
+    start_time: SystemTime,
+    last_send: Instant,
+    error_handler: ErrorHandler,
+}
+
+impl MetricsExporter {
+    pub(crate) fn new(endpoint: Url, config: MetricsConfig, error_handler: ErrorHandler) -> Self {
+        Self {
+            endpoint,
+            interval: config.interval,
+            self_metrics: config.self_metrics,
+            span_metrics: config.span_metrics.then(SpanMetrics::default),
+            start_time: now(),
+            last_send: Instant::now(),
+            error_handler,
+        }
+    }
+
+    pub(crate) fn instant_next_send(&self) -> Instant {
+        self.last_send + self.interval
+    }
+
+    /// Aggregates a completed span into the span metrics, if enabled.
+    pub(crate) fn record_span(&mut self, span: &Span) {
+        if let Some(span_metrics) = &mut self.span_metrics {
+            span_metrics.record(span);
+        }
+    }
+
+    /// Returns the request to send if the interval is up.
+    pub(crate) fn poll(
+        &mut self,
+        resource: &Resource,
+        counters: &ExporterCounters,
+        queue_depth: u64,
+    ) -> Option<ExportMetricsServiceRequest> {
+        if self.last_send.elapsed() < self.interval {
+            return None;
+        }
+        self.last_send = Instant::now();
+        Some(self.request(resource, counters, queue_depth))
+    }
+
+    fn request(
+        &self,
+        resource: &Resource,
+        counters: &ExporterCounters,
+        queue_depth: u64,
+    ) -> ExportMetricsServiceRequest {
+        let start_time_unix_nano = system_time_to_unix_nanos(&self.start_time, &self.error_handler);
+        let time_unix_nano = system_time_to_unix_nanos(&now(), &self.error_handler);
+
+        let mut metrics = Vec::new();
+        if self.self_metrics {
+            metrics.extend(self_metrics(
+                counters,
+                queue_depth,
+                start_time_unix_nano,
+                time_unix_nano,
+            ));
+        }
+        if let Some(span_metrics) = &self.span_metrics {
+            metrics.extend(span_metrics.metrics(resource, start_time_unix_nano, time_unix_nano));
+        }
+
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(resource.clone()),
+                scope_metrics: vec![ScopeMetrics {
+                    scope: Some(InstrumentationScope {
+                        name: env!("CARGO_PKG_NAME").to_string(),
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                        attributes: vec![],
+                        dropped_attributes_count: 0,
+                    }),
+                    metrics,
+                    schema_url: "".to_string(),
+                }],
+                schema_url: "".to_string(),
+            }],
+        }
+    }
+}
+
+fn self_metrics(
+    counters: &ExporterCounters,
+    queue_depth: u64,
+    start_time_unix_nano: u64,
+    time_unix_nano: u64,
+) -> Vec<Metric> {
+    let data_point = |value: u64| NumberDataPoint {
+        attributes: vec![],
+        start_time_unix_nano,
+        time_unix_nano,
+        exemplars: vec![],
+        flags: 0,
+        value: Some(number_data_point::Value::AsInt(value as i64)),
+    };
+    let sum = |name: &str, description: &str, unit: &str, counter: &AtomicU64| Metric {
+        name: name.to_string(),
+        description: description.to_string(),
+        unit: unit.to_string(),
+        metadata: vec![],
+        data: Some(Data::Sum(Sum {
+            data_points: vec![data_point(counter.load(Ordering::Relaxed))],
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            is_monotonic: true,
+        })),
+    };
+
+    vec![
+        sum(
+            "otlp.exporter.spans.enqueued",
+            "Spans handed to the exporter",
+            "{span}",
+            &counters.spans_enqueued,
+        ),
+        sum(
+            "otlp.exporter.spans.exported",
+            "Spans accepted by the OTLP endpoint",
+            "{span}",
+            &counters.spans_exported,
+        ),
+        sum(
+            "otlp.exporter.spans.dropped",
+            "Spans lost by the exporter",
+            "{span}",
+            &counters.spans_dropped,
+        ),
+        sum(
+            "otlp.exporter.batches.failed",
+            "Export requests which failed",
+            "{batch}",
+            &counters.batches_failed,
+        ),
+        Metric {
+            name: "otlp.exporter.queue.depth".to_string(),
+            description: "Spans waiting to be exported".to_string(),
+            unit: "{span}".to_string(),
+            metadata: vec![],
+            data: Some(Data::Gauge(Gauge {
+                data_points: vec![data_point(queue_depth)],
+            })),
+        },
+    ]
+}
+
+/// Upper bounds of the span duration histogram buckets in milliseconds, matching the
+/// defaults of the collector's spanmetrics connector.
+const DURATION_BOUNDS_MS: [f64; 16] = [
+    2.0, 4.0, 6.0, 8.0, 10.0, 50.0, 100.0, 200.0, 400.0, 800.0, 1000.0, 1400.0, 2000.0, 5000.0,
+    10000.0, 15000.0,
+];
+
+/// Maximum number of series of the span metrics, matching the default cardinality limit of
+/// the OpenTelemetry SDKs. Spans of further name, kind and status combinations are
+/// aggregated into a single overflow series.
+const MAX_SPAN_METRICS_SERIES: usize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct SpanMetricsKey {
+    name: String,
+    kind: i32,
+    status: i32,
+}
+
+#[derive(Debug, Clone)]
+struct DurationHistogram {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    bucket_counts: [u64; DURATION_BOUNDS_MS.len() + 1],
+}
+
+impl Default for DurationHistogram {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            bucket_counts: Default::default(),
+        }
+    }
+}
+
+impl DurationHistogram {
+    fn record(&mut self, duration_ms: f64) {
+        self.count += 1;
+        self.sum += duration_ms;
+        self.min = self.min.min(duration_ms);
+        self.max = self.max.max(duration_ms);
+        let bucket = DURATION_BOUNDS_MS.partition_point(|bound| *bound < duration_ms);
+        self.bucket_counts[bucket] += 1;
+    }
+}
+
+/// Cumulative histograms of the duration of completed spans, per span name, kind and status.
+///
+/// These give request rate, error rate and duration (RED) metrics without a separate metrics
+/// pipeline, like the spanmetrics connector of the collector.
+#[derive(Debug, Default)]
+pub(crate) struct SpanMetrics {
+    histograms: BTreeMap<SpanMetricsKey, DurationHistogram>,
+    /// Spans of the series beyond [`MAX_SPAN_METRICS_SERIES`], reported with the
+    /// `otel.metric.overflow` attribute
+    overflow: DurationHistogram,
+}
+
+impl SpanMetrics {
+    pub(crate) fn record(&mut self, span: &Span) {
+        let key = SpanMetricsKey {
+            name: span.name.clone(),
+            kind: span.kind,
+            status: span.status.as_ref().map_or(0, |status| status.code),
+        };
+        let duration_ms = span
+            .end_time_unix_nano
+            .saturating_sub(span.start_time_unix_nano) as f64
+            / 1_000_000.0;
+        if self.histograms.len() >= MAX_SPAN_METRICS_SERIES && !self.histograms.contains_key(&key) {
+            self.overflow.record(duration_ms);
+            return;
+        }
+        self.histograms.entry(key).or_default().record(duration_ms);
+    }
+
+    fn metrics(
+        &self,
+        resource: &Resource,
+        start_time_unix_nano: u64,
+        time_unix_nano: u64,
+    ) -> Vec<Metric> {
+        if self.histograms.is_empty() {
+            return vec![];
+        }
+
+        let service_name = resource
+            .attributes
+            .iter()
+            .find(|kv| kv.key == "service.name")
+            .cloned();
+        let string_attribute = |key: &str, value: &str| KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(Value::StringValue(value.to_string())),
+            }),
+        };
+
+        let data_point =
+            |attributes: Vec<KeyValue>, histogram: &DurationHistogram| HistogramDataPoint {
+                attributes: service_name.iter().cloned().chain(attributes).collect(),
+                start_time_unix_nano,
+                time_unix_nano,
+                count: histogram.count,
+                sum: Some(histogram.sum),
+                bucket_counts: histogram.bucket_counts.to_vec(),
+                explicit_bounds: DURATION_BOUNDS_MS.to_vec(),
+                exemplars: vec![],
+                flags: 0,
+                min: Some(histogram.min),
+                max: Some(histogram.max),
+            };
+
+        let mut data_points: Vec<_> = self
+            .histograms
+            .iter()
+            .map(|(key, histogram)| {
+                let attributes = vec![
+                    string_attribute("span.name", &key.name),
+                    string_attribute(
+                        "span.kind",
+                        SpanKind::try_from(key.kind)
+                            .unwrap_or_default()
+                            .as_str_name(),
+                    ),
+                    string_attribute(
+                        "status.code",
+                        StatusCode::try_from(key.status)
+                            .unwrap_or_default()
+                            .as_str_name(),
+                    ),
+                ];
+                data_point(attributes, histogram)
+            })
+            .collect();
+        if self.overflow.count > 0 {
+            let overflow = KeyValue {
+                key: "otel.metric.overflow".to_string(),
+                value: Some(AnyValue {
+                    value: Some(Value::BoolValue(true)),
+                }),
+            };
+            data_points.push(data_point(vec![overflow], &self.overflow));
+        }
+
+        vec![Metric {
+            name: "traces.span.metrics.duration".to_string(),
+            description: "Duration of completed spans".to_string(),
+            unit: "ms".to_string(),
+            metadata: vec![],
+            data: Some(Data::Histogram(Histogram {
+                data_points,
+                aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            })),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::prost::trace::v1::Status;
+
+    fn config(self_metrics: bool, span_metrics: bool) -> MetricsConfig {
+        MetricsConfig {
+            interval: Duration::ZERO,
+            self_metrics,
+            span_metrics,
+        }
+    }
+
+    fn exporter(config: MetricsConfig) -> MetricsExporter {
+        MetricsExporter::new(
+            Url::parse("http://127.0.0.1:4318/v1/metrics").unwrap(),
+            config,
+            Default::default(),
+        )
+    }
+
+    fn span(name: &str, duration_ms: u64, status: StatusCode) -> Span {
+        Span {
+            name: name.to_string(),
+            start_time_unix_nano: 1_000_000_000,
+            end_time_unix_nano: 1_000_000_000 + duration_ms * 1_000_000,
+            status: Some(Status {
+                message: String::new(),
+                code: status as i32,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stats_include_queued_and_pending_spans() {
+        let stats = OtlpStats(Arc::default());
+        ExporterCounters::add(&stats.0.spans_enqueued, 5);
+        ExporterCounters::add(&stats.0.spans_dequeued, 4);
+        stats.0.spans_pending.store(2, Ordering::Relaxed);
+
+        assert_eq!(stats.spans_received(), 5);
+        assert_eq!(stats.queue_depth(), 3);
+        assert_eq!(stats.last_export_latency(), None);
+    }
+
+    #[test]
+    fn reports_counters_and_queue_depth() {
+        let counters = ExporterCounters::default();
+        ExporterCounters::add(&counters.spans_enqueued, 5);
+        ExporterCounters::add(&counters.spans_exported, 3);
+        ExporterCounters::add(&counters.batches_failed, 1);
+
+        let req = exporter(config(true, false))
+            .poll(&Resource::default(), &counters, 2)
+            .expect("interval is up");
+
+        let values: Vec<_> = req.resource_metrics[0].scope_metrics[0]
+            .metrics
+            .iter()
+            .map(|metric| {
+                let data_points = match metric.data.as_ref().unwrap() {
+                    Data::Sum(sum) => &sum.data_points,
+                    Data::Gauge(gauge) => &gauge.data_points,
+                    _ => unreachable!(),
+                };
+                (metric.name.as_str(), data_points[0].value)
+            })
+            .collect();
+        let int = |v| Some(number_data_point::Value::AsInt(v));
+        assert_eq!(
+            values,
+            vec![
+                ("otlp.exporter.spans.enqueued", int(5)),
+                ("otlp.exporter.spans.exported", int(3)),
+                ("otlp.exporter.spans.dropped", int(0)),
+                ("otlp.exporter.batches.failed", int(1)),
+                ("otlp.exporter.queue.depth", int(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregates_span_durations_per_name_and_status() {
+        let mut exporter = exporter(config(false, true));
+        exporter.record_span(&span("GET /users", 3, StatusCode::Unset));
+        exporter.record_span(&span("GET /users", 120, StatusCode::Unset));
+        exporter.record_span(&span("GET /users", 7, StatusCode::Error));
+
+        let resource = Resource {
+            attributes: vec![KeyValue {
+                key: "service.name".to_string(),
+                value: Some(AnyValue {
+                    value: Some(Value::StringValue("api".to_string())),
+                }),
+            }],
+            dropped_attributes_count: 0,
+        };
+        let req = exporter
+            .poll(&resource, &ExporterCounters::default(), 0)
+            .expect("interval is up");
+
+        let metrics = &req.resource_metrics[0].scope_metrics[0].metrics;
+        assert_eq!(metrics.len(), 1);
+        let Some(Data::Histogram(histogram)) = &metrics[0].data else {
+            panic!("expected a histogram")
+        };
+        assert_eq!(histogram.data_points.len(), 2);
+
+        let unset = &histogram.data_points[0];
+        let attributes: Vec<_> = unset
+            .attributes
+            .iter()
+            .map(|kv| match &kv.value.as_ref().unwrap().value {
+                Some(Value::StringValue(s)) => (kv.key.as_str(), s.as_str()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            attributes,
+            vec![
+                ("service.name", "api"),
+                ("span.name", "GET /users"),
+                ("span.kind", "SPAN_KIND_UNSPECIFIED"),
+                ("status.code", "STATUS_CODE_UNSET"),
+            ]
+        );
+        assert_eq!(unset.count, 2);
+        assert_eq!(unset.sum, Some(123.0));
+        assert_eq!(unset.min, Some(3.0));
+        assert_eq!(unset.max, Some(120.0));
+        // 3ms falls in the (2, 4] bucket, 120ms in (100, 200]
+        assert_eq!(unset.bucket_counts[1], 1);
+        assert_eq!(unset.bucket_counts[7], 1);
+
+        assert_eq!(histogram.data_points[1].count, 1);
+    }
+
+    #[test]
+    fn aggregates_series_beyond_the_maximum_into_an_overflow_series() {
+        let mut exporter = exporter(config(false, true));
+        for i in 0..MAX_SPAN_METRICS_SERIES + 2 {
+            exporter.record_span(&span(&format!("GET /users/{i}"), 1, StatusCode::Unset));
+        }
+        // Existing series still aggregate their spans
+        exporter.record_span(&span("GET /users/0", 1, StatusCode::Unset));
+        exporter.record_span(&span("GET /users/0", 1, StatusCode::Error));
+
+        let req = exporter
+            .poll(&Resource::default(), &ExporterCounters::default(), 0)
+            .expect("interval is up");
+        let Some(Data::Histogram(histogram)) =
+            &req.resource_metrics[0].scope_metrics[0].metrics[0].data
+        else {
+            panic!("expected a histogram")
+        };
+        assert_eq!(histogram.data_points.len(), MAX_SPAN_METRICS_SERIES + 1);
+        let counts: u64 = histogram.data_points.iter().map(|point| point.count).sum();
+        assert_eq!(counts, MAX_SPAN_METRICS_SERIES as u64 + 4);
+
+        let overflow = histogram.data_points.last().unwrap();
+        assert_eq!(overflow.count, 3);
+        assert_eq!(
+            overflow.attributes,
+            [KeyValue {
+                key: "otel.metric.overflow".to_string(),
+                value: Some(AnyValue {
+                    value: Some(Value::BoolValue(true)),
+                }),
+            }]
+        );
+    }
+}
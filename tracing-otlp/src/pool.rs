@@ -0,0 +1,181 @@
+use std::{
+    error::Error,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use prost::Message;
+use url::Url;
+
+use crate::{
+    http::HttpClient,
+    prost::{collector::trace::v1::ExportTraceServiceRequest, trace::v1::Span},
+    route::ResolvedRoute,
+    time,
+    worker::{panic_message, SERVICE_NAME},
+};
+
+/// A batch of spans to export, with the attempts to export it so far.
+pub(crate) struct Batch {
+    pub(crate) req: ExportTraceServiceRequest,
+    /// Number of failed attempts to export the batch
+    pub(crate) failures: u32,
+    /// When the first attempt failed, if any
+    pub(crate) failing_since: Option<time::Instant>,
+    /// The route the spans are sent to, instead of the endpoint of the exporter
+    pub(crate) route: Option<Arc<ResolvedRoute>>,
+}
+
+impl Batch {
+    pub(crate) fn new(req: ExportTraceServiceRequest, route: Option<Arc<ResolvedRoute>>) -> Self {
+        Self {
+            req,
+            route,
+            failures: 0,
+            failing_since: None,
+        }
+    }
+
+    /// Takes the spans out of the batch, with the `service.name` of their resource as an
+    /// attribute so they are grouped by service again when exported.
+    pub(crate) fn into_spans(self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        for resource_spans in self.req.resource_spans {
+            let service = resource_spans.resource.and_then(|resource| {
+                resource
+                    .attributes
+                    .into_iter()
+                    .rfind(|kv| kv.key == SERVICE_NAME)
+            });
+            for mut span in resource_spans.scope_spans.into_iter().flat_map(|s| s.spans) {
+                span.attributes.extend(service.clone());
+                spans.push(span);
+            }
+        }
+        spans
+    }
+}
+
+/// Result of an export, performed by the worker itself or by an [`ExportPool`] thread.
+pub(crate) struct ExportOutcome {
+    pub(crate) batch: Batch,
+    pub(crate) spans: usize,
+    pub(crate) duration: Duration,
+    pub(crate) result: Result<usize, Box<dyn Error + Send + Sync>>,
+}
+
+/// A batch handed to an [`ExportPool`] thread, with the endpoint and headers to send it
+/// with.
+type Job = (Batch, Url, Vec<(String, String)>);
+
+/// Threads exporting batches concurrently, so throughput is not limited by the latency of
+/// the endpoint. Set with [`crate::Builder::max_concurrent_exports`].
+pub(crate) struct ExportPool {
+    jobs: Sender<Job>,
+    outcomes: Receiver<ExportOutcome>,
+    size: usize,
+    in_flight: usize,
+    in_flight_spans: usize,
+}
+
+impl ExportPool {
+    /// Spawns `size` threads exporting batches, which exit once the pool is dropped.
+    pub(crate) fn spawn(size: usize, client: HttpClient) -> io::Result<Self> {
+        let (jobs, job_rx) = channel::<Job>();
+        let (outcome_tx, outcomes) = channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+
+        for i in 0..size {
+            let (job_rx, outcome_tx) = (job_rx.clone(), outcome_tx.clone());
+            let client = client.clone();
+            thread::Builder::new()
+                .name(format!("OTLP exporter {i}"))
+                .spawn(move || loop {
+                    let Ok((batch, url, headers)) =
+                        job_rx.lock().expect("job lock poisoned").recv()
+                    else {
+                        break;
+                    };
+                    let started = Instant::now();
+                    // A panic fails the export, rather than leaving the worker waiting for it
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        client.send_traces(&url, &headers, &batch.req.encode_to_vec())
+                    }))
+                    .unwrap_or_else(|panic| {
+                        let message = panic_message(panic.as_ref());
+                        Err(format!("export panicked: {message}").into())
+                    });
+                    let outcome = ExportOutcome {
+                        spans: span_count(&batch.req),
+                        batch,
+                        duration: started.elapsed(),
+                        result,
+                    };
+                    if outcome_tx.send(outcome).is_err() {
+                        break;
+                    }
+                })?;
+        }
+
+        Ok(Self {
+            jobs,
+            outcomes,
+            size,
+            in_flight: 0,
+            in_flight_spans: 0,
+        })
+    }
+
+    /// Whether another batch can be exported right away.
+    pub(crate) fn has_capacity(&self) -> bool {
+        self.in_flight < self.size
+    }
+
+    /// Spans in batches being exported.
+    pub(crate) fn in_flight_spans(&self) -> usize {
+        self.in_flight_spans
+    }
+
+    /// Hands a batch to the next free thread, to send to `url` with the extra `headers`.
+    pub(crate) fn submit(&mut self, batch: Batch, url: Url, headers: Vec<(String, String)>) {
+        let spans = span_count(&batch.req);
+        // The threads only exit once the pool is dropped
+        if self.jobs.send((batch, url, headers)).is_ok() {
+            self.in_flight += 1;
+            self.in_flight_spans += spans;
+        }
+    }
+
+    /// Waits for the outcome of the next export in flight, if any.
+    pub(crate) fn wait_outcome(&mut self) -> Option<ExportOutcome> {
+        if self.in_flight == 0 {
+            return None;
+        }
+        let outcome = self.outcomes.recv().ok()?;
+        self.in_flight -= 1;
+        self.in_flight_spans -= outcome.spans;
+        Some(outcome)
+    }
+
+    /// Returns the outcome of a finished export, if any.
+    pub(crate) fn try_outcome(&mut self) -> Option<ExportOutcome> {
+        let outcome = self.outcomes.try_recv().ok()?;
+        self.in_flight -= 1;
+        self.in_flight_spans -= outcome.spans;
+        Some(outcome)
+    }
+}
+
+pub(crate) fn span_count(req: &ExportTraceServiceRequest) -> usize {
+    req.resource_spans
+        .iter()
+        .flat_map(|resource_spans| &resource_spans.scope_spans)
+        .map(|scope_spans| scope_spans.spans.len())
+        .sum()
+}
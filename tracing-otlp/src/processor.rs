@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use tracing::Metadata;
+
+use crate::prost::{
+    common::v1::{any_value::Value, KeyValue},
+    trace::v1::Span,
+};
+
+/// Observes and modifies spans as they start and before they are exported, registered with
+/// [`crate::Builder::add_processor`].
+///
+/// Processors are called in the order they were added. Both methods do nothing by default.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{prost::trace::v1::Span, Builder, SpanProcessor};
+/// /// Drops health checks and prefixes the names of the other spans.
+/// struct Conventions;
+///
+/// impl SpanProcessor for Conventions {
+///     fn on_end(&self, span: &mut Span) -> bool {
+///         span.name = format!("checkout.{}", span.name);
+///         span.name != "checkout.health"
+///     }
+/// }
+///
+/// Builder::new().add_processor(Conventions);
+/// ```
+pub trait SpanProcessor: Send + Sync + 'static {
+    /// Called when a span is created, with the attributes it was created with. Fields
+    /// recorded later are appended to these.
+    ///
+    /// The trace of the span is not known yet, as the span may still be registered as the
+    /// root of a trace.
+    fn on_start(&self, _metadata: &'static Metadata<'static>, _attributes: &mut Vec<KeyValue>) {}
+
+    /// Called when a span of a sampled trace ended, before it is exported. Returns whether
+    /// to export the span, the processors after one dropping it are not called.
+    ///
+    /// The attribute renames and filters of the [`crate::Builder`] apply after all
+    /// processors ran.
+    fn on_end(&self, _span: &mut Span) -> bool {
+        true
+    }
+}
+
+/// [`SpanProcessor`] adding constant attributes to every exported span, such as
+/// `deployment.environment` or `region`.
+///
+/// Unlike resource attributes, these are attributes of the spans themselves, for backends
+/// which do not index resource attributes. Attributes the span already has are kept.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{Builder, StaticAttributes};
+/// Builder::new().add_processor(
+///     StaticAttributes::new()
+///         .attribute("deployment.environment", "production")
+///         .attribute("region", "eu-west-1"),
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StaticAttributes {
+    attributes: Vec<KeyValue>,
+}
+
+impl StaticAttributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an attribute to every span.
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.attributes
+            .push(KeyValue::new(key.into(), value.into()));
+        self
+    }
+}
+
+impl SpanProcessor for StaticAttributes {
+    fn on_end(&self, span: &mut Span) -> bool {
+        for kv in &self.attributes {
+            if !span
+                .attributes
+                .iter()
+                .any(|existing| existing.key == kv.key)
+            {
+                span.attributes.push(kv.clone());
+            }
+        }
+        true
+    }
+}
+
+/// The processors added to the [`crate::Builder`], in order.
+#[derive(Clone, Default)]
+pub(crate) struct Processors(Vec<Arc<dyn SpanProcessor>>);
+
+impl Processors {
+    pub(crate) fn push(&mut self, processor: impl SpanProcessor) {
+        self.0.push(Arc::new(processor));
+    }
+
+    pub(crate) fn on_start(
+        &self,
+        metadata: &'static Metadata<'static>,
+        attributes: &mut Vec<KeyValue>,
+    ) {
+        for processor in &self.0 {
+            processor.on_start(metadata, attributes);
+        }
+    }
+
+    /// Returns whether the span is exported.
+    pub(crate) fn on_end(&self, span: &mut Span) -> bool {
+        self.0.iter().all(|processor| processor.on_end(span))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Rename(&'static str);
+
+    impl SpanProcessor for Rename {
+        fn on_end(&self, span: &mut Span) -> bool {
+            span.name = self.0.to_string();
+            true
+        }
+    }
+
+    struct DropNamed(&'static str);
+
+    impl SpanProcessor for DropNamed {
+        fn on_end(&self, span: &mut Span) -> bool {
+            span.name != self.0
+        }
+    }
+
+    #[test]
+    fn runs_processors_in_order_until_one_drops_the_span() {
+        let mut processors = Processors::default();
+        processors.push(Rename("renamed"));
+        processors.push(DropNamed("renamed"));
+        processors.push(Rename("never"));
+
+        let mut span = Span::default();
+        assert!(!processors.on_end(&mut span));
+        assert_eq!(span.name, "renamed");
+
+        assert!(Processors::default().on_end(&mut span));
+    }
+
+    #[test]
+    fn adds_static_attributes_unless_set() {
+        let processor = StaticAttributes::new()
+            .attribute("region", "eu-west-1")
+            .attribute("deployment.environment", "production");
+        let mut span = Span {
+            attributes: vec![KeyValue::new("region".to_string(), "local".into())],
+            ..Default::default()
+        };
+        assert!(processor.on_end(&mut span));
+        assert_eq!(
+            span.attributes,
+            [
+                KeyValue::new("region".to_string(), "local".into()),
+                KeyValue::new("deployment.environment".to_string(), "production".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use crate::{SpanContext, SpanId, TraceId};
+
+/// Formats the W3C `traceparent` header of the span, e.g.
+/// `00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01`.
+///
+/// Send the `trace_state` of the context as the `tracestate` header, if there is any.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{SpanContext, SpanId, TraceId};
+/// let ctx = SpanContext::new(TraceId(1), SpanId(2), true);
+/// assert_eq!(
+///     tracing_otlp::traceparent(&ctx),
+///     "00-00000000000000000000000000000001-0000000000000002-01"
+/// );
+/// ```
+pub fn traceparent(ctx: &SpanContext) -> String {
+    format!(
+        "00-{}-{}-{:02x}",
+        ctx.trace_id, ctx.span_id, ctx.sampled as u8
+    )
+}
+
+/// Formats the single `b3` header of Zipkin for the span, e.g.
+/// `0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-1`.
+#[cfg(any(feature = "tower", feature = "hyper"))]
+pub(crate) fn b3(ctx: &SpanContext) -> String {
+    format!("{}-{}-{}", ctx.trace_id, ctx.span_id, ctx.sampled as u8)
+}
+
+/// Parses a W3C `traceparent` header along with the `tracestate` header, if any, into the
+/// context of the remote parent span. Returns `None` if the `traceparent` is invalid.
+///
+/// Register the root of the trace continuing it with [`crate::TraceRoot::remote`].
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{SpanId, TraceId};
+/// let ctx = tracing_otlp::parse_traceparent(
+///     "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+///     Some("vendor=value"),
+/// )
+/// .unwrap();
+/// assert_eq!(ctx.span_id, SpanId(0xb7ad6b7169203331));
+/// assert!(ctx.sampled);
+/// ```
+pub fn parse_traceparent(traceparent: &str, tracestate: Option<&str>) -> Option<SpanContext> {
+    let mut parts = traceparent.trim().split('-');
+    let version = parts.next().filter(|v| is_lower_hex(v, 2) && *v != "ff")?;
+    let trace_id = parts
+        .next()
+        .filter(|id| is_lower_hex(id, 32))?
+        .parse::<TraceId>()
+        .ok()?;
+    let span_id = parts
+        .next()
+        .filter(|id| is_lower_hex(id, 16))?
+        .parse::<SpanId>()
+        .ok()?;
+    let flags = parts.next().filter(|flags| is_lower_hex(flags, 2))?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    // Later versions may add parts, which are ignored
+    if version == "00" && parts.next().is_some() {
+        return None;
+    }
+
+    let ctx = SpanContext::new(trace_id, span_id, flags & 1 == 1);
+    Some(match tracestate.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(tracestate) => ctx.with_trace_state(tracestate),
+        None => ctx,
+    })
+}
+
+/// Formats the AWS X-Ray trace header of the span, e.g.
+/// `Root=1-0af76519-16cd43dd8448eb211c80319c;Parent=b7ad6b7169203331;Sampled=1`.
+///
+/// Set it as the `AWSTraceHeader` system attribute of SQS messages, so their consumers, such
+/// as Lambda functions, continue the trace of the producer. The first 8 hexadecimal digits
+/// of the trace id are taken as the timestamp of the X-Ray trace id.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{SpanContext, SpanId, TraceId};
+/// let ctx = SpanContext::new(TraceId(1), SpanId(2), true);
+/// assert_eq!(
+///     tracing_otlp::xray_trace_header(&ctx),
+///     "Root=1-00000000-000000000000000000000001;Parent=0000000000000002;Sampled=1"
+/// );
+/// ```
+pub fn xray_trace_header(ctx: &SpanContext) -> String {
+    let trace_id = ctx.trace_id.to_string();
+    let (epoch, unique) = trace_id.split_at(8);
+    format!(
+        "Root=1-{epoch}-{unique};Parent={};Sampled={}",
+        ctx.span_id, ctx.sampled as u8
+    )
+}
+
+/// Parses an AWS X-Ray trace header, such as the `AWSTraceHeader` system attribute of SQS
+/// messages or the `_X_AMZN_TRACE_ID` variable of Lambda functions, into the context of the
+/// remote parent span. Returns `None` if the header has no valid `Root` or `Parent`.
+///
+/// The span is not sampled unless the header has `Sampled=1`.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{SpanId, TraceId};
+/// let ctx = tracing_otlp::parse_xray_trace_header(
+///     "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1",
+/// )
+/// .unwrap();
+/// assert_eq!(ctx.trace_id, TraceId(0x5759e988bd862e3fe1be46a994272793));
+/// assert_eq!(ctx.span_id, SpanId(0x53995c3f42cd8ad8));
+/// ```
+pub fn parse_xray_trace_header(header: &str) -> Option<SpanContext> {
+    let (mut trace_id, mut span_id, mut sampled) = (None, None, false);
+    for part in header.split(';') {
+        let Some((key, value)) = part.trim().split_once('=') else {
+            continue;
+        };
+        match key {
+            "Root" => {
+                let mut root = value.split('-');
+                let (version, epoch, unique) = (root.next()?, root.next()?, root.next()?);
+                if version != "1" || !is_lower_hex(epoch, 8) || !is_lower_hex(unique, 24) {
+                    return None;
+                }
+                trace_id = Some(format!("{epoch}{unique}").parse::<TraceId>().ok()?);
+            }
+            "Parent" => {
+                span_id = Some(
+                    Some(value)
+                        .filter(|id| is_lower_hex(id, 16))?
+                        .parse::<SpanId>()
+                        .ok()?,
+                );
+            }
+            "Sampled" => sampled = value == "1",
+            _ => {}
+        }
+    }
+    Some(SpanContext::new(trace_id?, span_id?, sampled))
+}
+
+fn is_lower_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Extracts the context of the remote parent span from the W3C `traceparent` and
+/// `tracestate` headers of a request, see [`parse_traceparent`].
+#[cfg(feature = "http")]
+pub fn extract_http_headers(headers: &http::HeaderMap) -> Option<SpanContext> {
+    let traceparent = headers.get("traceparent")?.to_str().ok()?;
+    // The `tracestate` may be split over several headers
+    let tracestate = headers
+        .get_all("tracestate")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+    parse_traceparent(traceparent, Some(&tracestate))
+}
+
+/// Sets the W3C `traceparent` and `tracestate` headers of a request to the given context,
+/// e.g. of [`crate::current_span_context`].
+#[cfg(feature = "http")]
+pub fn inject_http_headers(ctx: &SpanContext, headers: &mut http::HeaderMap) {
+    let traceparent =
+        http::HeaderValue::try_from(traceparent(ctx)).expect("traceparent is a valid header value");
+    headers.insert("traceparent", traceparent);
+    match ctx
+        .trace_state
+        .as_deref()
+        .and_then(|tracestate| http::HeaderValue::try_from(tracestate).ok())
+    {
+        Some(tracestate) => headers.insert("tracestate", tracestate),
+        None => headers.remove("tracestate"),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_what_it_formats() {
+        let ctx = SpanContext::new(TraceId(0xabc), SpanId(0xdef), false).with_trace_state("a=1");
+        let parsed = parse_traceparent(&traceparent(&ctx), ctx.trace_state.as_deref());
+        assert_eq!(parsed, Some(ctx));
+    }
+
+    #[test]
+    fn rejects_invalid_traceparents() {
+        let valid = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        assert!(parse_traceparent(valid, None).is_some());
+        for invalid in [
+            "",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+        ] {
+            assert_eq!(parse_traceparent(invalid, None), None, "{invalid}");
+        }
+        // Later versions may have more parts
+        let future = "01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00-extra";
+        assert!(!parse_traceparent(future, Some(" ")).unwrap().sampled);
+    }
+
+    #[test]
+    fn parses_xray_trace_headers() {
+        let ctx = SpanContext::new(TraceId(0xabc), SpanId(0xdef), true);
+        assert_eq!(parse_xray_trace_header(&xray_trace_header(&ctx)), Some(ctx));
+
+        let unsampled = "Root=1-5759e988-bd862e3fe1be46a994272793; Parent=53995c3f42cd8ad8";
+        assert!(!parse_xray_trace_header(unsampled).unwrap().sampled);
+        for invalid in [
+            "",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1",
+            "Root=2-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8",
+            "Root=1-5759e988bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8",
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f",
+        ] {
+            assert_eq!(parse_xray_trace_header(invalid), None, "{invalid}");
+        }
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn round_trips_http_headers() {
+        let ctx = SpanContext::new(TraceId(1), SpanId(2), true).with_trace_state("a=1");
+        let mut headers = http::HeaderMap::new();
+        headers.insert("tracestate", "stale".parse().unwrap());
+        inject_http_headers(&SpanContext::new(TraceId(1), SpanId(2), true), &mut headers);
+        assert!(!headers.contains_key("tracestate"));
+
+        inject_http_headers(&ctx, &mut headers);
+        headers.append("tracestate", "b=2".parse().unwrap());
+        let extracted = extract_http_headers(&headers).unwrap();
+        assert_eq!(extracted.trace_state.as_deref(), Some("a=1,b=2"));
+        assert_eq!(
+            (extracted.trace_id, extracted.span_id),
+            (TraceId(1), SpanId(2))
+        );
+    }
+}
@@ -1,4 +1,13 @@
 pub mod collector {
+    pub mod metrics {
+        pub mod v1 {
+            include!(concat!(
+                env!("OUT_DIR"),
+                "/opentelemetry.proto.collector.metrics.v1.rs"
+            ));
+        }
+    }
+
     pub mod trace {
         pub mod v1 {
             include!(concat!(
@@ -39,6 +48,248 @@ pub mod common {
                 Self::BoolValue(value)
             }
         }
+
+        impl From<&str> for any_value::Value {
+            fn from(value: &str) -> Self {
+                Self::StringValue(value.to_string())
+            }
+        }
+
+        impl From<Vec<u8>> for any_value::Value {
+            fn from(value: Vec<u8>) -> Self {
+                Self::BytesValue(value)
+            }
+        }
+
+        impl From<ArrayValue> for any_value::Value {
+            fn from(value: ArrayValue) -> Self {
+                Self::ArrayValue(value)
+            }
+        }
+
+        impl From<KeyValueList> for any_value::Value {
+            fn from(value: KeyValueList) -> Self {
+                Self::KvlistValue(value)
+            }
+        }
+
+        impl<T: Into<any_value::Value>> From<Vec<T>> for ArrayValue {
+            fn from(values: Vec<T>) -> Self {
+                values.into_iter().collect()
+            }
+        }
+
+        impl<T: Into<any_value::Value>> FromIterator<T> for ArrayValue {
+            fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+                Self {
+                    values: iter
+                        .into_iter()
+                        .map(|v| AnyValue {
+                            value: Some(v.into()),
+                        })
+                        .collect(),
+                }
+            }
+        }
+
+        impl<K: Into<String>, V: Into<any_value::Value>> FromIterator<(K, V)> for KeyValueList {
+            fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+                Self {
+                    values: iter
+                        .into_iter()
+                        .map(|(k, v)| KeyValue::new(k.into(), v.into()))
+                        .collect(),
+                }
+            }
+        }
+
+        /// Exported as an `ArrayValue`, with each element converted to an `AnyValue`.
+        impl<T: Into<any_value::Value>> From<Vec<T>> for any_value::Value {
+            fn from(values: Vec<T>) -> Self {
+                Self::ArrayValue(values.into())
+            }
+        }
+
+        /// Exported as a `KvlistValue`. Keys are sorted, as iteration order of a `HashMap` is
+        /// not stable.
+        impl<K: Into<String> + Ord, V: Into<any_value::Value>> From<std::collections::HashMap<K, V>>
+            for any_value::Value
+        {
+            fn from(map: std::collections::HashMap<K, V>) -> Self {
+                map.into_iter()
+                    .collect::<std::collections::BTreeMap<_, _>>()
+                    .into()
+            }
+        }
+
+        /// JSON objects are exported as a `KvlistValue` and arrays as an `ArrayValue`.
+        ///
+        /// Numbers that fit in an `i64` are exported as ints, all others as doubles. `null`
+        /// becomes an empty `AnyValue`.
+        #[cfg(feature = "json")]
+        impl From<serde_json::Value> for AnyValue {
+            fn from(value: serde_json::Value) -> Self {
+                match value {
+                    serde_json::Value::Null => AnyValue { value: None },
+                    value => AnyValue {
+                        value: Some(value.into()),
+                    },
+                }
+            }
+        }
+
+        /// See the conversion into `AnyValue`, which nested values go through. As a bare
+        /// `any_value::Value` can not be empty, a top-level `null` is exported as an empty
+        /// string; [`crate::Builder::resource_attributes_json`] leaves such entries out.
+        #[cfg(feature = "json")]
+        impl From<serde_json::Value> for any_value::Value {
+            fn from(value: serde_json::Value) -> Self {
+                match value {
+                    serde_json::Value::Null => Self::StringValue(String::new()),
+                    serde_json::Value::Bool(b) => Self::BoolValue(b),
+                    serde_json::Value::Number(n) => n
+                        .as_i64()
+                        .map(Self::IntValue)
+                        .unwrap_or_else(|| Self::DoubleValue(n.as_f64().unwrap_or(f64::NAN))),
+                    serde_json::Value::String(s) => Self::StringValue(s),
+                    serde_json::Value::Array(values) => Self::ArrayValue(ArrayValue {
+                        values: values.into_iter().map(Into::into).collect(),
+                    }),
+                    serde_json::Value::Object(map) => Self::KvlistValue(KeyValueList {
+                        values: map
+                            .into_iter()
+                            .map(|(key, value)| KeyValue {
+                                key,
+                                value: Some(value.into()),
+                            })
+                            .collect(),
+                    }),
+                }
+            }
+        }
+
+        /// The reverse of the conversion into `AnyValue`, an empty `AnyValue` becomes `null`.
+        #[cfg(feature = "json")]
+        impl From<&AnyValue> for serde_json::Value {
+            fn from(value: &AnyValue) -> Self {
+                value
+                    .value
+                    .as_ref()
+                    .map_or(serde_json::Value::Null, Into::into)
+            }
+        }
+
+        /// `KvlistValue`s become JSON objects, `ArrayValue`s arrays and bytes arrays of
+        /// numbers.
+        #[cfg(feature = "json")]
+        impl From<&any_value::Value> for serde_json::Value {
+            fn from(value: &any_value::Value) -> Self {
+                use any_value::Value;
+                match value {
+                    Value::StringValue(s) => s.clone().into(),
+                    Value::BoolValue(b) => (*b).into(),
+                    Value::IntValue(i) => (*i).into(),
+                    Value::DoubleValue(d) => (*d).into(),
+                    Value::BytesValue(bytes) => bytes.clone().into(),
+                    Value::ArrayValue(array) => serde_json::Value::Array(
+                        array.values.iter().map(serde_json::Value::from).collect(),
+                    ),
+                    Value::KvlistValue(kvlist) => kvlist
+                        .values
+                        .iter()
+                        .map(|kv| {
+                            (
+                                kv.key.clone(),
+                                kv.value
+                                    .as_ref()
+                                    .map_or(serde_json::Value::Null, Into::into),
+                            )
+                        })
+                        .collect::<serde_json::Map<_, _>>()
+                        .into(),
+                }
+            }
+        }
+
+        /// Exported as a `KvlistValue`.
+        impl<K: Into<String>, V: Into<any_value::Value>> From<std::collections::BTreeMap<K, V>>
+            for any_value::Value
+        {
+            fn from(map: std::collections::BTreeMap<K, V>) -> Self {
+                Self::KvlistValue(map.into_iter().collect())
+            }
+        }
+
+        #[cfg(test)]
+        mod test {
+            use super::*;
+            use std::collections::HashMap;
+
+            /// Converts an array back into the `Value`s it was built from.
+            fn array_values(value: any_value::Value) -> Vec<any_value::Value> {
+                let any_value::Value::ArrayValue(array) = value else {
+                    panic!("not an array: {value:?}");
+                };
+                array.values.into_iter().map(|v| v.value.unwrap()).collect()
+            }
+
+            #[test]
+            fn converts_vecs_into_arrays() {
+                let value = any_value::Value::from(vec![1i64, 2, 3]);
+                let ints = [1, 2, 3].map(any_value::Value::IntValue);
+                assert_eq!(array_values(value), ints);
+
+                let nested = any_value::Value::from(vec![vec!["a"], vec![]]);
+                let arrays: Vec<_> = array_values(nested).into_iter().map(array_values).collect();
+                assert_eq!(arrays, [vec!["a".into()], vec![]]);
+            }
+
+            #[test]
+            fn converts_maps_into_key_value_lists() {
+                let map = HashMap::from([("b", 2i64), ("a", 1), ("c", 3)]);
+                let any_value::Value::KvlistValue(kvlist) = map.clone().into() else {
+                    panic!("maps are exported as key-value lists");
+                };
+                // Sorted by key, as the order of a `HashMap` is not stable
+                let keys: Vec<_> = kvlist.values.iter().map(|kv| kv.key.as_str()).collect();
+                assert_eq!(keys, ["a", "b", "c"]);
+                let entries: HashMap<_, _> = kvlist
+                    .values
+                    .into_iter()
+                    .map(|kv| match kv.value.and_then(|v| v.value) {
+                        Some(any_value::Value::IntValue(i)) => (kv.key, i),
+                        value => panic!("not an int: {value:?}"),
+                    })
+                    .collect();
+                let map: HashMap<_, _> = map.into_iter().map(|(k, v)| (k.to_string(), v)).collect();
+                assert_eq!(entries, map);
+            }
+
+            #[cfg(feature = "json")]
+            #[test]
+            fn converts_json_nulls_into_empty_values() {
+                assert_eq!(
+                    AnyValue::from(serde_json::Value::Null),
+                    AnyValue { value: None }
+                );
+
+                // Nested nulls become empty values as well, and `null` again on the way back
+                let json = serde_json::json!({ "list": [1, null], "map": { "key": null } });
+                let value = AnyValue::from(json.clone());
+                assert_eq!(serde_json::Value::from(&value), json);
+            }
+        }
+    }
+}
+
+pub mod metrics {
+    // The upstream comments contain ASCII diagrams which clippy mistakes for markdown lists
+    #[allow(clippy::doc_overindented_list_items, clippy::doc_lazy_continuation)]
+    pub mod v1 {
+        include!(concat!(
+            env!("OUT_DIR"),
+            "/opentelemetry.proto.metrics.v1.rs"
+        ));
     }
 }
 
@@ -56,3 +307,65 @@ pub mod trace {
         include!(concat!(env!("OUT_DIR"), "/opentelemetry.proto.trace.v1.rs"));
     }
 }
+
+/// Conversions from and into the messages of the `opentelemetry-proto` crate, which are
+/// generated from the same protobuf definitions. They go through the protobuf encoding, so
+/// fields unknown to the other version of the definitions are dropped.
+#[cfg(feature = "opentelemetry-proto")]
+mod opentelemetry_proto_impls {
+    use opentelemetry_proto::tonic;
+    use prost::Message;
+
+    macro_rules! convert {
+        ($($ours:ty => $theirs:ty,)*) => {
+            $(
+                impl From<$ours> for $theirs {
+                    fn from(value: $ours) -> Self {
+                        Self::decode(value.encode_to_vec().as_slice())
+                            .expect("messages are generated from the same definitions")
+                    }
+                }
+
+                impl From<$theirs> for $ours {
+                    fn from(value: $theirs) -> Self {
+                        Self::decode(value.encode_to_vec().as_slice())
+                            .expect("messages are generated from the same definitions")
+                    }
+                }
+            )*
+        };
+    }
+
+    convert! {
+        super::collector::trace::v1::ExportTraceServiceRequest
+            => tonic::collector::trace::v1::ExportTraceServiceRequest,
+        super::trace::v1::ResourceSpans => tonic::trace::v1::ResourceSpans,
+        super::trace::v1::ScopeSpans => tonic::trace::v1::ScopeSpans,
+        super::trace::v1::Span => tonic::trace::v1::Span,
+        super::resource::v1::Resource => tonic::resource::v1::Resource,
+        super::common::v1::InstrumentationScope => tonic::common::v1::InstrumentationScope,
+        super::common::v1::KeyValue => tonic::common::v1::KeyValue,
+        super::common::v1::AnyValue => tonic::common::v1::AnyValue,
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::prost::{common::v1::KeyValue, trace::v1::Span};
+
+        #[test]
+        fn converts_spans() {
+            let span = Span {
+                trace_id: vec![1; 16],
+                span_id: vec![2; 8],
+                name: "request".to_string(),
+                attributes: vec![KeyValue::new("user.id".to_string(), 7.into())],
+                ..Default::default()
+            };
+            let theirs = tonic::trace::v1::Span::from(span.clone());
+            assert_eq!(theirs.name, "request");
+            assert_eq!(theirs.attributes[0].key, "user.id");
+            assert_eq!(Span::from(theirs), span);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::env;
+
+use crate::prost::common::v1::{any_value::Value, ArrayValue};
+
+/// Detects attributes describing the environment the process runs in, which are added to
+/// the OpenTelemetry resource.
+///
+/// Detectors are opt-in, register them using [`crate::Builder::resource_detector`].
+pub trait ResourceDetector {
+    /// Returns the detected attributes. Attributes which could not be detected are omitted.
+    fn detect(&self) -> Vec<(String, Value)>;
+}
+
+/// Detects `host.name`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostDetector;
+
+impl ResourceDetector for HostDetector {
+    fn detect(&self) -> Vec<(String, Value)> {
+        hostname()
+            .map(|name| ("host.name".to_string(), name.into()))
+            .into_iter()
+            .collect()
+    }
+}
+
+fn hostname() -> Option<String> {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .into_iter()
+        .find_map(|var| env::var(var).ok())
+        .or_else(|| {
+            ["/proc/sys/kernel/hostname", "/etc/hostname"]
+                .into_iter()
+                .find_map(|path| std::fs::read_to_string(path).ok())
+        })
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Detects `os.type`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsDetector;
+
+impl ResourceDetector for OsDetector {
+    fn detect(&self) -> Vec<(String, Value)> {
+        // Map Rust's names onto the values defined by the semantic conventions
+        let os_type = match env::consts::OS {
+            "macos" | "ios" => "darwin",
+            "dragonfly" => "dragonflybsd",
+            os => os,
+        };
+        vec![("os.type".to_string(), os_type.into())]
+    }
+}
+
+/// Detects `container.id` from the cgroup of the current process.
+///
+/// Supports both cgroup v1 and v2. With cgroup v2 namespaces the cgroup path does not
+/// contain the id, in which case the mount table is searched instead. Only works on Linux.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContainerDetector;
+
+impl ResourceDetector for ContainerDetector {
+    fn detect(&self) -> Vec<(String, Value)> {
+        let from_file = |path: &str, parse: fn(&str) -> Option<String>| {
+            std::fs::read_to_string(path)
+                .ok()
+                .and_then(|contents| parse(&contents))
+        };
+        from_file("/proc/self/cgroup", container_id_from_cgroup)
+            .or_else(|| from_file("/proc/self/mountinfo", container_id_from_mountinfo))
+            .map(|id| ("container.id".to_string(), id.into()))
+            .into_iter()
+            .collect()
+    }
+}
+
+const CONTAINER_ID_LEN: usize = 64;
+
+fn is_container_id(s: &str) -> bool {
+    s.len() == CONTAINER_ID_LEN && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Finds the container id in the last path segment of a cgroup, e.g.
+/// `12:cpu:/docker/<id>` (v1) or `0::/system.slice/docker-<id>.scope` (v2).
+fn container_id_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup.lines().find_map(|line| {
+        let segment = line.rsplit('/').next()?;
+        let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+        let id = segment.rsplit(['-', ':']).next()?;
+        is_container_id(id).then(|| id.to_string())
+    })
+}
+
+/// Finds the container id in a mount of the container runtime's directory, e.g.
+/// `/var/lib/docker/containers/<id>/hostname`.
+fn container_id_from_mountinfo(mountinfo: &str) -> Option<String> {
+    mountinfo.lines().find_map(|line| {
+        line.split(' ').find_map(|field| {
+            let (_, rest) = field.split_once("/containers/")?;
+            let id = rest.split('/').next()?;
+            is_container_id(id).then(|| id.to_string())
+        })
+    })
+}
+
+/// Detects `process.pid`, `process.executable.name`, `process.executable.path` and
+/// `process.command_args`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessDetector;
+
+impl ResourceDetector for ProcessDetector {
+    fn detect(&self) -> Vec<(String, Value)> {
+        let mut attributes = vec![(
+            "process.pid".to_string(),
+            (std::process::id() as i64).into(),
+        )];
+        if let Ok(exe) = env::current_exe() {
+            if let Some(name) = exe.file_name() {
+                attributes.push((
+                    "process.executable.name".to_string(),
+                    name.to_string_lossy().into_owned().into(),
+                ));
+            }
+            attributes.push((
+                "process.executable.path".to_string(),
+                exe.to_string_lossy().into_owned().into(),
+            ));
+        }
+        attributes.push((
+            "process.command_args".to_string(),
+            env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<ArrayValue>()
+                .into(),
+        ));
+        attributes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ID: &str = "3d4cd9b1ab8dbc9c4e5ac62bc58d0ef8e8a4fb09bc7ab3aa8d2e3d5c0de1f2a9";
+
+    #[test]
+    fn cgroup_v1() {
+        let cgroup = format!("12:pids:/docker/{ID}\n11:cpu,cpuacct:/docker/{ID}\n");
+        assert_eq!(container_id_from_cgroup(&cgroup).as_deref(), Some(ID));
+    }
+
+    #[test]
+    fn cgroup_v2() {
+        let cgroup = format!("0::/system.slice/docker-{ID}.scope\n");
+        assert_eq!(container_id_from_cgroup(&cgroup).as_deref(), Some(ID));
+        let cgroup = format!("0::/kubepods/besteffort/pod1234/cri-containerd-{ID}\n");
+        assert_eq!(container_id_from_cgroup(&cgroup).as_deref(), Some(ID));
+    }
+
+    #[test]
+    fn cgroup_namespaced() {
+        assert_eq!(container_id_from_cgroup("0::/\n"), None);
+        let mountinfo = format!(
+            "1 2 0:3 / / rw - overlay overlay rw\n\
+             4 1 8:1 /var/lib/docker/containers/{ID}/hostname /etc/hostname rw - ext4 /dev/sda1 rw\n"
+        );
+        assert_eq!(container_id_from_mountinfo(&mountinfo).as_deref(), Some(ID));
+    }
+}
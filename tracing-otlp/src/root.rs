@@ -0,0 +1,173 @@
+use std::{future::Future, sync::Arc, time::SystemTime};
+
+use tracing::instrument::{Instrument, Instrumented};
+
+use crate::prost::common::v1::{any_value::Value, KeyValue};
+use crate::{SpanContext, SpanId, TraceCtxError, TraceId, Visitor};
+
+/// Registers the current span as the local root of a distributed trace, with attributes and
+/// a start time for the root span which were not known when it was created.
+///
+/// This extends [`crate::register_dist_tracing_root`]: the trace is sampled following the
+/// sampling of the OTLP layer unless [`TraceRoot::sampled`] is given.
+///
+/// # Examples
+/// ```
+/// # use std::time::{Duration, SystemTime};
+/// # use tracing_otlp::{TraceId, TraceRoot};
+/// // E.g. from the `X-Request-Start` header set by the gateway
+/// let received_at = SystemTime::now() - Duration::from_millis(30);
+/// tracing::info_span!("request").in_scope(|| {
+///     # let _ =
+///     TraceRoot::new(TraceId::new())
+///         .attribute("http.route", "/checkout")
+///         .started_at(received_at)
+///         .register();
+/// });
+/// ```
+#[derive(Debug, Clone)]
+pub struct TraceRoot {
+    trace_id: TraceId,
+    remote_parent: Option<SpanId>,
+    sampled: Option<bool>,
+    trace_state: Option<Arc<str>>,
+    attributes: Vec<KeyValue>,
+    started_at: Option<SystemTime>,
+}
+
+impl TraceRoot {
+    pub fn new(trace_id: TraceId) -> Self {
+        Self {
+            trace_id,
+            remote_parent: None,
+            sampled: None,
+            trace_state: None,
+            attributes: Vec::new(),
+            started_at: None,
+        }
+    }
+
+    /// Continues the trace of a span of another process, following its sampling decision and
+    /// keeping its `tracestate`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use tracing_otlp::{SpanContext, SpanId, TraceId, TraceRoot};
+    /// // E.g. parsed from the `traceparent` and `tracestate` headers of a request
+    /// let remote = SpanContext::new(TraceId(1), SpanId(2), true).with_trace_state("vendor=1");
+    /// tracing::info_span!("request").in_scope(|| {
+    ///     # let _ =
+    ///     TraceRoot::remote(remote).register();
+    /// });
+    /// ```
+    pub fn remote(ctx: SpanContext) -> Self {
+        Self {
+            remote_parent: Some(ctx.span_id),
+            sampled: Some(ctx.sampled),
+            trace_state: ctx.trace_state,
+            ..Self::new(ctx.trace_id)
+        }
+    }
+
+    /// Sets the span of another process the root span is a child of.
+    pub fn remote_parent(mut self, span_id: SpanId) -> Self {
+        self.remote_parent = Some(span_id);
+        self
+    }
+
+    /// Follows the sampling decision made by the remote parent, see
+    /// [`crate::register_dist_tracing_root_sampled`].
+    pub fn sampled(mut self, sampled: bool) -> Self {
+        self.sampled = Some(sampled);
+        self
+    }
+
+    /// Sets the W3C `tracestate` of the trace, exported with its spans and returned by
+    /// [`crate::current_span_context`].
+    pub fn trace_state(mut self, trace_state: impl Into<Arc<str>>) -> Self {
+        self.trace_state = Some(trace_state.into());
+        self
+    }
+
+    /// Adds an attribute to the root span.
+    pub fn attribute(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.attributes
+            .push(KeyValue::new(key.into(), value.into()));
+        self
+    }
+
+    /// Reports the root span as started at `started_at` instead of when it was created, e.g.
+    /// to include the time a request was queued before this process received it.
+    pub fn started_at(mut self, started_at: SystemTime) -> Self {
+        self.started_at = Some(started_at);
+        self
+    }
+
+    /// Registers the current span as the root.
+    pub fn register(self) -> Result<(), TraceCtxError> {
+        self.register_for(&tracing::Span::current())
+    }
+
+    /// Registers `span` as the root, without having to enter it first.
+    pub fn register_for(self, span: &tracing::Span) -> Result<(), TraceCtxError> {
+        let sampled = self
+            .sampled
+            .unwrap_or_else(|| crate::layer_samples(span, self.trace_id));
+        tracing_distributed::register_dist_tracing_root_for(
+            span,
+            self.trace_id,
+            self.remote_parent,
+            sampled,
+            self.started_at,
+        )?;
+        if let Some(trace_state) = self.trace_state {
+            tracing_distributed::set_trace_state_for::<SpanId, TraceId>(span, trace_state)?;
+        }
+        if !self.attributes.is_empty() {
+            tracing_distributed::with_span_values_mut(span, |_, values: &mut Visitor| {
+                values.0.extend(self.attributes)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `f` in a new INFO span named `remote_parent`, registered as the local root of the
+/// trace of the remote span `ctx`, and exits the span once `f` returns.
+///
+/// Registering the span silently fails if there is no OTLP layer, `f` then runs without the
+/// trace. Create the span yourself and register it with [`TraceRoot::register_for`] to give it
+/// another name, level or fields.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{SpanContext, SpanId, TraceId};
+/// // E.g. parsed from the `traceparent` header of a message
+/// let remote = SpanContext::new(TraceId(1), SpanId(2), true);
+/// let handled = tracing_otlp::with_remote_parent(remote, || "handled");
+/// ```
+pub fn with_remote_parent<R>(ctx: SpanContext, f: impl FnOnce() -> R) -> R {
+    remote_parent_span(ctx).in_scope(f)
+}
+
+/// Instruments `future` with a new INFO span named `remote_parent`, registered as the local
+/// root of the trace of the remote span `ctx`, as [`with_remote_parent`] does for closures.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{SpanContext, SpanId, TraceId};
+/// # async fn handle() {}
+/// # async fn consume() {
+/// let remote = SpanContext::new(TraceId(1), SpanId(2), true);
+/// tracing_otlp::with_remote_parent_async(remote, handle()).await;
+/// # }
+/// ```
+pub fn with_remote_parent_async<F: Future>(ctx: SpanContext, future: F) -> Instrumented<F> {
+    future.instrument(remote_parent_span(ctx))
+}
+
+fn remote_parent_span(ctx: SpanContext) -> tracing::Span {
+    let span = tracing::info_span!("remote_parent");
+    let _ = TraceRoot::remote(ctx).register_for(&span);
+    span
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use url::Url;
+
+use crate::{
+    error::OtlpBuildError,
+    prost::{
+        common::v1::{any_value::Value, KeyValue},
+        resource::v1::Resource,
+        trace::v1::Span,
+    },
+};
+
+/// Sends the spans matching it to another endpoint than the one given to the `build`
+/// methods, such as the backend of a tenant. Added with [`crate::Builder::route`].
+///
+/// The `endpoint` works like the one of [`crate::Builder::build`], with spans sent to
+/// `v1/traces` below its path, and the headers of the route are added to those of the
+/// exporter.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{Builder, Route};
+/// Builder::new()
+///     .route(
+///         Route::attribute("https://acme.example.com", "tenant.id", "acme")
+///             .header("X-Api-Key", "acme-key"),
+///     )
+///     // Queries of one trace, to debug them apart from the rest
+///     .route(Route::matching("http://127.0.0.1:4318", |span| {
+///         span.trace_id.ends_with(&[0x2a])
+///     }));
+/// ```
+#[derive(Clone)]
+pub struct Route {
+    pub(crate) endpoint: Result<Url, OtlpBuildError>,
+    pub(crate) headers: Vec<(String, Vec<u8>)>,
+    pub(crate) rule: Rule,
+}
+
+/// Which spans a [`Route`] applies to.
+#[derive(Clone)]
+pub(crate) enum Rule {
+    /// Spans with the attribute, or all spans if the resource has it
+    Attribute(KeyValue),
+    Predicate(Arc<dyn Fn(&Span) -> bool + Send + Sync>),
+}
+
+impl Route {
+    /// Routes the spans with an attribute, such as `tenant.id`, or all spans if the resource
+    /// of the exporter has it.
+    pub fn attribute<U>(endpoint: U, key: impl Into<String>, value: impl Into<Value>) -> Self
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        Self::new(
+            endpoint,
+            Rule::Attribute(KeyValue::new(key.into(), value.into())),
+        )
+    }
+
+    /// Routes the spans for which `predicate` returns true, such as those of some trace ids.
+    pub fn matching<U>(
+        endpoint: U,
+        predicate: impl Fn(&Span) -> bool + Send + Sync + 'static,
+    ) -> Self
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        Self::new(endpoint, Rule::Predicate(Arc::new(predicate)))
+    }
+
+    fn new<U>(endpoint: U, rule: Rule) -> Self
+    where
+        U: TryInto<Url>,
+        U::Error: Into<OtlpBuildError>,
+    {
+        Self {
+            endpoint: endpoint.try_into().map_err(Into::into),
+            headers: Vec::new(),
+            rule,
+        }
+    }
+
+    /// Adds an HTTP header to the requests of the route, replacing the header of the
+    /// exporter with the same name. Invalid headers are returned by the `build` methods,
+    /// like those of [`crate::Builder::http_headers`].
+    pub fn header(mut self, name: impl AsRef<str>, value: impl AsRef<[u8]>) -> Self {
+        self.headers
+            .push((name.as_ref().to_string(), value.as_ref().to_vec()));
+        self
+    }
+}
+
+/// A [`Route`] as used by the worker, with its `v1/traces` URL and validated headers.
+pub(crate) struct ResolvedRoute {
+    pub(crate) traces: Url,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) rule: Rule,
+}
+
+impl ResolvedRoute {
+    /// Whether the route applies to a span exported with `resource`.
+    pub(crate) fn matches(&self, resource: &Resource, span: &Span) -> bool {
+        match &self.rule {
+            Rule::Attribute(kv) => span.attributes.contains(kv) || resource.attributes.contains(kv),
+            Rule::Predicate(predicate) => predicate(span),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn resolve(route: Route) -> ResolvedRoute {
+        ResolvedRoute {
+            traces: route.endpoint.unwrap(),
+            headers: Vec::new(),
+            rule: route.rule,
+        }
+    }
+
+    #[test]
+    fn matches_span_and_resource_attributes() {
+        let tenant = |tenant: &str| KeyValue::new("tenant.id".to_string(), tenant.into());
+        let route = resolve(Route::attribute("http://acme:4318", "tenant.id", "acme"));
+        let span = |attributes| Span {
+            attributes,
+            ..Default::default()
+        };
+        let resource = |attributes| Resource {
+            attributes,
+            dropped_attributes_count: 0,
+        };
+
+        assert!(route.matches(&resource(vec![]), &span(vec![tenant("acme")])));
+        assert!(!route.matches(&resource(vec![]), &span(vec![tenant("other")])));
+        assert!(route.matches(&resource(vec![tenant("acme")]), &span(vec![])));
+
+        let route = resolve(Route::matching("http://debug:4318", |span| {
+            span.trace_id == [1; 16]
+        }));
+        let traced = Span {
+            trace_id: vec![1; 16],
+            ..Default::default()
+        };
+        assert!(route.matches(&resource(vec![]), &traced));
+        assert!(!route.matches(&resource(vec![]), &span(vec![])));
+    }
+}
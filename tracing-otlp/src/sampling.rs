@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use crate::{prost::common::v1::KeyValue, TraceId, Visitor};
+
+/// Decides whether a trace with a local root is sampled, i.e. whether its spans are
+/// exported.
+///
+/// Traces continuing a remote parent that was registered with a sampling decision, using
+/// [`crate::register_dist_tracing_root_sampled`], always follow the decision of the parent.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Sampler {
+    /// Sample every trace.
+    #[default]
+    AlwaysOn,
+    /// Sample no traces.
+    AlwaysOff,
+    /// Sample the given ratio (between 0 and 1) of traces.
+    ///
+    /// The decision is derived from the trace id, so every process with the same ratio makes
+    /// the same decision for a given trace.
+    TraceIdRatio(f64),
+}
+
+impl Sampler {
+    /// Returns whether the trace with the given id is sampled.
+    pub fn should_sample(&self, trace_id: TraceId) -> bool {
+        match *self {
+            Sampler::AlwaysOn => true,
+            Sampler::AlwaysOff => false,
+            Sampler::TraceIdRatio(ratio) => {
+                // Compares the lower 63 bits of the id against the ratio of their range, like
+                // the OpenTelemetry SDKs do
+                let upper_bound = (ratio.clamp(0.0, 1.0) * (1u64 << 63) as f64) as u64;
+                ((trace_id.0 as u64) >> 1) < upper_bound
+            }
+        }
+    }
+}
+
+/// The result of a sampling callback, see [`crate::Builder::sampling_callback`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingDecision {
+    /// Export the spans of this trace.
+    Sample,
+    /// Do not export any spans of this trace.
+    Drop,
+    /// Leave the decision to the configured [`Sampler`].
+    Defer,
+}
+
+/// Callback deciding whether a trace is sampled, based on its id and the metadata and
+/// attributes of its root span.
+pub(crate) type SamplingCallback =
+    dyn Fn(&TraceId, &tracing::Metadata<'static>, &[KeyValue]) -> SamplingDecision + Send + Sync;
+
+/// How the sampling decision for traces with a local root is made.
+#[derive(Clone, Default)]
+pub(crate) struct Sampling {
+    pub(crate) sampler: Sampler,
+    pub(crate) callback: Option<Arc<SamplingCallback>>,
+}
+
+impl Sampling {
+    /// Decides whether a trace rooted at `span` is sampled.
+    ///
+    /// Must not be called from within `tracing::dispatcher::get_default`, as the callback
+    /// needs to look up the span.
+    pub(crate) fn should_sample(&self, span: &tracing::Span, trace_id: TraceId) -> bool {
+        let decision = self.callback.as_ref().and_then(|callback| {
+            tracing_distributed::with_span_values(span, |meta, values: &Visitor| {
+                callback(&trace_id, meta, &values.0)
+            })
+            .ok()
+        });
+        match decision {
+            Some(SamplingDecision::Sample) => true,
+            Some(SamplingDecision::Drop) => false,
+            Some(SamplingDecision::Defer) | None => self.sampler.should_sample(trace_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn trace_id_ratio() {
+        let sampled = (1..=10_000u128)
+            .map(|i| TraceId(i.wrapping_mul(0x9e3779b97f4a7c15f39cc0605cedc835)))
+            .filter(|id| Sampler::TraceIdRatio(0.25).should_sample(*id))
+            .count();
+        assert!((2_000..3_000).contains(&sampled), "sampled {sampled}");
+
+        assert!(Sampler::TraceIdRatio(1.0).should_sample(TraceId(u128::MAX)));
+        assert!(!Sampler::TraceIdRatio(0.0).should_sample(TraceId(0)));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::prost::{
+    common::v1::{any_value::Value, KeyValue},
+    trace::v1::{span::SpanKind, status::StatusCode, Span, Status},
+};
+
+/// Applies the `span.name`, `span.kind`, `span.status` and `span.status_message` fields of a
+/// span to the span itself, removing them from its attributes.
+///
+/// The `otel.name`, `otel.kind`, `otel.status_code` and `otel.status_message` fields of
+/// `tracing-opentelemetry` are the same fields under other keys. Unknown kinds and statuses
+/// are ignored.
+pub(crate) fn apply(span: &mut Span) {
+    if let Some(name) = take_str(&mut span.attributes, ["span.name", "otel.name"]) {
+        span.name = name;
+    }
+    if let Some(kind) = take_str(&mut span.attributes, ["span.kind", "otel.kind"]) {
+        span.kind = match kind.to_ascii_lowercase().as_str() {
+            "internal" => SpanKind::Internal,
+            "server" => SpanKind::Server,
+            "client" => SpanKind::Client,
+            "producer" => SpanKind::Producer,
+            "consumer" => SpanKind::Consumer,
+            _ => SpanKind::Unspecified,
+        } as i32;
+    }
+    let message = take_str(
+        &mut span.attributes,
+        ["span.status_message", "otel.status_message"],
+    );
+    let code =
+        take_str(&mut span.attributes, ["span.status", "otel.status_code"]).and_then(|status| {
+            match status.to_ascii_lowercase().as_str() {
+                "ok" => Some(StatusCode::Ok),
+                "error" => Some(StatusCode::Error),
+                _ => None,
+            }
+        });
+    if let Some(code) = code {
+        span.status = Some(Status {
+            // Only errors have a message
+            message: message
+                .filter(|_| code == StatusCode::Error)
+                .unwrap_or_default(),
+            code: code as i32,
+        });
+    }
+}
+
+/// Removes the attributes with either key, returning the last recorded value if it is a
+/// string.
+fn take_str(attributes: &mut Vec<KeyValue>, keys: [&str; 2]) -> Option<String> {
+    let index = attributes
+        .iter()
+        .rposition(|kv| keys.contains(&kv.key.as_str()))?;
+    let value = attributes.remove(index).value.and_then(|value| value.value);
+    attributes.retain(|kv| !keys.contains(&kv.key.as_str()));
+    match value {
+        Some(Value::StringValue(value)) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn kv(key: &str, value: &str) -> KeyValue {
+        KeyValue::new(key.to_string(), value.to_string().into())
+    }
+
+    #[test]
+    fn applies_span_fields() {
+        let mut span = Span {
+            name: "request".to_string(),
+            attributes: vec![
+                kv("span.name", "GET /users"),
+                kv("span.kind", "Server"),
+                kv("span.status", "ok"),
+                kv("http.route", "/users"),
+                kv("span.status", "error"),
+                kv("span.status_message", "timed out"),
+            ],
+            ..Default::default()
+        };
+        apply(&mut span);
+        assert_eq!(span.name, "GET /users");
+        assert_eq!(span.kind, SpanKind::Server as i32);
+        assert_eq!(
+            span.status,
+            Some(Status {
+                message: "timed out".to_string(),
+                code: StatusCode::Error as i32,
+            })
+        );
+        assert_eq!(span.attributes, [kv("http.route", "/users")]);
+    }
+
+    #[test]
+    fn applies_tracing_opentelemetry_fields() {
+        let mut span = Span {
+            attributes: vec![
+                kv("otel.name", "GET /users"),
+                kv("otel.kind", "client"),
+                kv("span.status", "ok"),
+                kv("otel.status_code", "ERROR"),
+                kv("otel.status_message", "refused"),
+            ],
+            ..Default::default()
+        };
+        apply(&mut span);
+        assert_eq!(span.name, "GET /users");
+        assert_eq!(span.kind, SpanKind::Client as i32);
+        assert_eq!(
+            span.status,
+            Some(Status {
+                message: "refused".to_string(),
+                code: StatusCode::Error as i32,
+            })
+        );
+        assert!(span.attributes.is_empty());
+    }
+
+    #[test]
+    fn ignores_unknown_values() {
+        let mut span = Span {
+            attributes: vec![
+                kv("span.kind", "sideways"),
+                kv("span.status", "maybe"),
+                KeyValue::new("span.name".to_string(), 1.into()),
+            ],
+            ..Default::default()
+        };
+        apply(&mut span);
+        assert_eq!(span.kind, SpanKind::Unspecified as i32);
+        assert_eq!((span.name.as_str(), span.status), ("", None));
+        assert!(span.attributes.is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{io, thread};
+
+use tracing::Dispatch;
+#[cfg(feature = "tokio")]
+use {
+    crate::DistTraceExt,
+    std::future::Future,
+    tracing::instrument::{Instrument, WithSubscriber},
+};
+
+use crate::{current_span_context, TraceRoot};
+
+/// Spawns a thread named `name` running `f` in a span continuing the distributed trace of
+/// the current span, if it is part of one.
+///
+/// The span of the thread, named `thread` with a `thread.name` field, is registered as a
+/// local root with the current span as its parent, following the sampling decision of the
+/// trace. The subscriber of the current thread is also used by the spawned one. Fails like
+/// [`std::thread::Builder::spawn`] if the thread could not be created.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::{register_dist_tracing_root, TraceId};
+/// tracing::info_span!("request").in_scope(|| {
+///     # let _ =
+///     register_dist_tracing_root(TraceId::new(), None);
+///     let worker = tracing_otlp::spawn_traced("resize", || {
+///         tracing::info_span!("resize image").in_scope(|| {})
+///     });
+///     worker.unwrap().join().unwrap();
+/// });
+/// ```
+pub fn spawn_traced<F, T>(name: impl Into<String>, f: F) -> io::Result<thread::JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let name = name.into();
+    let ctx = current_span_context().ok();
+    let dispatch = tracing::dispatcher::get_default(Dispatch::clone);
+    thread::Builder::new().name(name.clone()).spawn(move || {
+        tracing::dispatcher::with_default(&dispatch, || {
+            let span = tracing::info_span!("thread", thread.name = %name, span.kind = "internal");
+            if let Some(ctx) = ctx {
+                // Cannot fail, the span was created by the subscriber the context is from
+                let _ = TraceRoot::remote(ctx).register_for(&span);
+            }
+            span.in_scope(f)
+        })
+    })
+}
+
+/// Spawns a tokio task running `future` in a span continuing the distributed trace of the
+/// current span if any, see [`DistTraceExt::in_current_dist_trace`].
+///
+/// Use this for fan-out work which is part of the current operation, so it shows up under
+/// the originating trace.
+///
+/// # Examples
+/// ```
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// # async fn fetch(_: u32) {}
+/// let tasks: Vec<_> = (0..3).map(|shard| tracing_otlp::spawn_child(fetch(shard))).collect();
+/// for task in tasks {
+///     task.await.unwrap();
+/// }
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub fn spawn_child<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future.in_current_dist_trace())
+}
+
+/// Spawns a tokio task running `future` in a span named `task`, the root of a new
+/// distributed trace linked from the current span.
+///
+/// Use this for work outliving the current operation, such as background jobs it triggers,
+/// which would otherwise make its trace last as long as the job.
+#[cfg(feature = "tokio")]
+pub fn spawn_linked<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let span = tracing::info_span!(parent: None, "task", span.kind = "internal");
+    let _ = TraceRoot::new(crate::new_trace_id()).register_for(&span);
+    span.follows_from(tracing::Span::current());
+    tokio::spawn(future.instrument(span).with_current_subscriber())
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Directory of encoded export requests which could not be sent, replayed once the endpoint
+/// is reachable again. Set with [`crate::Builder::disk_buffer`].
+///
+/// Each batch is a file named after its sequence number and span count, so batches are
+/// replayed in order, also by a later process.
+#[derive(Debug)]
+pub(crate) struct Spool {
+    dir: PathBuf,
+    max_bytes: u64,
+    next_seq: u64,
+    /// Size of the batches in the spool, as the directory is not listed on every push
+    bytes: u64,
+}
+
+/// A batch read back from the [`Spool`].
+pub(crate) struct SpooledBatch {
+    path: PathBuf,
+    pub(crate) spans: usize,
+    pub(crate) payload: Vec<u8>,
+}
+
+impl Spool {
+    /// Opens the spool in `dir`, creating the directory if needed and continuing after the
+    /// batches left by a previous process. Batches it did not finish writing are deleted.
+    pub(crate) fn open(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "tmp") {
+                fs::remove_file(path)?;
+            }
+        }
+        let mut spool = Self {
+            dir,
+            max_bytes,
+            next_seq: 0,
+            bytes: 0,
+        };
+        let batches = spool.batches()?;
+        spool.next_seq = batches.last().map_or(0, |(seq, _, _)| seq + 1);
+        spool.bytes = batches
+            .iter()
+            .map(|(_, _, path)| fs::metadata(path).map_or(0, |meta| meta.len()))
+            .sum();
+        Ok(spool)
+    }
+
+    /// Stores an encoded batch of `spans` spans, returns false if that would exceed the size
+    /// limit of the spool.
+    pub(crate) fn push(&mut self, spans: usize, payload: &[u8]) -> io::Result<bool> {
+        if self.bytes + payload.len() as u64 > self.max_bytes {
+            return Ok(false);
+        }
+
+        // Written under a temporary name first, so a crash never leaves a partial batch
+        let path = self.dir.join(format!("{:020}-{spans}.pb", self.next_seq));
+        let tmp = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(payload)?;
+        file.sync_all()?;
+        fs::rename(tmp, path)?;
+
+        self.next_seq += 1;
+        self.bytes += payload.len() as u64;
+        Ok(true)
+    }
+
+    /// Reads the oldest batch, if any.
+    pub(crate) fn oldest(&self) -> io::Result<Option<SpooledBatch>> {
+        let Some((_, spans, path)) = self.batches()?.into_iter().next() else {
+            return Ok(None);
+        };
+        let payload = fs::read(&path)?;
+        Ok(Some(SpooledBatch {
+            path,
+            spans,
+            payload,
+        }))
+    }
+
+    /// Deletes a batch once it was sent.
+    pub(crate) fn remove(&mut self, batch: SpooledBatch) -> io::Result<()> {
+        fs::remove_file(batch.path)?;
+        self.bytes = self.bytes.saturating_sub(batch.payload.len() as u64);
+        Ok(())
+    }
+
+    /// Lists the batches as sequence number, span count and path, oldest first.
+    fn batches(&self) -> io::Result<Vec<(u64, usize, PathBuf)>> {
+        let mut batches = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "pb") {
+                continue;
+            }
+            let stem = path.file_stem().and_then(|stem| stem.to_str());
+            if let Some((seq, spans)) = stem.and_then(|stem| stem.split_once('-')) {
+                if let (Ok(seq), Ok(spans)) = (seq.parse(), spans.parse()) {
+                    batches.push((seq, spans, path));
+                }
+            }
+        }
+        batches.sort_by_key(|(seq, _, _)| *seq);
+        Ok(batches)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replays_batches_in_order_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("tracing-otlp-spool-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut spool = Spool::open(dir.clone(), 10).unwrap();
+        assert!(spool.push(1, b"abcd").unwrap());
+        assert!(spool.push(2, b"efgh").unwrap());
+        assert!(!spool.push(3, b"ijk").unwrap(), "exceeds the size limit");
+
+        let oldest = spool.oldest().unwrap().unwrap();
+        assert_eq!((oldest.spans, oldest.payload.as_slice()), (1, &b"abcd"[..]));
+        spool.remove(oldest).unwrap();
+
+        let mut spool = Spool::open(dir.clone(), 10).unwrap();
+        assert!(spool.push(3, b"ijk").unwrap());
+        let mut replayed = Vec::new();
+        while let Some(batch) = spool.oldest().unwrap() {
+            replayed.push(batch.spans);
+            spool.remove(batch).unwrap();
+        }
+        assert_eq!(replayed, [2, 3]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn deletes_partial_batches_on_open() {
+        let dir =
+            std::env::temp_dir().join(format!("tracing-otlp-spool-tmp-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let partial = dir.join(format!("{:020}-1.tmp", 0));
+        fs::write(&partial, b"abcdefgh").unwrap();
+
+        let mut spool = Spool::open(dir.clone(), 10).unwrap();
+        assert!(!partial.exists());
+        assert!(spool.push(1, b"abcdefgh").unwrap());
+        assert!(
+            !spool.push(1, b"ijk").unwrap(),
+            "counts the batches in the spool"
+        );
+
+        // Removed batches free their space
+        let oldest = spool.oldest().unwrap().unwrap();
+        spool.remove(oldest).unwrap();
+        assert!(spool.push(1, b"ijk").unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use tracing_distributed::{Event, Span, Telemetry, TelemetryLayer};
+
+use crate::{
+    error::ErrorHandler,
+    prost::common::v1::{any_value::Value, KeyValue},
+    time::Instant,
+    IdGenerator, OtlpError, RandomIdGenerator, SpanId, TraceId, Visitor,
+};
+
+type ReportedSpan = (
+    Span<Visitor, SpanId, TraceId>,
+    Vec<Event<Visitor, SpanId, TraceId>>,
+);
+
+/// The spans of a trace whose local root did not close yet.
+struct BufferedTrace {
+    opened_at: Instant,
+    spans: Vec<ReportedSpan>,
+}
+
+/// [`Telemetry`] implementation printing traces as indented, human-readable trees, for use
+/// during local development.
+///
+/// The spans of a trace are buffered until its local root closes, after which the whole
+/// trace is printed with the ids, durations and attributes of its spans and their events.
+/// Events outside of a trace are printed right away. Once [`StdoutTelemetry::max_traces`]
+/// traces are buffered, the oldest one is printed as it is, marked as incomplete.
+///
+/// # Examples
+/// ```
+/// # use tracing_subscriber::layer::SubscriberExt;
+/// # use tracing_otlp::StdoutTelemetry;
+/// let subscriber = tracing_subscriber::registry().with(StdoutTelemetry::new().layer());
+/// ```
+pub struct StdoutTelemetry {
+    writer: Mutex<Box<dyn Write + Send>>,
+    traces: Mutex<HashMap<TraceId, BufferedTrace>>,
+    max_traces: usize,
+    error_handler: ErrorHandler,
+}
+
+impl Default for StdoutTelemetry {
+    fn default() -> Self {
+        Self::with_writer(std::io::stdout())
+    }
+}
+
+impl StdoutTelemetry {
+    /// Creates a [`StdoutTelemetry`] printing to stdout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a [`StdoutTelemetry`] printing to the given writer instead of stdout.
+    pub fn with_writer(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            traces: Default::default(),
+            max_traces: 1024,
+            error_handler: Default::default(),
+        }
+    }
+
+    /// Sets the maximum number of traces buffered until their local root closes, defaults
+    /// to 1024.
+    ///
+    /// Limits the memory used by traces whose root never closes, such as the root of a
+    /// long-running task.
+    pub fn max_traces(mut self, max_traces: usize) -> Self {
+        self.max_traces = max_traces.max(1);
+        self
+    }
+
+    /// Sets the handler called when the output cannot be written to, as
+    /// [`crate::Builder::on_error`] does for OTLP.
+    pub fn on_error(mut self, handler: impl Fn(OtlpError) + Send + Sync + 'static) -> Self {
+        self.error_handler = ErrorHandler::new(handler);
+        self
+    }
+
+    /// Builds a [`TelemetryLayer`] based on this [`StdoutTelemetry`], using random span ids.
+    pub fn layer(self) -> TelemetryLayer<Self, SpanId, TraceId> {
+        let id_generator = Arc::new(RandomIdGenerator::default());
+        TelemetryLayer::new("", self, move |_| id_generator.new_span_id())
+    }
+
+    fn print(&self, output: &str) {
+        let mut writer = self.writer.lock().expect("writer lock poisoned");
+        if let Err(err) = writer.write_all(output.as_bytes()) {
+            self.error_handler.handle(OtlpError::Output(err));
+        }
+    }
+}
+
+impl Telemetry for StdoutTelemetry {
+    type Visitor = Visitor;
+
+    type TraceId = TraceId;
+
+    type SpanId = SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        Default::default()
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        let trace_id = span.trace_id;
+        let local_root = span.local_root;
+
+        let (evicted, completed) = {
+            let mut traces = self.traces.lock().expect("trace buffer lock poisoned");
+            let evicted = if !traces.contains_key(&trace_id) && traces.len() >= self.max_traces {
+                traces
+                    .iter()
+                    .min_by_key(|(_, trace)| trace.opened_at)
+                    .map(|(id, _)| *id)
+                    .and_then(|id| traces.remove_entry(&id))
+            } else {
+                None
+            };
+            let trace = traces.entry(trace_id).or_insert_with(|| BufferedTrace {
+                opened_at: Instant::now(),
+                spans: Vec::new(),
+            });
+            trace.spans.push((span, events));
+            let completed = local_root
+                .then(|| traces.remove(&trace_id))
+                .flatten()
+                .map(|trace| trace.spans);
+            (evicted, completed)
+        };
+
+        if let Some((evicted_id, trace)) = evicted {
+            self.print(&format_trace(evicted_id, trace.spans, false));
+        }
+        if let Some(spans) = completed {
+            self.print(&format_trace(trace_id, spans, true));
+        }
+    }
+
+    fn report_event(&self, event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "{} {}{}",
+            event.meta.level(),
+            event.meta.target(),
+            format_attributes(&event.values.0)
+        );
+        self.print(&output);
+    }
+}
+
+/// Formats the spans of a trace as a tree, with the children of each span ordered by their
+/// start time.
+fn format_trace(trace_id: TraceId, mut spans: Vec<ReportedSpan>, complete: bool) -> String {
+    spans.sort_by_key(|(span, _)| span.initialized_at);
+
+    let mut children: HashMap<SpanId, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+    for (i, (span, _)) in spans.iter().enumerate() {
+        match span.parent_id {
+            Some(parent_id) if !span.local_root && spans.iter().any(|(s, _)| s.id == parent_id) => {
+                children.entry(parent_id).or_default().push(i)
+            }
+            _ => roots.push(i),
+        }
+    }
+
+    let mut output = String::new();
+    let _ = write!(output, "trace {trace_id}");
+    let _ = writeln!(output, "{}", if complete { "" } else { " (incomplete)" });
+    for root in roots {
+        format_span(&mut output, &spans, &children, root, 1);
+    }
+    output
+}
+
+fn format_span(
+    output: &mut String,
+    spans: &[ReportedSpan],
+    children: &HashMap<SpanId, Vec<usize>>,
+    i: usize,
+    depth: usize,
+) {
+    let (span, events) = &spans[i];
+    let indent = "  ".repeat(depth);
+    let duration = elapsed(span.initialized_at, span.completed_at);
+    let _ = write!(output, "{indent}{} [{}] {duration:?}", span.name, span.id);
+    if let (true, Some(parent_id)) = (span.local_root, span.parent_id) {
+        let _ = write!(output, " (remote parent {parent_id})");
+    }
+    let _ = writeln!(output, "{}", format_attributes(&span.values.0));
+
+    for event in events {
+        let _ = writeln!(
+            output,
+            "{indent}  @{:?} {}{}",
+            elapsed(span.initialized_at, event.initialized_at),
+            event.meta.level(),
+            format_attributes(&event.values.0)
+        );
+    }
+
+    for child in children.get(&span.id).into_iter().flatten() {
+        format_span(output, spans, children, *child, depth + 1);
+    }
+}
+
+fn elapsed(from: SystemTime, to: SystemTime) -> Duration {
+    to.duration_since(from).unwrap_or_default()
+}
+
+fn format_attributes(attributes: &[KeyValue]) -> String {
+    attributes.iter().fold(String::new(), |mut output, kv| {
+        let _ = write!(output, " {}=", kv.key);
+        format_value(
+            &mut output,
+            kv.value.as_ref().and_then(|value| value.value.as_ref()),
+        );
+        output
+    })
+}
+
+/// Formats an attribute value in a human-readable way, with strings quoted.
+pub(crate) fn format_value(output: &mut String, value: Option<&Value>) {
+    let _ = match value {
+        None => write!(output, "null"),
+        Some(Value::StringValue(s)) => write!(output, "{s:?}"),
+        Some(Value::BoolValue(b)) => write!(output, "{b}"),
+        Some(Value::IntValue(i)) => write!(output, "{i}"),
+        Some(Value::DoubleValue(d)) => write!(output, "{d}"),
+        Some(Value::BytesValue(bytes)) => write!(output, "{bytes:?}"),
+        Some(Value::ArrayValue(array)) => {
+            output.push('[');
+            for (i, value) in array.values.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                format_value(output, value.value.as_ref());
+            }
+            write!(output, "]")
+        }
+        Some(Value::KvlistValue(kvlist)) => {
+            output.push('{');
+            output.push_str(format_attributes(&kvlist.values).trim_start());
+            write!(output, "}}")
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn prints_trace_as_tree() {
+        let writer = SharedWriter::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(StdoutTelemetry::with_writer(writer.clone()).layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("request", http.method = "GET");
+            let _root = root.enter();
+            crate::register_dist_tracing_root(TraceId(1), Some(SpanId(2))).unwrap();
+
+            for i in 0..2 {
+                let child = tracing::info_span!("query", i);
+                let _child = child.enter();
+                tracing::warn!(slow = true, "took a while");
+            }
+        });
+
+        let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 6, "{output}");
+        assert_eq!(lines[0], "trace 00000000000000000000000000000001");
+        assert!(lines[1].starts_with("  request ["));
+        assert!(lines[1].ends_with(" (remote parent 0000000000000002) http.method=\"GET\""));
+        assert!(lines[2].starts_with("    query ["));
+        assert!(lines[2].ends_with(" i=0"));
+        assert!(lines[3].starts_with("      @"));
+        assert!(lines[3].ends_with(" WARN message=\"took a while\" slow=true"));
+        assert!(lines[4].ends_with(" i=1"));
+    }
+
+    struct FailingWriter;
+
+    impl Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors_are_passed_to_the_error_handler() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let handled = errors.clone();
+        let telemetry = StdoutTelemetry::with_writer(FailingWriter)
+            .on_error(move |err| handled.lock().unwrap().push(err));
+        let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("outside of a trace");
+        });
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(&errors[0], OtlpError::Output(err) if err.kind() == std::io::ErrorKind::BrokenPipe),
+            "{:?}",
+            errors[0]
+        );
+    }
+
+    #[test]
+    fn prints_the_oldest_trace_when_too_many_are_buffered() {
+        let writer = SharedWriter::default();
+        let subscriber = tracing_subscriber::registry().with(
+            StdoutTelemetry::with_writer(writer.clone())
+                .max_traces(1)
+                .layer(),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            // The roots stay open, so nothing is printed unless the traces are evicted
+            let first = tracing::info_span!("first");
+            first.in_scope(|| {
+                crate::register_dist_tracing_root(TraceId(1), None).unwrap();
+                tracing::info_span!("child").in_scope(|| {});
+            });
+            let second = tracing::info_span!("second");
+            second.in_scope(|| {
+                crate::register_dist_tracing_root(TraceId(2), None).unwrap();
+                tracing::info_span!("child").in_scope(|| {});
+            });
+
+            let output = String::from_utf8(writer.0.lock().unwrap().clone()).unwrap();
+            let lines: Vec<_> = output.lines().collect();
+            assert_eq!(lines.len(), 2, "{output}");
+            assert_eq!(
+                lines[0],
+                "trace 00000000000000000000000000000001 (incomplete)"
+            );
+            assert!(lines[1].starts_with("  child ["));
+        });
+    }
+}
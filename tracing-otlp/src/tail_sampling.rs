@@ -0,0 +1,275 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{prost::trace::v1::Span, time::Instant};
+
+/// Configures tail sampling: spans are buffered per trace in the worker, and only traces
+/// matching one of the policies are exported once the decision window has passed.
+///
+/// # Examples
+/// ```
+/// # use std::time::Duration;
+/// # use tracing_otlp::TailSampler;
+/// // Keep all failures, and successful requests slower than 200ms
+/// TailSampler::errors(Duration::from_secs(5)).or_slower_than(Duration::from_millis(200));
+/// ```
+///
+/// Tail sampling is applied after the head sampling done by [`crate::Sampler`]. Spans of a
+/// trace arriving after its window has passed start a new window, so long-running traces
+/// may be exported partially. Set it using [`crate::Builder::tail_sampling`].
+#[derive(Debug, Clone)]
+pub struct TailSampler {
+    pub(crate) window: Duration,
+    pub(crate) max_traces: usize,
+    pub(crate) keep_errors: bool,
+    pub(crate) latency_threshold: Option<Duration>,
+}
+
+impl TailSampler {
+    /// Only export traces containing a span with an error status or an `ERROR` level event.
+    ///
+    /// The first span of a trace opens a window of the given duration, after which the
+    /// spans of the trace received so far are either exported or dropped.
+    pub fn errors(window: Duration) -> Self {
+        Self {
+            window,
+            max_traces: 4096,
+            keep_errors: true,
+            latency_threshold: None,
+        }
+    }
+
+    /// Only export traces whose root span took longer than `threshold`.
+    ///
+    /// The root span is the span of the trace whose parent is not part of the spans
+    /// received within the window, i.e. the local root. As the root finishes last, the window
+    /// should be longer than the typical duration of a trace.
+    pub fn latency(window: Duration, threshold: Duration) -> Self {
+        Self {
+            window,
+            max_traces: 4096,
+            keep_errors: false,
+            latency_threshold: Some(threshold),
+        }
+    }
+
+    /// Also export traces containing errors, see [`TailSampler::errors`].
+    pub fn or_errors(mut self) -> Self {
+        self.keep_errors = true;
+        self
+    }
+
+    /// Also export traces whose root span took longer than `threshold`, see
+    /// [`TailSampler::latency`].
+    pub fn or_slower_than(mut self, threshold: Duration) -> Self {
+        self.latency_threshold = Some(threshold);
+        self
+    }
+
+    /// Sets the maximum number of traces buffered at once, defaults to 4096.
+    ///
+    /// Once reached, the decision for the oldest trace is made early to make room.
+    pub fn max_traces(mut self, max_traces: usize) -> Self {
+        self.max_traces = max_traces.max(1);
+        self
+    }
+}
+
+/// A span as queued for export, with the information needed for tail sampling.
+#[derive(Debug)]
+pub(crate) struct QueuedSpan {
+    pub(crate) span: Span,
+    /// Whether the span has an error status or an `ERROR` event
+    pub(crate) has_error: bool,
+    /// Whether the span is a snapshot of a span which is still open, see
+    /// [`crate::Builder::heartbeat`]
+    pub(crate) in_progress: bool,
+}
+
+#[derive(Debug)]
+struct BufferedTrace {
+    opened_at: Instant,
+    spans: Vec<Span>,
+    keep: bool,
+}
+
+/// The spans buffered for tail sampling by the worker.
+#[derive(Debug)]
+pub(crate) struct TailBuffer {
+    sampler: TailSampler,
+    traces: HashMap<Vec<u8>, BufferedTrace>,
+}
+
+impl TailBuffer {
+    pub(crate) fn new(sampler: TailSampler) -> Self {
+        Self {
+            sampler,
+            traces: HashMap::new(),
+        }
+    }
+
+    /// Buffers a span, moving the spans of the oldest trace to `export` if the buffer is full.
+    ///
+    /// Returns the number of spans discarded, those of the oldest trace if it is not kept.
+    pub(crate) fn push(&mut self, span: QueuedSpan, export: &mut Vec<Span>) -> usize {
+        let mut discarded = 0;
+        if !self.traces.contains_key(&span.span.trace_id)
+            && self.traces.len() >= self.sampler.max_traces
+        {
+            if let Some(oldest) = self
+                .traces
+                .iter()
+                .min_by_key(|(_, t)| t.opened_at)
+                .map(|(id, _)| id.clone())
+            {
+                discarded = self.decide(&oldest, export);
+            }
+        }
+
+        let keep = self.sampler.keep_errors && span.has_error;
+        let trace = self
+            .traces
+            .entry(span.span.trace_id.clone())
+            .or_insert_with(|| BufferedTrace {
+                opened_at: Instant::now(),
+                spans: Vec::new(),
+                keep: false,
+            });
+        trace.keep |= keep;
+        trace.spans.push(span.span);
+        discarded
+    }
+
+    /// Discards all buffered spans.
+    pub(crate) fn clear(&mut self) {
+        self.traces.clear();
+    }
+
+    /// The number of spans awaiting a decision.
+    pub(crate) fn buffered_spans(&self) -> usize {
+        self.traces.values().map(|t| t.spans.len()).sum()
+    }
+
+    /// Makes the decision for all traces whose window has passed, moving the spans of the
+    /// kept traces to `export` and returning the number of spans discarded.
+    pub(crate) fn flush_expired(&mut self, export: &mut Vec<Span>) -> usize {
+        let expired: Vec<_> = self
+            .traces
+            .iter()
+            .filter(|(_, t)| t.opened_at.elapsed() >= self.sampler.window)
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired.iter().map(|id| self.decide(id, export)).sum()
+    }
+
+    /// Makes the decision for all buffered traces right away, when flushing, returning the
+    /// number of spans discarded.
+    pub(crate) fn flush_all(&mut self, export: &mut Vec<Span>) -> usize {
+        let ids: Vec<_> = self.traces.keys().cloned().collect();
+        ids.iter().map(|id| self.decide(id, export)).sum()
+    }
+
+    /// Moves the spans of a trace to `export` if it is kept, returning the number of spans
+    /// discarded otherwise.
+    fn decide(&mut self, trace_id: &[u8], export: &mut Vec<Span>) -> usize {
+        let Some(trace) = self.traces.remove(trace_id) else {
+            return 0;
+        };
+        let slow = || {
+            self.sampler
+                .latency_threshold
+                .is_some_and(|threshold| root_duration(&trace.spans) > Some(threshold))
+        };
+        if trace.keep || slow() {
+            export.extend(trace.spans);
+            0
+        } else {
+            trace.spans.len()
+        }
+    }
+}
+
+/// Returns the duration of the first span whose parent is not part of `spans`.
+fn root_duration(spans: &[Span]) -> Option<Duration> {
+    spans
+        .iter()
+        .find(|span| {
+            !spans
+                .iter()
+                .any(|parent| parent.span_id == span.parent_span_id)
+        })
+        .map(|root| {
+            Duration::from_nanos(
+                root.end_time_unix_nano
+                    .saturating_sub(root.start_time_unix_nano),
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn span(trace_id: u8, has_error: bool) -> QueuedSpan {
+        QueuedSpan {
+            span: Span {
+                trace_id: vec![trace_id],
+                ..Default::default()
+            },
+            has_error,
+            in_progress: false,
+        }
+    }
+
+    #[test]
+    fn keeps_only_traces_with_errors() {
+        let mut buffer = TailBuffer::new(TailSampler::errors(Duration::ZERO));
+        let mut export = Vec::new();
+        buffer.push(span(1, false), &mut export);
+        buffer.push(span(1, true), &mut export);
+        buffer.push(span(2, false), &mut export);
+        assert_eq!(buffer.flush_expired(&mut export), 1);
+
+        assert_eq!(export.len(), 2);
+        assert!(export.iter().all(|s| s.trace_id == vec![1]));
+    }
+
+    #[test]
+    fn keeps_traces_with_slow_roots() {
+        let mut buffer = TailBuffer::new(TailSampler::latency(
+            Duration::ZERO,
+            Duration::from_millis(100),
+        ));
+        let mut export = Vec::new();
+        for (trace_id, duration) in [(1, 50_000_000), (2, 150_000_000)] {
+            let mut root = span(trace_id, false);
+            root.span.span_id = vec![1];
+            root.span.end_time_unix_nano = duration;
+            let mut child = span(trace_id, false);
+            child.span.span_id = vec![2];
+            child.span.parent_span_id = vec![1];
+            buffer.push(child, &mut export);
+            buffer.push(root, &mut export);
+        }
+        assert_eq!(buffer.flush_expired(&mut export), 2);
+
+        assert_eq!(export.len(), 2);
+        assert!(export.iter().all(|s| s.trace_id == vec![2]));
+    }
+
+    #[test]
+    fn evicts_oldest_trace_when_full() {
+        let mut buffer =
+            TailBuffer::new(TailSampler::errors(Duration::from_secs(60)).max_traces(1));
+        let mut export = Vec::new();
+        buffer.push(span(1, true), &mut export);
+        assert!(export.is_empty());
+        assert_eq!(buffer.push(span(2, false), &mut export), 0);
+        assert_eq!(export.len(), 1);
+        assert_eq!(export[0].trace_id, vec![1]);
+
+        // Evicted traces which are not kept are discarded
+        assert_eq!(buffer.push(span(3, false), &mut export), 1);
+        assert_eq!(buffer.flush_all(&mut export), 1);
+        assert_eq!(export.len(), 1);
+    }
+}
@@ -0,0 +1,17 @@
+//! Clocks which also work on `wasm32-unknown-unknown`, where those of `std::time` panic.
+
+use std::time::SystemTime;
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use instant::Instant;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time::Instant;
+
+/// The current time, read from the JS `Date` on `wasm32-unknown-unknown`.
+pub(crate) fn now() -> SystemTime {
+    #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+    return std::time::UNIX_EPOCH
+        + std::time::Duration::from_secs_f64(js_sys::Date::now() / 1000.0);
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    SystemTime::now()
+}
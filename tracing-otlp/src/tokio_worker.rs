@@ -0,0 +1,222 @@
+use std::{
+    error::Error,
+    panic::AssertUnwindSafe,
+    time::{Duration, Instant},
+};
+
+use futures_util::FutureExt;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+    Request, Response,
+};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use prost::Message;
+use tokio::sync::mpsc::Receiver;
+use url::Url;
+
+use crate::{
+    error::{ErrorHandler, OtlpError},
+    http::{merge_headers, rejected_spans, CompressionConfig, HttpClient, SignalHeaders, PROTOBUF},
+    pool::{span_count, ExportOutcome},
+    worker::{Worker, WorkerMessage},
+};
+
+/// Async counterpart of [`HttpClient`], used by the worker when it runs as a tokio task.
+///
+/// Only plain `http` endpoints are supported.
+struct AsyncHttpClient {
+    client: Client<HttpConnector, Full<Bytes>>,
+    request_timeout: Option<Duration>,
+    compression: CompressionConfig,
+    headers: SignalHeaders,
+    error_handler: ErrorHandler,
+}
+
+impl AsyncHttpClient {
+    fn new(client: &HttpClient) -> Self {
+        let config = &client.agent_config;
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(config.connect_timeout);
+        Self {
+            client: Client::builder(TokioExecutor::new())
+                .pool_max_idle_per_host(config.idle_connections())
+                .build(connector),
+            request_timeout: config.request_timeout,
+            compression: config.compression.clone(),
+            headers: client.headers.clone(),
+            error_handler: client.error_handler.clone(),
+        }
+    }
+
+    /// Sends encoded traces to the server, handling its response and returning the number of
+    /// spans rejected by the server.
+    async fn send_traces(
+        &self,
+        url: &Url,
+        route_headers: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let headers = merge_headers(&self.headers.for_traces(), route_headers.to_vec());
+        let res = self.post(url, &headers, body).await?;
+        if res.headers().get(CONTENT_TYPE).map(|v| v.as_bytes()) != Some(PROTOBUF.as_bytes()) {
+            return Ok(0);
+        }
+        match res.into_body().collect().await {
+            Ok(body) => Ok(rejected_spans(&self.error_handler, &body.to_bytes())),
+            Err(err) => {
+                self.error_handler
+                    .handle(OtlpError::InvalidResponse(Box::new(err)));
+                Ok(0)
+            }
+        }
+    }
+
+    /// Sends a POST request with a protobuf body and the given HTTP headers, failing on
+    /// error status codes.
+    async fn post(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<Response<Incoming>, Box<dyn Error + Send + Sync>> {
+        let req = Request::post(url.as_str()).header(CONTENT_TYPE, PROTOBUF);
+        let req = headers.iter().fold(req, |r, (k, v)| r.header(k, v));
+        let (req, body) = match self.compression.compress(&body) {
+            Some((body, encoding)) => (req.header(CONTENT_ENCODING, encoding), body),
+            None => (req, body),
+        };
+        let res = self.client.request(req.body(Full::new(body.into()))?);
+        let res = match self.request_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, res)
+                .await
+                .map_err(|_| format!("{url}: timed out"))??,
+            None => res.await?,
+        };
+        if !res.status().is_success() {
+            return Err(format!("{url}: status code {}", res.status().as_u16()).into());
+        }
+        Ok(res)
+    }
+}
+
+/// Runs the worker until the layer is dropped, restarting it if it panics.
+pub(crate) async fn run(mut worker: Worker, mut rx: Receiver<WorkerMessage>) {
+    let client = AsyncHttpClient::new(worker.client());
+    loop {
+        let run = AssertUnwindSafe(run_loop(&mut worker, &mut rx, &client));
+        match run.catch_unwind().await {
+            Ok(()) => break,
+            Err(panic) => worker.recover(panic),
+        }
+    }
+}
+
+async fn run_loop(worker: &mut Worker, rx: &mut Receiver<WorkerMessage>, client: &AsyncHttpClient) {
+    loop {
+        // Receive spans at most until the interval is up
+        match tokio::time::timeout(worker.duration_to_next_send(), rx.recv()).await {
+            Ok(Some(WorkerMessage::Span(span))) => worker.receive(span),
+            Ok(Some(WorkerMessage::Flush(ack))) => {
+                flush(worker, client).await;
+                let _ = ack.send(());
+            }
+            Ok(None) => {
+                flush(worker, client).await;
+                break;
+            }
+            Err(_) => {}
+        }
+
+        worker.maintain();
+        if let Some((endpoint, body)) = worker.poll_metrics() {
+            if let Err(err) = client
+                .post(&endpoint, &client.headers.for_metrics(), body)
+                .await
+            {
+                worker.metrics_failed(&endpoint, err);
+            }
+        }
+
+        // Send spans on the given interval or once they fill a batch, if we have any to send
+        if (worker.batch_ready() || worker.send_due())
+            && worker.has_pending()
+            && !worker.exports_suspended()
+        {
+            export_pending(worker, client).await;
+        }
+    }
+}
+
+/// Exports all spans held by the worker right away.
+async fn flush(worker: &mut Worker, client: &AsyncHttpClient) {
+    worker.release_buffered();
+    while worker.has_pending() && !worker.exports_suspended() {
+        if !export_pending(worker, client).await {
+            break;
+        }
+    }
+}
+
+/// Exports a batch of pending spans, returning whether it succeeded.
+async fn export_pending(worker: &mut Worker, client: &AsyncHttpClient) -> bool {
+    let batch = worker.take_batch();
+    let spans = span_count(&batch.req);
+    let started = Instant::now();
+    let endpoint = worker
+        .batch_endpoint(&batch)
+        .map(|(traces, headers)| (traces.clone(), headers.to_vec()));
+    let result = match endpoint {
+        Some((traces, headers)) => {
+            client
+                .send_traces(&traces, &headers, batch.req.encode_to_vec())
+                .await
+        }
+        // Not reached, as the task only exports over HTTP: the other destinations export with
+        // blocking I/O, which would stall the runtime
+        None => worker.export(&batch),
+    };
+    let exported = result.is_ok();
+    worker.handle_outcome(ExportOutcome {
+        batch,
+        spans,
+        duration: started.elapsed(),
+        result,
+    });
+    worker.update_pending();
+    exported
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::{register_dist_tracing_root, test::mock_collector, Builder, TraceId};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn flush_exports_through_the_task() {
+        let layer = Builder::new()
+            .send_interval(Duration::from_secs(3600))
+            .build_tokio(mock_collector().as_str())
+            .unwrap();
+        let otlp = layer.telemetry().clone();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+
+        let stats = otlp.stats();
+        // Flushing blocks, so not on a thread running the worker
+        let flushed = tokio::task::spawn_blocking(move || otlp.flush(Duration::from_secs(5)));
+        assert!(flushed.await.unwrap());
+        assert_eq!(stats.spans_exported(), 1);
+    }
+}
@@ -0,0 +1,296 @@
+use std::task::{Context, Poll};
+
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::http_trace::{
+    client_span, inject, server_span, trace_id_header, Propagation, TraceFuture,
+};
+
+/// [`Layer`] tracing each request handled by the wrapped `tower` service in a `SERVER` span,
+/// continuing the trace of the W3C `traceparent` header of the request if any.
+///
+/// The span has the HTTP semantic convention attributes of the request and the status code
+/// of the response, named after the method of the request. Its status is an error when the
+/// service fails or responds with a 5xx status code.
+///
+/// With the `axum` feature, spans of requests routed by an `axum::Router` the layer is added
+/// to also have the `http.route` attribute, and are named after the method and route.
+///
+/// # Examples
+/// ```
+/// # use tower_layer::Layer;
+/// # #[derive(Clone)]
+/// # struct App;
+/// let service = tracing_otlp::ServerTraceLayer::new()
+///     .trace_id_header(http::HeaderName::from_static("x-trace-id"))
+///     .layer(App);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ServerTraceLayer {
+    trace_id_header: Option<http::HeaderName>,
+}
+
+impl ServerTraceLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the id of the trace of each request to its response in the given header, so
+    /// clients can refer to the trace, e.g. in their own logs or error reports.
+    pub fn trace_id_header(mut self, header: http::HeaderName) -> Self {
+        self.trace_id_header = Some(header);
+        self
+    }
+}
+
+impl<S> Layer<S> for ServerTraceLayer {
+    type Service = ServerTrace<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ServerTrace {
+            inner,
+            trace_id_header: self.trace_id_header.clone(),
+        }
+    }
+}
+
+/// Service tracing the requests handled by the inner service, see [`ServerTraceLayer`].
+#[derive(Debug, Clone)]
+pub struct ServerTrace<S> {
+    inner: S,
+    trace_id_header: Option<http::HeaderName>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for ServerTrace<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TraceFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let span = server_span(&request);
+        let response_header = trace_id_header(self.trace_id_header.as_ref(), &span);
+        let inner = span.in_scope(|| self.inner.call(request));
+        TraceFuture::server(inner, span, response_header)
+    }
+}
+
+/// [`Layer`] tracing each request sent by the wrapped `tower` HTTP client in a `CLIENT` span
+/// and propagating its context in the W3C `traceparent` header of the request.
+///
+/// The span is a child of the current span, named after the method of the request, with the
+/// HTTP semantic convention attributes of the request and the status code of the response.
+/// Its status is an error when the request fails or gets a 4xx or 5xx response.
+///
+/// # Examples
+/// ```
+/// # use tower_layer::Layer;
+/// # use tracing_otlp::{ClientTraceLayer, Propagation};
+/// # #[derive(Clone)]
+/// # struct HttpClient;
+/// let client = ClientTraceLayer::new()
+///     .propagate(Propagation::B3)
+///     .layer(HttpClient);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClientTraceLayer {
+    propagation: Vec<Propagation>,
+}
+
+impl ClientTraceLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also propagates the trace context in the given format, for services which do not
+    /// support the W3C trace context yet.
+    pub fn propagate(mut self, propagation: Propagation) -> Self {
+        if !self.propagation.contains(&propagation) {
+            self.propagation.push(propagation);
+        }
+        self
+    }
+}
+
+impl<S> Layer<S> for ClientTraceLayer {
+    type Service = ClientTrace<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientTrace {
+            inner,
+            propagation: self.propagation.clone(),
+        }
+    }
+}
+
+/// HTTP client tracing the requests sent by the inner client, see [`ClientTraceLayer`].
+#[derive(Debug, Clone)]
+pub struct ClientTrace<S> {
+    inner: S,
+    propagation: Vec<Propagation>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for ClientTrace<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = TraceFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let span = client_span(&request);
+        inject(&span, &self.propagation, request.headers_mut());
+        let inner = span.in_scope(|| self.inner.call(request));
+        TraceFuture::client(inner, span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{convert::Infallible, future::Ready};
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{
+        propagation::b3,
+        prost::{
+            common::v1::KeyValue,
+            trace::v1::{span::SpanKind, status::StatusCode},
+        },
+        test::{capture_exporter, current_thread_runtime},
+    };
+
+    /// Responds with the status code of the `x-status` header of the request.
+    struct Respond;
+
+    impl Service<http::Request<()>> for Respond {
+        type Response = http::Response<()>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<()>) -> Self::Future {
+            let status = request.headers()["x-status"].to_str().unwrap();
+            let mut response = http::Response::new(());
+            *response.status_mut() = status.parse().unwrap();
+            std::future::ready(Ok(response))
+        }
+    }
+
+    fn get(status: u16) -> http::Request<()> {
+        http::Request::get("http://localhost/users?page=2")
+            .header("x-status", status)
+            .header(
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn traces_requests_in_server_spans() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let runtime = current_thread_runtime();
+        tracing::subscriber::with_default(subscriber, || {
+            let mut service = ServerTraceLayer::new().layer(Respond);
+            for status in [200, 503] {
+                runtime.block_on(service.call(get(status))).unwrap();
+            }
+        });
+
+        let spans = capture.0.lock().unwrap();
+        let (ok, failed) = (&spans[0], &spans[1]);
+        assert_eq!(ok.name, "GET");
+        assert_eq!(ok.kind, SpanKind::Server as i32);
+        assert_eq!(ok.status, None);
+        assert_eq!(ok.parent_span_id, 0xb7ad6b7169203331u64.to_be_bytes());
+        let kv = |key: &str, value: &str| KeyValue::new(key.to_string(), value.to_string().into());
+        assert_eq!(
+            ok.attributes,
+            [
+                kv("http.request.method", "GET"),
+                kv("url.path", "/users"),
+                kv("url.query", "page=2"),
+                kv("url.scheme", "http"),
+                kv("network.protocol.version", "1.1"),
+                KeyValue::new("http.response.status_code".to_string(), 200.into()),
+            ]
+        );
+        assert_eq!(
+            failed.status.as_ref().map(|status| status.code),
+            Some(StatusCode::Error as i32)
+        );
+    }
+
+    /// Responds with the trace context headers of the request.
+    struct Echo;
+
+    impl Service<http::Request<()>> for Echo {
+        type Response = http::Response<http::HeaderMap>;
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<()>) -> Self::Future {
+            let mut response = http::Response::new(request.headers().clone());
+            *response.status_mut() = http::StatusCode::NOT_FOUND;
+            std::future::ready(Ok(response))
+        }
+    }
+
+    #[test]
+    fn propagates_the_context_of_client_spans() {
+        let (otlp, capture) = capture_exporter();
+        let subscriber = tracing_subscriber::Registry::default().with(otlp.layer());
+        let runtime = current_thread_runtime();
+        let headers = tracing::subscriber::with_default(subscriber, || {
+            let mut client = ClientTraceLayer::new()
+                .propagate(Propagation::B3)
+                .layer(Echo);
+            tracing::info_span!("job").in_scope(|| {
+                crate::register_dist_tracing_root(crate::TraceId(1), None).unwrap();
+                let request = http::Request::get("http://api:8080/users")
+                    .body(())
+                    .unwrap();
+                runtime.block_on(client.call(request)).unwrap().into_body()
+            })
+        });
+
+        let spans = capture.0.lock().unwrap();
+        let (client, job) = (&spans[0], &spans[1]);
+        assert_eq!(client.kind, SpanKind::Client as i32);
+        assert_eq!(client.parent_span_id, job.span_id);
+        assert_eq!(
+            client.status.as_ref().map(|status| status.code),
+            Some(StatusCode::Error as i32)
+        );
+        let span_id = crate::SpanId(u64::from_be_bytes(client.span_id[..].try_into().unwrap()));
+        let ctx = crate::SpanContext::new(crate::TraceId(1), span_id, true);
+        assert_eq!(headers["traceparent"], crate::traceparent(&ctx));
+        assert_eq!(headers["b3"], b3(&ctx));
+        assert!(client
+            .attributes
+            .contains(&KeyValue::new("server.port".to_string(), 8080.into())));
+    }
+}
@@ -0,0 +1,71 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{prost::common::v1::KeyValue, TraceId};
+
+/// Attributes set with [`crate::set_trace_attribute`], per trace, until the local root of
+/// the trace is exported.
+#[derive(Default)]
+pub(crate) struct TraceAttributes(pub(crate) Mutex<HashMap<TraceId, Vec<KeyValue>>>);
+
+impl TraceAttributes {
+    /// Sets an attribute of a trace, replacing the one with the same key.
+    pub(crate) fn set(&self, trace_id: TraceId, kv: KeyValue) {
+        let mut traces = self.0.lock().expect("trace attributes lock poisoned");
+        let attributes = traces.entry(trace_id).or_default();
+        match attributes
+            .iter_mut()
+            .find(|existing| existing.key == kv.key)
+        {
+            Some(existing) => *existing = kv,
+            None => attributes.push(kv),
+        }
+    }
+
+    /// Adds the attributes of the trace to those of one of its spans, except those the span
+    /// already has. Forgets them if the span is the local root of the trace, which ends last.
+    pub(crate) fn apply(&self, trace_id: TraceId, local_root: bool, span: &mut Vec<KeyValue>) {
+        let mut traces = self.0.lock().expect("trace attributes lock poisoned");
+        if traces.is_empty() {
+            return;
+        }
+        let attributes = if local_root {
+            traces.remove(&trace_id)
+        } else {
+            traces.get(&trace_id).cloned()
+        };
+        for kv in attributes.into_iter().flatten() {
+            if !span.iter().any(|existing| existing.key == kv.key) {
+                span.push(kv);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn applies_attributes_until_the_root_ends() {
+        let kv = |key: &str, value: &str| KeyValue::new(key.to_string(), value.into());
+        let (trace_id, other) = (TraceId::new(), TraceId::new());
+        let attributes = TraceAttributes::default();
+        attributes.set(trace_id, kv("user.id", "1"));
+        attributes.set(trace_id, kv("user.id", "2"));
+        attributes.set(trace_id, kv("request.id", "a"));
+
+        let mut span = vec![kv("request.id", "span")];
+        attributes.apply(trace_id, false, &mut span);
+        assert_eq!(span, [kv("request.id", "span"), kv("user.id", "2")]);
+
+        let mut span = vec![];
+        attributes.apply(other, false, &mut span);
+        assert!(span.is_empty());
+
+        attributes.apply(trace_id, true, &mut span);
+        assert_eq!(span.len(), 2);
+        let mut span = vec![];
+        attributes.apply(trace_id, false, &mut span);
+        assert!(span.is_empty(), "forgotten once the root ended");
+    }
+}
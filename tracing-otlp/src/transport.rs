@@ -0,0 +1,155 @@
+use std::error::Error;
+
+use prost::Message;
+
+use crate::prost::collector::trace::v1::ExportTraceServiceRequest;
+
+/// Encodes the export requests sent by a [`Transport`], see
+/// [`crate::Builder::build_transport`].
+pub trait Encoder: Send + 'static {
+    /// The media type of the encoded requests, e.g. `application/x-protobuf`.
+    fn content_type(&self) -> &str;
+
+    fn encode(&self, request: &ExportTraceServiceRequest) -> Vec<u8>;
+}
+
+/// [`Encoder`] of the OTLP protobuf encoding, as sent by the built-in HTTP exporter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProtobufEncoder;
+
+impl Encoder for ProtobufEncoder {
+    fn content_type(&self) -> &str {
+        "application/x-protobuf"
+    }
+
+    fn encode(&self, request: &ExportTraceServiceRequest) -> Vec<u8> {
+        request.encode_to_vec()
+    }
+}
+
+/// [`Encoder`] of the OTLP/JSON encoding, as written by [`crate::Builder::build_file`].
+#[cfg(feature = "file")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonEncoder;
+
+#[cfg(feature = "file")]
+impl Encoder for JsonEncoder {
+    fn content_type(&self) -> &str {
+        "application/json"
+    }
+
+    fn encode(&self, request: &ExportTraceServiceRequest) -> Vec<u8> {
+        crate::json::trace_request_to_json(request)
+            .to_string()
+            .into_bytes()
+    }
+}
+
+/// Sends the export requests encoded by an [`Encoder`] to their destination, see
+/// [`crate::Builder::build_transport`].
+///
+/// # Examples
+/// ```
+/// # use std::error::Error;
+/// # use tracing_otlp::{Builder, ProtobufEncoder, Transport};
+/// /// Keeps the encoded requests, e.g. to assert on them in tests.
+/// #[derive(Default)]
+/// struct Recorded(Vec<Vec<u8>>);
+///
+/// impl Transport for Recorded {
+///     fn send(
+///         &mut self,
+///         _content_type: &str,
+///         body: &[u8],
+///     ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+///         self.0.push(body.to_vec());
+///         Ok(0)
+///     }
+/// }
+///
+/// Builder::new().build_transport(ProtobufEncoder, Recorded::default());
+/// ```
+pub trait Transport: Send + 'static {
+    /// Sends an encoded request, returning the number of its spans rejected by the
+    /// destination, if it tells. Requests it fails to send are retried like failed HTTP
+    /// exports.
+    fn send(
+        &mut self,
+        content_type: &str,
+        body: &[u8],
+    ) -> Result<usize, Box<dyn Error + Send + Sync>>;
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::{register_dist_tracing_root, Builder, TraceId};
+
+    /// The content type and body of a sent request.
+    type Sent = (String, Vec<u8>);
+
+    #[derive(Clone, Default)]
+    struct Recorded(Arc<Mutex<Vec<Sent>>>);
+
+    impl Transport for Recorded {
+        fn send(
+            &mut self,
+            content_type: &str,
+            body: &[u8],
+        ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+            let request = (content_type.to_string(), body.to_vec());
+            self.0.lock().unwrap().push(request);
+            Ok(0)
+        }
+    }
+
+    fn export_root(encoder: impl Encoder) -> Vec<Sent> {
+        let recorded = Recorded::default();
+        let layer = Builder::new()
+            .build_transport(encoder, recorded.clone())
+            .unwrap();
+        let otlp = layer.telemetry().clone();
+        let subscriber = tracing_subscriber::Registry::default().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("root").in_scope(|| {
+                register_dist_tracing_root(TraceId::new(), None).unwrap();
+            })
+        });
+        assert!(otlp.flush(Duration::from_secs(5)));
+        assert_eq!(otlp.stats().spans_exported(), 1);
+        let requests = recorded.0.lock().unwrap();
+        requests.clone()
+    }
+
+    #[test]
+    fn sends_encoded_requests() {
+        let requests = export_root(ProtobufEncoder);
+        let (content_type, body) = &requests[0];
+        assert_eq!(content_type, "application/x-protobuf");
+        let request = ExportTraceServiceRequest::decode(body.as_slice()).unwrap();
+        assert_eq!(
+            request.resource_spans[0].scope_spans[0].spans[0].name,
+            "root"
+        );
+    }
+
+    #[cfg(feature = "file")]
+    #[test]
+    fn encodes_json() {
+        let requests = export_root(JsonEncoder);
+        let (content_type, body) = &requests[0];
+        assert_eq!(content_type, "application/json");
+        let request: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!(
+            request["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"],
+            "root"
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use tracing::field::{Field, Visit};
 
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+use crate::prost::common::v1::KeyValueList;
 use crate::prost::common::v1::{any_value::Value, AnyValue, KeyValue};
 
 #[derive(Default, Clone, Debug)]
@@ -8,26 +10,34 @@ pub struct Visitor(pub Vec<KeyValue>);
 impl Visit for Visitor {
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         self.0.push(KeyValue::new(
-            field.to_string(),
+            field.name().to_string(),
             format!("{:?}", value).into(),
         ))
     }
-    // TODO: This may allow hashmaps to be used as attributes
-    // fn record_value(&mut self, field: &Field, value: Value<'_>) {
-    //     todo!()
-    // }
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+        self.0.push(KeyValue::new(
+            field.name().to_string(),
+            valuable_to_value(value),
+        ))
+    }
     fn record_f64(&mut self, field: &Field, value: f64) {
-        self.0.push(KeyValue::new(field.to_string(), value.into()))
+        self.0
+            .push(KeyValue::new(field.name().to_string(), value.into()))
     }
     fn record_i64(&mut self, field: &Field, value: i64) {
-        self.0.push(KeyValue::new(field.to_string(), value.into()))
+        self.0
+            .push(KeyValue::new(field.name().to_string(), value.into()))
     }
     fn record_bool(&mut self, field: &Field, value: bool) {
-        self.0.push(KeyValue::new(field.to_string(), value.into()))
+        self.0
+            .push(KeyValue::new(field.name().to_string(), value.into()))
     }
     fn record_str(&mut self, field: &Field, value: &str) {
-        self.0
-            .push(KeyValue::new(field.to_string(), value.to_string().into()))
+        self.0.push(KeyValue::new(
+            field.name().to_string(),
+            value.to_string().into(),
+        ))
     }
 }
 
@@ -39,3 +49,202 @@ impl KeyValue {
         }
     }
 }
+
+/// Converts a `valuable::Value` into an OTLP value, keeping the structure of lists, maps,
+/// structs and enums intact as `ArrayValue`s and `KvlistValue`s.
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+fn valuable_to_value(value: valuable::Value<'_>) -> Value {
+    use valuable::Fields;
+
+    // Integers which do not fit in an OTLP int are exported as their decimal representation
+    fn int<T: TryInto<i64> + ToString + Copy>(i: T) -> Value {
+        i.try_into()
+            .map(Value::IntValue)
+            .unwrap_or_else(|_| i.to_string().into())
+    }
+
+    match value {
+        valuable::Value::Bool(b) => b.into(),
+        valuable::Value::Char(c) => c.to_string().into(),
+        valuable::Value::F32(f) => (f as f64).into(),
+        valuable::Value::F64(f) => f.into(),
+        valuable::Value::I8(i) => int(i),
+        valuable::Value::I16(i) => int(i),
+        valuable::Value::I32(i) => int(i),
+        valuable::Value::I64(i) => int(i),
+        valuable::Value::I128(i) => int(i),
+        valuable::Value::Isize(i) => int(i),
+        valuable::Value::U8(i) => int(i),
+        valuable::Value::U16(i) => int(i),
+        valuable::Value::U32(i) => int(i),
+        valuable::Value::U64(i) => int(i),
+        valuable::Value::U128(i) => int(i),
+        valuable::Value::Usize(i) => int(i),
+        valuable::Value::String(s) => s.into(),
+        valuable::Value::Path(p) => p.display().to_string().into(),
+        valuable::Value::Error(e) => e.to_string().into(),
+        valuable::Value::Enumerable(e) => {
+            let variant = e.variant();
+            match variant.fields() {
+                // Unit variants are exported as just their name
+                Fields::Unnamed(0) => variant.name().into(),
+                _ => KeyValueList {
+                    values: vec![KeyValue::new(
+                        variant.name().to_string(),
+                        ValuableVisitor::collect(e),
+                    )],
+                }
+                .into(),
+            }
+        }
+        valuable::Value::Listable(l) => ValuableVisitor::collect(l),
+        valuable::Value::Mappable(m) => ValuableVisitor::collect(m),
+        valuable::Value::Structable(s) => ValuableVisitor::collect(s),
+        valuable::Value::Tuplable(t) => ValuableVisitor::collect(t),
+        valuable::Value::Unit => "()".into(),
+        value => format!("{:?}", value).into(),
+    }
+}
+
+/// Collects the contents of a structured `valuable::Value`.
+///
+/// Named fields and map entries become a key-value list, anything else an array.
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+#[derive(Default)]
+struct ValuableVisitor {
+    values: Vec<AnyValue>,
+    entries: Vec<KeyValue>,
+}
+
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+impl ValuableVisitor {
+    fn collect(value: &dyn valuable::Valuable) -> Value {
+        let mut visitor = Self::default();
+        value.visit(&mut visitor);
+        visitor.finish()
+    }
+
+    fn finish(self) -> Value {
+        if self.entries.is_empty() {
+            crate::prost::common::v1::ArrayValue {
+                values: self.values,
+            }
+            .into()
+        } else {
+            KeyValueList {
+                values: self.entries,
+            }
+            .into()
+        }
+    }
+}
+
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+impl valuable::Visit for ValuableVisitor {
+    fn visit_value(&mut self, value: valuable::Value<'_>) {
+        self.values.push(AnyValue {
+            value: Some(valuable_to_value(value)),
+        })
+    }
+
+    fn visit_named_fields(&mut self, named_values: &valuable::NamedValues<'_>) {
+        for (field, value) in named_values {
+            self.entries.push(KeyValue::new(
+                field.name().to_string(),
+                valuable_to_value(*value),
+            ))
+        }
+    }
+
+    fn visit_unnamed_fields(&mut self, values: &[valuable::Value<'_>]) {
+        for value in values {
+            self.visit_value(*value)
+        }
+    }
+
+    fn visit_entry(&mut self, key: valuable::Value<'_>, value: valuable::Value<'_>) {
+        let key = match key {
+            valuable::Value::String(s) => s.to_string(),
+            key => format!("{:?}", key),
+        };
+        self.entries
+            .push(KeyValue::new(key, valuable_to_value(value)))
+    }
+}
+
+#[cfg(all(test, tracing_unstable, feature = "valuable"))]
+mod test {
+    use super::*;
+    use crate::prost::common::v1::ArrayValue;
+    use std::collections::BTreeMap;
+    use valuable::Valuable;
+
+    #[derive(Valuable)]
+    struct User {
+        id: u64,
+        roles: Vec<Role>,
+    }
+
+    #[derive(Valuable)]
+    enum Role {
+        Admin,
+        Member(String),
+    }
+
+    fn array(values: impl IntoIterator<Item = Value>) -> Value {
+        ArrayValue {
+            values: values
+                .into_iter()
+                .map(|value| AnyValue { value: Some(value) })
+                .collect(),
+        }
+        .into()
+    }
+
+    fn kvlist<'a>(entries: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+        KeyValueList {
+            values: entries
+                .into_iter()
+                .map(|(key, value)| KeyValue::new(key.to_string(), value))
+                .collect(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn converts_structs_enums_lists_and_maps() {
+        let user = User {
+            id: 7,
+            roles: vec![Role::Admin, Role::Member("billing".to_string())],
+        };
+        assert_eq!(
+            valuable_to_value(user.as_value()),
+            kvlist([
+                ("id", Value::IntValue(7)),
+                (
+                    "roles",
+                    array([
+                        "Admin".into(),
+                        kvlist([("Member", array(["billing".into()]))]),
+                    ])
+                ),
+            ])
+        );
+
+        assert_eq!(
+            valuable_to_value(vec![1u8, 2].as_value()),
+            array([Value::IntValue(1), Value::IntValue(2)])
+        );
+        // Integers which do not fit in an OTLP int
+        assert_eq!(
+            valuable_to_value(u64::MAX.as_value()),
+            u64::MAX.to_string().into()
+        );
+
+        let map = BTreeMap::from([("eu", 2), ("us", 1)]);
+        assert_eq!(
+            valuable_to_value(map.as_value()),
+            kvlist([("eu", Value::IntValue(2)), ("us", Value::IntValue(1))])
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::{error::Error, time::Duration};
+
+use futures_channel::mpsc::UnboundedReceiver;
+use futures_util::{
+    future::{select, Either},
+    StreamExt,
+};
+use js_sys::{Function, Promise, Reflect, Uint8Array};
+use prost::Message;
+use url::Url;
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Headers, Request, RequestInit, Response};
+
+use crate::{
+    error::{ErrorHandler, OtlpError},
+    http::{merge_headers, rejected_spans, CompressionConfig, HttpClient, SignalHeaders, PROTOBUF},
+    pool::{span_count, ExportOutcome},
+    time::Instant,
+    worker::{Worker, WorkerMessage},
+};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The global `fetch`, available both in windows and in web workers.
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_request(input: &Request) -> Promise;
+}
+
+/// Counterpart of [`HttpClient`] using `fetch`, used by the worker when it runs in a browser.
+struct FetchClient {
+    compression: CompressionConfig,
+    headers: SignalHeaders,
+    error_handler: ErrorHandler,
+}
+
+impl FetchClient {
+    fn new(client: &HttpClient) -> Self {
+        Self {
+            compression: client.agent_config.compression.clone(),
+            headers: client.headers.clone(),
+            error_handler: client.error_handler.clone(),
+        }
+    }
+
+    /// Sends encoded traces to the server, handling its response and returning the number of
+    /// spans rejected by the server.
+    async fn send_traces(
+        &self,
+        url: &Url,
+        route_headers: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let headers = merge_headers(&self.headers.for_traces(), route_headers.to_vec());
+        let res = self.post(url, &headers, body).await?;
+        let content_type = res.headers().get("content-type").map_err(js_error)?;
+        if content_type.as_deref() != Some(PROTOBUF) {
+            return Ok(0);
+        }
+        let body = match res.array_buffer() {
+            Ok(promise) => JsFuture::from(promise).await,
+            Err(err) => Err(err),
+        };
+        match body {
+            Ok(body) => Ok(rejected_spans(
+                &self.error_handler,
+                &Uint8Array::new(&body).to_vec(),
+            )),
+            Err(err) => {
+                self.error_handler
+                    .handle(OtlpError::InvalidResponse(js_error(err)));
+                Ok(0)
+            }
+        }
+    }
+
+    /// Sends a POST request with a protobuf body and the given HTTP headers, failing on
+    /// error status codes.
+    async fn post(
+        &self,
+        url: &Url,
+        headers: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<Response, Box<dyn Error + Send + Sync>> {
+        let js_headers = Headers::new().map_err(js_error)?;
+        js_headers.set("Content-Type", PROTOBUF).map_err(js_error)?;
+        for (k, v) in headers {
+            js_headers.set(k, v).map_err(js_error)?;
+        }
+        let body = match self.compression.compress(&body) {
+            Some((body, encoding)) => {
+                js_headers
+                    .set("Content-Encoding", encoding)
+                    .map_err(js_error)?;
+                body
+            }
+            None => body,
+        };
+        let init = RequestInit::new();
+        init.set_method("POST");
+        init.set_headers(&js_headers);
+        init.set_body(&Uint8Array::from(body.as_slice()));
+        let req = Request::new_with_str_and_init(url.as_str(), &init).map_err(js_error)?;
+
+        let res: Response = JsFuture::from(fetch_with_request(&req))
+            .await
+            .map_err(js_error)?
+            .unchecked_into();
+        if !res.ok() {
+            return Err(format!("{url}: status code {}", res.status()).into());
+        }
+        Ok(res)
+    }
+}
+
+/// Converts a JS exception, which cannot be sent across threads, into an error message.
+fn js_error(err: JsValue) -> Box<dyn Error + Send + Sync> {
+    err.as_string()
+        .or_else(|| {
+            err.dyn_ref::<js_sys::Error>()
+                .map(|err| err.message().into())
+        })
+        .unwrap_or_else(|| format!("{err:?}"))
+        .into()
+}
+
+/// Resolves after `duration`, using the global `setTimeout`.
+async fn sleep(duration: Duration) {
+    let promise = Promise::new(&mut |resolve, _| {
+        let global = js_sys::global();
+        let set_timeout = Reflect::get(&global, &"setTimeout".into())
+            .ok()
+            .and_then(|f| f.dyn_into::<Function>().ok());
+        if let Some(set_timeout) = set_timeout {
+            let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+            let _ = set_timeout.call2(&global, &resolve, &millis.into());
+        }
+    });
+    let _ = JsFuture::from(promise).await;
+}
+
+/// Runs the worker until the layer is dropped.
+///
+/// Panics abort on `wasm32-unknown-unknown`, so unlike the other modes the worker is not
+/// restarted.
+pub(crate) async fn run(mut worker: Worker, mut rx: UnboundedReceiver<WorkerMessage>) {
+    let client = FetchClient::new(worker.client());
+    loop {
+        // Receive spans at most until the interval is up
+        match select(rx.next(), Box::pin(sleep(worker.duration_to_next_send()))).await {
+            Either::Left((Some(WorkerMessage::Span(span)), _)) => worker.receive(span),
+            Either::Left((Some(WorkerMessage::Flush(ack)), _)) => {
+                flush(&mut worker, &client).await;
+                let _ = ack.send(());
+            }
+            Either::Left((None, _)) => {
+                flush(&mut worker, &client).await;
+                break;
+            }
+            Either::Right(_) => {}
+        }
+
+        worker.maintain();
+        if let Some((endpoint, body)) = worker.poll_metrics() {
+            if let Err(err) = client
+                .post(&endpoint, &client.headers.for_metrics(), body)
+                .await
+            {
+                worker.metrics_failed(&endpoint, err);
+            }
+        }
+
+        // Send spans on the given interval or once they fill a batch, if we have any to send
+        if (worker.batch_ready() || worker.send_due())
+            && worker.has_pending()
+            && !worker.exports_suspended()
+        {
+            export_pending(&mut worker, &client).await;
+        }
+    }
+}
+
+/// Exports all spans held by the worker right away.
+async fn flush(worker: &mut Worker, client: &FetchClient) {
+    worker.release_buffered();
+    while worker.has_pending() && !worker.exports_suspended() {
+        if !export_pending(worker, client).await {
+            break;
+        }
+    }
+}
+
+/// Exports a batch of pending spans, returning whether it succeeded.
+async fn export_pending(worker: &mut Worker, client: &FetchClient) -> bool {
+    let batch = worker.take_batch();
+    let spans = span_count(&batch.req);
+    let started = Instant::now();
+    let endpoint = worker
+        .batch_endpoint(&batch)
+        .map(|(traces, headers)| (traces.clone(), headers.to_vec()));
+    let result = match endpoint {
+        Some((traces, headers)) => {
+            client
+                .send_traces(&traces, &headers, batch.req.encode_to_vec())
+                .await
+        }
+        None => worker.export(&batch),
+    };
+    let exported = result.is_ok();
+    worker.handle_outcome(ExportOutcome {
+        batch,
+        spans,
+        duration: started.elapsed(),
+        result,
+    });
+    worker.update_pending();
+    exported
+}
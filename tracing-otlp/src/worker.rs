@@ -1,43 +1,211 @@
 use std::{
-    sync::mpsc::{Receiver, RecvTimeoutError},
-    time::{Duration, Instant},
+    any::Any,
+    collections::VecDeque,
+    error::Error,
+    fmt::Display,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{
+        atomic::Ordering,
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc,
+    },
+    time::Duration,
 };
 
 use prost::Message;
-use ureq::Agent;
+use rand::Rng;
 use url::Url;
 
-use crate::prost::{
-    collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse},
-    common::v1::{any_value::Value, AnyValue, KeyValue},
-    resource::v1::Resource,
-    trace::v1::{ResourceSpans, ScopeSpans, Span},
+#[cfg(feature = "file")]
+use crate::file::FileWriter;
+use crate::{
+    builder::LayerFilter,
+    circuit::CircuitBreaker,
+    error::{ErrorHandler, OtlpBuildError, OtlpError},
+    handle::{Pause, SettingChange, SettingChanges},
+    http::{AgentConfig, HttpClient, SignalHeaders},
+    metrics::{BatchResult, BatchResultCallback, ExporterCounters, MetricsConfig, MetricsExporter},
+    pool::{span_count, Batch, ExportOutcome, ExportPool},
+    prost::{
+        collector::trace::v1::ExportTraceServiceRequest,
+        common::v1::{any_value::Value, AnyValue, KeyValue},
+        resource::v1::Resource,
+        trace::v1::{ResourceSpans, ScopeSpans, Span},
+    },
+    route::ResolvedRoute,
+    spool::Spool,
+    tail_sampling::{QueuedSpan, TailBuffer, TailSampler},
+    time::Instant,
+    transport::{Encoder, Transport},
+    SpanId, TraceId,
 };
 
+/// Attribute of the resource naming the service, which spans override to belong to another
+/// service of the process.
+pub(crate) const SERVICE_NAME: &str = "service.name";
+
+/// Number of spans held in memory while they cannot be exported, beyond which the oldest are
+/// moved to the disk buffer or dropped.
+const MAX_OUTSTANDING: usize = 1024;
+
+/// Configuration of the [`Worker`], as set on the [`crate::Builder`].
+pub(crate) struct WorkerConfig {
+    pub(crate) send_interval: Duration,
+    pub(crate) send_interval_jitter: Duration,
+    pub(crate) max_queue_size: usize,
+    pub(crate) level_aware_shedding: bool,
+    /// How long the layer waits for each span to be exported, see [`crate::Builder::simple`]
+    pub(crate) wait_for_export: Option<Duration>,
+    /// How often the layer reports snapshots of open spans, see [`crate::Builder::heartbeat`]
+    pub(crate) heartbeat: Option<Duration>,
+    /// See [`crate::Builder::max_events_per_span`]
+    pub(crate) max_events_per_span: Option<usize>,
+    /// See [`crate::Builder::implicit_traces`]
+    pub(crate) implicit_traces: bool,
+    /// See [`crate::Builder::default_trace`]
+    pub(crate) default_trace: Option<(TraceId, Option<SpanId>)>,
+    /// See [`crate::Builder::max_level`]
+    pub(crate) layer_filter: LayerFilter,
+    pub(crate) max_export_batch_size: usize,
+    pub(crate) max_export_batch_bytes: Option<usize>,
+    pub(crate) max_concurrent_exports: usize,
+    pub(crate) resource_attributes: Vec<(String, Value)>,
+    pub(crate) headers: SignalHeaders,
+    pub(crate) agent: AgentConfig,
+    pub(crate) tail_sampler: Option<TailSampler>,
+    pub(crate) metrics: Option<MetricsConfig>,
+    pub(crate) error_handler: ErrorHandler,
+    pub(crate) on_batch_result: Option<BatchResultCallback>,
+    pub(crate) disk_buffer: Option<(PathBuf, u64)>,
+    pub(crate) circuit_breaker: Option<(u32, Duration)>,
+    pub(crate) retry_budget: Option<(u32, Duration)>,
+    pub(crate) routes: Vec<Arc<ResolvedRoute>>,
+    pub(crate) changes: Arc<SettingChanges>,
+}
+
+/// Message from the layer to its [`Worker`].
+// Nearly all messages are spans, boxing them would only add an allocation per span
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum WorkerMessage {
+    Span(QueuedSpan),
+    /// Export all spans held by the worker right away, acknowledging once done
+    Flush(Sender<()>),
+}
+
+/// Exports the requests of a [`Destination::Exporter`], see [`crate::Builder::build_exporter`].
+pub(crate) type ExportFn =
+    Box<dyn Fn(&ExportTraceServiceRequest) -> Result<(), Box<dyn Error + Send + Sync>> + Send>;
+
+/// Where the worker exports spans to.
+pub(crate) enum Destination {
+    /// The `/v1/traces` and `/v1/metrics` endpoints of an OTLP/HTTP server
+    Http { traces: Url, metrics: Url },
+    /// A file in the OTLP JSON file format
+    #[cfg(feature = "file")]
+    File(FileWriter),
+    /// A function exporting the requests
+    Exporter(ExportFn),
+    /// An encoder and transport sending the requests
+    Transport(Box<dyn Encoder>, Box<dyn Transport>),
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Destination::Http { traces, .. } => write!(f, "{traces}"),
+            #[cfg(feature = "file")]
+            Destination::File(writer) => write!(f, "{}", writer.path().display()),
+            Destination::Exporter(_) => write!(f, "custom exporter"),
+            Destination::Transport(_, _) => write!(f, "custom transport"),
+        }
+    }
+}
+
 pub struct Worker {
     send_interval: Duration,
-    endpoint_trace: Url,
-    rx: Receiver<Span>,
+    send_interval_jitter: Duration,
+    /// The current send interval, with jitter applied
+    interval: Duration,
+    destination: Destination,
     resource: Resource,
-    agent: Agent,
+    client: HttpClient,
+    pool: Option<ExportPool>,
     last_send: Instant,
-    http_headers: Vec<(String, String)>,
+    /// Spans waiting for the next export
+    pending: Vec<Span>,
+    /// Batches which failed to export, oldest first, retried before the pending spans
+    failed: VecDeque<Batch>,
+    /// Attempts and time after which a failing batch is dropped, see
+    /// [`crate::Builder::retry_budget`]
+    retry_budget: Option<(u32, Duration)>,
+    /// Number of spans at the start of `pending` whose size is included in `pending_bytes`,
+    /// measured lazily as only appending spans is frequent
+    pending_measured: usize,
+    /// Encoded size of the first `pending_measured` pending spans
+    pending_bytes: usize,
+    max_batch_spans: usize,
+    max_batch_bytes: Option<usize>,
+    tail_buffer: Option<TailBuffer>,
+    counters: Arc<ExporterCounters>,
+    metrics: Option<MetricsExporter>,
+    error_handler: ErrorHandler,
+    on_batch_result: Option<BatchResultCallback>,
+    spool: Option<Spool>,
+    circuit: Option<CircuitBreaker>,
+    /// Routes of spans to other endpoints, see [`crate::Builder::route`]
+    routes: Vec<Arc<ResolvedRoute>>,
+    /// Settings changed with an [`crate::OtlpHandle`]
+    changes: Arc<SettingChanges>,
+    /// Whether exports are paused with [`crate::OtlpHandle::pause`], and what happens to
+    /// the spans meanwhile
+    paused: Option<Pause>,
 }
 
 impl Worker {
-    pub fn new(
-        send_interval: Duration,
-        endpoint_trace: Url,
-        rx: Receiver<Span>,
-        resource_attributes: Vec<(String, Value)>,
-        http_headers: Vec<(String, String)>,
-    ) -> Self {
-        Self {
-            send_interval,
-            endpoint_trace,
-            rx,
+    pub(crate) fn new(
+        destination: Destination,
+        config: WorkerConfig,
+        counters: Arc<ExporterCounters>,
+    ) -> Result<Self, OtlpBuildError> {
+        // Metrics, disk buffering and routes only apply to HTTP
+        let (metrics, disk_buffer, routes) = match &destination {
+            Destination::Http { metrics, .. } => (
+                config.metrics.map(|metrics_config| {
+                    MetricsExporter::new(
+                        metrics.clone(),
+                        metrics_config,
+                        config.error_handler.clone(),
+                    )
+                }),
+                config.disk_buffer,
+                config.routes,
+            ),
+            #[cfg(feature = "file")]
+            Destination::File(_) => (None, None, Vec::new()),
+            Destination::Exporter(_) | Destination::Transport(_, _) => (None, None, Vec::new()),
+        };
+        let client = HttpClient::new(config.agent, config.headers, config.error_handler.clone());
+        let pool = match &destination {
+            Destination::Http { .. } if config.max_concurrent_exports > 1 => Some(
+                ExportPool::spawn(config.max_concurrent_exports, client.clone())
+                    .map_err(|err| OtlpBuildError::Spawn(Arc::new(err)))?,
+            ),
+            _ => None,
+        };
+        let spool = disk_buffer.and_then(|(dir, max_bytes)| {
+            Spool::open(dir, max_bytes)
+                .map_err(|err| config.error_handler.handle(OtlpError::DiskBuffer(err)))
+                .ok()
+        });
+        Ok(Self {
+            send_interval: config.send_interval,
+            send_interval_jitter: config.send_interval_jitter,
+            interval: jittered(config.send_interval, config.send_interval_jitter),
+            destination,
             resource: Resource {
-                attributes: resource_attributes
+                attributes: config
+                    .resource_attributes
                     .into_iter()
                     .map(|(key, v)| KeyValue {
                         key,
@@ -46,99 +214,1086 @@ impl Worker {
                     .collect(),
                 dropped_attributes_count: 0,
             },
-            agent: Agent::new(),
+            client,
+            pool,
             last_send: Instant::now(),
-            http_headers,
+            pending: Vec::new(),
+            failed: VecDeque::new(),
+            retry_budget: config.retry_budget,
+            pending_measured: 0,
+            pending_bytes: 0,
+            max_batch_spans: config.max_export_batch_size,
+            max_batch_bytes: config.max_export_batch_bytes,
+            tail_buffer: config.tail_sampler.map(TailBuffer::new),
+            counters,
+            metrics,
+            error_handler: config.error_handler,
+            on_batch_result: config.on_batch_result,
+            spool,
+            circuit: config
+                .circuit_breaker
+                .map(|(failures, probe_interval)| CircuitBreaker::new(failures, probe_interval)),
+            routes,
+            changes: config.changes,
+            paused: None,
+        })
+    }
+
+    /// Runs the worker until the layer is dropped, restarting it if it panics.
+    pub(crate) fn run(mut self, rx: Receiver<WorkerMessage>) {
+        while let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| self.run_loop(&rx))) {
+            self.recover(panic);
         }
     }
 
-    pub fn run_loop(&mut self) {
-        let mut spans = Vec::new();
+    /// Resets the state of the worker after a panic, dropping the spans it held as they may
+    /// be what caused it.
+    pub(crate) fn recover(&mut self, panic: Box<dyn Any + Send>) {
+        let message = panic_message(panic.as_ref());
+        self.error_handler
+            .handle(OtlpError::WorkerPanicked { message });
+
+        let dropped = self.counters.spans_pending.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            ExporterCounters::add(&self.counters.spans_dropped, dropped as usize);
+            self.error_handler.handle(OtlpError::SpansDropped {
+                count: dropped,
+                reason: "worker panicked",
+            });
+        }
+        self.pending.clear();
+        self.pending_resized();
+        self.failed.clear();
+        if let Some(tail_buffer) = &mut self.tail_buffer {
+            tail_buffer.clear();
+        }
+        self.client.reconnect();
+        self.last_send = Instant::now();
+    }
+
+    fn run_loop(&mut self, rx: &Receiver<WorkerMessage>) {
         loop {
             // Receive spans at most until the interval is up
-            match self.rx.recv_timeout(self.duration_to_next_send()) {
-                Ok(span) => spans.push(span),
+            match rx.recv_timeout(self.duration_to_next_send()) {
+                Ok(WorkerMessage::Span(span)) => self.receive(span),
+                Ok(WorkerMessage::Flush(ack)) => {
+                    self.flush();
+                    let _ = ack.send(());
+                }
                 Err(RecvTimeoutError::Timeout) => {}
-                Err(RecvTimeoutError::Disconnected) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush();
+                    break;
+                }
+            }
+
+            self.maintain();
+            if let Some((endpoint, body)) = self.poll_metrics() {
+                if let Err(err) = self.client.send_metrics(&endpoint, &body) {
+                    self.metrics_failed(&endpoint, Box::new(err));
+                }
+            }
+
+            // Send spans on the given interval, or as soon as they fill a batch
+            if !self.batch_ready() && !self.send_due() {
+                continue;
+            }
+
+            // Only send spans if we have any to send
+            if !self.has_pending() {
+                self.replay_spool();
+                continue;
             }
 
-            // Send spans on the given interval
-            if self.last_send.elapsed() >= self.send_interval {
-                self.last_send = Instant::now();
+            if self.exports_suspended() {
+                continue;
+            }
+
+            // Keep collecting spans until an export thread is free
+            if self.pool.as_ref().is_some_and(|pool| !pool.has_capacity()) {
+                continue;
+            }
+
+            let batch = self.take_batch();
+            let endpoint = self
+                .batch_endpoint(&batch)
+                .map(|(traces, headers)| (traces.clone(), headers.to_vec()));
+            match (&mut self.pool, endpoint) {
+                (Some(pool), Some((traces, headers))) => pool.submit(batch, traces, headers),
+                _ => {
+                    let outcome = self.export_outcome(batch);
+                    self.handle_outcome(outcome);
+                }
+            }
+            self.update_pending();
+        }
+    }
+
+    /// Exports all spans held by the worker on this thread, and waits for the exports in
+    /// flight on the export threads.
+    fn flush(&mut self) {
+        self.release_buffered();
+        while self.has_pending() && !self.exports_suspended() {
+            let batch = self.take_batch();
+            let outcome = self.export_outcome(batch);
+            let failed = outcome.result.is_err();
+            self.handle_outcome(outcome);
+            if failed {
+                break;
+            }
+        }
+        while let Some(outcome) = self.pool.as_mut().and_then(ExportPool::wait_outcome) {
+            self.handle_outcome(outcome);
+        }
+        self.update_pending();
+    }
+
+    /// Exports a batch of spans on this thread.
+    fn export_outcome(&mut self, batch: Batch) -> ExportOutcome {
+        let spans = span_count(&batch.req);
+        let started = Instant::now();
+        let result = self.export(&batch);
+        ExportOutcome {
+            batch,
+            spans,
+            duration: started.elapsed(),
+            result,
+        }
+    }
+
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub(crate) fn client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Takes in a span received from the layer.
+    pub(crate) fn receive(&mut self, span: QueuedSpan) {
+        ExporterCounters::add(&self.counters.spans_dequeued, 1);
+        // Snapshots are counted once the span ended, and not known to be kept before
+        if span.in_progress {
+            if self.tail_buffer.is_none() {
+                self.pending.push(span.span);
+            }
+            return;
+        }
+        if let Some(metrics) = &mut self.metrics {
+            metrics.record_span(&span.span);
+        }
+        match &mut self.tail_buffer {
+            Some(tail_buffer) => {
+                let discarded = tail_buffer.push(span, &mut self.pending);
+                ExporterCounters::add(&self.counters.spans_tail_dropped, discarded);
+            }
+            None => self.pending.push(span.span),
+        }
+    }
+
+    /// Applies the settings changed with an [`crate::OtlpHandle`], releases the expired
+    /// traces of the tail buffer, and accounts for the exports finished by the export
+    /// threads.
+    pub(crate) fn maintain(&mut self) {
+        for change in self.changes.take() {
+            self.apply(change);
+        }
+
+        if let Some(tail_buffer) = &mut self.tail_buffer {
+            let discarded = tail_buffer.flush_expired(&mut self.pending);
+            ExporterCounters::add(&self.counters.spans_tail_dropped, discarded);
+        }
 
-                // Only send spans if we have any to send
-                if spans.is_empty() {
+        while let Some(outcome) = self.pool.as_mut().and_then(ExportPool::try_outcome) {
+            self.handle_outcome(outcome);
+        }
+
+        self.update_pending();
+    }
+
+    fn apply(&mut self, change: SettingChange) {
+        match change {
+            SettingChange::Endpoint {
+                traces: new_traces,
+                metrics: new_metrics,
+            } => {
+                if let Destination::Http { traces, metrics } = &mut self.destination {
+                    *traces = new_traces;
+                    *metrics = new_metrics.clone();
+                    if let Some(metrics) = &mut self.metrics {
+                        metrics.endpoint = new_metrics;
+                    }
+                }
+            }
+            SettingChange::SendInterval(interval) => {
+                self.send_interval = interval;
+                self.interval = jittered(interval, self.send_interval_jitter);
+            }
+            SettingChange::MaxExportBatchSize(max) => self.max_batch_spans = max,
+            SettingChange::MaxExportBatchBytes(max) => self.max_batch_bytes = max,
+            SettingChange::Paused(paused) => self.paused = paused,
+        }
+    }
+
+    /// Makes the tail sampling decision for all buffered traces right away, so their spans
+    /// are exported with the next batch.
+    pub(crate) fn release_buffered(&mut self) {
+        if let Some(tail_buffer) = &mut self.tail_buffer {
+            let discarded = tail_buffer.flush_all(&mut self.pending);
+            ExporterCounters::add(&self.counters.spans_tail_dropped, discarded);
+        }
+    }
+
+    /// Returns whether the send interval is up, starting the next one if so.
+    pub(crate) fn send_due(&mut self) -> bool {
+        if self.last_send.elapsed() < self.interval {
+            return false;
+        }
+        self.last_send = Instant::now();
+        self.interval = jittered(self.send_interval, self.send_interval_jitter);
+        true
+    }
+
+    /// Whether exports are paused or suspended by the circuit breaker. While suspended, the
+    /// pending spans are moved to the disk buffer, or dropped if there is none.
+    pub(crate) fn exports_suspended(&mut self) -> bool {
+        match self.paused {
+            Some(Pause::Buffer) => {
+                self.limit_outstanding("too many spans outstanding while exports are paused");
+                return true;
+            }
+            Some(Pause::Drop) => {
+                let spans = self.failed_spans() + self.pending.len();
+                self.failed.clear();
+                self.pending.clear();
+                self.pending_resized();
+                self.drop_spans(spans, "exports paused");
+                return true;
+            }
+            None => {}
+        }
+        if !self.circuit.as_ref().is_some_and(CircuitBreaker::is_open) {
+            return false;
+        }
+        let mut spans: Vec<_> = self.failed.drain(..).flat_map(Batch::into_spans).collect();
+        spans.append(&mut self.pending);
+        self.pending_resized();
+        if !spans.is_empty() {
+            self.spill(spans, "exports suspended by the circuit breaker");
+        }
+        true
+    }
+
+    /// Whether there are spans waiting to be exported, or batches to retry.
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty() || !self.failed.is_empty()
+    }
+
+    /// Number of spans in batches waiting to be retried.
+    fn failed_spans(&self) -> usize {
+        self.failed.iter().map(|batch| span_count(&batch.req)).sum()
+    }
+
+    /// Whether the pending spans fill a batch, so they should be exported without waiting
+    /// for the send interval.
+    pub(crate) fn batch_ready(&mut self) -> bool {
+        if self.pending.len() >= self.max_batch_spans {
+            return true;
+        }
+        let Some(max_bytes) = self.max_batch_bytes else {
+            return false;
+        };
+        for span in &self.pending[self.pending_measured..] {
+            self.pending_bytes += span.encoded_len();
+        }
+        self.pending_measured = self.pending.len();
+        self.pending_bytes >= max_bytes
+    }
+
+    /// Takes the oldest failed batch to retry it, or else the oldest pending spans, at most a
+    /// batch of them. With routes, only the spans of the route of the oldest pending span
+    /// are taken.
+    pub(crate) fn take_batch(&mut self) -> Batch {
+        if let Some(batch) = self.failed.pop_front() {
+            return batch;
+        }
+        if !self.routes.is_empty() {
+            return self.take_routed_batch();
+        }
+        let mut len = self.pending.len().min(self.max_batch_spans);
+        if let Some(max_bytes) = self.max_batch_bytes {
+            let mut bytes = 0;
+            let fits = self.pending[..len].iter().position(|span| {
+                bytes += span.encoded_len();
+                bytes > max_bytes
+            });
+            // Always take at least one span, even if it exceeds the limit on its own
+            if let Some(fits) = fits {
+                len = fits.max(1);
+            }
+        }
+        let rest = self.pending.split_off(len);
+        let spans = std::mem::replace(&mut self.pending, rest);
+        self.pending_resized();
+        Batch::new(self.request(spans), None)
+    }
+
+    /// Takes the oldest pending spans of the route of the oldest one, at most a batch of
+    /// them, keeping the spans of other routes pending in order.
+    fn take_routed_batch(&mut self) -> Batch {
+        let route = self
+            .pending
+            .first()
+            .and_then(|span| self.route_of(span))
+            .cloned();
+        let same_route = |other: Option<&Arc<ResolvedRoute>>| match (&route, other) {
+            (Some(route), Some(other)) => Arc::ptr_eq(route, other),
+            (route, other) => route.is_none() && other.is_none(),
+        };
+        let (mut spans, mut rest) = (Vec::new(), Vec::new());
+        let (mut bytes, mut full) = (0, false);
+        for span in std::mem::take(&mut self.pending) {
+            if full || !same_route(self.route_of(&span)) {
+                rest.push(span);
+                continue;
+            }
+            if let Some(max_bytes) = self.max_batch_bytes {
+                bytes += span.encoded_len();
+                // Always take at least one span, even if it exceeds the limit on its own
+                if bytes > max_bytes && !spans.is_empty() {
+                    full = true;
+                    rest.push(span);
                     continue;
                 }
+            }
+            spans.push(span);
+            full = spans.len() >= self.max_batch_spans;
+        }
+        self.pending = rest;
+        self.pending_resized();
+        Batch::new(self.request(spans), route)
+    }
+
+    /// The first route matching a span, if any.
+    fn route_of(&self, span: &Span) -> Option<&Arc<ResolvedRoute>> {
+        self.routes
+            .iter()
+            .find(|route| route.matches(&self.resource, span))
+    }
+
+    /// Resets the measured size of the pending spans, after spans were removed or inserted
+    /// other than at the end.
+    fn pending_resized(&mut self) {
+        self.pending_measured = 0;
+        self.pending_bytes = 0;
+    }
+
+    /// Accounts for an export, keeping a failed batch to be retried unless its retry budget
+    /// is exhausted.
+    pub(crate) fn handle_outcome(&mut self, outcome: ExportOutcome) {
+        let ExportOutcome {
+            mut batch,
+            spans: batch_len,
+            duration,
+            result,
+        } = outcome;
+        self.counters
+            .last_export_nanos
+            .store((duration.as_nanos() as u64).max(1), Ordering::Relaxed);
+
+        if let Some(on_batch_result) = &self.on_batch_result {
+            on_batch_result(BatchResult {
+                spans: batch_len,
+                payload_size: batch.req.encoded_len(),
+                duration,
+                rejected_spans: *result.as_ref().unwrap_or(&0),
+                error: result.as_ref().err().map(ToString::to_string),
+            });
+        }
+
+        match result {
+            Ok(rejected) => {
+                if let Some(circuit) = &mut self.circuit {
+                    circuit.succeeded();
+                }
+                self.count_exported(batch_len, rejected);
+                self.replay_spool();
+            }
+            Err(err) => {
+                ExporterCounters::add(&self.counters.batches_failed, 1);
+                self.error_handler.handle(OtlpError::Export {
+                    destination: self.destination.to_string(),
+                    source: err,
+                });
+                if let Some(circuit) = &mut self.circuit {
+                    if let Some(failures) = circuit.failed() {
+                        self.error_handler.handle(OtlpError::CircuitOpen {
+                            failures,
+                            probe_interval: circuit.probe_interval(),
+                        });
+                    }
+                }
+
+                batch.failures += 1;
+                let failing_since = *batch.failing_since.get_or_insert_with(Instant::now);
+                if let Some((max_attempts, max_duration)) = self.retry_budget {
+                    if batch.failures >= max_attempts || failing_since.elapsed() >= max_duration {
+                        self.spill(batch.into_spans(), "retry budget of the batch exhausted");
+                        return;
+                    }
+                }
 
-                let mut protobuf_req = ExportTraceServiceRequest {
-                    resource_spans: vec![ResourceSpans {
-                        resource: Some(self.resource.clone()),
+                // Retry the batch ahead of the spans which finished since
+                self.failed.push_front(batch);
+                self.limit_outstanding("too many spans outstanding after failed exports");
+            }
+        }
+    }
+
+    /// Moves the oldest spans out of memory if too many are outstanding, for `reason`.
+    fn limit_outstanding(&mut self, reason: &'static str) {
+        let mut outstanding = self.failed_spans() + self.pending.len();
+        while outstanding > MAX_OUTSTANDING {
+            let Some(batch) = self.failed.pop_front() else {
+                break;
+            };
+            let spans = batch.into_spans();
+            outstanding -= spans.len();
+            self.spill(spans, reason);
+        }
+        let excess = outstanding.saturating_sub(MAX_OUTSTANDING);
+        if excess > 0 {
+            let overflow: Vec<_> = self.pending.drain(..excess).collect();
+            self.pending_resized();
+            self.spill(overflow, reason);
+        }
+    }
+
+    /// Publishes the number of spans held by the worker, for the queue depth.
+    pub(crate) fn update_pending(&self) {
+        let buffered = self
+            .tail_buffer
+            .as_ref()
+            .map_or(0, TailBuffer::buffered_spans);
+        let in_flight = self.pool.as_ref().map_or(0, ExportPool::in_flight_spans);
+        self.counters.spans_pending.store(
+            (self.pending.len() + self.failed_spans() + buffered + in_flight) as u64,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Builds the request exporting `spans`, with the spans which have a `service.name`
+    /// attribute in the resource of that service instead of the resource of the worker.
+    fn request(&self, spans: Vec<Span>) -> ExportTraceServiceRequest {
+        let mut services: Vec<(Option<KeyValue>, Vec<Span>)> = Vec::new();
+        for mut span in spans {
+            let service = span
+                .attributes
+                .iter()
+                .rposition(|kv| kv.key == SERVICE_NAME)
+                .map(|index| span.attributes.remove(index))
+                .filter(|service| !self.resource.attributes.contains(service));
+            span.attributes.retain(|kv| kv.key != SERVICE_NAME);
+            match services.iter_mut().find(|(other, _)| *other == service) {
+                Some((_, spans)) => spans.push(span),
+                None => services.push((service, vec![span])),
+            }
+        }
+        if services.is_empty() {
+            services.push((None, Vec::new()));
+        }
+        ExportTraceServiceRequest {
+            resource_spans: services
+                .into_iter()
+                .map(|(service, spans)| {
+                    let mut resource = self.resource.clone();
+                    if let Some(service) = service {
+                        resource.attributes.retain(|kv| kv.key != SERVICE_NAME);
+                        resource.attributes.push(service);
+                    }
+                    ResourceSpans {
+                        resource: Some(resource),
                         scope_spans: vec![ScopeSpans {
                             scope: None,
-                            spans: std::mem::take(&mut spans),
+                            spans,
                             schema_url: "".to_string(),
                         }],
                         schema_url: "".to_string(),
-                    }],
-                };
-
-                let encoded = protobuf_req.encode_to_vec();
-
-                let mut req = self
-                    .agent
-                    .request_url("POST", &self.endpoint_trace)
-                    .set("Content-Type", "application/x-protobuf");
-
-                // Set the HTTP headers passed by the user
-                req = self.http_headers.iter().fold(req, |r, (k, v)| r.set(k, v));
-                // Send the traces to the server
-                match req.send_bytes(&encoded) {
-                    Ok(res) => {
-                        if let Some("application/x-protobuf") = res.header("content-type") {
-                            let mut buf: Vec<u8> = Vec::new();
-                            if let Err(err) = res.into_reader().read_to_end(&mut buf) {
-                                eprintln!("Protobuf response interrupted: {err}")
-                            }
-                            match ExportTraceServiceResponse::decode(&*buf) {
-                                Ok(res) => {
-                                    if let Some(err) = res.partial_success {
-                                        if !err.error_message.is_empty() || err.rejected_spans != 0
-                                        {
-                                            eprintln!("Server returned protobuf error: {:?}", err)
-                                        }
-                                    }
-                                }
-                                Err(err) => {
-                                    eprintln!("Could not decode protobuf response: {err:?}")
-                                }
-                            }
-                        }
                     }
+                })
+                .collect(),
+        }
+    }
+
+    /// Moves spans which cannot be kept in memory to the disk buffer, or drops them for
+    /// `reason` if there is none or it is full.
+    fn spill(&mut self, mut spans: Vec<Span>, reason: &'static str) {
+        if self.spool.is_none() {
+            self.drop_spans(spans.len(), reason);
+            return;
+        }
+        if !self.routes.is_empty() {
+            // The disk buffer is replayed to the endpoint of the exporter
+            let count = spans.len();
+            spans.retain(|span| self.route_of(span).is_none());
+            self.drop_spans(
+                count - spans.len(),
+                "spans of routes are not buffered on disk",
+            );
+            if spans.is_empty() {
+                return;
+            }
+        }
+        let count = spans.len();
+        let payload = self.request(spans).encode_to_vec();
+        let reason = match self.spool.as_mut().map(|spool| spool.push(count, &payload)) {
+            Some(Ok(true)) => return,
+            Some(Ok(false)) => "disk buffer full",
+            Some(Err(err)) => {
+                self.error_handler.handle(OtlpError::DiskBuffer(err));
+                "disk buffer failed"
+            }
+            None => reason,
+        };
+        self.drop_spans(count, reason);
+    }
+
+    /// Accounts for and reports `count` spans dropped for `reason`.
+    fn drop_spans(&self, count: usize, reason: &'static str) {
+        if count == 0 {
+            return;
+        }
+        ExporterCounters::add(&self.counters.spans_dropped, count);
+        self.error_handler.handle(OtlpError::SpansDropped {
+            count: count as u64,
+            reason,
+        });
+    }
+
+    /// Sends the batches in the disk buffer, oldest first, as long as the endpoint accepts
+    /// them. Failures are not reported, the batches are retried after the next export.
+    fn replay_spool(&mut self) {
+        if self.paused.is_some() || self.circuit.as_ref().is_some_and(CircuitBreaker::is_open) {
+            return;
+        }
+        // Taken out while sending, as removing batches updates its size
+        let Some(mut spool) = self.spool.take() else {
+            return;
+        };
+        if let Some(traces) = self.traces_endpoint() {
+            loop {
+                let batch = match spool.oldest() {
+                    Ok(Some(batch)) => batch,
+                    Ok(None) => break,
                     Err(err) => {
-                        const MAX_OUTSTANDING: usize = 1024;
-
-                        // Sending failed, so put spans back into vec
-                        spans = std::mem::take(
-                            &mut protobuf_req.resource_spans[0].scope_spans[0].spans,
-                        )
-                        .into_iter()
-                        .rev()
-                        .take(MAX_OUTSTANDING)
-                        .rev()
-                        .collect();
-                        eprintln!("Error sending spans to {}: {:?}", &self.endpoint_trace, err)
+                        self.error_handler.handle(OtlpError::DiskBuffer(err));
+                        break;
                     }
+                };
+                let Ok(rejected) = self.client.send_traces(traces, &[], &batch.payload) else {
+                    break;
+                };
+                self.count_exported(batch.spans, rejected);
+                if let Err(err) = spool.remove(batch) {
+                    self.error_handler.handle(OtlpError::DiskBuffer(err));
+                    break;
                 }
             }
         }
+        self.spool = Some(spool);
+    }
+
+    /// Accounts for a batch of `spans` spans accepted by the destination, except for the
+    /// `rejected` ones.
+    ///
+    /// Rejected spans are counted as dropped rather than retried, as the response does not
+    /// tell which spans were rejected, and they would usually be rejected again.
+    fn count_exported(&self, spans: usize, rejected: usize) {
+        let rejected = rejected.min(spans);
+        ExporterCounters::add(&self.counters.batches_exported, 1);
+        ExporterCounters::add(&self.counters.spans_exported, spans - rejected);
+        ExporterCounters::add(&self.counters.spans_dropped, rejected);
+    }
+
+    /// The endpoint receiving the spans, if the worker exports over HTTP.
+    pub(crate) fn traces_endpoint(&self) -> Option<&Url> {
+        match &self.destination {
+            Destination::Http { traces, .. } => Some(traces),
+            #[cfg(feature = "file")]
+            Destination::File(_) => None,
+            Destination::Exporter(_) | Destination::Transport(_, _) => None,
+        }
+    }
+
+    /// The endpoint receiving a batch, and the headers of its route, if the worker exports
+    /// over HTTP.
+    pub(crate) fn batch_endpoint<'a>(
+        &'a self,
+        batch: &'a Batch,
+    ) -> Option<(&'a Url, &'a [(String, String)])> {
+        match &batch.route {
+            Some(route) => Some((&route.traces, &route.headers)),
+            None => self.traces_endpoint().map(|traces| (traces, &[][..])),
+        }
+    }
+
+    /// Exports a batch of spans to the destination, returning the number of spans rejected
+    /// by it.
+    pub(crate) fn export(&mut self, batch: &Batch) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let req = &batch.req;
+        if let Some((traces, headers)) = self.batch_endpoint(batch) {
+            return self
+                .client
+                .send_traces(traces, headers, &req.encode_to_vec());
+        }
+        match &mut self.destination {
+            Destination::Http { .. } => unreachable!("HTTP batches have an endpoint"),
+            #[cfg(feature = "file")]
+            Destination::File(writer) => {
+                writer.write(req)?;
+                Ok(0)
+            }
+            Destination::Exporter(export) => {
+                export(req)?;
+                Ok(0)
+            }
+            Destination::Transport(encoder, transport) => {
+                transport.send(encoder.content_type(), &encoder.encode(req))
+            }
+        }
+    }
+
+    /// Returns the endpoint and encoded request of the metrics to send, if enabled and
+    /// their interval is up.
+    pub(crate) fn poll_metrics(&mut self) -> Option<(Url, Vec<u8>)> {
+        if self.paused.is_some() {
+            return None;
+        }
+        let metrics = self.metrics.as_mut()?;
+        let req = metrics.poll(&self.resource, &self.counters, self.counters.queue_depth())?;
+        Some((metrics.endpoint.clone(), req.encode_to_vec()))
+    }
+
+    /// Reports that sending the metrics to `endpoint` failed.
+    pub(crate) fn metrics_failed(&self, endpoint: &Url, err: Box<dyn Error + Send + Sync>) {
+        self.error_handler.handle(OtlpError::Metrics {
+            destination: endpoint.to_string(),
+            source: err,
+        });
     }
 
     fn instant_next_send(&self) -> Instant {
-        self.last_send + self.send_interval
+        let next_send = self.last_send + self.interval;
+        match &self.metrics {
+            Some(metrics) if self.paused.is_none() => next_send.min(metrics.instant_next_send()),
+            _ => next_send,
+        }
+    }
+
+    pub(crate) fn duration_to_next_send(&self) -> Duration {
+        self.instant_next_send()
+            .saturating_duration_since(Instant::now())
+    }
+}
+
+/// Adds a random duration of up to `jitter` to `interval`.
+fn jittered(interval: Duration, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        return interval;
     }
+    interval + rand::thread_rng().gen_range(Duration::ZERO..=jitter)
+}
+
+/// Returns the message of a panic, if its payload is a string.
+pub(crate) fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_worker(max_export_batch_size: usize, max_export_batch_bytes: Option<usize>) -> Worker {
+        let config = WorkerConfig {
+            send_interval: Duration::from_secs(1),
+            send_interval_jitter: Duration::ZERO,
+            max_queue_size: 2048,
+            level_aware_shedding: false,
+            wait_for_export: None,
+            heartbeat: None,
+            max_events_per_span: None,
+            implicit_traces: false,
+            default_trace: None,
+            layer_filter: Default::default(),
+            max_export_batch_size,
+            max_export_batch_bytes,
+            max_concurrent_exports: 1,
+            resource_attributes: Vec::new(),
+            headers: Default::default(),
+            agent: Default::default(),
+            tail_sampler: None,
+            metrics: None,
+            error_handler: Default::default(),
+            on_batch_result: None,
+            disk_buffer: None,
+            circuit_breaker: None,
+            retry_budget: None,
+            routes: Vec::new(),
+            changes: Default::default(),
+        };
+        let destination = Destination::Http {
+            traces: Url::parse("http://127.0.0.1:1/v1/traces").unwrap(),
+            metrics: Url::parse("http://127.0.0.1:1/v1/metrics").unwrap(),
+        };
+        Worker::new(destination, config, Default::default()).unwrap()
+    }
+
+    fn fail(worker: &mut Worker) {
+        let batch = worker.take_batch();
+        worker.handle_outcome(ExportOutcome {
+            spans: span_count(&batch.req),
+            batch,
+            duration: Duration::ZERO,
+            result: Err("connection refused".into()),
+        });
+    }
+
+    fn succeed(worker: &mut Worker, rejected: usize) {
+        let batch = worker.take_batch();
+        worker.handle_outcome(ExportOutcome {
+            spans: span_count(&batch.req),
+            batch,
+            duration: Duration::from_millis(5),
+            result: Ok(rejected),
+        });
+    }
+
+    fn receive(worker: &mut Worker, count: usize) {
+        for _ in 0..count {
+            worker.receive(QueuedSpan {
+                span: Span {
+                    name: "span".to_string(),
+                    ..Default::default()
+                },
+                has_error: false,
+                in_progress: false,
+            });
+        }
+    }
+
+    #[test]
+    fn limits_batches_by_span_count() {
+        let mut worker = test_worker(3, None);
+        receive(&mut worker, 2);
+        assert!(!worker.batch_ready());
+        receive(&mut worker, 3);
+        assert!(worker.batch_ready());
+        assert_eq!(span_count(&worker.take_batch().req), 3);
+        assert!(!worker.batch_ready());
+        assert_eq!(span_count(&worker.take_batch().req), 2);
+    }
+
+    #[test]
+    fn groups_spans_by_service() {
+        let mut worker = test_worker(10, None);
+        worker.resource.attributes =
+            vec![KeyValue::new(SERVICE_NAME.to_string(), "gateway".into())];
+        for service in [None, Some("billing"), Some("gateway"), Some("billing")] {
+            let attributes = service
+                .map(|service| KeyValue::new(SERVICE_NAME.to_string(), service.into()))
+                .into_iter()
+                .collect();
+            worker.receive(QueuedSpan {
+                span: Span {
+                    attributes,
+                    ..Default::default()
+                },
+                has_error: false,
+                in_progress: false,
+            });
+        }
+
+        let batch = worker.take_batch();
+        let services: Vec<_> = batch
+            .req
+            .resource_spans
+            .iter()
+            .map(|resource_spans| {
+                let resource = resource_spans.resource.as_ref().unwrap();
+                let spans = &resource_spans.scope_spans[0].spans;
+                assert!(spans.iter().all(|span| span.attributes.is_empty()));
+                (resource.attributes[0].clone(), spans.len())
+            })
+            .collect();
+        assert_eq!(
+            services,
+            [
+                (KeyValue::new(SERVICE_NAME.to_string(), "gateway".into()), 2),
+                (KeyValue::new(SERVICE_NAME.to_string(), "billing".into()), 2),
+            ]
+        );
+
+        // The spans of failed batches end up in the same resources when retried
+        let request = worker.request(batch.into_spans());
+        assert_eq!(request.resource_spans.len(), 2);
+        assert_eq!(span_count(&request), 4);
+    }
+
+    #[test]
+    fn batches_spans_per_route() {
+        let mut worker = test_worker(2, None);
+        let tenant = |tenant: &str| KeyValue::new("tenant.id".to_string(), tenant.into());
+        worker.routes = ["acme", "globex"]
+            .into_iter()
+            .map(|name| {
+                let route = crate::Route::attribute("http://127.0.0.1:1", "tenant.id", name);
+                Arc::new(ResolvedRoute {
+                    traces: Url::parse(&format!("http://{name}:4318/v1/traces")).unwrap(),
+                    headers: vec![("X-Tenant".to_string(), name.to_string())],
+                    rule: route.rule,
+                })
+            })
+            .collect();
+        for name in [
+            None,
+            Some("acme"),
+            Some("globex"),
+            Some("acme"),
+            None,
+            Some("acme"),
+        ] {
+            worker.receive(QueuedSpan {
+                span: Span {
+                    attributes: name.map(tenant).into_iter().collect(),
+                    ..Default::default()
+                },
+                has_error: false,
+                in_progress: false,
+            });
+        }
+
+        let mut batches = Vec::new();
+        while worker.has_pending() {
+            let batch = worker.take_batch();
+            let endpoint = worker.batch_endpoint(&batch).unwrap();
+            batches.push((
+                endpoint.0.host_str().unwrap().to_string(),
+                span_count(&batch.req),
+            ));
+        }
+        let batch = |host: &str, spans| (host.to_string(), spans);
+        assert_eq!(
+            batches,
+            [
+                batch("127.0.0.1", 2),
+                batch("acme", 2),
+                batch("globex", 1),
+                batch("acme", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_setting_changes() {
+        let mut worker = test_worker(512, None);
+        let base = Url::parse("http://collector:4318").unwrap();
+        worker.changes.push(SettingChange::Endpoint {
+            traces: base.join("v1/traces").unwrap(),
+            metrics: base.join("v1/metrics").unwrap(),
+        });
+        worker.changes.push(SettingChange::MaxExportBatchSize(2));
+        worker.maintain();
+        assert_eq!(
+            worker.traces_endpoint(),
+            Some(&base.join("v1/traces").unwrap())
+        );
+        receive(&mut worker, 3);
+        assert!(worker.batch_ready());
+        assert_eq!(span_count(&worker.take_batch().req), 2);
+    }
+
+    #[test]
+    fn pauses_exports() {
+        let mut worker = test_worker(512, None);
+        worker
+            .changes
+            .push(SettingChange::Paused(Some(Pause::Buffer)));
+        worker.maintain();
+        receive(&mut worker, MAX_OUTSTANDING + 2);
+        assert!(worker.exports_suspended());
+        assert_eq!(
+            worker.pending.len(),
+            MAX_OUTSTANDING,
+            "the oldest spans are dropped"
+        );
+
+        worker
+            .changes
+            .push(SettingChange::Paused(Some(Pause::Drop)));
+        worker.maintain();
+        assert!(worker.exports_suspended());
+        assert!(!worker.has_pending());
+        assert_eq!(
+            worker.counters.spans_dropped.load(Ordering::Relaxed),
+            MAX_OUTSTANDING as u64 + 2
+        );
 
-    fn duration_to_next_send(&self) -> Duration {
-        self.instant_next_send() - Instant::now()
+        worker.changes.push(SettingChange::Paused(None));
+        worker.maintain();
+        receive(&mut worker, 1);
+        assert!(!worker.exports_suspended());
+        assert!(worker.has_pending());
+    }
+
+    #[test]
+    fn limits_batches_by_size() {
+        let span_len = Span {
+            name: "span".to_string(),
+            ..Default::default()
+        }
+        .encoded_len();
+        let mut worker = test_worker(512, Some(span_len * 2));
+        receive(&mut worker, 1);
+        assert!(!worker.batch_ready());
+        receive(&mut worker, 4);
+        assert!(worker.batch_ready());
+        assert_eq!(span_count(&worker.take_batch().req), 2);
+        assert!(worker.batch_ready(), "remaining spans are measured again");
+        assert_eq!(span_count(&worker.take_batch().req), 2);
+        assert!(!worker.batch_ready());
+
+        let mut worker = test_worker(512, Some(1));
+        receive(&mut worker, 2);
+        assert_eq!(
+            span_count(&worker.take_batch().req),
+            1,
+            "oversized spans are sent alone"
+        );
+    }
+
+    #[test]
+    fn drops_batches_exceeding_the_retry_budget() {
+        let mut worker = test_worker(2, None);
+        worker.retry_budget = Some((2, Duration::from_secs(3600)));
+        receive(&mut worker, 2);
+        fail(&mut worker);
+        receive(&mut worker, 1);
+        assert_eq!(worker.failed_spans(), 2);
+
+        // The failed batch is retried first, and kept apart from the newer spans
+        fail(&mut worker);
+        assert_eq!(worker.failed_spans(), 0);
+        assert_eq!(worker.counters.spans_dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(span_count(&worker.take_batch().req), 1);
+
+        let mut worker = test_worker(2, None);
+        worker.retry_budget = Some((u32::MAX, Duration::ZERO));
+        receive(&mut worker, 1);
+        fail(&mut worker);
+        assert!(!worker.has_pending(), "the batch ran out of time");
+    }
+
+    #[test]
+    fn spools_batches_exceeding_the_retry_budget() {
+        let dir =
+            std::env::temp_dir().join(format!("tracing-otlp-worker-spool-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut worker = test_worker(512, None);
+        worker.retry_budget = Some((1, Duration::from_secs(3600)));
+        worker.spool = Some(Spool::open(dir.clone(), 1024 * 1024).unwrap());
+        receive(&mut worker, 2);
+        fail(&mut worker);
+
+        assert_eq!(worker.failed_spans(), 0);
+        assert_eq!(worker.counters.spans_dropped.load(Ordering::Relaxed), 0);
+        let spooled = worker.spool.as_ref().unwrap().oldest().unwrap().unwrap();
+        assert_eq!(spooled.spans, 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn suspends_exports_after_consecutive_failures() {
+        let mut worker = test_worker(512, None);
+        worker.circuit = Some(CircuitBreaker::new(2, Duration::from_secs(60)));
+
+        receive(&mut worker, 3);
+        fail(&mut worker);
+        assert!(!worker.exports_suspended());
+        assert_eq!(worker.failed_spans(), 3, "failed spans are retried");
+        fail(&mut worker);
+        assert!(worker.exports_suspended());
+        assert!(!worker.has_pending());
+        assert_eq!(worker.counters.spans_dropped.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn reports_batch_results() {
+        let results = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut worker = test_worker(512, None);
+        worker.on_batch_result = Some({
+            let results = results.clone();
+            Arc::new(move |result| results.lock().unwrap().push(result))
+        });
+
+        receive(&mut worker, 3);
+        let payload_size = worker.request(worker.pending.clone()).encoded_len();
+        succeed(&mut worker, 1);
+        receive(&mut worker, 2);
+        fail(&mut worker);
+
+        let results = results.lock().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].spans, 3);
+        assert_eq!(results[0].payload_size, payload_size);
+        assert_eq!(results[0].duration, Duration::from_millis(5));
+        assert_eq!(results[0].rejected_spans, 1);
+        assert_eq!(results[0].error, None);
+        assert_eq!(results[1].spans, 2);
+        assert_eq!(results[1].rejected_spans, 0);
+        assert_eq!(results[1].error.as_deref(), Some("connection refused"));
+    }
+
+    #[test]
+    fn counts_rejected_spans_as_dropped() {
+        let mut worker = test_worker(512, None);
+        receive(&mut worker, 3);
+        succeed(&mut worker, 2);
+        assert_eq!(worker.counters.spans_exported.load(Ordering::Relaxed), 1);
+        assert_eq!(worker.counters.spans_dropped.load(Ordering::Relaxed), 2);
+
+        // Destinations rejecting more spans than sent do not drop more than the batch
+        receive(&mut worker, 1);
+        succeed(&mut worker, 5);
+        assert_eq!(worker.counters.spans_exported.load(Ordering::Relaxed), 1);
+        assert_eq!(worker.counters.spans_dropped.load(Ordering::Relaxed), 3);
+        assert_eq!(worker.counters.batches_exported.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn counts_spans_discarded_by_tail_sampling() {
+        let mut worker = test_worker(512, None);
+        worker.tail_buffer = Some(TailBuffer::new(crate::TailSampler::errors(Duration::ZERO)));
+        receive(&mut worker, 3);
+        worker.maintain();
+        assert!(!worker.has_pending());
+        assert_eq!(
+            worker.counters.spans_tail_dropped.load(Ordering::Relaxed),
+            3
+        );
+        assert_eq!(worker.counters.spans_dropped.load(Ordering::Relaxed), 0);
+        assert_eq!(worker.counters.queue_depth(), 0);
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let interval = Duration::from_secs(1);
+        assert_eq!(jittered(interval, Duration::ZERO), interval);
+        for _ in 0..100 {
+            let jittered = jittered(interval, Duration::from_millis(500));
+            assert!((interval..=interval + Duration::from_millis(500)).contains(&jittered));
+        }
     }
 }
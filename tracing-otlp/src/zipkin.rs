@@ -0,0 +1,280 @@
+use std::{
+    str::FromStr,
+    sync::{mpsc::Sender, Arc},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::{json, Map, Value as Json};
+use tracing_distributed::{Event, Span, Telemetry, TelemetryLayer};
+use url::Url;
+
+use crate::{
+    error::ErrorHandler,
+    json_worker::JsonWorker,
+    prost::common::v1::{any_value::Value, KeyValue},
+    stdout::format_value,
+    IdGenerator, OtlpBuildError, OtlpError, RandomIdGenerator, SpanId, TraceId, Visitor,
+};
+
+/// Builder for the [`Zipkin`] `tracing` layer, which posts spans in the Zipkin v2 JSON format
+/// to the `/api/v2/spans` endpoint of a Zipkin server.
+///
+/// # Examples
+/// ```
+/// # use tracing_otlp::ZipkinBuilder;
+/// ZipkinBuilder::new()
+///     .service_name("api".to_string())
+///     .build("http://127.0.0.1:9411");
+/// ```
+pub struct ZipkinBuilder {
+    service_name: String,
+    send_interval: Duration,
+    headers: Vec<(String, String)>,
+    id_generator: Arc<dyn IdGenerator>,
+    error_handler: ErrorHandler,
+}
+
+impl Default for ZipkinBuilder {
+    fn default() -> Self {
+        Self {
+            service_name: "unknown_service".to_string(),
+            send_interval: Duration::from_secs(1),
+            headers: Default::default(),
+            id_generator: Arc::new(RandomIdGenerator::default()),
+            error_handler: Default::default(),
+        }
+    }
+}
+
+impl ZipkinBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the service name reported as the local endpoint of all spans.
+    pub fn service_name(mut self, service_name: String) -> Self {
+        self.service_name = service_name;
+        self
+    }
+
+    /// Configures the interval at which spans are reported to the Zipkin endpoint
+    pub fn send_interval(mut self, interval: Duration) -> Self {
+        self.send_interval = interval;
+        self
+    }
+
+    /// Sets the HTTP headers to be added to Zipkin requests.
+    pub fn http_headers(mut self, headers: Vec<(String, String)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets the [`IdGenerator`] used for the ids of new spans.
+    pub fn id_generator(mut self, id_generator: impl IdGenerator) -> Self {
+        self.id_generator = Arc::new(id_generator);
+        self
+    }
+
+    /// Sets the handler called with the errors encountered while sending spans, as
+    /// [`crate::Builder::on_error`] does for OTLP.
+    pub fn on_error(mut self, handler: impl Fn(OtlpError) + Send + Sync + 'static) -> Self {
+        self.error_handler = ErrorHandler::new(handler);
+        self
+    }
+
+    /// Builds a [`TelemetryLayer`] based on [`Zipkin`] the settings provided.
+    ///
+    /// The `endpoint` given should be the HTTP URL of the Zipkin server.
+    pub fn build(
+        self,
+        endpoint: &str,
+    ) -> Result<TelemetryLayer<Zipkin, SpanId, TraceId>, OtlpBuildError> {
+        let endpoint = Url::from_str(endpoint)?.join("/api/v2/spans")?;
+        let tx = JsonWorker::spawn(
+            "Zipkin worker",
+            endpoint,
+            self.send_interval,
+            self.headers,
+            self.error_handler.clone(),
+        )?;
+
+        let id_generator = self.id_generator;
+        Ok(TelemetryLayer::new(
+            "",
+            Zipkin {
+                tx,
+                service_name: self.service_name,
+                error_handler: self.error_handler,
+            },
+            move |_| id_generator.new_span_id(),
+        ))
+    }
+}
+
+/// Zipkin v2 implementation of [`Telemetry`]. Use [`ZipkinBuilder`] to instantiate this.
+pub struct Zipkin {
+    tx: Sender<Json>,
+    service_name: String,
+    error_handler: ErrorHandler,
+}
+
+impl Telemetry for Zipkin {
+    type Visitor = Visitor;
+
+    type TraceId = TraceId;
+
+    type SpanId = SpanId;
+
+    fn mk_visitor(&self) -> Self::Visitor {
+        Default::default()
+    }
+
+    fn report_span(
+        &self,
+        span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+        events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+    ) {
+        let span = span_to_json(&self.service_name, span, events);
+        if self.tx.send(span).is_err() {
+            self.error_handler.handle(OtlpError::SpansDropped {
+                count: 1,
+                reason: "worker thread stopped",
+            });
+        }
+    }
+
+    fn report_event(&self, _event: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {}
+}
+
+fn span_to_json(
+    service_name: &str,
+    span: Span<Visitor, SpanId, TraceId>,
+    events: Vec<Event<Visitor, SpanId, TraceId>>,
+) -> Json {
+    let timestamp = micros(span.initialized_at);
+    let mut tags = tags(&span.values.0);
+    if events
+        .iter()
+        .any(|event| *event.meta.level() == tracing::Level::ERROR)
+    {
+        tags.entry("error").or_insert_with(|| json!(""));
+    }
+
+    let mut object = json!({
+        "traceId": span.trace_id.to_string(),
+        "id": span.id.to_string(),
+        "name": span.name,
+        "timestamp": timestamp,
+        "duration": micros(span.completed_at).saturating_sub(timestamp),
+        "localEndpoint": { "serviceName": service_name },
+        "annotations": events
+            .iter()
+            .map(|event| json!({
+                "timestamp": micros(event.initialized_at),
+                "value": annotation(event),
+            }))
+            .collect::<Vec<_>>(),
+        "tags": tags,
+    });
+    if let Some(parent_id) = span.parent_id {
+        object["parentId"] = json!(parent_id.to_string());
+    }
+    object
+}
+
+/// Zipkin tags must be strings, strings are used as is and other values formatted.
+fn tags(attributes: &[KeyValue]) -> Map<String, Json> {
+    attributes
+        .iter()
+        .map(|kv| {
+            let value = match kv.value.as_ref().and_then(|value| value.value.as_ref()) {
+                Some(Value::StringValue(s)) => s.clone(),
+                value => {
+                    let mut output = String::new();
+                    format_value(&mut output, value);
+                    output
+                }
+            };
+            (kv.key.clone(), Json::String(value))
+        })
+        .collect()
+}
+
+/// Annotations only have a string value, so the level and fields of the event are
+/// formatted into it.
+fn annotation(event: &Event<Visitor, SpanId, TraceId>) -> String {
+    let mut value = event.meta.level().to_string();
+    for kv in &event.values.0 {
+        value.push(' ');
+        value.push_str(&kv.key);
+        value.push('=');
+        format_value(&mut value, kv.value.as_ref().and_then(|v| v.value.as_ref()));
+    }
+    value
+}
+
+fn micros(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    struct Capture(Arc<Mutex<Vec<Json>>>);
+
+    impl Telemetry for Capture {
+        type Visitor = Visitor;
+        type TraceId = TraceId;
+        type SpanId = SpanId;
+
+        fn mk_visitor(&self) -> Self::Visitor {
+            Default::default()
+        }
+
+        fn report_span(
+            &self,
+            span: Span<Self::Visitor, Self::SpanId, Self::TraceId>,
+            events: Vec<Event<Self::Visitor, Self::SpanId, Self::TraceId>>,
+        ) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(span_to_json("api", span, events));
+        }
+
+        fn report_event(&self, _: Event<Self::Visitor, Self::SpanId, Self::TraceId>) {}
+    }
+
+    #[test]
+    fn converts_spans_to_zipkin_v2() {
+        let spans = Arc::new(Mutex::new(vec![]));
+        let layer = TelemetryLayer::new("", Capture(spans.clone()), |_| SpanId(3));
+        let subscriber = tracing_subscriber::registry().with(layer);
+
+        tracing::subscriber::with_default(subscriber, || {
+            let root = tracing::info_span!("request", http.status_code = 500i64);
+            let _root = root.enter();
+            crate::register_dist_tracing_root(TraceId(1), Some(SpanId(2))).unwrap();
+            tracing::error!(retry = false, "failed");
+        });
+
+        let spans = spans.lock().unwrap();
+        let span = &spans[0];
+        assert_eq!(span["traceId"], "00000000000000000000000000000001");
+        assert_eq!(span["id"], "0000000000000003");
+        assert_eq!(span["parentId"], "0000000000000002");
+        assert_eq!(span["name"], "request");
+        assert_eq!(span["localEndpoint"]["serviceName"], "api");
+        assert_eq!(
+            span["tags"],
+            json!({ "http.status_code": "500", "error": "" })
+        );
+        assert_eq!(
+            span["annotations"][0]["value"],
+            "ERROR message=\"failed\" retry=false"
+        );
+    }
+}